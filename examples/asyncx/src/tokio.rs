@@ -4,7 +4,6 @@ use hivemqtt_core::v5::{
     commons::packet::Packet,
 };
 use std::env;
-use tokio_util::compat::TokioAsyncReadCompatExt;
 
 pub(crate) struct Handler;
 
@@ -29,7 +28,6 @@ async fn main() {
 
     println!("connected!!!****");
     let stream = tokio::io::BufStream::new(stream);
-    let stream = stream.compat();
 
     let options = ConnectOptions {
         username,
@@ -37,7 +35,7 @@ async fn main() {
         ..Default::default()
     };
 
-    let network = Network::new(options, stream).await;
+    let network = Network::new_tokio(options, stream).await;
     let (mut result, client) = network.inspect(|x| println!("x {:?}", x)).unwrap();
 
     tokio::spawn(async move {
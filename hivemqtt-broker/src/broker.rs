@@ -0,0 +1,491 @@
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex, MutexGuard,
+    },
+    time::Instant,
+};
+
+use async_net::TcpListener;
+use futures::{
+    future::BoxFuture, select, stream::FuturesUnordered, AsyncRead, AsyncWrite, FutureExt,
+    StreamExt,
+};
+use hivemqtt_core::v5::{
+    client::connector::MemoryListener,
+    commons::error::MQTTError,
+    packet::publish::Publish,
+};
+
+use crate::{config::BrokerConfig, connection::Connection, session::Session};
+
+#[derive(Debug, Default)]
+pub(crate) struct State {
+    pub(crate) sessions: HashMap<String, Session>,
+    /// Retained messages, by topic name
+    pub(crate) retained: HashMap<String, Publish>,
+}
+
+impl State {
+    /// Forwards `message` to every session subscribed to its topic, and updates the retained message if asked to.
+    /// `from` is the client id of the publisher, if any. Returns the number of sessions the message was forwarded to.
+    /// Expired sessions are dropped first, so that no message is queued for a client that will never resume them
+    pub(crate) fn publish(&mut self, message: &Publish, from: Option<&str>) -> usize {
+        self.expire_sessions(Instant::now());
+
+        if message.retain {
+            // A retained message with a zero length payload removes the retained message for that topic [MQTT-3.3.1-6]
+            if message.payload.is_empty() {
+                self.retained.remove(&message.topic);
+            } else {
+                self.retained.insert(message.topic.clone(), message.clone());
+            }
+        }
+
+        let mut delivered = 0;
+        for (client_id, session) in self.sessions.iter_mut() {
+            let own = from == Some(client_id.as_str());
+            if let Some((qos, retain_as_published)) = session.matches(&message.topic, own) {
+                session.deliver(message, qos, message.retain && retain_as_published);
+                delivered += 1;
+            }
+        }
+        delivered
+    }
+
+    /// Drops every session whose expiry interval elapsed since its client disconnected
+    pub(crate) fn expire_sessions(&mut self, now: Instant) {
+        self.sessions.retain(|_, session| !session.is_expired(now));
+    }
+}
+
+#[derive(Debug)]
+pub(crate) struct Shared {
+    pub(crate) config: BrokerConfig,
+    pub(crate) state: Mutex<State>,
+    next_connection: AtomicU64,
+}
+
+impl Shared {
+    pub(crate) fn state(&self) -> MutexGuard<'_, State> {
+        self.state.lock().unwrap()
+    }
+
+    pub(crate) fn next_connection_id(&self) -> u64 {
+        self.next_connection.fetch_add(1, Ordering::Relaxed)
+    }
+}
+
+/// An in-process MQTT 5.0 broker.
+/// Cloning the broker is cheap, and every clone serves the same sessions, subscriptions and retained messages
+#[derive(Debug, Clone)]
+pub struct Broker {
+    shared: Arc<Shared>,
+}
+
+impl Default for Broker {
+    fn default() -> Self {
+        Self::new(BrokerConfig::default())
+    }
+}
+
+impl Broker {
+    pub fn new(config: BrokerConfig) -> Self {
+        Self {
+            shared: Arc::new(Shared {
+                config,
+                state: Mutex::new(State::default()),
+                next_connection: AtomicU64::new(1),
+            }),
+        }
+    }
+
+    /// Serves a single client over `stream`, until the connection closes
+    pub async fn handle<S>(&self, stream: S)
+    where
+        S: AsyncRead + AsyncWrite + Unpin,
+    {
+        Connection::new(self.shared.clone()).run(stream).await
+    }
+
+    /// Accepts TCP connections on `listener` and serves them concurrently, until accepting fails
+    pub async fn serve(&self, listener: TcpListener) -> Result<(), MQTTError> {
+        let listener = &listener;
+        self.serve_with(move || async move { Ok(listener.accept().await?.0) })
+            .await
+    }
+
+    /// Accepts in-memory connections (see [`MemoryConnector`](hivemqtt_core::v5::client::connector::MemoryConnector))
+    /// and serves them concurrently, until every connector is dropped
+    pub async fn serve_memory(&self, listener: MemoryListener) -> Result<(), MQTTError> {
+        let listener = &listener;
+        self.serve_with(move || listener.accept()).await
+    }
+
+    async fn serve_with<F, Fut, S>(&self, mut accept: F) -> Result<(), MQTTError>
+    where
+        F: FnMut() -> Fut,
+        Fut: std::future::Future<Output = Result<S, MQTTError>>,
+        S: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+    {
+        let mut connections = FuturesUnordered::<BoxFuture<'static, ()>>::new();
+
+        loop {
+            select! {
+                stream = accept().fuse() => {
+                    let stream = stream?;
+                    let broker = self.clone();
+                    connections.push(Box::pin(async move { broker.handle(stream).await }));
+                },
+                _ = connections.select_next_some() => {},
+            }
+        }
+    }
+
+    /// The retained message on `topic`, if any
+    pub fn retained(&self, topic: &str) -> Option<Publish> {
+        self.shared.state().retained.get(topic).cloned()
+    }
+
+    /// Whether the broker holds a session (connected or not, and not expired) for `client_id`
+    pub fn has_session(&self, client_id: &str) -> bool {
+        let state = self.shared.state();
+        state
+            .sessions
+            .get(client_id)
+            .is_some_and(|session| !session.is_expired(Instant::now()))
+    }
+
+    /// Whether `client_id` is currently connected
+    pub fn is_connected(&self, client_id: &str) -> bool {
+        let state = self.shared.state();
+        state
+            .sessions
+            .get(client_id)
+            .is_some_and(|session| session.is_connected())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+
+    use bytes::Bytes;
+    use futures::executor::block_on;
+    use hivemqtt_core::v5::{
+        client::connector::{Connector, DuplexStream, MemoryConnector},
+        commons::{packet::Packet, qos::QoS},
+        packet::{
+            connack::{reason_code::ConnAckReasonCode, ConnAck},
+            connect::{will::Will, Connect, ConnectProperties},
+            disconnect::{Disconnect, DisconnectReasonCode},
+            puback::{PubAck, PubAckReasonCode},
+            pubcomp::PubComp,
+            pubrec::PubRec,
+            pubrel::PubRel,
+            suback::SubAckReasonCode,
+            subscribe::{Subscribe, SubscriptionOptions},
+        },
+    };
+
+    use super::*;
+
+    /// A bare client, that sends and receives packets exactly as the tests script them
+    struct TestClient(DuplexStream);
+
+    impl TestClient {
+        async fn connect(connector: &MemoryConnector, connect: Connect) -> (Self, ConnAck) {
+            let mut client = Self(connector.connect().await.unwrap());
+            client.send(Packet::Connect(connect)).await;
+            let Packet::ConnAck(connack) = client.recv().await else {
+                panic!("expected a CONNACK");
+            };
+            (client, connack)
+        }
+
+        async fn send(&mut self, packet: Packet) {
+            packet.write_to(&mut self.0).await.unwrap();
+        }
+
+        async fn recv(&mut self) -> Packet {
+            Packet::read_from(&mut self.0, usize::MAX).await.unwrap()
+        }
+
+        async fn subscribe(&mut self, filter: &str, qos: QoS) {
+            let options = SubscriptionOptions {
+                qos,
+                ..Default::default()
+            };
+            self.send(Packet::Subscribe(Subscribe {
+                pkid: 1,
+                payload: vec![(filter.to_string(), options)],
+                ..Default::default()
+            }))
+            .await;
+            let Packet::SubAck(suback) = self.recv().await else {
+                panic!("expected a SUBACK");
+            };
+            assert_ne!(suback.payload[0], SubAckReasonCode::TopicFilterInvalid);
+        }
+    }
+
+    fn connect(client_id: &str) -> Connect {
+        Connect {
+            client_id: client_id.to_string(),
+            ..Default::default()
+        }
+    }
+
+    fn message(topic: &str, qos: QoS, pkid: Option<u16>) -> Publish {
+        Publish {
+            topic: topic.to_string(),
+            qos,
+            pkid,
+            payload: Bytes::from_static(b"21.5"),
+            ..Default::default()
+        }
+    }
+
+    /// Serves in-memory connections on a background thread, until the returned connector is dropped
+    fn start(broker: &Broker) -> MemoryConnector {
+        let (connector, listener) = MemoryConnector::new(4096);
+        let broker = broker.clone();
+        thread::spawn(move || block_on(broker.serve_memory(listener)));
+        connector
+    }
+
+    #[test]
+    fn assigns_a_client_identifier_when_the_client_has_none() {
+        let broker = Broker::default();
+        let connector = start(&broker);
+
+        block_on(async {
+            let (_client, connack) = TestClient::connect(&connector, connect("")).await;
+
+            assert_eq!(connack.reason, ConnAckReasonCode::Success);
+            assert!(!connack.session_present);
+            let client_id = connack.properties.assigned_client_id.unwrap();
+            assert!(broker.is_connected(&client_id));
+        });
+    }
+
+    #[test]
+    fn routes_publications_to_matching_subscriptions() {
+        let broker = Broker::default();
+        let connector = start(&broker);
+
+        block_on(async {
+            let (mut subscriber, _) = TestClient::connect(&connector, connect("subscriber")).await;
+            let (mut publisher, _) = TestClient::connect(&connector, connect("publisher")).await;
+            subscriber.subscribe("sensors/+", QoS::One).await;
+
+            publisher.send(Packet::Publish(message("actuators/valve", QoS::One, Some(7)))).await;
+            let Packet::PubAck(puback) = publisher.recv().await else {
+                panic!("expected a PUBACK");
+            };
+            assert_eq!(puback.reason_code, PubAckReasonCode::NoMatchingSubscribers);
+
+            publisher.send(Packet::Publish(message("sensors/temperature", QoS::Two, Some(8)))).await;
+            assert_eq!(
+                publisher.recv().await,
+                Packet::PubRec(PubRec {
+                    pkid: 8,
+                    ..Default::default()
+                })
+            );
+            publisher.send(Packet::PubRel(PubRel { pkid: 8, ..Default::default() })).await;
+            assert_eq!(
+                publisher.recv().await,
+                Packet::PubComp(PubComp {
+                    pkid: 8,
+                    ..Default::default()
+                })
+            );
+
+            // delivered once, downgraded to the QoS of the subscription
+            let Packet::Publish(publish) = subscriber.recv().await else {
+                panic!("expected a PUBLISH");
+            };
+            assert_eq!(publish.topic, "sensors/temperature");
+            assert_eq!(publish.qos, QoS::One);
+            assert_eq!(&publish.payload[..], b"21.5");
+            subscriber
+                .send(Packet::PubAck(PubAck {
+                    pkid: publish.pkid.unwrap(),
+                    ..Default::default()
+                }))
+                .await;
+        });
+    }
+
+    #[test]
+    fn discards_messages_larger_than_the_maximum_packet_size_of_the_client() {
+        let broker = Broker::default();
+        let connector = start(&broker);
+
+        block_on(async {
+            let small = Connect {
+                properties: ConnectProperties {
+                    maximum_packet_size: Some(64),
+                    ..Default::default()
+                },
+                ..connect("subscriber")
+            };
+            let (mut subscriber, _) = TestClient::connect(&connector, small).await;
+            let (mut publisher, _) = TestClient::connect(&connector, connect("publisher")).await;
+            subscriber.subscribe("sensors/+", QoS::One).await;
+
+            let large = Publish {
+                payload: Bytes::from(vec![b'x'; 64]),
+                ..message("sensors/camera", QoS::One, Some(1))
+            };
+            publisher.send(Packet::Publish(large)).await;
+            publisher.recv().await;
+            publisher.send(Packet::Publish(message("sensors/temperature", QoS::One, Some(2)))).await;
+            publisher.recv().await;
+
+            // the large message was dropped as if it had been sent, it does not hold up the next one
+            let Packet::Publish(publish) = subscriber.recv().await else {
+                panic!("expected a PUBLISH");
+            };
+            assert_eq!(publish.topic, "sensors/temperature");
+        });
+    }
+
+    #[test]
+    fn disconnects_clients_exceeding_the_receive_maximum() {
+        let broker = Broker::new(BrokerConfig {
+            receive_maximum: 1,
+            ..Default::default()
+        });
+        let connector = start(&broker);
+
+        block_on(async {
+            let (mut publisher, connack) = TestClient::connect(&connector, connect("publisher")).await;
+            assert_eq!(connack.properties.receive_maximum, Some(1));
+
+            publisher.send(Packet::Publish(message("sensors/temperature", QoS::Two, Some(1)))).await;
+            assert!(matches!(publisher.recv().await, Packet::PubRec(_)));
+            // a duplicate of the unacknowledged message is not another one
+            publisher.send(Packet::Publish(message("sensors/temperature", QoS::Two, Some(1)))).await;
+            assert!(matches!(publisher.recv().await, Packet::PubRec(_)));
+
+            publisher.send(Packet::Publish(message("sensors/humidity", QoS::One, Some(2)))).await;
+            assert_eq!(
+                publisher.recv().await,
+                Packet::Disconnect(Disconnect {
+                    reason_code: DisconnectReasonCode::ReceiveMaximumExceeded,
+                    ..Default::default()
+                })
+            );
+        });
+    }
+
+    #[test]
+    fn sends_retained_messages_to_new_subscriptions() {
+        let broker = Broker::default();
+        let connector = start(&broker);
+
+        block_on(async {
+            let (mut publisher, _) = TestClient::connect(&connector, connect("publisher")).await;
+            let retained = Publish {
+                retain: true,
+                ..message("sensors/temperature", QoS::One, Some(1))
+            };
+            publisher.send(Packet::Publish(retained)).await;
+            publisher.recv().await;
+            assert!(broker.retained("sensors/temperature").is_some());
+
+            let (mut subscriber, _) = TestClient::connect(&connector, connect("subscriber")).await;
+            subscriber.subscribe("sensors/#", QoS::Zero).await;
+            let Packet::Publish(publish) = subscriber.recv().await else {
+                panic!("expected a PUBLISH");
+            };
+            assert!(publish.retain);
+            assert_eq!(publish.qos, QoS::Zero);
+        });
+    }
+
+    #[test]
+    fn publishes_the_will_when_the_connection_drops() {
+        let broker = Broker::default();
+        let connector = start(&broker);
+
+        block_on(async {
+            let (mut subscriber, _) = TestClient::connect(&connector, connect("subscriber")).await;
+            subscriber.subscribe("status/+", QoS::Zero).await;
+
+            let will = Will {
+                topic: String::from("status/sensor"),
+                payload: Bytes::from_static(b"offline"),
+                ..Default::default()
+            };
+            let (client, _) = TestClient::connect(
+                &connector,
+                Connect {
+                    will: Some(will),
+                    ..connect("sensor")
+                },
+            )
+            .await;
+            drop(client);
+
+            let Packet::Publish(publish) = subscriber.recv().await else {
+                panic!("expected a PUBLISH");
+            };
+            assert_eq!(publish.topic, "status/sensor");
+            assert_eq!(&publish.payload[..], b"offline");
+        });
+    }
+
+    #[test]
+    fn resumes_persistent_sessions() {
+        let broker = Broker::default();
+        let connector = start(&broker);
+        let persistent = || Connect {
+            clean_start: false,
+            properties: ConnectProperties {
+                session_expiry_interval: Some(60),
+                ..Default::default()
+            },
+            ..connect("subscriber")
+        };
+
+        block_on(async {
+            let (mut subscriber, _) = TestClient::connect(&connector, persistent()).await;
+            subscriber.subscribe("sensors/#", QoS::One).await;
+            drop(subscriber);
+
+            let (mut publisher, _) = TestClient::connect(&connector, connect("publisher")).await;
+            publisher.send(Packet::Publish(message("sensors/temperature", QoS::One, Some(1)))).await;
+            publisher.recv().await;
+            assert!(broker.has_session("subscriber"));
+
+            let (mut subscriber, connack) = TestClient::connect(&connector, persistent()).await;
+            assert!(connack.session_present);
+            let Packet::Publish(publish) = subscriber.recv().await else {
+                panic!("expected a PUBLISH");
+            };
+            assert_eq!(publish.topic, "sensors/temperature");
+        });
+    }
+
+    #[test]
+    fn does_not_queue_messages_for_expired_sessions() {
+        let mut state = State::default();
+        let mut session = Session::new();
+        let options = SubscriptionOptions {
+            qos: QoS::One,
+            ..Default::default()
+        };
+        session.subscriptions.insert(String::from("sensors/#"), options);
+        session.expiry_interval = 1;
+        session.disconnected_at = Some(Instant::now() - std::time::Duration::from_secs(2));
+        state.sessions.insert(String::from("subscriber"), session);
+
+        let delivered = state.publish(&message("sensors/temperature", QoS::One, Some(1)), None);
+        assert_eq!(delivered, 0);
+        assert!(state.sessions.is_empty());
+    }
+}
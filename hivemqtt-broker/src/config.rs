@@ -0,0 +1,26 @@
+/// Limits the broker applies to every client
+#[derive(Debug, Clone)]
+pub struct BrokerConfig {
+    /// 3.2.2.3.3 Maximum number of QoS 1 and QoS 2 publications the broker processes concurrently for each client
+    pub receive_maximum: u16,
+    /// 3.2.2.3.6 Largest packet (in bytes) the broker accepts, bigger packets close the connection
+    pub max_packet_size: u32,
+    /// Upper bound for the session expiry interval (in seconds) requested by clients
+    pub max_session_expiry_interval: u32,
+    /// 3.2.2.3.14 When set, replaces the keep alive requested by clients
+    pub server_keep_alive: Option<u16>,
+}
+
+/// Largest packet the MQTT encoding can express: a 268,435,455 bytes Remaining Length, plus a 5 bytes fixed header
+pub(crate) const MAX_PACKET_SIZE: u32 = 268_435_460;
+
+impl Default for BrokerConfig {
+    fn default() -> Self {
+        Self {
+            receive_maximum: u16::MAX,
+            max_packet_size: MAX_PACKET_SIZE,
+            max_session_expiry_interval: u32::MAX,
+            server_keep_alive: None,
+        }
+    }
+}
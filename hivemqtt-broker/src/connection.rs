@@ -0,0 +1,530 @@
+use std::{
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use async_channel::{unbounded, Receiver, Sender};
+use async_io::Timer;
+use futures::{pin_mut, select, AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, FutureExt};
+use hivemqtt_core::v5::{
+    commons::{
+        error::{DecodeError, MQTTError},
+        packet::Packet,
+        qos::QoS,
+        version::Version,
+    },
+    packet::{
+        connack::{reason_code::ConnAckReasonCode, ConnAck, ConnAckProperties},
+        connect::{will::Will, Connect},
+        disconnect::{Disconnect, DisconnectReasonCode},
+        ping::PingResp,
+        puback::{PubAck, PubAckReasonCode},
+        pubcomp::{PubComp, PubCompReasonCode},
+        publish::{Publish, PublishProperties},
+        pubrec::{PubRec, PubRecReasonCode},
+        pubrel::{PubRel, PubRelReasonCode},
+        suback::{SubAck, SubAckReasonCode},
+        subscribe::{RetainHandling, Subscribe},
+        unsuback::{UnSubAck, UnSubAckReasonCode},
+        unsubscribe::UnSubscribe,
+    },
+    utils::topic,
+};
+
+use crate::{
+    broker::{Shared, State},
+    config::MAX_PACKET_SIZE,
+    session::Session,
+};
+
+/// How the client left
+#[derive(Debug, PartialEq, Eq)]
+enum Exit {
+    /// The client sent a DISCONNECT, `publish_will` is set when its reason code was 0x04 (Disconnect with Will Message)
+    Disconnect { publish_will: bool },
+    /// The connection failed, timed out, or was closed by the broker
+    Closed,
+}
+
+/// A client connection, from the CONNECT packet until the network connection closes
+pub(crate) struct Connection {
+    shared: Arc<Shared>,
+    id: u64,
+}
+
+impl Connection {
+    pub(crate) fn new(shared: Arc<Shared>) -> Self {
+        let id = shared.next_connection_id();
+        Self { shared, id }
+    }
+
+    pub(crate) async fn run<S>(self, stream: S)
+    where
+        S: AsyncRead + AsyncWrite + Unpin,
+    {
+        let (mut reader, mut writer) = stream.split();
+        let max_size = self.shared.config.max_packet_size as usize;
+
+        // The first packet sent from the Client to the Server MUST be a CONNECT packet [MQTT-3.1.0-1]
+        let connect = match Packet::read_from(&mut reader, max_size).await {
+            Ok(Packet::Connect(connect)) if connect.version == Version::V5 => connect,
+            Ok(Packet::Connect(_)) | Err(DecodeError::Protocol(MQTTError::VersionNotSupported(_))) => {
+                let connack = ConnAck {
+                    reason: ConnAckReasonCode::UnsupportedProtocolVersion,
+                    ..Default::default()
+                };
+                let _ = Packet::ConnAck(connack).write_to(&mut writer).await;
+                let _ = writer.close().await;
+                return;
+            }
+            _ => return,
+        };
+
+        let (tx, rx) = unbounded();
+        let client_id = self.accept(&connect, tx.clone());
+        let keep_alive = self.shared.config.server_keep_alive.unwrap_or(connect.keep_alive);
+
+        let exit = {
+            let reading = self.read_loop(&mut reader, &client_id, keep_alive, tx).fuse();
+            let writing = write_loop(&mut writer, rx).fuse();
+            pin_mut!(reading, writing);
+
+            select! {
+                exit = reading => {
+                    // nothing else gets routed to this connection once it is detached, so the writer stops after the packets queued so far
+                    self.detach(&client_id);
+                    writing.await;
+                    exit
+                },
+                _ = writing => {
+                    self.detach(&client_id);
+                    Exit::Closed
+                },
+            }
+        };
+        let _ = writer.close().await;
+
+        // The Will Message MUST be removed from the stored Session State once it has been published
+        // or the Server has received a DISCONNECT packet with a Reason Code of 0x00 [MQTT-3.1.2-10]
+        let Some(will) = connect.will else {
+            return;
+        };
+        if exit == (Exit::Disconnect { publish_will: false }) {
+            return;
+        }
+        self.publish_will(&client_id, will).await;
+    }
+
+    /// Attaches the connection to the client's session (creating or taking it over), and queues the CONNACK.
+    /// Returns the client identifier
+    fn accept(&self, connect: &Connect, tx: Sender<Packet>) -> String {
+        let config = &self.shared.config;
+        let mut properties = ConnAckProperties {
+            shared_subscription_available: Some(false),
+            subscription_identifiers_available: Some(false),
+            ..Default::default()
+        };
+
+        // The Server MAY allow a Client to supply a ClientID that has a length of zero bytes,
+        // however if it does so the Server MUST treat this as a special case and assign a unique ClientID to that Client [MQTT-3.1.3-6]
+        let client_id = if connect.client_id.is_empty() {
+            let client_id = format!("hivemqtt-broker-{}", self.id);
+            properties.assigned_client_id = Some(client_id.clone());
+            client_id
+        } else {
+            connect.client_id.clone()
+        };
+
+        let requested_expiry = connect.properties.session_expiry_interval.unwrap_or(0);
+        let expiry_interval = requested_expiry.min(config.max_session_expiry_interval);
+        if expiry_interval != requested_expiry {
+            properties.session_expiry_interval = Some(expiry_interval);
+        }
+        if config.receive_maximum != u16::MAX {
+            properties.receive_maximum = Some(config.receive_maximum);
+        }
+        if config.max_packet_size < MAX_PACKET_SIZE {
+            properties.maximum_packet_size = Some(config.max_packet_size);
+        }
+        properties.server_keep_alive = config.server_keep_alive;
+
+        let mut state = self.shared.state();
+        state.expire_sessions(Instant::now());
+
+        if let Some((_, previous)) = state
+            .sessions
+            .get_mut(&client_id)
+            .and_then(|session| session.connection.take())
+        {
+            // If the ClientID represents a Client already connected to the Server, the Server sends a DISCONNECT packet
+            // to the existing Client with Reason Code of 0x8E (Session taken over) [MQTT-3.1.4-3]
+            let _ = previous.try_send(disconnect(DisconnectReasonCode::SessionTakenOver));
+        }
+
+        if connect.clean_start {
+            state.sessions.remove(&client_id);
+        }
+
+        let session_present = state.sessions.contains_key(&client_id);
+        let session = state
+            .sessions
+            .entry(client_id.clone())
+            .or_insert_with(Session::new);
+        session.expiry_interval = expiry_interval;
+        session.disconnected_at = None;
+        session.receive_maximum = connect.properties.receive_maximum.unwrap_or(u16::MAX);
+        session.max_packet_size = connect
+            .properties
+            .maximum_packet_size
+            .unwrap_or(MAX_PACKET_SIZE);
+        session.connection = Some((self.id, tx.clone()));
+
+        let _ = tx.try_send(Packet::ConnAck(ConnAck {
+            session_present,
+            reason: ConnAckReasonCode::Success,
+            properties,
+        }));
+        session.resume();
+
+        client_id
+    }
+
+    /// Detaches the connection from its session, and ends the session if it is not meant to outlive the connection
+    fn detach(&self, client_id: &str) {
+        let mut state = self.shared.state();
+
+        let Some(session) = state.sessions.get_mut(client_id) else {
+            return;
+        };
+        if session.connection.as_ref().is_none_or(|(id, _)| *id != self.id) {
+            return; // taken over by another connection
+        }
+
+        session.connection = None;
+        session.disconnected_at = Some(Instant::now());
+        if session.expiry_interval == 0 {
+            state.sessions.remove(client_id);
+        }
+        state.expire_sessions(Instant::now());
+    }
+
+    async fn read_loop<R>(
+        &self,
+        reader: &mut R,
+        client_id: &str,
+        keep_alive: u16,
+        tx: Sender<Packet>,
+    ) -> Exit
+    where
+        R: AsyncRead + Unpin,
+    {
+        let max_size = self.shared.config.max_packet_size as usize;
+        // If the Server does not receive a Control Packet from the Client within one and a half times the Keep Alive time period,
+        // it MUST disconnect the Network Connection to the Client as if the network had failed [MQTT-3.1.2-22]
+        let timeout = Duration::from_millis(keep_alive as u64 * 1500);
+
+        loop {
+            let packet = {
+                let read = Packet::read_from(reader, max_size).fuse();
+                let timer = FutureExt::fuse(Timer::after(timeout));
+                pin_mut!(read, timer);
+
+                select! {
+                    packet = read => packet,
+                    _ = timer => if keep_alive > 0 {
+                        let _ = tx.try_send(disconnect(DisconnectReasonCode::KeepAliveTimeout));
+                        return Exit::Closed;
+                    } else {
+                        read.await
+                    },
+                }
+            };
+
+            let packet = match packet {
+                Ok(packet) => packet,
+                Err(DecodeError::Io(_)) => return Exit::Closed,
+                Err(error) => {
+                    let _ = tx.try_send(disconnect(error.reason_code()));
+                    return Exit::Closed;
+                }
+            };
+
+            let mut state = self.shared.state();
+            match self.handle(&mut state, client_id, packet, &tx) {
+                Ok(None) => {}
+                Ok(Some(exit)) => return exit,
+                Err(reason) => {
+                    let _ = tx.try_send(disconnect(reason));
+                    return Exit::Closed;
+                }
+            }
+        }
+    }
+
+    /// Handles a packet received from the client. Errors are the reason code the connection gets closed with
+    fn handle(
+        &self,
+        state: &mut State,
+        client_id: &str,
+        packet: Packet,
+        tx: &Sender<Packet>,
+    ) -> Result<Option<Exit>, DisconnectReasonCode> {
+        let State { sessions, retained } = state;
+        let Some(session) = sessions
+            .get_mut(client_id)
+            .filter(|session| session.connection.as_ref().is_some_and(|(id, _)| *id == self.id))
+        else {
+            return Ok(Some(Exit::Closed)); // taken over by another connection
+        };
+
+        let send = |packet| {
+            let _ = tx.try_send(packet);
+        };
+
+        match packet {
+            Packet::Publish(publish) => {
+                // the broker does not allow topic aliases (its Topic Alias Maximum is 0)
+                if publish.properties.topic_alias.is_some() {
+                    return Err(DisconnectReasonCode::TopicAliasInvalid);
+                }
+                if publish.topic.is_empty() || publish.topic.contains(['#', '+']) {
+                    return Err(DisconnectReasonCode::TopicNameInvalid);
+                }
+                // QoS 1 messages are acknowledged right away, the QoS 2 ones waiting for their PUBREL are the only
+                // unacknowledged ones. Going over the broker's Receive Maximum is answered with a DISCONNECT with
+                // Reason Code 0x93 (Receive Maximum exceeded) (3.3.4)
+                let receive_maximum = self.shared.config.receive_maximum as usize;
+                if publish.qos != QoS::Zero
+                    && publish.pkid.is_some_and(|pkid| !session.incoming.contains(&pkid))
+                    && session.incoming.len() >= receive_maximum
+                {
+                    return Err(DisconnectReasonCode::ReceiveMaximumExceeded);
+                }
+
+                match (publish.qos, publish.pkid) {
+                    (QoS::Zero, _) => {
+                        state.publish(&publish, Some(client_id));
+                    }
+                    (QoS::One, Some(pkid)) => {
+                        let delivered = state.publish(&publish, Some(client_id));
+                        send(Packet::PubAck(PubAck {
+                            pkid,
+                            reason_code: match delivered {
+                                0 => PubAckReasonCode::NoMatchingSubscribers,
+                                _ => PubAckReasonCode::Success,
+                            },
+                            ..Default::default()
+                        }));
+                    }
+                    (QoS::Two, Some(pkid)) => {
+                        // a duplicate is acknowledged, but not forwarded a second time
+                        let mut reason_code = PubRecReasonCode::Success;
+                        if session.incoming.insert(pkid)
+                            && state.publish(&publish, Some(client_id)) == 0
+                        {
+                            reason_code = PubRecReasonCode::NoMatchingSubscribers;
+                        }
+                        send(Packet::PubRec(PubRec {
+                            pkid,
+                            reason_code,
+                            ..Default::default()
+                        }));
+                    }
+                    _ => return Err(DisconnectReasonCode::ProtocolError),
+                }
+            }
+            Packet::PubAck(PubAck { pkid, .. }) | Packet::PubComp(PubComp { pkid, .. }) => {
+                session.complete(pkid);
+            }
+            Packet::PubRec(PubRec {
+                pkid, reason_code, ..
+            }) => {
+                if u8::from(reason_code) >= 0x80 {
+                    session.complete(pkid);
+                } else {
+                    let reason_code = match session.release(pkid) {
+                        true => PubRelReasonCode::Success,
+                        false => PubRelReasonCode::PacketIdentifierNotFound,
+                    };
+                    send(Packet::PubRel(PubRel {
+                        pkid,
+                        reason_code,
+                        ..Default::default()
+                    }));
+                }
+            }
+            Packet::PubRel(PubRel { pkid, .. }) => {
+                let reason_code = match session.incoming.remove(&pkid) {
+                    true => PubCompReasonCode::Success,
+                    false => PubCompReasonCode::PacketIdentifierNotFound,
+                };
+                send(Packet::PubComp(PubComp {
+                    pkid,
+                    reason_code,
+                    ..Default::default()
+                }));
+            }
+            Packet::Subscribe(Subscribe {
+                pkid,
+                properties,
+                payload,
+            }) => {
+                // subscription identifiers are not supported
+                if properties.subscription_id.is_some() {
+                    return Err(DisconnectReasonCode::SubscriptionIdentifiersNotSupported);
+                }
+
+                let mut codes = Vec::with_capacity(payload.len());
+                let mut accepted = Vec::with_capacity(payload.len());
+                for (filter, options) in payload {
+                    if filter.starts_with("$share/") {
+                        codes.push(SubAckReasonCode::SharedSubscriptionsNotSupported);
+                    } else if topic::validate_filter(&filter).is_err() {
+                        codes.push(SubAckReasonCode::TopicFilterInvalid);
+                    } else {
+                        codes.push(match options.qos {
+                            QoS::Zero => SubAckReasonCode::GrantedQoS0,
+                            QoS::One => SubAckReasonCode::GrantedQoS1,
+                            QoS::Two => SubAckReasonCode::GrantedQoS2,
+                        });
+                        let existed = session.subscriptions.insert(filter.clone(), options).is_some();
+                        accepted.push((filter, options, existed));
+                    }
+                }
+
+                send(Packet::SubAck(SubAck {
+                    pkid,
+                    payload: codes,
+                    ..Default::default()
+                }));
+
+                // 3.3.1.3 retained messages are sent when the subscription is made, with the RETAIN flag set
+                for (filter, options, existed) in accepted {
+                    let send_retained = match options.retain_handling {
+                        RetainHandling::Zero => true,
+                        RetainHandling::One => !existed,
+                        RetainHandling::Two => false,
+                    };
+                    if !send_retained {
+                        continue;
+                    }
+
+                    retained
+                        .values()
+                        .filter(|message| topic::matches(&filter, &message.topic))
+                        .for_each(|message| session.deliver(message, options.qos, true));
+                }
+            }
+            Packet::UnSubscribe(UnSubscribe { pkid, payload, .. }) => {
+                let payload = payload
+                    .iter()
+                    .map(|filter| match session.subscriptions.remove(filter) {
+                        Some(_) => UnSubAckReasonCode::Success,
+                        None => UnSubAckReasonCode::NoSubscriptionExisted,
+                    })
+                    .collect();
+
+                send(Packet::UnSubAck(UnSubAck {
+                    pkid,
+                    payload,
+                    ..Default::default()
+                }));
+            }
+            Packet::PingReq(_) => send(Packet::PingResp(PingResp)),
+            Packet::Disconnect(Disconnect {
+                reason_code,
+                properties,
+            }) => {
+                if let Some(expiry) = properties.session_expiry_interval {
+                    // If the Session Expiry Interval in the CONNECT packet was zero,
+                    // then it is a Protocol Error to set a non-zero Session Expiry Interval in the DISCONNECT packet [MQTT-3.14.2-2]
+                    if session.expiry_interval == 0 && expiry != 0 {
+                        return Err(DisconnectReasonCode::ProtocolError);
+                    }
+                    session.expiry_interval = expiry.min(self.shared.config.max_session_expiry_interval);
+                }
+
+                return Ok(Some(Exit::Disconnect {
+                    publish_will: reason_code == DisconnectReasonCode::DisconnectWithWillMessage,
+                }));
+            }
+            // A second CONNECT is a Protocol Error [MQTT-3.1.0-2], enhanced authentication is not supported,
+            // and the other packets are never sent by clients
+            _ => return Err(DisconnectReasonCode::ProtocolError),
+        }
+
+        Ok(None)
+    }
+
+    async fn publish_will(&self, client_id: &str, will: Will) {
+        let expiry = self
+            .shared
+            .state()
+            .sessions
+            .get(client_id)
+            .map_or(0, |session| session.expiry_interval);
+
+        // 3.1.3.2.2 The Server delays publishing the Client's Will Message until the Will Delay Interval has passed or the Session ends,
+        // whichever happens first. The will is not sent if a new Network Connection to this Session is made before then
+        let delay = will.properties.delay_interval.unwrap_or(0).min(expiry);
+        if delay > 0 {
+            Timer::after(Duration::from_secs(delay as u64)).await;
+
+            if self
+                .shared
+                .state()
+                .sessions
+                .get(client_id)
+                .is_some_and(|session| session.is_connected())
+            {
+                return;
+            }
+        }
+
+        let message = Publish {
+            topic: will.topic,
+            payload: will.payload,
+            qos: will.qos,
+            retain: will.retain,
+            properties: PublishProperties {
+                payload_format_indicator: will.properties.payload_format_indicator,
+                message_expiry_internal: will.properties.message_expiry_interval,
+                content_type: will.properties.content_type,
+                response_topic: will.properties.response_topic,
+                correlation_data: will.properties.correlation_data,
+                user_property: will.properties.user_property,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        self.shared.state().publish(&message, Some(client_id));
+    }
+}
+
+fn disconnect(reason_code: DisconnectReasonCode) -> Packet {
+    Packet::Disconnect(Disconnect {
+        reason_code,
+        ..Default::default()
+    })
+}
+
+/// Writes the packets queued for the client, until the broker closes the connection or nothing can be queued anymore
+async fn write_loop<W>(writer: &mut W, rx: Receiver<Packet>)
+where
+    W: AsyncWrite + Unpin,
+{
+    while let Ok(packet) = rx.recv().await {
+        let closing = matches!(packet, Packet::Disconnect(_));
+
+        if packet.write_to(writer).await.is_err() {
+            return;
+        }
+        // packets that are ready together are flushed together
+        if (rx.is_empty() || closing) && writer.flush().await.is_err() {
+            return;
+        }
+        if closing {
+            return;
+        }
+    }
+}
@@ -0,0 +1,20 @@
+//! A small, in-process MQTT 5.0 broker.
+//!
+//! It is meant to be embedded in tests and local development setups, so that nothing has to be installed or started next to them:
+//! ```ignore
+//! let broker = Broker::new(BrokerConfig::default());
+//! let (connector, listener) = MemoryConnector::new(4096);
+//! // drive `broker.serve_memory(listener)` next to the client under test
+//! ```
+//! Supported: the CONNECT/CONNACK handshake, persistent sessions with session expiry, wildcard subscriptions,
+//! retained messages, will messages (including the will delay interval) and QoS 0, 1 and 2 in both directions.
+//!
+//! Not supported: MQTT 3.1.1 clients, enhanced authentication (AUTH), shared subscriptions, subscription identifiers
+//! and topic aliases sent by clients.
+mod broker;
+mod config;
+mod connection;
+mod session;
+
+pub use broker::Broker;
+pub use config::BrokerConfig;
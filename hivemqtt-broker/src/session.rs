@@ -0,0 +1,308 @@
+use std::{
+    collections::{BTreeMap, HashMap, HashSet, VecDeque},
+    time::{Duration, Instant},
+};
+
+use async_channel::Sender;
+use hivemqtt_core::v5::{
+    commons::{packet::Packet, qos::QoS},
+    packet::{
+        publish::Publish,
+        pubrel::PubRel,
+        subscribe::SubscriptionOptions,
+    },
+    utils::topic,
+};
+
+use crate::config::MAX_PACKET_SIZE;
+
+/// A QoS 1 or QoS 2 message sent to the client, and not yet fully acknowledged
+#[derive(Debug)]
+pub(crate) enum InFlight {
+    /// Waiting for the PUBACK (QoS 1) or the PUBREC (QoS 2)
+    Publish(Box<Publish>),
+    /// Waiting for the PUBCOMP (QoS 2)
+    PubRel,
+}
+
+/// 4.1 Session State (server side)
+#[derive(Debug)]
+pub(crate) struct Session {
+    /// Connection currently attached to the session, as `(connection id, outgoing packets)`
+    pub(crate) connection: Option<(u64, Sender<Packet>)>,
+    pub(crate) subscriptions: HashMap<String, SubscriptionOptions>,
+    /// Session Expiry Interval in seconds, `u32::MAX` means the session never expires
+    pub(crate) expiry_interval: u32,
+    pub(crate) disconnected_at: Option<Instant>,
+    /// The client's Receive Maximum
+    pub(crate) receive_maximum: u16,
+    /// The client's Maximum Packet Size
+    pub(crate) max_packet_size: u32,
+
+    outgoing: BTreeMap<u16, InFlight>,
+    /// QoS 1 and QoS 2 messages waiting for the client to connect, or for a free slot in its Receive Maximum
+    queued: VecDeque<Publish>,
+    /// Packet identifiers of QoS 2 messages received from the client, and waiting for its PUBREL
+    pub(crate) incoming: HashSet<u16>,
+    next_pkid: u16,
+}
+
+impl Session {
+    pub(crate) fn new() -> Self {
+        Self {
+            connection: None,
+            subscriptions: HashMap::new(),
+            expiry_interval: 0,
+            disconnected_at: None,
+            receive_maximum: u16::MAX,
+            max_packet_size: MAX_PACKET_SIZE,
+            outgoing: BTreeMap::new(),
+            queued: VecDeque::new(),
+            incoming: HashSet::new(),
+            next_pkid: 0,
+        }
+    }
+
+    pub(crate) fn is_connected(&self) -> bool {
+        self.connection.is_some()
+    }
+
+    pub(crate) fn is_expired(&self, now: Instant) -> bool {
+        match self.disconnected_at {
+            Some(_) if self.expiry_interval == u32::MAX => false,
+            Some(at) => now >= at + Duration::from_secs(self.expiry_interval as u64),
+            None => false,
+        }
+    }
+
+    fn send(&self, packet: Packet) {
+        if let Some((_, tx)) = &self.connection {
+            // the connection is closing if its channel is closed, and the message stays in the session
+            let _ = tx.try_send(packet);
+        }
+    }
+
+    fn allocate_pkid(&mut self) -> u16 {
+        loop {
+            self.next_pkid = self.next_pkid.checked_add(1).unwrap_or(1);
+            if !self.outgoing.contains_key(&self.next_pkid) {
+                return self.next_pkid;
+            }
+        }
+    }
+
+    /// The highest QoS granted by the subscriptions matching `topic`, and whether any of them asked for the retain flag to be kept.
+    /// `own` is set when the message was published by the client of this session
+    pub(crate) fn matches(&self, name: &str, own: bool) -> Option<(QoS, bool)> {
+        self.subscriptions
+            .iter()
+            .filter(|(filter, options)| !(own && options.no_local) && topic::matches(filter, name))
+            .fold(None, |acc, (_, options)| {
+                let (qos, retain) = acc.unwrap_or((QoS::Zero, false));
+                Some((max_qos(qos, options.qos), retain || options.retain_as_published))
+            })
+    }
+
+    /// Sends `message` to the client with (at most) the QoS granted by the subscription
+    pub(crate) fn deliver(&mut self, message: &Publish, qos: QoS, retain: bool) {
+        let mut message = Publish {
+            dup: false,
+            retain,
+            qos: min_qos(message.qos, qos),
+            pkid: None,
+            ..message.clone()
+        };
+        // topic aliases are only valid on the connection they were sent on
+        message.properties.topic_alias = None;
+
+        // The Server MUST NOT send packets exceeding Maximum Packet Size to the Client [MQTT-3.1.2-24],
+        // it discards them and behaves as if it had completed sending them [MQTT-3.1.2-25]
+        if !fits(&message, self.max_packet_size) {
+            return;
+        }
+
+        if message.qos == QoS::Zero {
+            self.send(Packet::Publish(message));
+            return;
+        }
+
+        self.queued.push_back(message);
+        self.flush();
+    }
+
+    /// Sends as many queued messages as the client's Receive Maximum allows
+    pub(crate) fn flush(&mut self) {
+        while self.is_connected() && self.outgoing.len() < self.receive_maximum as usize {
+            let Some(mut message) = self.queued.pop_front() else {
+                break;
+            };
+            // queued for a previous connection, with a larger Maximum Packet Size
+            if !fits(&message, self.max_packet_size) {
+                continue;
+            }
+
+            let pkid = self.allocate_pkid();
+            message.pkid = Some(pkid);
+            self.outgoing.insert(pkid, InFlight::Publish(Box::new(message.clone())));
+            self.send(Packet::Publish(message));
+        }
+    }
+
+    /// Resends every unacknowledged message after the client reconnects (4.4 Message delivery retry)
+    pub(crate) fn resume(&mut self) {
+        let max_packet_size = self.max_packet_size;
+        self.outgoing.retain(|_, inflight| match inflight {
+            InFlight::Publish(message) => fits(message, max_packet_size),
+            InFlight::PubRel => true,
+        });
+
+        for (pkid, inflight) in &self.outgoing {
+            let packet = match inflight {
+                InFlight::Publish(message) => Packet::Publish(Publish {
+                    dup: true,
+                    ..*message.clone()
+                }),
+                InFlight::PubRel => Packet::PubRel(PubRel {
+                    pkid: *pkid,
+                    ..Default::default()
+                }),
+            };
+            self.send(packet);
+        }
+
+        self.flush();
+    }
+
+    /// PUBACK (QoS 1) or PUBCOMP (QoS 2) received
+    pub(crate) fn complete(&mut self, pkid: u16) {
+        if self.outgoing.remove(&pkid).is_some() {
+            self.flush();
+        }
+    }
+
+    /// PUBREC received, returns whether `pkid` belongs to an unacknowledged QoS 2 message
+    pub(crate) fn release(&mut self, pkid: u16) -> bool {
+        match self.outgoing.get_mut(&pkid) {
+            Some(inflight @ InFlight::Publish(_)) => {
+                *inflight = InFlight::PubRel;
+                true
+            }
+            Some(InFlight::PubRel) => true,
+            None => false,
+        }
+    }
+}
+
+/// Whether `message` is not larger than `max_size` bytes, once encoded
+fn fits(message: &Publish, max_size: u32) -> bool {
+    Packet::Publish(message.clone()).encoded_len() <= max_size as usize
+}
+
+fn max_qos(a: QoS, b: QoS) -> QoS {
+    if u8::from(a) >= u8::from(b) {
+        a
+    } else {
+        b
+    }
+}
+
+fn min_qos(a: QoS, b: QoS) -> QoS {
+    if u8::from(a) <= u8::from(b) {
+        a
+    } else {
+        b
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use async_channel::unbounded;
+    use bytes::Bytes;
+
+    use super::*;
+
+    fn message(qos: QoS) -> Publish {
+        Publish {
+            topic: String::from("sensors/temperature"),
+            qos,
+            payload: Bytes::from_static(b"21.5"),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn grants_the_highest_qos_of_the_overlapping_subscriptions() {
+        let mut session = Session::new();
+        session.subscriptions.insert(
+            String::from("sensors/#"),
+            SubscriptionOptions {
+                qos: QoS::One,
+                ..Default::default()
+            },
+        );
+        session.subscriptions.insert(
+            String::from("sensors/+"),
+            SubscriptionOptions {
+                qos: QoS::Two,
+                no_local: true,
+                ..Default::default()
+            },
+        );
+
+        assert_eq!(session.matches("sensors/temperature", false), Some((QoS::Two, false)));
+        assert_eq!(session.matches("sensors/temperature", true), Some((QoS::One, false)));
+        assert_eq!(session.matches("actuators/valve", false), None);
+    }
+
+    #[test]
+    fn queues_messages_beyond_the_receive_maximum() {
+        let (tx, rx) = unbounded();
+        let mut session = Session::new();
+        session.connection = Some((1, tx));
+        session.receive_maximum = 1;
+
+        session.deliver(&message(QoS::Two), QoS::One, false);
+        session.deliver(&message(QoS::One), QoS::One, false);
+
+        let Ok(Packet::Publish(first)) = rx.try_recv() else {
+            panic!("expected a PUBLISH");
+        };
+        assert_eq!(first.qos, QoS::One);
+        assert!(rx.try_recv().is_err());
+
+        session.complete(first.pkid.unwrap());
+        let Ok(Packet::Publish(second)) = rx.try_recv() else {
+            panic!("expected a PUBLISH");
+        };
+        assert_ne!(second.pkid, first.pkid);
+    }
+
+    #[test]
+    fn resends_unacknowledged_messages_on_resume() {
+        let (tx, rx) = unbounded();
+        let mut session = Session::new();
+        session.connection = Some((1, tx));
+
+        session.deliver(&message(QoS::Two), QoS::Two, false);
+        session.deliver(&message(QoS::Two), QoS::Two, false);
+        let _ = (rx.try_recv(), rx.try_recv());
+        assert!(session.release(1));
+
+        let (tx, rx) = unbounded();
+        session.connection = Some((2, tx));
+        session.resume();
+
+        assert_eq!(
+            rx.try_recv(),
+            Ok(Packet::PubRel(PubRel {
+                pkid: 1,
+                ..Default::default()
+            }))
+        );
+        let Ok(Packet::Publish(publish)) = rx.try_recv() else {
+            panic!("expected a PUBLISH");
+        };
+        assert!(publish.dup);
+        assert_eq!(publish.pkid, Some(2));
+    }
+}
@@ -1,7 +1,18 @@
+//! Without the `std` feature (on by default, through `asyncx`), only the packet model and its codec are built,
+//! for `no_std` targets with an allocator.
+#![cfg_attr(not(any(feature = "std", test)), no_std)]
+
+extern crate alloc;
+
+#[macro_use]
+mod trace;
+
 pub mod constants;
-#[cfg(test)]
+#[cfg(all(test, feature = "std"))]
 mod retest_utils;
 pub mod v5;
+#[cfg(feature = "std")]
+pub(crate) mod v311;
 
 // #[cfg(all(feature = "syncx", feature = "asyncx"))]
 // compile_error!("feature \"syncx\" and feature \"asyncx\" cannot be enabled at the same time");
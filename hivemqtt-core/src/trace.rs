@@ -0,0 +1,57 @@
+//! Instrumentation with `tracing`, compiled out unless the `tracing` feature is enabled.
+//!
+//! Events only ever record what a packet is (its type, packet identifier, QoS and size), never its payload nor any of
+//! the credentials it may carry (user name, password, authentication data).
+//! The macros below are only meant to be used as statements, so that they can expand to nothing. They are in scope
+//! of every module declared after this one.
+
+macro_rules! trace {
+    ($($arg:tt)*) => {
+        #[cfg(feature = "tracing")]
+        ::tracing::trace!($($arg)*);
+    };
+}
+
+macro_rules! debug {
+    ($($arg:tt)*) => {
+        #[cfg(feature = "tracing")]
+        ::tracing::debug!($($arg)*);
+    };
+}
+
+macro_rules! warn {
+    ($($arg:tt)*) => {
+        #[cfg(feature = "tracing")]
+        ::tracing::warn!($($arg)*);
+    };
+}
+
+/// Records a packet sent or received, `size` being the size of the whole packet on the wire
+macro_rules! packet {
+    ($direction:literal, $packet:expr, $size:expr) => {
+        #[cfg(feature = "tracing")]
+        {
+            let packet: &$crate::v5::commons::packet::Packet = $packet;
+            ::tracing::debug!(
+                direction = $direction,
+                packet_type = %packet.packet_type(),
+                pkid = packet.pkid(),
+                qos = packet.qos().map(|qos| qos as u8),
+                size = $size,
+                "packet"
+            );
+        }
+    };
+}
+
+/// Runs `future` within `span`, `span` only has to exist when the `tracing` feature is enabled
+macro_rules! instrument {
+    ($span:expr, $future:expr) => {{
+        #[cfg(feature = "tracing")]
+        let span = $span.clone();
+        let future = $future;
+        #[cfg(feature = "tracing")]
+        let future = ::tracing::Instrument::instrument(future, span);
+        future
+    }};
+}
@@ -0,0 +1,621 @@
+use alloc::{
+    format,
+    string::{String, ToString},
+};
+
+use bytes::{Bytes, BytesMut};
+
+use crate::{
+    constants::PROTOCOL_NAME,
+    v5::{
+        commons::{
+            error::MQTTError, fixed_header::FixedHeader, packet::Packet, packet_type::PacketType,
+            qos::QoS, version::Version,
+        },
+        packet::{
+            connack::{reason_code::ConnAckReasonCode, ConnAck},
+            connect::{will::Will, Connect, ConnectFlags},
+            disconnect::Disconnect,
+            ping::{PingReq, PingResp},
+            puback::PubAck,
+            pubcomp::PubComp,
+            publish::Publish,
+            pubrec::PubRec,
+            pubrel::PubRel,
+            suback::{SubAck, SubAckReasonCode},
+            subscribe::{Subscribe, SubscriptionOptions},
+            unsuback::UnSubAck,
+            unsubscribe::UnSubscribe,
+        },
+        traits::{
+            bufferio::BufferIO,
+            syncx::{read::Read, write::Write},
+        },
+    },
+};
+
+/// 3.2.2.3 Connect Return code
+fn connack_return_code(reason: ConnAckReasonCode) -> Result<u8, MQTTError> {
+    match reason {
+        ConnAckReasonCode::Success => Ok(0),
+        ConnAckReasonCode::UnsupportedProtocolVersion => Ok(1),
+        ConnAckReasonCode::ClientIdentifierNotValid => Ok(2),
+        ConnAckReasonCode::ServerUnavailable => Ok(3),
+        ConnAckReasonCode::BadUserNameOrPassword => Ok(4),
+        ConnAckReasonCode::NotAuthorized => Ok(5),
+        reason => Err(MQTTError::UnknownData(format!(
+            "{reason:?} cannot be sent to an MQTT 3.1.1 client"
+        ))),
+    }
+}
+
+fn connack_reason_code(code: u8) -> Result<ConnAckReasonCode, MQTTError> {
+    match code {
+        0 => Ok(ConnAckReasonCode::Success),
+        1 => Ok(ConnAckReasonCode::UnsupportedProtocolVersion),
+        2 => Ok(ConnAckReasonCode::ClientIdentifierNotValid),
+        3 => Ok(ConnAckReasonCode::ServerUnavailable),
+        4 => Ok(ConnAckReasonCode::BadUserNameOrPassword),
+        5 => Ok(ConnAckReasonCode::NotAuthorized),
+        code => Err(MQTTError::UnknownData(format!(
+            "Unrecognized connect return code: {code}"
+        ))),
+    }
+}
+
+/// 3.9.3 SUBACK Payload: only the granted QoS, or 0x80 (Failure)
+fn suback_return_code(reason: SubAckReasonCode) -> u8 {
+    match reason {
+        SubAckReasonCode::GrantedQoS0 => 0x00,
+        SubAckReasonCode::GrantedQoS1 => 0x01,
+        SubAckReasonCode::GrantedQoS2 => 0x02,
+        _ => 0x80,
+    }
+}
+
+fn suback_reason_code(code: u8) -> Result<SubAckReasonCode, MQTTError> {
+    match code {
+        0x00 => Ok(SubAckReasonCode::GrantedQoS0),
+        0x01 => Ok(SubAckReasonCode::GrantedQoS1),
+        0x02 => Ok(SubAckReasonCode::GrantedQoS2),
+        0x80 => Ok(SubAckReasonCode::UnspecifiedError),
+        code => Err(MQTTError::UnknownData(format!(
+            "Unrecognized subscribe return code: {code}"
+        ))),
+    }
+}
+
+/// Writes `packet` (fixed header included) into `buf` using the MQTT 3.1.1 wire format
+pub(crate) fn encode(packet: &Packet, buf: &mut BytesMut) -> Result<(), MQTTError> {
+    let mut body = BytesMut::new();
+
+    let flags = match packet {
+        Packet::Connect(packet) => {
+            encode_connect(packet, &mut body)?;
+            0
+        }
+        Packet::ConnAck(packet) => {
+            u8::from(packet.session_present).write(&mut body)?;
+            connack_return_code(packet.reason)?.write(&mut body)?;
+            0
+        }
+        Packet::Publish(packet) => {
+            packet.topic.write(&mut body)?;
+            if packet.qos != QoS::Zero {
+                packet.pkid.ok_or(MQTTError::PacketIdRequired)?.write(&mut body)?;
+            }
+            // the payload is not length-prefixed, it takes up the rest of the packet (3.3.3)
+            body.extend_from_slice(&packet.payload);
+            (packet.dup as u8) << 3 | (packet.qos as u8) << 1 | (packet.retain as u8)
+        }
+        Packet::PubAck(packet) => {
+            packet.pkid.write(&mut body)?;
+            0
+        }
+        Packet::PubRec(packet) => {
+            packet.pkid.write(&mut body)?;
+            0
+        }
+        Packet::PubRel(packet) => {
+            packet.pkid.write(&mut body)?;
+            0b10
+        }
+        Packet::PubComp(packet) => {
+            packet.pkid.write(&mut body)?;
+            0
+        }
+        Packet::Subscribe(packet) => {
+            if packet.payload.is_empty() {
+                return Err(MQTTError::ProtocolError(
+                    "Must contain at least one topic/subscription option pair",
+                ));
+            }
+
+            packet.pkid.write(&mut body)?;
+            for (topic, options) in &packet.payload {
+                topic.write(&mut body)?;
+                u8::from(options.qos).write(&mut body)?; // 3.8.3.1 only the QoS, the other bits are reserved
+            }
+            0b10
+        }
+        Packet::SubAck(packet) => {
+            packet.pkid.write(&mut body)?;
+            packet
+                .payload
+                .iter()
+                .try_for_each(|code| suback_return_code(*code).write(&mut body))?;
+            0
+        }
+        Packet::UnSubscribe(packet) => {
+            packet.pkid.write(&mut body)?;
+            packet.payload.iter().try_for_each(|topic| topic.write(&mut body))?;
+            0b10
+        }
+        Packet::UnSubAck(packet) => {
+            packet.pkid.write(&mut body)?;
+            0
+        }
+        Packet::PingReq(_) | Packet::PingResp(_) | Packet::Disconnect(_) => 0,
+        Packet::Auth(_) => {
+            return Err(MQTTError::ProtocolError(
+                "AUTH packets do not exist in MQTT 3.1.1",
+            ))
+        }
+    };
+
+    FixedHeader::new(packet.packet_type(), flags, body.len()).write(buf)?;
+    buf.extend_from_slice(&body);
+
+    Ok(())
+}
+
+fn encode_connect(packet: &Connect, buf: &mut BytesMut) -> Result<(), MQTTError> {
+    // If the User Name Flag is set to 0, the Password Flag MUST be set to 0 [MQTT-3.1.2-22]
+    if packet.password.is_some() && packet.username.is_none() {
+        return Err(MQTTError::ProtocolError(
+            "MQTT 3.1.1 does not allow a password without a username",
+        ));
+    }
+
+    let mut flags = ConnectFlags {
+        clean_start: packet.clean_start,
+        password: packet.password.is_some(),
+        username: packet.username.is_some(),
+        ..Default::default()
+    };
+
+    if let Some(will) = &packet.will {
+        flags.will_retain = will.retain;
+        flags.will_flag = true;
+        flags.will_qos = will.qos;
+    }
+
+    PROTOCOL_NAME.to_string().write(buf)?; // 3.1.2.1
+    (Version::V4 as u8).write(buf)?; // 3.1.2.2
+    u8::from(flags).write(buf)?; // 3.1.2.3
+    packet.keep_alive.write(buf)?; // 3.1.2.10
+
+    packet.client_id.write(buf)?; // 3.1.3.1
+    if let Some(will) = &packet.will {
+        will.topic.write(buf)?; // 3.1.3.2
+        will.payload.write(buf)?; // 3.1.3.3
+    }
+    if let Some(username) = &packet.username {
+        username.write(buf)?; // 3.1.3.4
+    }
+    if let Some(password) = &packet.password {
+        password.write(buf)?; // 3.1.3.5
+    }
+
+    Ok(())
+}
+
+/// Reads the packet described by `header` out of `buf`, which must contain the whole variable header and payload
+pub(crate) fn decode(header: FixedHeader, buf: &mut Bytes) -> Result<Packet, MQTTError> {
+    if buf.len() < header.remaining_length {
+        return Err(MQTTError::IncompleteData(
+            "MQTT 3.1.1 packet",
+            header.remaining_length,
+            buf.len(),
+        ));
+    }
+
+    let mut buf = buf.split_to(header.remaining_length);
+    let buf = &mut buf;
+
+    let packet = match header.packet_type {
+        PacketType::Connect => Packet::Connect(decode_connect(buf)?),
+        PacketType::ConnAck => {
+            let session_present = (u8::read(buf)? & 0b1) != 0;
+            let reason = connack_reason_code(u8::read(buf)?)?;
+            Packet::ConnAck(ConnAck {
+                session_present,
+                reason,
+                ..Default::default()
+            })
+        }
+        PacketType::Publish => {
+            let flags = header.flags.unwrap_or(0);
+            let qos = (flags & 0b0110) >> 1;
+            let qos = QoS::try_from(qos).map_err(|_| MQTTError::UnsupportedQoS(qos))?;
+
+            let topic = String::read(buf)?;
+            let pkid = match qos {
+                QoS::Zero => None,
+                _ => Some(u16::read(buf).map_err(|_| MQTTError::PacketIdRequired)?),
+            };
+
+            Packet::Publish(Publish {
+                dup: (flags & 0b1000) != 0,
+                retain: (flags & 0b1) != 0,
+                qos,
+                topic,
+                pkid,
+                payload: buf.split_to(buf.len()),
+                ..Default::default()
+            })
+        }
+        PacketType::PubAck => Packet::PubAck(PubAck {
+            pkid: u16::read(buf)?,
+            ..Default::default()
+        }),
+        PacketType::PubRec => Packet::PubRec(PubRec {
+            pkid: u16::read(buf)?,
+            ..Default::default()
+        }),
+        PacketType::PubRel => Packet::PubRel(PubRel {
+            pkid: u16::read(buf)?,
+            ..Default::default()
+        }),
+        PacketType::PubComp => Packet::PubComp(PubComp {
+            pkid: u16::read(buf)?,
+            ..Default::default()
+        }),
+        PacketType::Subscribe => {
+            let mut packet = Subscribe {
+                pkid: u16::read(buf)?,
+                ..Default::default()
+            };
+
+            while !buf.is_empty() {
+                let topic = String::read(buf)?;
+                let options = u8::read(buf)?;
+                // The upper 6 bits of the Requested QoS byte are reserved [MQTT-3-8.3-4]
+                if options & 0b1111_1100 != 0 {
+                    return Err(MQTTError::MalformedPacket);
+                }
+                let qos = QoS::try_from(options).map_err(|_| MQTTError::UnsupportedQoS(options))?;
+                packet.payload.push((
+                    topic,
+                    SubscriptionOptions {
+                        qos,
+                        ..Default::default()
+                    },
+                ));
+            }
+
+            if packet.payload.is_empty() {
+                return Err(MQTTError::ProtocolError(
+                    "Must contain at least one topic/subscription option pair",
+                ));
+            }
+            Packet::Subscribe(packet)
+        }
+        PacketType::SubAck => {
+            let mut packet = SubAck {
+                pkid: u16::read(buf)?,
+                ..Default::default()
+            };
+            while !buf.is_empty() {
+                packet.payload.push(suback_reason_code(u8::read(buf)?)?);
+            }
+            Packet::SubAck(packet)
+        }
+        PacketType::UnSubscribe => {
+            let mut packet = UnSubscribe {
+                pkid: u16::read(buf)?,
+                ..Default::default()
+            };
+            while !buf.is_empty() {
+                packet.payload.push(String::read(buf)?);
+            }
+            Packet::UnSubscribe(packet)
+        }
+        PacketType::UnSubAck => Packet::UnSubAck(UnSubAck {
+            pkid: u16::read(buf)?,
+            ..Default::default()
+        }),
+        PacketType::PingReq => Packet::PingReq(PingReq::default()),
+        PacketType::PingResp => Packet::PingResp(PingResp),
+        PacketType::Disconnect => Packet::Disconnect(Disconnect::default()),
+        packet_type => {
+            return Err(MQTTError::UnknownData(format!(
+                "Unexpected Packet type {packet_type:?}"
+            )))
+        }
+    };
+
+    // the packet must take up its whole Remaining Length
+    if !buf.is_empty() {
+        return Err(MQTTError::MalformedPacket);
+    }
+    Ok(packet)
+}
+
+fn decode_connect(buf: &mut Bytes) -> Result<Connect, MQTTError> {
+    if String::read(buf)? != PROTOCOL_NAME {
+        return Err(MQTTError::MalformedPacket);
+    }
+
+    let level = u8::read(buf)?;
+    if level != Version::V4 as u8 {
+        return Err(MQTTError::VersionNotSupported(level));
+    }
+
+    let byte = u8::read(buf)?;
+    // The Server MUST validate that the reserved flag in the CONNECT Control Packet is set to zero [MQTT-3.1.2-3]
+    if byte & 0b1 != 0 {
+        return Err(MQTTError::MalformedPacket);
+    }
+    let flags = ConnectFlags::try_from(byte)?;
+
+    let mut packet = Connect {
+        version: Version::V4,
+        clean_start: flags.clean_start,
+        keep_alive: u16::read(buf)?,
+        client_id: String::read(buf)?,
+        ..Default::default()
+    };
+
+    if flags.will_flag {
+        packet.will = Some(Will {
+            topic: String::read(buf)?,
+            payload: Bytes::read(buf)?,
+            qos: flags.will_qos,
+            retain: flags.will_retain,
+            ..Default::default()
+        });
+    }
+
+    if flags.username {
+        packet.username = Some(String::read(buf)?);
+    }
+    if flags.password {
+        packet.password = Some(String::read(buf)?);
+    }
+
+    Ok(packet)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::v5::packet::unsuback::UnSubAckReasonCode;
+
+    fn round_trip(packet: Packet) -> Packet {
+        let mut buf = BytesMut::new();
+        encode(&packet, &mut buf).unwrap();
+
+        let mut buf = buf.freeze();
+        let header = <FixedHeader as BufferIO>::read(&mut buf).unwrap();
+        let decoded = decode(header, &mut buf).unwrap();
+        assert!(buf.is_empty());
+        decoded
+    }
+
+    #[test]
+    fn writes_a_level_4_connect_without_properties() {
+        let connect = Connect {
+            client_id: String::from("legacy"),
+            username: Some(String::from("user")),
+            password: Some(String::from("pass")),
+            keep_alive: 60,
+            will: Some(Will {
+                topic: String::from("lwt"),
+                payload: Bytes::from_static(b"gone"),
+                qos: QoS::One,
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+
+        let mut buf = BytesMut::new();
+        encode(&Packet::Connect(connect), &mut buf).unwrap();
+
+        let expected =
+            b"\x10\x29\0\x04MQTT\x04\xce\0\x3c\0\x06legacy\0\x03lwt\0\x04gone\0\x04user\0\x04pass";
+        assert_eq!(buf.to_vec(), expected.to_vec());
+    }
+
+    #[test]
+    fn round_trips_connect() {
+        let connect = Connect {
+            client_id: String::from("legacy"),
+            version: Version::V4,
+            clean_start: false,
+            keep_alive: 30,
+            username: Some(String::from("user")),
+            will: Some(Will {
+                topic: String::from("lwt"),
+                payload: Bytes::from_static(b"gone"),
+                qos: QoS::Two,
+                retain: true,
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+
+        assert_eq!(
+            round_trip(Packet::Connect(connect)),
+            Packet::Connect(Connect {
+                client_id: String::from("legacy"),
+                version: Version::V4,
+                clean_start: false,
+                keep_alive: 30,
+                username: Some(String::from("user")),
+                will: Some(Will {
+                    topic: String::from("lwt"),
+                    payload: Bytes::from_static(b"gone"),
+                    qos: QoS::Two,
+                    retain: true,
+                    ..Default::default()
+                }),
+                ..Default::default()
+            })
+        );
+    }
+
+    #[test]
+    fn rejects_a_password_without_a_username() {
+        let connect = Connect {
+            password: Some(String::from("pass")),
+            ..Default::default()
+        };
+
+        let mut buf = BytesMut::new();
+        assert!(matches!(
+            encode(&Packet::Connect(connect), &mut buf),
+            Err(MQTTError::ProtocolError(_))
+        ));
+    }
+
+    #[test]
+    fn maps_connack_return_codes() {
+        let mut buf = Bytes::from_static(b"\x01\x01");
+        let header = FixedHeader::new(PacketType::ConnAck, 0, 2);
+        let Packet::ConnAck(connack) = decode(header, &mut buf).unwrap() else {
+            panic!("expected a CONNACK");
+        };
+        assert!(connack.session_present);
+        assert_eq!(connack.reason, ConnAckReasonCode::UnsupportedProtocolVersion);
+
+        let connack = ConnAck {
+            reason: ConnAckReasonCode::BadUserNameOrPassword,
+            ..Default::default()
+        };
+        let mut buf = BytesMut::new();
+        encode(&Packet::ConnAck(connack), &mut buf).unwrap();
+        assert_eq!(buf.to_vec(), b"\x20\x02\0\x04".to_vec());
+
+        let connack = ConnAck {
+            reason: ConnAckReasonCode::QuotaExceeded,
+            ..Default::default()
+        };
+        assert!(encode(&Packet::ConnAck(connack), &mut BytesMut::new()).is_err());
+    }
+
+    #[test]
+    fn writes_the_publish_payload_without_a_length_prefix() {
+        let publish = Publish {
+            qos: QoS::One,
+            retain: true,
+            topic: String::from("a/b"),
+            pkid: Some(10),
+            payload: Bytes::from_static(b"hello"),
+            ..Default::default()
+        };
+
+        let mut buf = BytesMut::new();
+        encode(&Packet::Publish(publish.clone()), &mut buf).unwrap();
+        assert_eq!(buf.to_vec(), b"\x33\x0c\0\x03a/b\0\x0ahello".to_vec());
+
+        assert_eq!(round_trip(Packet::Publish(publish.clone())), Packet::Publish(publish));
+    }
+
+    #[test]
+    fn round_trips_acknowledgements() {
+        assert_eq!(
+            round_trip(Packet::PubAck(PubAck {
+                pkid: 7,
+                ..Default::default()
+            })),
+            Packet::PubAck(PubAck {
+                pkid: 7,
+                ..Default::default()
+            })
+        );
+
+        let mut buf = BytesMut::new();
+        let pubrel = PubRel {
+            pkid: 7,
+            ..Default::default()
+        };
+        encode(&Packet::PubRel(pubrel), &mut buf).unwrap();
+        assert_eq!(buf.to_vec(), b"\x62\x02\0\x07".to_vec());
+
+        let suback = SubAck {
+            pkid: 3,
+            payload: vec![
+                SubAckReasonCode::GrantedQoS1,
+                SubAckReasonCode::NotAuthorized,
+            ],
+            ..Default::default()
+        };
+        assert_eq!(
+            round_trip(Packet::SubAck(suback)),
+            Packet::SubAck(SubAck {
+                pkid: 3,
+                payload: vec![
+                    SubAckReasonCode::GrantedQoS1,
+                    SubAckReasonCode::UnspecifiedError
+                ],
+                ..Default::default()
+            })
+        );
+
+        let unsuback = UnSubAck {
+            pkid: 4,
+            payload: vec![UnSubAckReasonCode::Success],
+            ..Default::default()
+        };
+        let mut buf = BytesMut::new();
+        encode(&Packet::UnSubAck(unsuback), &mut buf).unwrap();
+        assert_eq!(buf.to_vec(), b"\xb0\x02\0\x04".to_vec());
+    }
+
+    #[test]
+    fn round_trips_subscriptions() {
+        let subscribe = Subscribe {
+            pkid: 12,
+            payload: vec![
+                (String::from("a/+"), SubscriptionOptions::default()),
+                (
+                    String::from("b/#"),
+                    SubscriptionOptions {
+                        qos: QoS::Two,
+                        no_local: true,
+                        ..Default::default()
+                    },
+                ),
+            ],
+            ..Default::default()
+        };
+
+        let mut buf = BytesMut::new();
+        encode(&Packet::Subscribe(subscribe), &mut buf).unwrap();
+        assert_eq!(
+            buf.to_vec(),
+            b"\x82\x0e\0\x0c\0\x03a/+\0\0\x03b/#\x02".to_vec()
+        );
+
+        let unsubscribe = UnSubscribe {
+            pkid: 13,
+            payload: vec![String::from("a/+")],
+            ..Default::default()
+        };
+        assert_eq!(
+            round_trip(Packet::UnSubscribe(unsubscribe.clone())),
+            Packet::UnSubscribe(unsubscribe)
+        );
+
+        let mut buf = Bytes::from_static(b"\0\x01\0\x01a\x04");
+        let header = FixedHeader::new(PacketType::Subscribe, 0b10, 6);
+        assert_eq!(decode(header, &mut buf), Err(MQTTError::MalformedPacket));
+    }
+
+    #[test]
+    fn has_no_auth_packet() {
+        let header = FixedHeader::new(PacketType::Auth, 0, 0);
+        assert!(decode(header, &mut Bytes::new()).is_err());
+    }
+}
@@ -0,0 +1,7 @@
+//! MQTT 3.1.1 (protocol level 4) support.
+//!
+//! There is no separate packet model for 3.1.1: packets are decoded into (and encoded from) the same [`Packet`](crate::v5::commons::packet::Packet)
+//! used for MQTT 5.0, so that the client, state and handlers work the same way regardless of the negotiated version.
+//! Everything that does not exist in 3.1.1 (properties, most reason codes, subscription options other than the QoS, AUTH) is dropped on the way out,
+//! and left at its default value on the way in.
+pub(crate) mod codec;
@@ -0,0 +1,611 @@
+//! Wire-level capture and replay of MQTT sessions.
+//!
+//! [`Capture`] wraps the stream given to the [`Network`](super::network::asyncx::Network), and records every packet that goes
+//! through it (as the exact bytes exchanged) with its direction and a timestamp:
+//! ```ignore
+//! let file = File::create("session.hmqc")?;
+//! let stream = Capture::new(TcpStream::connect("broker:1883").await?, file)?;
+//! let (mut network, client) = Network::new(options, stream).await?;
+//! ```
+//! The capture file can later be read with [`read_capture`], exported to JSON lines with [`export_json_lines`],
+//! or [`replay`]ed against a client under test to reproduce a problem offline.
+//!
+//! The capture file holds the session exactly as it went on the wire, credentials included: it should be kept as
+//! private as they are. The JSON lines export leaves them out (see [`Redact`]).
+//!
+//! The file starts with the 4 bytes `HMQC` and a format version (1), followed by one entry per packet:
+//! the direction (1 byte), the timestamp in microseconds since the UNIX epoch (8 bytes), the frame length (4 bytes)
+//! and the frame itself. Integers are big-endian
+use std::{
+    fmt::Write as _,
+    io::{self, Read, Write},
+    pin::Pin,
+    task::{Context, Poll},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use bytes::{Buf, Bytes, BytesMut};
+use futures::{AsyncRead, AsyncWrite, AsyncWriteExt};
+
+use crate::{
+    v311,
+    v5::{
+        commons::{
+            error::MQTTError,
+            packet::{read_frame, Packet},
+            packet_type::PacketType,
+            version::Version,
+        },
+        traits::bufferio::BufferIO,
+    },
+};
+
+const MAGIC: &[u8; 4] = b"HMQC";
+const FORMAT_VERSION: u8 = 1;
+
+/// Which way a packet went, from the client's point of view
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    /// Sent by the server
+    Incoming = 0,
+    /// Sent by the client
+    Outgoing = 1,
+}
+
+/// A packet captured on the wire
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Record {
+    pub direction: Direction,
+    /// Time since the UNIX epoch, in microseconds precision
+    pub timestamp: Duration,
+    /// The whole packet, fixed header included
+    pub frame: Bytes,
+}
+
+impl Record {
+    /// Decodes the captured frame
+    pub fn packet(&self, version: Version) -> Result<Packet, MQTTError> {
+        let mut frame = self.frame.clone();
+        match version {
+            Version::V5 => <Packet as BufferIO>::read(&mut frame),
+            Version::V4 => {
+                let header = BufferIO::read(&mut frame)?;
+                v311::codec::decode(header, &mut frame)
+            }
+        }
+    }
+
+    fn write<W: Write>(&self, sink: &mut W) -> io::Result<()> {
+        let mut entry = Vec::with_capacity(13 + self.frame.len());
+        entry.push(self.direction as u8);
+        entry.extend_from_slice(&(self.timestamp.as_micros() as u64).to_be_bytes());
+        entry.extend_from_slice(&(self.frame.len() as u32).to_be_bytes());
+        entry.extend_from_slice(&self.frame);
+        sink.write_all(&entry)
+    }
+}
+
+/// A stream that records every packet read from or written to the inner stream into `sink`
+#[derive(Debug)]
+pub struct Capture<S, W> {
+    inner: S,
+    sink: W,
+    /// Bytes of incomplete frames, in each direction
+    incoming: BytesMut,
+    outgoing: BytesMut,
+}
+
+impl<S, W> Capture<S, W>
+where
+    W: Write,
+{
+    /// Writes the capture file header to `sink`, and starts capturing
+    pub fn new(inner: S, mut sink: W) -> io::Result<Self> {
+        sink.write_all(MAGIC)?;
+        sink.write_all(&[FORMAT_VERSION])?;
+
+        Ok(Self {
+            inner,
+            sink,
+            incoming: BytesMut::new(),
+            outgoing: BytesMut::new(),
+        })
+    }
+
+    /// Returns the inner stream and the sink
+    pub fn into_inner(self) -> (S, W) {
+        (self.inner, self.sink)
+    }
+
+    /// Adds `bytes` to the direction's pending bytes, and records every frame completed by them
+    fn record(&mut self, direction: Direction, bytes: &[u8]) -> io::Result<()> {
+        let pending = match direction {
+            Direction::Incoming => &mut self.incoming,
+            Direction::Outgoing => &mut self.outgoing,
+        };
+        pending.extend_from_slice(bytes);
+
+        while let Some(len) = frame_len(pending) {
+            let record = Record {
+                direction,
+                timestamp: now(),
+                frame: pending.split_to(len).freeze(),
+            };
+            record.write(&mut self.sink)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl<S, W> AsyncRead for Capture<S, W>
+where
+    S: AsyncRead + Unpin,
+    W: Write + Unpin,
+{
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+        let read = match Pin::new(&mut this.inner).poll_read(cx, buf) {
+            Poll::Ready(Ok(read)) => read,
+            other => return other,
+        };
+
+        this.record(Direction::Incoming, &buf[..read])?;
+        Poll::Ready(Ok(read))
+    }
+}
+
+impl<S, W> AsyncWrite for Capture<S, W>
+where
+    S: AsyncWrite + Unpin,
+    W: Write + Unpin,
+{
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+        let written = match Pin::new(&mut this.inner).poll_write(cx, buf) {
+            Poll::Ready(Ok(written)) => written,
+            other => return other,
+        };
+
+        this.record(Direction::Outgoing, &buf[..written])?;
+        Poll::Ready(Ok(written))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        this.sink.flush()?;
+        Pin::new(&mut this.inner).poll_flush(cx)
+    }
+
+    fn poll_close(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        this.sink.flush()?;
+        Pin::new(&mut this.inner).poll_close(cx)
+    }
+}
+
+/// Length of the first frame in `buf`, if all of it is there.
+/// A Remaining Length longer than 4 bytes can never be completed: all of `buf` is then taken as one frame
+/// (that does not decode), rather than holding the bytes back forever
+fn frame_len(buf: &[u8]) -> Option<usize> {
+    let mut remaining_length = 0usize;
+
+    // 1.5.5 Variable Byte Integer, at most 4 bytes after the packet type
+    for (i, byte) in buf.iter().skip(1).take(4).enumerate() {
+        remaining_length += ((byte & 0x7F) as usize) << (7 * i);
+        if byte & 0x80 == 0 {
+            let len = 1 + (i + 1) + remaining_length;
+            return (buf.len() >= len).then_some(len);
+        }
+    }
+
+    (buf.len() > 4).then_some(buf.len())
+}
+
+fn now() -> Duration {
+    let since_epoch = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default();
+    Duration::from_micros(since_epoch.as_micros() as u64)
+}
+
+/// Reads back the records of a capture file
+pub fn read_capture<R: Read>(mut reader: R) -> io::Result<Vec<Record>> {
+    let mut data = Vec::new();
+    reader.read_to_end(&mut data)?;
+    let mut data = Bytes::from(data);
+
+    let invalid = |message: &str| io::Error::new(io::ErrorKind::InvalidData, message.to_string());
+
+    if data.len() < 5 || &data[..4] != MAGIC {
+        return Err(invalid("not a capture file"));
+    }
+    if data[4] != FORMAT_VERSION {
+        return Err(invalid("unsupported capture format version"));
+    }
+    data.advance(5);
+
+    let mut records = Vec::new();
+    while data.has_remaining() {
+        if data.remaining() < 13 {
+            return Err(invalid("truncated capture entry"));
+        }
+        let direction = match data.get_u8() {
+            0 => Direction::Incoming,
+            1 => Direction::Outgoing,
+            _ => return Err(invalid("unknown packet direction")),
+        };
+        let timestamp = Duration::from_micros(data.get_u64());
+        let len = data.get_u32() as usize;
+        if data.remaining() < len {
+            return Err(invalid("truncated capture entry"));
+        }
+
+        records.push(Record {
+            direction,
+            timestamp,
+            frame: data.split_to(len),
+        });
+    }
+
+    Ok(records)
+}
+
+/// What [`export_json_lines`] leaves out, on top of the credentials: the User Name and Password of CONNECT packets,
+/// and the Authentication Data of CONNECT, CONNACK and AUTH packets are never exported
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Redact {
+    /// Leaves out the payloads of PUBLISH packets and Will Messages
+    pub payloads: bool,
+}
+
+const REDACTED: &str = "<redacted>";
+
+fn hide(value: &mut Bytes) {
+    *value = Bytes::from_static(REDACTED.as_bytes());
+}
+
+impl Redact {
+    /// Replaces what is left out of `packet`, returns whether anything was
+    fn packet(&self, packet: &mut Packet) -> bool {
+        match packet {
+            Packet::Connect(connect) => {
+                let username = connect
+                    .username
+                    .as_mut()
+                    .map(|username| *username = String::from(REDACTED));
+                let password = connect
+                    .password
+                    .as_mut()
+                    .map(|password| *password = String::from(REDACTED));
+                let data = connect.properties.authentication_data.as_mut().map(hide);
+                let payload = connect
+                    .will
+                    .as_mut()
+                    .filter(|_| self.payloads)
+                    .map(|will| hide(&mut will.payload));
+                username.or(password).or(data).or(payload).is_some()
+            }
+            Packet::ConnAck(connack) => connack.properties.authentication_data.as_mut().map(hide).is_some(),
+            Packet::Auth(auth) => auth.properties.auth_data.as_mut().map(hide).is_some(),
+            Packet::Publish(publish) if self.payloads => {
+                hide(&mut publish.payload);
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Whether a frame that could not be decoded may hold something that is left out
+    fn frame(&self, frame: &[u8]) -> bool {
+        let packet_type = frame.first().and_then(|byte| PacketType::try_from(byte & 0xF0).ok());
+        match packet_type {
+            Some(PacketType::Connect | PacketType::ConnAck | PacketType::Auth) => true,
+            Some(PacketType::Publish) => self.payloads,
+            _ => false,
+        }
+    }
+}
+
+/// Writes one JSON object per record, e.g.
+/// `{"timestamp_us":1700000000000000,"direction":"incoming","type":"PingResp","frame":"d000","packet":"PingResp(PingResp)"}`.
+/// `packet` is the decoded packet (in its debug representation), or the decoding error.
+/// What `redact` leaves out is replaced by `<redacted>` in `packet`, and `frame` is `null` when the packet held any of it
+pub fn export_json_lines<W: Write>(
+    records: &[Record],
+    version: Version,
+    redact: Redact,
+    mut writer: W,
+) -> io::Result<()> {
+    for record in records {
+        let direction = match record.direction {
+            Direction::Incoming => "incoming",
+            Direction::Outgoing => "outgoing",
+        };
+        let (packet_type, packet, hidden) = match record.packet(version) {
+            Ok(mut packet) => {
+                let hidden = redact.packet(&mut packet);
+                (format!("{:?}", packet.packet_type()), format!("{packet:?}"), hidden)
+            }
+            Err(e) => (String::from("Unknown"), format!("{e:?}"), redact.frame(&record.frame)),
+        };
+        let frame = match hidden {
+            true => String::from("null"),
+            false => record.frame.iter().fold(String::from("\""), |mut hex, byte| {
+                let _ = write!(hex, "{byte:02x}");
+                hex
+            }) + "\"",
+        };
+
+        writeln!(
+            writer,
+            "{{\"timestamp_us\":{},\"direction\":\"{direction}\",\"type\":\"{packet_type}\",\"frame\":{frame},\"packet\":\"{}\"}}",
+            record.timestamp.as_micros(),
+            escape(&packet)
+        )?;
+    }
+
+    Ok(())
+}
+
+fn escape(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            '\t' => escaped.push_str("\\t"),
+            c if c.is_control() => {
+                let _ = write!(escaped, "\\u{:04x}", c as u32);
+            }
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Plays the server's side of a captured session on `stream`, which is connected to the client under test.
+/// Incoming packets are sent as they were captured, and every outgoing packet is awaited from the client before going on
+/// (its content is not compared, but its packet type must match). Timestamps are ignored: the session is replayed as fast as the client allows
+pub async fn replay<S>(records: &[Record], stream: &mut S) -> Result<(), MQTTError>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    for record in records {
+        match record.direction {
+            Direction::Incoming => {
+                stream.write_all(&record.frame).await?;
+                stream.flush().await?;
+            }
+            Direction::Outgoing => {
+                let (header, _) = read_frame(stream, usize::MAX).await?;
+                let expected = record.frame[0] & 0xF0;
+                if u8::from(header.packet_type) != expected {
+                    return Err(MQTTError::UnknownData(format!(
+                        "Expected packet type {expected:#04x} from the client, but received {:?}",
+                        header.packet_type
+                    )));
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+
+    use futures::{executor::block_on, AsyncReadExt};
+
+    use super::*;
+    use crate::v5::{
+        client::{
+            connector::duplex, handler::AsyncHandler, network::asyncx::Network, ConnectOptions,
+        },
+        packet::{
+            connack::ConnAck,
+            connect::Connect,
+            ping::{PingReq, PingResp},
+            publish::Publish,
+            pubcomp::{PubComp, PubCompReasonCode},
+            pubrel::PubRel,
+        },
+    };
+
+    fn frame(packet: Packet) -> Bytes {
+        let mut buf = BytesMut::new();
+        BufferIO::write(&packet, &mut buf).unwrap();
+        buf.freeze()
+    }
+
+    #[test]
+    fn records_whole_frames_in_both_directions() {
+        block_on(async {
+            let (client, mut server) = duplex(1024);
+            let mut capture = Capture::new(client, Vec::new()).unwrap();
+
+            // a frame written in two parts is recorded once, when complete
+            let ping = frame(Packet::PingReq(PingReq {}));
+            capture.write_all(&ping[..1]).await.unwrap();
+            capture.write_all(&ping[1..]).await.unwrap();
+            server
+                .write_all(&frame(Packet::PingResp(PingResp)))
+                .await
+                .unwrap();
+            let mut pong = [0u8; 2];
+            capture.read_exact(&mut pong).await.unwrap();
+
+            let (_, file) = capture.into_inner();
+            let records = read_capture(&file[..]).unwrap();
+            assert_eq!(records.len(), 2);
+            assert_eq!(records[0].direction, Direction::Outgoing);
+            assert_eq!(
+                records[0].packet(Version::V5),
+                Ok(Packet::PingReq(PingReq {}))
+            );
+            assert_eq!(records[1].direction, Direction::Incoming);
+            assert_eq!(records[1].frame, Bytes::from_static(b"\xd0\x00"));
+            assert!(records[0].timestamp <= records[1].timestamp);
+        });
+    }
+
+    #[test]
+    fn exports_one_json_object_per_record() {
+        let records = [Record {
+            direction: Direction::Incoming,
+            timestamp: Duration::from_micros(1_700_000_000_000_000),
+            frame: frame(Packet::PingResp(PingResp)),
+        }];
+
+        let mut json = Vec::new();
+        export_json_lines(&records, Version::V5, Redact::default(), &mut json).unwrap();
+        assert_eq!(
+            String::from_utf8(json).unwrap(),
+            "{\"timestamp_us\":1700000000000000,\"direction\":\"incoming\",\"type\":\"PingResp\",\"frame\":\"d000\",\"packet\":\"PingResp(PingResp)\"}\n"
+        );
+    }
+
+    #[test]
+    fn leaves_credentials_out_of_the_export() {
+        let connect = Connect {
+            username: Some(String::from("alice")),
+            password: Some(String::from("hunter2")),
+            ..Default::default()
+        };
+        let publish = Publish {
+            topic: String::from("sensors/temperature"),
+            payload: Bytes::from_static(b"21.5"),
+            ..Default::default()
+        };
+        let records = [
+            frame(Packet::Connect(connect)),
+            frame(Packet::Publish(publish)),
+            // a CONNECT cut short, that does not decode
+            Bytes::from_static(b"\x10\x04\x00\x04MQ"),
+        ]
+        .map(|frame| Record {
+            direction: Direction::Outgoing,
+            timestamp: Duration::ZERO,
+            frame,
+        });
+        let export = |redact| {
+            let mut json = Vec::new();
+            export_json_lines(&records, Version::V5, redact, &mut json).unwrap();
+            String::from_utf8(json).unwrap()
+        };
+
+        let json = export(Redact::default());
+        let lines = json.lines().collect::<Vec<_>>();
+        assert!(!json.contains("hunter2") && !json.contains("alice"));
+        assert!(lines[0].contains("\"frame\":null") && lines[0].contains("<redacted>"));
+        assert!(lines[1].contains("21.5") && !lines[1].contains("\"frame\":null"));
+        assert!(lines[2].contains("\"frame\":null"));
+
+        let json = export(Redact { payloads: true });
+        assert!(!json.contains("21.5"));
+        assert!(json.lines().all(|line| line.contains("\"frame\":null")));
+    }
+
+    #[test]
+    fn records_a_frame_with_a_malformed_remaining_length_as_is() {
+        block_on(async {
+            let (client, _server) = duplex(1024);
+            let mut capture = Capture::new(client, Vec::new()).unwrap();
+
+            // the 4th Remaining Length byte still has its continuation bit set
+            capture.write_all(b"\x30\xff\xff\xff\xff\x01").await.unwrap();
+            capture.write_all(&frame(Packet::PingReq(PingReq {}))).await.unwrap();
+
+            let (_, file) = capture.into_inner();
+            let records = read_capture(&file[..]).unwrap();
+            assert_eq!(records.len(), 2);
+            assert_eq!(records[0].frame, Bytes::from_static(b"\x30\xff\xff\xff\xff\x01"));
+            assert!(records[0].packet(Version::V5).is_err());
+            assert_eq!(records[1].packet(Version::V5), Ok(Packet::PingReq(PingReq {})));
+        });
+    }
+
+    #[test]
+    fn rejects_files_that_are_not_captures() {
+        let error = read_capture(&b"MQTT\x01"[..]).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn replays_the_server_side_of_a_captured_session() {
+        #[derive(Default)]
+        struct Recorder(Vec<Packet>);
+
+        impl AsyncHandler for Recorder {
+            async fn handle(&mut self, packet: Packet) {
+                self.0.push(packet);
+            }
+        }
+
+        let records = [
+            (
+                Direction::Outgoing,
+                frame(Packet::Connect(Default::default())),
+            ),
+            (
+                Direction::Incoming,
+                frame(Packet::ConnAck(ConnAck::default())),
+            ),
+            (
+                Direction::Incoming,
+                frame(Packet::PubRel(PubRel {
+                    pkid: 5,
+                    ..Default::default()
+                })),
+            ),
+            (
+                Direction::Outgoing,
+                frame(Packet::PubComp(PubComp {
+                    pkid: 5,
+                    reason_code: PubCompReasonCode::PacketIdentifierNotFound,
+                    ..Default::default()
+                })),
+            ),
+        ]
+        .map(|(direction, frame)| Record {
+            direction,
+            timestamp: Duration::ZERO,
+            frame,
+        });
+
+        let (client, mut server) = duplex(1024);
+        let server = thread::spawn(move || block_on(replay(&records, &mut server)));
+
+        block_on(async {
+            let options = ConnectOptions {
+                keep_alive: 0,
+                ..Default::default()
+            };
+            let (mut network, _client) = Network::new(options, client).await.unwrap();
+            let mut handler = Recorder::default();
+
+            // the replay closes the connection once it is over
+            assert!(network.run(&mut handler).await.is_err());
+            assert_eq!(
+                handler.0,
+                [Packet::PubRel(PubRel {
+                    pkid: 5,
+                    ..Default::default()
+                })]
+            );
+        });
+        server.join().unwrap().unwrap();
+    }
+}
@@ -1,26 +1,113 @@
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
 
-use async_channel::Sender;
+use async_channel::{Sender, TrySendError};
 
-use crate::v5::{commons::packet::Packet, traits::pkid_mgr::PacketIdAlloc};
+use crate::v5::{
+    client::{
+        interceptor::{Interceptor, Interceptors},
+        packet_id::PacketIdManager,
+        routes::Routes,
+        stats::{ReconnectReason, Stats, StatsRecorder},
+        ConnectOptions,
+    },
+    commons::packet::Packet,
+    packet::unsubscribe::UnSubscribe,
+    traits::pkid_mgr::{PacketIdAlloc, PacketIdRelease},
+};
 
-#[derive(Debug)]
-pub struct MqttClient<T> {
+/// A handle to send packets through the [`Network`](super::network::asyncx::Network) it was created with.
+/// Cloning it is cheap, and every clone can be used from its own task
+#[derive(Debug, Clone)]
+pub struct MqttClient {
     /// sends packets to the channel
     tx: Sender<Packet>,
-    pkid_alloc: Arc<T>,
+    pkid_alloc: Arc<PacketIdManager>,
+    /// filled by the network
+    stats: Arc<StatsRecorder>,
+    /// How the last run of the network ended, `None` while it is running
+    exit: Arc<Mutex<Option<ReconnectReason>>>,
+    /// where the network delivers the messages of each subscription
+    pub(super) routes: Arc<Routes>,
+    /// run by the network on the packets it sends and receives
+    interceptors: Arc<Interceptors>,
     max_size: usize,
+    /// Report packets over `max_size` as the normative statement they break (see [`ConnectOptions::strict`](super::ConnectOptions::strict))
+    strict: bool,
 }
 
-impl<T> MqttClient<T>
-where
-    T: PacketIdAlloc,
-{
-    pub(crate) fn new(tx: Sender<Packet>, pkid_alloc: Arc<T>, max_size: usize) -> Self {
+impl MqttClient {
+    pub(crate) fn new(
+        tx: Sender<Packet>,
+        pkid_alloc: Arc<PacketIdManager>,
+        stats: Arc<StatsRecorder>,
+        exit: Arc<Mutex<Option<ReconnectReason>>>,
+        routes: Arc<Routes>,
+        interceptors: Arc<Interceptors>,
+        options: &ConnectOptions,
+    ) -> Self {
         Self {
             tx,
             pkid_alloc,
-            max_size,
+            stats,
+            exit,
+            routes,
+            interceptors,
+            max_size: options.server_max_size.get() as usize,
+            strict: options.strict,
+        }
+    }
+
+    /// Whether the network is still there to send the packets handed to the client: it was not dropped, and its last
+    /// run did not end
+    pub fn is_alive(&self) -> bool {
+        !self.tx.is_closed() && self.exit.lock().unwrap().is_none()
+    }
+
+    /// How the network ended, if it did. A network dropped while it was running ended for an unknown reason
+    pub fn exit_reason(&self) -> Option<ReconnectReason> {
+        let exit = *self.exit.lock().unwrap();
+        exit.or(self.tx.is_closed().then_some(ReconnectReason::Unknown))
+    }
+
+    /// A snapshot of the statistics of the client, see [`stats`](super::stats)
+    pub fn stats(&self) -> Stats {
+        self.stats.snapshot(self.tx.len())
+    }
+
+    /// Adds `interceptor` to the end of the chain the network runs on the packets it sends and receives, and on the
+    /// messages it delivers, see [`interceptor`](super::interceptor). It applies to every clone of the client
+    pub fn intercept<I>(&self, interceptor: I)
+    where
+        I: Interceptor + 'static,
+    {
+        self.interceptors.add(Arc::new(interceptor));
+    }
+
+    /// Unsubscribes from the topic filter of the last [`Subscription`](super::Subscription) handle dropped.
+    /// A destructor cannot wait for room in the queue of outgoing packets, nor for a packet identifier: when the queue
+    /// is full, the network sends the UNSUBSCRIBE once it sent the packets of the queue, and when no packet identifier
+    /// is free, once one is released
+    pub(super) fn unsubscribe_dropped(&self, filter: String, subscription_id: Option<usize>) {
+        let Ok(pkid) = self.pkid_alloc.allocate() else {
+            let unsubscribe = UnSubscribe {
+                pkid: 0,
+                payload: vec![filter],
+                ..Default::default()
+            };
+            return self.routes.defer(unsubscribe, subscription_id);
+        };
+
+        let packet = Packet::UnSubscribe(UnSubscribe {
+            pkid,
+            payload: vec![filter],
+            ..Default::default()
+        });
+        match self.tx.try_send(packet) {
+            Ok(()) => {}
+            Err(TrySendError::Full(Packet::UnSubscribe(packet))) => {
+                self.routes.defer(packet, subscription_id)
+            }
+            Err(_) => self.pkid_alloc.release(pkid),
         }
     }
 }
@@ -29,22 +116,48 @@ mod asyncx {
     use super::MqttClient;
 
     use bytes::Bytes;
+    #[cfg(feature = "serde")]
+    use serde::Serialize;
 
+    #[cfg(feature = "serde")]
+    use crate::v5::client::payload::PayloadCodec;
     use crate::v5::{
-        commons::{error::MQTTError, packet::Packet, qos::QoS},
+        client::{
+            error::{AckError, PayloadError, PublishError, SubscribeError},
+            Subscription,
+        },
+        commons::{
+            conformance, error::MQTTError, packet::Packet, qos::QoS, reason_code::ReasonCode,
+        },
         packet::{
             disconnect::Disconnect,
+            puback::PubAck,
             publish::{Publish, PublishProperties},
+            pubrec::PubRec,
             subscribe::{Subscribe, SubscribeProperties, SubscriptionOptions},
             unsubscribe::{UnSubscribe, UnSubscribeProperties},
         },
-        traits::{pkid_mgr::PacketIdAlloc, streamio::StreamIO, utils::Utils},
+        traits::{
+            bufferio::BufferIO,
+            pkid_mgr::PacketIdRelease,
+            utils::Utils,
+        },
     };
 
-    impl<T> MqttClient<T>
-    where
-        T: PacketIdAlloc,
-    {
+    impl MqttClient {
+        /// Refuses packets the server is not willing to receive
+        fn check_size<P: BufferIO>(&self, packet: &P) -> Result<(), MQTTError> {
+            packet.is_valid(self.max_size).map_err(|e| match e {
+                MQTTError::MaxPacketSizeExceed(_) if self.strict => {
+                    conformance::CLIENT_PACKET_TOO_LARGE.into()
+                }
+                e => e,
+            })
+        }
+
+        /// Publishes a message. A QoS 1 or QoS 2 message waits for a packet identifier when every one the server
+        /// allows (its Receive Maximum) is awaiting an acknowledgement, and fails with [`PublishError::Disconnected`]
+        /// if the network stops running in the meantime
         pub async fn publish<U, V>(
             &self,
             topic: U,
@@ -52,80 +165,197 @@ mod asyncx {
             retain: bool,
             payload: V,
             properties: Option<PublishProperties>,
-        ) -> Result<(), MQTTError>
+        ) -> Result<(), PublishError>
         where
             U: Into<String>,
             V: Into<Bytes>,
         {
-            let pkid = match qos {
-                QoS::Zero => None,
-                _ => Some(self.pkid_alloc.allocate()?),
-            };
-
             let properties = properties.unwrap_or(Default::default());
 
-            let packet = Publish {
+            let mut packet = Publish {
                 dup: false,
                 retain,
                 qos,
                 topic: topic.into(),
-                pkid,
+                pkid: None,
                 payload: payload.into(),
                 properties,
             };
+            packet.check_payload_format().map_err(PayloadError::from)?;
 
-            packet.is_valid(self.max_size)?;
-            packet.validate_topic(&packet.topic)?;
+            // checked with a placeholder packet identifier (of the same size), so that none is taken by a packet
+            // that is not sent
+            packet.pkid = (qos != QoS::Zero).then_some(0);
+            self.check_size(&packet)?;
+            packet
+                .validate_topic(&packet.topic)
+                .map_err(|e| match self.strict {
+                    true => conformance::TOPIC_NAME_WILDCARD.into(),
+                    false => e,
+                })?;
 
+            if qos != QoS::Zero {
+                packet.pkid = self.pkid_alloc.allocate_wait().await;
+                if packet.pkid.is_none() {
+                    return Err(PublishError::Disconnected(Box::new(Packet::Publish(packet))));
+                }
+            }
             self.tx.send(Packet::Publish(packet)).await?;
 
             Ok(())
         }
 
-        pub async fn subscribe(
+        /// Publishes `value`, encoded by `codec`, which sets the Content Type and the Payload Format Indicator of the
+        /// message (see [`payload`](crate::v5::client::payload))
+        #[cfg(feature = "serde")]
+        pub async fn publish_typed<U, T, C>(
             &self,
-            payload: Vec<(String, SubscriptionOptions)>,
+            topic: U,
+            qos: QoS,
+            retain: bool,
+            value: &T,
+            codec: &C,
+            properties: Option<PublishProperties>,
+        ) -> Result<(), PublishError>
+        where
+            U: Into<String>,
+            T: Serialize + ?Sized,
+            C: PayloadCodec,
+        {
+            let payload = codec.encode(value)?;
+
+            let mut properties = properties.unwrap_or_default();
+            properties.content_type = Some(String::from(codec.content_type()));
+            properties.payload_format_indicator = codec.is_utf8().then_some(1);
+
+            self.publish(topic, qos, retain, payload, Some(properties))
+                .await
+        }
+
+        /// Subscribes to `filter`, and returns the messages received for it. Dropping the [`Subscription`]
+        /// unsubscribes, once no other handle of the same topic filter is left.
+        /// Like [`publish`](Self::publish), it waits for a packet identifier when none is available
+        pub async fn subscribe<F>(
+            &self,
+            filter: F,
+            options: SubscriptionOptions,
+        ) -> Result<Subscription, SubscribeError>
+        where
+            F: Into<String>,
+        {
+            self.subscribe_with(filter, options, None).await
+        }
+
+        /// Same as [`subscribe`](Self::subscribe), with the properties of the SUBSCRIBE.
+        /// When the server supports Subscription Identifiers, one is set in `properties` (unless it already is) to
+        /// tell which subscriptions a message was sent for. Otherwise, the messages whose topic matches the topic
+        /// filter are returned. Every message is handed to the handler of the network as well
+        pub async fn subscribe_with<F>(
+            &self,
+            filter: F,
+            options: SubscriptionOptions,
             properties: Option<SubscribeProperties>,
-        ) -> Result<(), MQTTError> {
-            let pkid = self.pkid_alloc.allocate()?;
-            let properties = properties.unwrap_or(Default::default());
+        ) -> Result<Subscription, SubscribeError>
+        where
+            F: Into<String>,
+        {
+            let filter = filter.into();
+            let mut properties = properties.unwrap_or(Default::default());
 
-            let packet = Subscribe {
-                pkid,
+            let added = self.routes.add(&filter, &mut properties);
+            if let Some(pkid) = added.cancelled {
+                self.pkid_alloc.release(pkid);
+            }
+            if added.first {
+                let payload = vec![(filter.clone(), options)];
+                if let Err(error) = self.send_subscribe(payload, properties).await {
+                    self.routes.remove(added.key);
+                    return Err(error);
+                }
+            }
+
+            let (key, messages) = (added.key, added.messages);
+            Ok(Subscription::new(filter, key, messages, self.clone()))
+        }
+
+        async fn send_subscribe(
+            &self,
+            payload: Vec<(String, SubscriptionOptions)>,
+            properties: SubscribeProperties,
+        ) -> Result<(), SubscribeError> {
+            let mut packet = Subscribe {
+                pkid: 0,
                 payload,
                 properties,
             };
 
-            packet.is_valid(self.max_size)?;
+            self.check_size(&packet)?;
 
+            let Some(pkid) = self.pkid_alloc.allocate_wait().await else {
+                return Err(SubscribeError::Disconnected(Box::new(Packet::Subscribe(packet))));
+            };
+            packet.pkid = pkid;
             self.tx.send(Packet::Subscribe(packet)).await?;
 
             Ok(())
         }
 
+        /// Unsubscribes from the topic filters of `payload`, it waits for a packet identifier when none is available
         pub async fn unsubscribe<P>(
             &self,
             payload: P,
             properties: Option<UnSubscribeProperties>,
-        ) -> Result<(), MQTTError>
+        ) -> Result<(), SubscribeError>
         where
             P: Into<Vec<String>>,
         {
-            let pkid = self.pkid_alloc.allocate()?;
             let properties = properties.unwrap_or(Default::default());
 
-            let packet = UnSubscribe {
-                pkid,
+            let mut packet = UnSubscribe {
+                pkid: 0,
                 properties,
                 payload: payload.into(),
             };
 
-            packet.is_valid(self.max_size)?;
+            self.check_size(&packet)?;
+            let Some(pkid) = self.pkid_alloc.allocate_wait().await else {
+                return Err(SubscribeError::Disconnected(Box::new(Packet::UnSubscribe(packet))));
+            };
+            packet.pkid = pkid;
             self.tx.send(Packet::UnSubscribe(packet)).await?;
 
             Ok(())
         }
 
+        /// Acknowledges a QoS 1 (PUBACK) or QoS 2 (PUBREC) message received while
+        /// [`ConnectOptions::manual_ack`](crate::v5::client::ConnectOptions::manual_ack) is set, with `reason`
+        /// (e.g. [`ReasonCode::ImplementationSpecificError`] for a message that cannot be processed).
+        /// Messages can be acknowledged in any order, the acknowledgements are sent in the order the messages were
+        /// received [MQTT-4.6.0-2]
+        pub async fn ack(&self, publish: &Publish, reason: ReasonCode) -> Result<(), AckError> {
+            let (Some(pkid), qos) = (publish.pkid, publish.qos) else {
+                return Err(AckError::QoS0);
+            };
+            let invalid = |_| AckError::InvalidReasonCode(reason, qos as u8);
+
+            let packet = match qos {
+                QoS::Zero => return Err(AckError::QoS0),
+                QoS::One => Packet::PubAck(PubAck {
+                    pkid,
+                    reason_code: reason.try_into().map_err(invalid)?,
+                    ..Default::default()
+                }),
+                QoS::Two => Packet::PubRec(PubRec {
+                    pkid,
+                    reason_code: reason.try_into().map_err(invalid)?,
+                    ..Default::default()
+                }),
+            };
+
+            self.tx.send(packet).await?;
+            Ok(())
+        }
+
         pub async fn disconnect(&self) -> Result<(), MQTTError> {
             let packet = Disconnect::default();
 
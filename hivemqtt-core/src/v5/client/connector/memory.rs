@@ -0,0 +1,269 @@
+use std::{
+    collections::VecDeque,
+    io,
+    pin::Pin,
+    sync::{Arc, Mutex},
+    task::{Context, Poll, Waker},
+};
+
+use async_channel::{Receiver, Sender};
+use futures::{AsyncRead, AsyncWrite};
+
+use crate::v5::commons::error::MQTTError;
+
+use super::Connector;
+
+/// One direction of a duplex stream: bytes written by one half and read by the other
+#[derive(Debug)]
+struct Pipe {
+    buf: VecDeque<u8>,
+    max_buf_size: usize,
+    /// set once either the writing or the reading half is dropped
+    closed: bool,
+    read_waker: Option<Waker>,
+    write_waker: Option<Waker>,
+}
+
+impl Pipe {
+    fn new(max_buf_size: usize) -> Self {
+        Self {
+            buf: VecDeque::with_capacity(max_buf_size),
+            max_buf_size,
+            closed: false,
+            read_waker: None,
+            write_waker: None,
+        }
+    }
+
+    fn close(&mut self) {
+        self.closed = true;
+        if let Some(waker) = self.read_waker.take() {
+            waker.wake();
+        }
+        if let Some(waker) = self.write_waker.take() {
+            waker.wake();
+        }
+    }
+}
+
+/// An in-memory, bidirectional byte stream.
+/// Created in pairs with [`duplex`]; whatever is written into one half can be read from the other.
+/// Dropping either half closes the stream: the peer reads EOF and its writes fail with `BrokenPipe`
+#[derive(Debug)]
+pub struct DuplexStream {
+    read: Arc<Mutex<Pipe>>,
+    write: Arc<Mutex<Pipe>>,
+}
+
+/// Creates a pair of connected [`DuplexStream`]s.
+/// `max_buf_size` is the number of bytes that can be buffered in each direction before writes wait for the peer to read
+pub fn duplex(max_buf_size: usize) -> (DuplexStream, DuplexStream) {
+    let max_buf_size = max_buf_size.max(1);
+    let one = Arc::new(Mutex::new(Pipe::new(max_buf_size)));
+    let two = Arc::new(Mutex::new(Pipe::new(max_buf_size)));
+
+    (
+        DuplexStream {
+            read: one.clone(),
+            write: two.clone(),
+        },
+        DuplexStream {
+            read: two,
+            write: one,
+        },
+    )
+}
+
+impl AsyncRead for DuplexStream {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        let mut pipe = self.read.lock().unwrap();
+
+        if pipe.buf.is_empty() {
+            if pipe.closed || buf.is_empty() {
+                return Poll::Ready(Ok(0));
+            }
+            pipe.read_waker = Some(cx.waker().clone());
+            return Poll::Pending;
+        }
+
+        let len = buf.len().min(pipe.buf.len());
+        for (dst, src) in buf.iter_mut().zip(pipe.buf.drain(..len)) {
+            *dst = src;
+        }
+
+        if let Some(waker) = pipe.write_waker.take() {
+            waker.wake();
+        }
+
+        Poll::Ready(Ok(len))
+    }
+}
+
+impl AsyncWrite for DuplexStream {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let mut pipe = self.write.lock().unwrap();
+
+        if pipe.closed {
+            return Poll::Ready(Err(io::ErrorKind::BrokenPipe.into()));
+        }
+
+        let available = pipe.max_buf_size - pipe.buf.len();
+        if available == 0 {
+            pipe.write_waker = Some(cx.waker().clone());
+            return Poll::Pending;
+        }
+
+        let len = buf.len().min(available);
+        pipe.buf.extend(&buf[..len]);
+
+        if let Some(waker) = pipe.read_waker.take() {
+            waker.wake();
+        }
+
+        Poll::Ready(Ok(len))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_close(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        self.write.lock().unwrap().close();
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl Drop for DuplexStream {
+    fn drop(&mut self) {
+        self.read.lock().unwrap().close();
+        self.write.lock().unwrap().close();
+    }
+}
+
+/// Connects to a [`MemoryListener`] over a fresh [`duplex`] stream each time [`Connector::connect`] is called.
+/// Mostly useful for tests, where the listener side plays the broker
+#[derive(Debug, Clone)]
+pub struct MemoryConnector {
+    tx: Sender<DuplexStream>,
+    max_buf_size: usize,
+}
+
+/// The accepting side of a [`MemoryConnector`]
+#[derive(Debug)]
+pub struct MemoryListener {
+    rx: Receiver<DuplexStream>,
+}
+
+impl MemoryConnector {
+    /// Creates a connector/listener pair, every connection gets buffers of `max_buf_size` bytes in each direction
+    pub fn new(max_buf_size: usize) -> (Self, MemoryListener) {
+        let (tx, rx) = async_channel::unbounded();
+        (Self { tx, max_buf_size }, MemoryListener { rx })
+    }
+}
+
+impl MemoryListener {
+    /// Waits for the next connection made through the paired [`MemoryConnector`]
+    pub async fn accept(&self) -> Result<DuplexStream, MQTTError> {
+        self.rx
+            .recv()
+            .await
+            .map_err(|_| io::Error::from(io::ErrorKind::ConnectionAborted).into())
+    }
+}
+
+impl Connector for MemoryConnector {
+    type Stream = DuplexStream;
+
+    async fn connect(&self) -> io::Result<Self::Stream> {
+        let (client, server) = duplex(self.max_buf_size);
+        self.tx
+            .send(server)
+            .await
+            .map_err(|_| io::Error::from(io::ErrorKind::ConnectionRefused))?;
+        Ok(client)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::{executor::block_on, AsyncReadExt, AsyncWriteExt};
+
+    use super::*;
+
+    #[test]
+    fn bytes_written_on_one_half_are_read_on_the_other() {
+        block_on(async {
+            let (mut a, mut b) = duplex(64);
+
+            a.write_all(b"ping").await.unwrap();
+            b.write_all(b"pong").await.unwrap();
+
+            let mut buf = [0u8; 4];
+            b.read_exact(&mut buf).await.unwrap();
+            assert_eq!(&buf, b"ping");
+            a.read_exact(&mut buf).await.unwrap();
+            assert_eq!(&buf, b"pong");
+        });
+    }
+
+    #[test]
+    fn writes_larger_than_the_buffer_complete_once_the_peer_reads() {
+        block_on(async {
+            let (mut a, mut b) = duplex(3);
+            let payload = b"a payload longer than three bytes".to_vec();
+
+            let expected = payload.clone();
+            let write = async move {
+                a.write_all(&payload).await.unwrap();
+            };
+            let read = async move {
+                let mut received = vec![0u8; expected.len()];
+                b.read_exact(&mut received).await.unwrap();
+                assert_eq!(received, expected);
+            };
+
+            futures::join!(write, read);
+        });
+    }
+
+    #[test]
+    fn dropping_one_half_closes_the_stream() {
+        block_on(async {
+            let (a, mut b) = duplex(8);
+            drop(a);
+
+            let mut buf = [0u8; 1];
+            assert_eq!(b.read(&mut buf).await.unwrap(), 0);
+            assert_eq!(
+                b.write_all(b"x").await.unwrap_err().kind(),
+                io::ErrorKind::BrokenPipe
+            );
+        });
+    }
+
+    #[test]
+    fn memory_connector_opens_a_fresh_stream_per_connect() {
+        block_on(async {
+            let (connector, listener) = MemoryConnector::new(16);
+
+            for i in 0..2u8 {
+                let mut client = connector.connect().await.unwrap();
+                let mut server = listener.accept().await.unwrap();
+
+                client.write_all(&[i]).await.unwrap();
+                let mut buf = [0u8; 1];
+                server.read_exact(&mut buf).await.unwrap();
+                assert_eq!(buf, [i]);
+            }
+        });
+    }
+}
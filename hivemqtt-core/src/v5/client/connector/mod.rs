@@ -0,0 +1,44 @@
+//! Transports the client can run over.
+//!
+//! A [`Connector`] knows how to open a brand new stream to the broker, which is what the [`Network`](super::network::asyncx::Network)
+//! needs to (re)establish a session without the caller having to build the stream themselves.
+//! Transport specific settings (e.g. TCP_NODELAY, connect timeouts, TLS configuration) belong to the connector and never to [`ConnectOptions`](super::ConnectOptions)
+use std::{future::Future, io};
+
+mod memory;
+mod tcp;
+#[cfg(feature = "tls")]
+mod tls;
+#[cfg(feature = "tokio")]
+mod tokio_tcp;
+#[cfg(unix)]
+mod unix;
+#[cfg(feature = "ws")]
+mod ws;
+
+pub use memory::{duplex, DuplexStream, MemoryConnector, MemoryListener};
+pub use tcp::{TcpConfig, TcpConnector};
+#[cfg(feature = "tls")]
+pub use tls::TlsConnector;
+#[cfg(feature = "tokio")]
+pub use tokio_tcp::TokioTcpConnector;
+#[cfg(unix)]
+pub use unix::UnixConnector;
+#[cfg(feature = "ws")]
+pub use ws::{WsConnector, WsStream};
+
+/// Opens a fresh, connected stream to the broker every time it is called
+pub trait Connector {
+    /// A stream implementing the `futures` IO traits, or tokio's for a network created with `Network::connect_tokio`
+    /// (the `tokio` feature)
+    type Stream: Unpin;
+
+    /// Failures are reported as the [`io::Error`] they were raised with, so that they can be told apart by their kind
+    /// (e.g. `ConnectionRefused`, or `TimedOut` once a connect timeout elapses)
+    fn connect(&self) -> impl Future<Output = io::Result<Self::Stream>>;
+
+    /// Where the streams are opened to (e.g. `host:port`), only used to describe the connection
+    fn broker(&self) -> Option<String> {
+        None
+    }
+}
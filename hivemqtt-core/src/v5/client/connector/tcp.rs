@@ -0,0 +1,213 @@
+use std::{future::Future, io, net::SocketAddr, time::Duration};
+
+use async_io::Timer;
+use async_net::TcpStream;
+use futures::{select, stream::FuturesUnordered, FutureExt, StreamExt};
+
+
+use super::Connector;
+
+/// Transport settings applied by [`TcpConnector`] on every connection attempt
+#[derive(Debug, Clone)]
+pub struct TcpConfig {
+    /// Disables Nagle's algorithm on the socket (TCP_NODELAY), so that small packets (acks, pings) are not delayed
+    pub nodelay: bool,
+    /// Maximum time allowed for name resolution and the TCP handshake combined. `None` waits for as long as the OS does
+    pub connect_timeout: Option<Duration>,
+    /// Happy Eyeballs (RFC 8305 section 5) "Connection Attempt Delay".
+    /// How long to wait on an attempt before racing it against the next resolved address
+    pub attempt_delay: Duration,
+    /// Whether IPv6 addresses are attempted first when the host resolves to both families
+    pub prefer_ipv6: bool,
+}
+
+impl Default for TcpConfig {
+    fn default() -> Self {
+        Self {
+            nodelay: true,
+            connect_timeout: Some(Duration::from_secs(30)),
+            attempt_delay: Duration::from_millis(250),
+            prefer_ipv6: true,
+        }
+    }
+}
+
+/// Opens plain TCP connections to `host:port`.
+/// `host` can be a domain name, an IPv4 or an IPv6 address. When a name resolves to several addresses, they are raced using Happy Eyeballs
+#[derive(Debug, Clone)]
+pub struct TcpConnector {
+    pub host: String,
+    pub port: u16,
+    pub config: TcpConfig,
+}
+
+impl TcpConnector {
+    pub fn new<H: Into<String>>(host: H, port: u16) -> Self {
+        Self {
+            host: host.into(),
+            port,
+            config: TcpConfig::default(),
+        }
+    }
+
+    pub fn with_config(mut self, config: TcpConfig) -> Self {
+        self.config = config;
+        self
+    }
+
+    async fn open(&self) -> io::Result<TcpStream> {
+        // IPv6 literals may be provided with or without their brackets
+        let host = self.host.trim_start_matches('[').trim_end_matches(']');
+        let addrs = async_net::resolve((host, self.port)).await?;
+        let addrs = interleave(addrs, self.config.prefer_ipv6);
+
+        let delay = self.config.attempt_delay;
+        let stream = happy_eyeballs(addrs, delay, TcpStream::connect, Timer::after).await?;
+        stream.set_nodelay(self.config.nodelay)?;
+        Ok(stream)
+    }
+}
+
+impl Connector for TcpConnector {
+    type Stream = TcpStream;
+
+    async fn connect(&self) -> io::Result<Self::Stream> {
+        let Some(timeout) = self.config.connect_timeout else {
+            return self.open().await;
+        };
+
+        select! {
+            stream = self.open().fuse() => stream,
+            _ = FutureExt::fuse(Timer::after(timeout)) => Err(io::ErrorKind::TimedOut.into()),
+        }
+    }
+
+    fn broker(&self) -> Option<String> {
+        Some(format!("{}:{}", self.host, self.port))
+    }
+}
+
+/// Orders the resolved addresses by alternating address families, starting with the preferred one (RFC 8305 section 4)
+pub(super) fn interleave(addrs: Vec<SocketAddr>, prefer_ipv6: bool) -> Vec<SocketAddr> {
+    let (preferred, other): (Vec<_>, Vec<_>) = addrs
+        .into_iter()
+        .partition(|addr| addr.is_ipv6() == prefer_ipv6);
+
+    let mut result = Vec::with_capacity(preferred.len() + other.len());
+    let mut preferred = preferred.into_iter();
+    let mut other = other.into_iter();
+
+    loop {
+        match (preferred.next(), other.next()) {
+            (None, None) => break,
+            (a, b) => result.extend(a.into_iter().chain(b)),
+        }
+    }
+
+    result
+}
+
+/// Starts a connection attempt to each address in order, a new attempt is started every time `delay` elapses
+/// or as soon as the previous one fails. The first attempt to succeed wins, and every other attempt is dropped.
+/// `connect` opens a stream to an address, and `sleep` waits for a delay, on the runtime of the connector
+pub(super) async fn happy_eyeballs<S, C, T>(
+    addrs: Vec<SocketAddr>,
+    delay: Duration,
+    connect: impl Fn(SocketAddr) -> C,
+    sleep: impl Fn(Duration) -> T,
+) -> io::Result<S>
+where
+    C: Future<Output = io::Result<S>>,
+    T: Future,
+{
+    let mut addrs = addrs.into_iter();
+    let mut attempts = FuturesUnordered::new();
+    let mut last_error = None;
+
+    if let Some(addr) = addrs.next() {
+        attempts.push(Box::pin(connect(addr)));
+    }
+
+    while !attempts.is_empty() {
+        let mut next_attempt = Box::pin(FutureExt::fuse(sleep(delay)));
+
+        select! {
+            result = attempts.select_next_some() => match result {
+                Ok(stream) => return Ok(stream),
+                Err(e) => {
+                    last_error = Some(e);
+                    if let Some(addr) = addrs.next() {
+                        attempts.push(Box::pin(connect(addr)));
+                    }
+                }
+            },
+            _ = next_attempt => {
+                if let Some(addr) = addrs.next() {
+                    attempts.push(Box::pin(connect(addr)));
+                }
+            }
+        }
+    }
+
+    Err(last_error.unwrap_or_else(|| {
+        io::Error::new(io::ErrorKind::NotFound, "host did not resolve to any address")
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::{Ipv4Addr, Ipv6Addr};
+
+    use async_net::TcpListener;
+    use futures::executor::block_on;
+
+    use super::*;
+
+    #[test]
+    fn interleaves_address_families_starting_with_the_preferred_one() {
+        let v4 = |n| SocketAddr::from((Ipv4Addr::new(10, 0, 0, n), 1883));
+        let v6 = |n| SocketAddr::from((Ipv6Addr::new(0, 0, 0, 0, 0, 0, 0, n as u16), 1883));
+
+        let addrs = vec![v4(1), v4(2), v4(3), v6(1)];
+        assert_eq!(interleave(addrs.clone(), true), vec![v6(1), v4(1), v4(2), v4(3)]);
+        assert_eq!(interleave(addrs, false), vec![v4(1), v6(1), v4(2), v4(3)]);
+    }
+
+    #[test]
+    fn falls_back_to_the_next_address_when_an_attempt_fails() {
+        block_on(async {
+            let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).await.unwrap();
+            let good = listener.local_addr().unwrap();
+
+            // nothing listens on the closed port, so that attempt is refused
+            let closed = TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).await.unwrap();
+            let bad = closed.local_addr().unwrap();
+            drop(closed);
+
+            let delay = Duration::from_secs(5);
+            let stream = happy_eyeballs(vec![bad, good], delay, TcpStream::connect, Timer::after)
+                .await
+                .unwrap();
+            assert_eq!(stream.peer_addr().unwrap(), good);
+        });
+    }
+
+    #[test]
+    fn connects_and_applies_the_transport_config() {
+        block_on(async {
+            let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).await.unwrap();
+            let port = listener.local_addr().unwrap().port();
+
+            let connector = TcpConnector::new("127.0.0.1", port);
+            let stream = connector.connect().await.unwrap();
+            assert!(stream.nodelay().unwrap());
+
+            let connector = TcpConnector::new("localhost", port).with_config(TcpConfig {
+                nodelay: false,
+                ..Default::default()
+            });
+            let stream = connector.connect().await.unwrap();
+            assert!(!stream.nodelay().unwrap());
+        });
+    }
+}
@@ -0,0 +1,53 @@
+use std::{io, sync::Arc};
+
+use futures::{AsyncRead, AsyncWrite};
+use futures_rustls::{
+    client::TlsStream,
+    rustls::{pki_types::ServerName, ClientConfig},
+};
+
+use crate::v5::commons::error::MQTTError;
+
+use super::Connector;
+
+/// Wraps the streams opened by another connector (usually a [`TcpConnector`](super::TcpConnector)) in TLS
+#[derive(Clone)]
+pub struct TlsConnector<C> {
+    inner: C,
+    domain: ServerName<'static>,
+    connector: futures_rustls::TlsConnector,
+}
+
+impl<C> TlsConnector<C> {
+    /// `domain` is the name the server's certificate is verified against
+    pub fn new<D>(inner: C, domain: D, config: Arc<ClientConfig>) -> Result<Self, MQTTError>
+    where
+        D: Into<String>,
+    {
+        let domain = ServerName::try_from(domain.into())
+            .map_err(|e| MQTTError::UnknownData(format!("Invalid TLS server name: {e}")))?;
+
+        Ok(Self {
+            inner,
+            domain,
+            connector: futures_rustls::TlsConnector::from(config),
+        })
+    }
+}
+
+impl<C> Connector for TlsConnector<C>
+where
+    C: Connector,
+    C::Stream: AsyncRead + AsyncWrite,
+{
+    type Stream = TlsStream<C::Stream>;
+
+    async fn connect(&self) -> io::Result<Self::Stream> {
+        let stream = self.inner.connect().await?;
+        self.connector.connect(self.domain.clone(), stream).await
+    }
+
+    fn broker(&self) -> Option<String> {
+        self.inner.broker()
+    }
+}
@@ -0,0 +1,97 @@
+use std::io;
+
+use tokio::net::TcpStream;
+
+use super::{
+    tcp::{happy_eyeballs, interleave},
+    Connector, TcpConfig,
+};
+
+/// Opens plain TCP connections to `host:port` on tokio, for a network created with
+/// [`Network::connect_tokio`](crate::v5::client::network::asyncx::Network::connect_tokio).
+/// Same as a [`TcpConnector`](super::TcpConnector) otherwise: names resolving to several addresses are raced using
+/// Happy Eyeballs, and the [`TcpConfig`] is applied on every connection attempt
+#[derive(Debug, Clone)]
+pub struct TokioTcpConnector {
+    pub host: String,
+    pub port: u16,
+    pub config: TcpConfig,
+}
+
+impl TokioTcpConnector {
+    pub fn new<H: Into<String>>(host: H, port: u16) -> Self {
+        Self {
+            host: host.into(),
+            port,
+            config: TcpConfig::default(),
+        }
+    }
+
+    pub fn with_config(mut self, config: TcpConfig) -> Self {
+        self.config = config;
+        self
+    }
+
+    async fn open(&self) -> io::Result<TcpStream> {
+        // IPv6 literals may be provided with or without their brackets
+        let host = self.host.trim_start_matches('[').trim_end_matches(']');
+        let addrs = tokio::net::lookup_host((host, self.port)).await?.collect();
+        let addrs = interleave(addrs, self.config.prefer_ipv6);
+
+        let delay = self.config.attempt_delay;
+        let stream = happy_eyeballs(addrs, delay, TcpStream::connect, tokio::time::sleep).await?;
+        stream.set_nodelay(self.config.nodelay)?;
+        Ok(stream)
+    }
+}
+
+impl Connector for TokioTcpConnector {
+    type Stream = TcpStream;
+
+    async fn connect(&self) -> io::Result<Self::Stream> {
+        let Some(timeout) = self.config.connect_timeout else {
+            return self.open().await;
+        };
+
+        tokio::time::timeout(timeout, self.open())
+            .await
+            .map_err(|_| io::ErrorKind::TimedOut)?
+    }
+
+    fn broker(&self) -> Option<String> {
+        Some(format!("{}:{}", self.host, self.port))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::Ipv4Addr;
+
+    use tokio::net::TcpListener;
+
+    use super::*;
+
+    #[test]
+    fn connects_and_applies_the_transport_config() {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap();
+
+        runtime.block_on(async {
+            let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).await.unwrap();
+            let port = listener.local_addr().unwrap().port();
+
+            let connector = TokioTcpConnector::new("127.0.0.1", port);
+            let stream = connector.connect().await.unwrap();
+            assert!(stream.nodelay().unwrap());
+
+            let connector = TokioTcpConnector::new("localhost", port).with_config(TcpConfig {
+                nodelay: false,
+                ..Default::default()
+            });
+            let stream = connector.connect().await.unwrap();
+            assert!(!stream.nodelay().unwrap());
+        });
+    }
+}
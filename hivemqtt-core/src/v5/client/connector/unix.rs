@@ -0,0 +1,29 @@
+use std::{io, path::PathBuf};
+
+use async_net::unix::UnixStream;
+
+use super::Connector;
+
+/// Opens connections to a broker listening on a Unix domain socket
+#[derive(Debug, Clone)]
+pub struct UnixConnector {
+    pub path: PathBuf,
+}
+
+impl UnixConnector {
+    pub fn new<P: Into<PathBuf>>(path: P) -> Self {
+        Self { path: path.into() }
+    }
+}
+
+impl Connector for UnixConnector {
+    type Stream = UnixStream;
+
+    async fn connect(&self) -> io::Result<Self::Stream> {
+        UnixStream::connect(&self.path).await
+    }
+
+    fn broker(&self) -> Option<String> {
+        Some(self.path.display().to_string())
+    }
+}
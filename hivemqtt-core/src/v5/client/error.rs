@@ -0,0 +1,243 @@
+//! Errors of the client's operations, each keeping whatever it was caused by (the [`io::Error`], the CONNACK, the packet
+//! that could not be sent, ...) so that the causes can be told apart:
+//! ```ignore
+//! match Network::connect_with(options, &connector).await {
+//!     Err(ConnectError::Refused(connack)) if connack.reason == ConnAckReasonCode::BadUserNameOrPassword => { ... }
+//!     Err(ConnectError::Io(e)) if e.kind() == io::ErrorKind::TimedOut => { ... }
+//!     Err(e) if e.is_retryable() => { ... }
+//!     ...
+//! }
+//! ```
+use std::{io, str::Utf8Error};
+
+use async_channel::{RecvError, SendError};
+
+use crate::v5::{
+    commons::{
+        conformance::ConformanceError,
+        error::{DecodeError, MQTTError},
+        packet::Packet,
+        reason_code::ReasonCode,
+    },
+    packet::connack::{reason_code::ConnAckReasonCode, ConnAck},
+};
+
+/// Why the MQTT connection could not be established
+#[derive(Debug, thiserror::Error)]
+pub enum ConnectError {
+    /// The server refused the connection, the CONNACK carries the Reason Code along with its Reason String and properties
+    #[error("Connection refused: {}", .0.reason)]
+    Refused(Box<ConnAck>),
+    /// The server answered the CONNECT with something else than a CONNACK
+    #[error("Expected a CONNACK, but received: {0:?}")]
+    UnexpectedPacket(Box<Packet>),
+    /// The CONNACK could not be decoded
+    #[error("Invalid CONNACK: {0}")]
+    Decode(DecodeError),
+    /// The stream could not be opened, or failed before the CONNACK was received
+    #[error("IO Error: {0}")]
+    Io(#[from] io::Error),
+    /// The CONNECT could not be encoded (e.g. one of its fields is over the limits of the protocol)
+    #[error("Invalid CONNECT: {0}")]
+    Encode(#[from] MQTTError),
+}
+
+impl ConnectError {
+    /// The Reason Code the server refused the connection with
+    pub fn reason_code(&self) -> Option<ConnAckReasonCode> {
+        match self {
+            Self::Refused(connack) => Some(connack.reason),
+            _ => None,
+        }
+    }
+
+    /// Whether connecting again may succeed: the network failed, or the server refused the connection for a reason
+    /// that may not hold later (e.g. Server busy)
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            Self::Io(_) => true,
+            Self::Refused(connack) => connack.reason.is_retryable(),
+            _ => false,
+        }
+    }
+}
+
+impl From<DecodeError> for ConnectError {
+    fn from(error: DecodeError) -> Self {
+        match error {
+            DecodeError::Io(error) => Self::Io(error),
+            error => Self::Decode(error),
+        }
+    }
+}
+
+/// Why [`Network::run`](super::network::asyncx::Network::run) stopped, when the connection was not closed by a DISCONNECT
+/// or the keep alive
+#[derive(Debug, thiserror::Error)]
+pub enum RunError {
+    /// The stream failed, or the server closed it without a DISCONNECT
+    #[error("IO Error: {0}")]
+    Io(#[from] io::Error),
+    /// Every [`MqttClient`](super::MqttClient) was dropped, no packet can be handed to the network anymore
+    #[error("Every client handle was dropped")]
+    ClientsDropped,
+    /// A packet broke the protocol, could not be decoded or encoded, or was refused by an interceptor.
+    /// When the packet was received, or is one the network sends on its own (an acknowledgement, a PINGREQ), the
+    /// DISCONNECT sent to the server carries the Reason Code of this error (see [`DisconnectReasonCode`](crate::v5::packet::disconnect::DisconnectReasonCode)).
+    /// A packet of the clients the session state refuses ends the run without one
+    #[error(transparent)]
+    Protocol(Box<MQTTError>),
+}
+
+impl RunError {
+    /// Whether connecting again may help: the network failed, rather than one side broke the protocol
+    pub fn is_retryable(&self) -> bool {
+        matches!(self, Self::Io(_))
+    }
+}
+
+impl From<MQTTError> for RunError {
+    fn from(error: MQTTError) -> Self {
+        Self::Protocol(Box::new(error))
+    }
+}
+
+impl From<InterceptError> for RunError {
+    fn from(error: InterceptError) -> Self {
+        MQTTError::from(error).into()
+    }
+}
+
+impl From<RecvError> for RunError {
+    fn from(_: RecvError) -> Self {
+        Self::ClientsDropped
+    }
+}
+
+/// Why a PUBLISH was not sent
+#[derive(Debug, thiserror::Error)]
+pub enum PublishError {
+    /// Every packet identifier the server allows (its Receive Maximum) is awaiting an acknowledgement
+    #[error("No Packet Identifier available")]
+    PacketIdsExhausted,
+    /// The size of the packet is over the Maximum Packet Size of the server
+    #[error("Packet too large: {0} bytes")]
+    TooLarge(usize),
+    /// The Topic Name is not valid
+    #[error("Invalid Topic Name, it contains: {0}")]
+    InvalidTopic(&'static str),
+    /// The payload is not what its Payload Format Indicator says, or could not be encoded
+    #[error(transparent)]
+    Payload(#[from] PayloadError),
+    /// Only raised in strict mode ([`ConnectOptions::strict`](super::ConnectOptions::strict))
+    #[error(transparent)]
+    Conformance(ConformanceError),
+    /// The [`Network`](super::network::asyncx::Network) is no longer running, this is the packet that could not be handed to it
+    #[error("The network is no longer running")]
+    Disconnected(Box<Packet>),
+    #[error(transparent)]
+    Other(Box<MQTTError>),
+}
+
+impl From<MQTTError> for PublishError {
+    fn from(error: MQTTError) -> Self {
+        match error {
+            MQTTError::PacketIdGenerationError => Self::PacketIdsExhausted,
+            MQTTError::MaxPacketSizeExceed(size) => Self::TooLarge(size),
+            MQTTError::InvalidTopic(reason) => Self::InvalidTopic(reason),
+            MQTTError::Conformance(rule) => Self::Conformance(rule),
+            MQTTError::ChannelClosed(SendError(packet)) => Self::Disconnected(Box::new(packet)),
+            error => Self::Other(Box::new(error)),
+        }
+    }
+}
+
+impl From<SendError<Packet>> for PublishError {
+    fn from(SendError(packet): SendError<Packet>) -> Self {
+        Self::Disconnected(Box::new(packet))
+    }
+}
+
+/// Why a payload could not be encoded or decoded (see [`payload`](super::payload))
+#[derive(Debug, thiserror::Error)]
+pub enum PayloadError {
+    /// The Payload Format Indicator says the payload is UTF-8 Encoded Character Data, and it is not
+    #[error("Invalid UTF-8 payload: {0}")]
+    InvalidUtf8(#[from] Utf8Error),
+    /// No codec is known (or enabled) for the Content Type of the message, `None` when it has none
+    #[error("No codec for the Content Type: {0:?}")]
+    UnknownContentType(Option<String>),
+    #[error("Cannot encode the payload: {0}")]
+    Encode(Box<dyn std::error::Error + Send + Sync>),
+    #[error("Cannot decode the payload: {0}")]
+    Decode(Box<dyn std::error::Error + Send + Sync>),
+}
+
+/// Why an [`Interceptor`](super::interceptor::Interceptor) refused a packet or a message, sent to the server as the
+/// Reason String of the DISCONNECT when the connection is closed for it
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+#[error("{0}")]
+pub struct InterceptError(pub String);
+
+impl From<InterceptError> for MQTTError {
+    fn from(InterceptError(reason): InterceptError) -> Self {
+        Self::Intercepted(reason)
+    }
+}
+
+/// Why a SUBSCRIBE or an UNSUBSCRIBE was not sent
+#[derive(Debug, thiserror::Error)]
+pub enum SubscribeError {
+    /// Every packet identifier the server allows (its Receive Maximum) is awaiting an acknowledgement
+    #[error("No Packet Identifier available")]
+    PacketIdsExhausted,
+    /// The size of the packet is over the Maximum Packet Size of the server
+    #[error("Packet too large: {0} bytes")]
+    TooLarge(usize),
+    /// Only raised in strict mode ([`ConnectOptions::strict`](super::ConnectOptions::strict))
+    #[error(transparent)]
+    Conformance(ConformanceError),
+    /// The [`Network`](super::network::asyncx::Network) is no longer running, this is the packet that could not be handed to it
+    #[error("The network is no longer running")]
+    Disconnected(Box<Packet>),
+    #[error(transparent)]
+    Other(Box<MQTTError>),
+}
+
+impl From<MQTTError> for SubscribeError {
+    fn from(error: MQTTError) -> Self {
+        match error {
+            MQTTError::PacketIdGenerationError => Self::PacketIdsExhausted,
+            MQTTError::MaxPacketSizeExceed(size) => Self::TooLarge(size),
+            MQTTError::Conformance(rule) => Self::Conformance(rule),
+            MQTTError::ChannelClosed(SendError(packet)) => Self::Disconnected(Box::new(packet)),
+            error => Self::Other(Box::new(error)),
+        }
+    }
+}
+
+impl From<SendError<Packet>> for SubscribeError {
+    fn from(SendError(packet): SendError<Packet>) -> Self {
+        Self::Disconnected(Box::new(packet))
+    }
+}
+
+/// Why a PUBLISH could not be acknowledged
+#[derive(Debug, thiserror::Error)]
+pub enum AckError {
+    /// QoS 0 messages are not acknowledged
+    #[error("QoS 0 messages are not acknowledged")]
+    QoS0,
+    /// The Reason Code cannot be sent in the acknowledgement (PUBACK for QoS 1, PUBREC for QoS 2)
+    #[error("{0} cannot acknowledge a QoS {1} message")]
+    InvalidReasonCode(ReasonCode, u8),
+    /// The [`Network`](super::network::asyncx::Network) is no longer running, this is the packet that could not be handed to it
+    #[error("The network is no longer running")]
+    Disconnected(Box<Packet>),
+}
+
+impl From<SendError<Packet>> for AckError {
+    fn from(SendError(packet): SendError<Packet>) -> Self {
+        Self::Disconnected(Box::new(packet))
+    }
+}
@@ -3,3 +3,9 @@ use crate::v5::commons::packet::Packet;
 pub trait AsyncHandler {
     fn handle(&mut self, packet: Packet) -> impl std::future::Future<Output = ()> + Send + Sync;
 }
+
+/// Leaves every packet alone, for applications that only read the messages of their
+/// [`Subscription`](super::Subscription) handles
+impl AsyncHandler for () {
+    async fn handle(&mut self, _packet: Packet) {}
+}
@@ -0,0 +1,170 @@
+//! Interceptors see the packets the [`Network`](super::network::asyncx::Network) exchanges with the server, at the
+//! boundary between the session state and the stream, and the messages it delivers. They are registered on the client
+//! with [`MqttClient::intercept`](super::MqttClient::intercept), and run in the order they were registered.
+//!
+//! Every hook gets the packet (or message) handed on by the previous interceptor, and can:
+//! - modify it, by returning another one (e.g. to add a User Property, or rewrite a topic)
+//! - drop it, by returning `Ok(None)`: the interceptors after it do not see it
+//! - short-circuit with an error: an error on an incoming packet or message, or on a packet the network sends on its
+//!   own (an acknowledgement it answers the server with, a PINGREQ), closes the connection with a DISCONNECT (Reason
+//!   Code 0x83, Implementation specific error). An error on a packet handed to the network by a client refuses that
+//!   packet alone, like dropping it does
+//!
+//! The CONNECT and CONNACK of a handshake are not handed to the interceptors: the first ones are exchanged before the
+//! client they are registered on exists. Nor are the SUBACKs [`Network::reconnect`] waits for when it subscribes again,
+//! it could not return if one of them was dropped. The SUBSCRIBE packets it sends are, as is the DISCONNECT the network
+//! sends before closing the connection on a protocol error.
+//!
+//! [`Network::reconnect`]: super::network::asyncx::Network::reconnect
+//!
+//! ```ignore
+//! struct Audit;
+//!
+//! impl Interceptor for Audit {
+//!     fn outgoing(&self, packet: Packet) -> Result<Option<Packet>, InterceptError> {
+//!         log::info!("sending {:?}", packet.packet_type());
+//!         Ok(Some(packet))
+//!     }
+//! }
+//!
+//! client.intercept(Audit);
+//! ```
+use std::sync::{Arc, RwLock};
+
+use super::{error::InterceptError, Message};
+use crate::v5::commons::packet::Packet;
+
+pub trait Interceptor: Send + Sync {
+    /// A packet about to be written to the stream, before the session state handles it (e.g. to track its packet
+    /// identifier). Dropping a PUBLISH, SUBSCRIBE or UNSUBSCRIBE releases its packet identifier, dropping a PINGREQ
+    /// lets the keep alive time out. The packet handed on is checked like the ones of the client: changing its packet
+    /// identifier, making it larger than the Maximum Packet Size of the server, or publishing to a topic with
+    /// wildcards fails like returning an error does
+    fn outgoing(&self, packet: Packet) -> Result<Option<Packet>, InterceptError> {
+        Ok(Some(packet))
+    }
+
+    /// A packet read from the stream, before the session state handles it. A PUBLISH dropped here is not acknowledged,
+    /// the server sends it again on the next connection (QoS 1 and QoS 2). A PUBACK, PUBREC, PUBCOMP, SUBACK or
+    /// UNSUBACK dropped here is only hidden from the handler: the session state handles it as received, so that its
+    /// packet identifier is released
+    fn incoming(&self, packet: Packet) -> Result<Option<Packet>, InterceptError> {
+        Ok(Some(packet))
+    }
+
+    /// A message about to be delivered to the subscriptions and the handler of the network, once the session state
+    /// accepted it. A QoS 1 or QoS 2 message dropped here is acknowledged all the same, even with manual
+    /// acknowledgements
+    fn message(&self, message: Message) -> Result<Option<Message>, InterceptError> {
+        Ok(Some(message))
+    }
+}
+
+/// The interceptors registered on the clients of a network
+#[derive(Default)]
+pub(crate) struct Interceptors(RwLock<Vec<Arc<dyn Interceptor>>>);
+
+impl core::fmt::Debug for Interceptors {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        let count = self.0.read().unwrap().len();
+        f.debug_tuple("Interceptors").field(&count).finish()
+    }
+}
+
+impl Interceptors {
+    pub(crate) fn add(&self, interceptor: Arc<dyn Interceptor>) {
+        self.0.write().unwrap().push(interceptor);
+    }
+
+    /// Hands `value` from one interceptor to the next, until one of them drops it or fails
+    fn run<T>(
+        &self,
+        value: T,
+        hook: impl Fn(&dyn Interceptor, T) -> Result<Option<T>, InterceptError>,
+    ) -> Result<Option<T>, InterceptError> {
+        // the chain is copied, an interceptor may register another one
+        let chain = self.0.read().unwrap().clone();
+        let mut value = value;
+        for interceptor in chain {
+            match hook(interceptor.as_ref(), value)? {
+                Some(next) => value = next,
+                None => return Ok(None),
+            }
+        }
+        Ok(Some(value))
+    }
+
+    pub(crate) fn outgoing(&self, packet: Packet) -> Result<Option<Packet>, InterceptError> {
+        self.run(packet, |interceptor, packet| interceptor.outgoing(packet))
+    }
+
+    pub(crate) fn incoming(&self, packet: Packet) -> Result<Option<Packet>, InterceptError> {
+        self.run(packet, |interceptor, packet| interceptor.incoming(packet))
+    }
+
+    pub(crate) fn message(&self, message: Message) -> Result<Option<Message>, InterceptError> {
+        self.run(message, |interceptor, message| interceptor.message(message))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::v5::packet::{ping::PingReq, publish::Publish};
+
+    /// Appends its name to the topic of the messages, and drops the ones on `drop`
+    struct Tag(&'static str);
+
+    impl Interceptor for Tag {
+        fn message(&self, message: Message) -> Result<Option<Message>, InterceptError> {
+            let mut publish = message.into_publish();
+            if publish.topic == "drop" {
+                return Ok(None);
+            }
+            publish.topic.push_str(self.0);
+            Ok(Some(Message::from(publish)))
+        }
+    }
+
+    struct Refuse;
+
+    impl Interceptor for Refuse {
+        fn outgoing(&self, _packet: Packet) -> Result<Option<Packet>, InterceptError> {
+            Err(InterceptError(String::from("refused")))
+        }
+    }
+
+    fn message(topic: &str) -> Message {
+        Message::from(Publish {
+            topic: String::from(topic),
+            ..Default::default()
+        })
+    }
+
+    #[test]
+    fn runs_the_interceptors_in_the_order_they_were_registered() {
+        let interceptors = Interceptors::default();
+        interceptors.add(Arc::new(Tag("/a")));
+        interceptors.add(Arc::new(Tag("/b")));
+
+        let tagged = interceptors.message(message("sensors")).unwrap().unwrap();
+        assert_eq!(tagged.topic, "sensors/a/b");
+        assert_eq!(interceptors.message(message("drop")).unwrap(), None);
+        // hooks left alone hand the packet on
+        let pingreq = || Packet::PingReq(PingReq::default());
+        assert_eq!(interceptors.incoming(pingreq()).unwrap(), Some(pingreq()));
+    }
+
+    #[test]
+    fn stops_at_the_first_error() {
+        let interceptors = Interceptors::default();
+        interceptors.add(Arc::new(Refuse));
+        interceptors.add(Arc::new(Tag("/a")));
+
+        let pingreq = Packet::PingReq(PingReq::default());
+        assert_eq!(
+            interceptors.outgoing(pingreq),
+            Err(InterceptError(String::from("refused")))
+        );
+    }
+}
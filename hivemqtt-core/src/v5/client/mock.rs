@@ -0,0 +1,111 @@
+//! A scripted broker, for tests that need packets a real broker never sends.
+//!
+//! The script is a list of steps, played in order on the server half of a [`duplex`] stream:
+//! ```ignore
+//! let (stream, mock) = MockBroker::new()
+//!     .handshake(ConnAck::default())
+//!     .send(Packet::PubRel(PubRel { pkid: 5, ..Default::default() }))
+//!     .expect(Packet::PubComp(PubComp { pkid: 5, reason_code: PubCompReasonCode::PacketIdentifierNotFound, ..Default::default() }))
+//!     .spawn();
+//! let (mut network, client) = Network::new(options, stream).await?;
+//! ```
+//! A step that does not go as scripted panics on the broker's thread, and [`MockBroker::spawn`]'s handle re-raises it when joined.
+use std::{
+    thread::{self, JoinHandle},
+    time::Duration,
+};
+
+use async_io::Timer;
+use futures::{executor::block_on, select, AsyncWriteExt, FutureExt};
+
+use crate::v5::{
+    client::connector::{duplex, DuplexStream},
+    commons::packet::Packet,
+    packet::connack::ConnAck,
+};
+
+/// How long the broker waits for a packet it expects, before failing the test
+const EXPECT_TIMEOUT: Duration = Duration::from_secs(5);
+
+#[derive(Debug)]
+enum Step {
+    /// Waits for a CONNECT, whatever its content, and answers it with the CONNACK
+    Handshake(ConnAck),
+    /// The next packet from the client must be equal to this one
+    Expect(Packet),
+    /// Sends the packet to the client
+    Send(Packet),
+    /// Sends raw bytes to the client, for packets the codec cannot (or would not) encode
+    SendRaw(Vec<u8>),
+}
+
+#[derive(Debug, Default)]
+pub(crate) struct MockBroker {
+    steps: Vec<Step>,
+}
+
+impl MockBroker {
+    pub(crate) fn new() -> Self {
+        Self::default()
+    }
+
+    pub(crate) fn handshake(mut self, connack: ConnAck) -> Self {
+        self.steps.push(Step::Handshake(connack));
+        self
+    }
+
+    pub(crate) fn expect(mut self, packet: Packet) -> Self {
+        self.steps.push(Step::Expect(packet));
+        self
+    }
+
+    pub(crate) fn send(mut self, packet: Packet) -> Self {
+        self.steps.push(Step::Send(packet));
+        self
+    }
+
+    pub(crate) fn send_raw(mut self, bytes: impl Into<Vec<u8>>) -> Self {
+        self.steps.push(Step::SendRaw(bytes.into()));
+        self
+    }
+
+    /// Plays the script on its own thread, and returns the client's half of the connection.
+    /// The connection is closed (without a DISCONNECT) once the script is over
+    pub(crate) fn spawn(self) -> (DuplexStream, JoinHandle<()>) {
+        let (client, server) = duplex(4096);
+        let handle = thread::spawn(move || block_on(self.play(server)));
+        (client, handle)
+    }
+
+    async fn play(self, mut stream: DuplexStream) {
+        for (index, step) in self.steps.into_iter().enumerate() {
+            match step {
+                Step::Handshake(connack) => {
+                    let packet = recv(&mut stream, index).await;
+                    assert!(
+                        matches!(packet, Packet::Connect(_)),
+                        "step {index}: expected a CONNECT, received {packet:?}"
+                    );
+                    Packet::ConnAck(connack)
+                        .write_to(&mut stream)
+                        .await
+                        .unwrap();
+                }
+                Step::Expect(expected) => {
+                    let packet = recv(&mut stream, index).await;
+                    assert_eq!(packet, expected, "step {index}: unexpected packet");
+                }
+                Step::Send(packet) => packet.write_to(&mut stream).await.unwrap(),
+                Step::SendRaw(bytes) => stream.write_all(&bytes).await.unwrap(),
+            }
+        }
+    }
+}
+
+async fn recv(stream: &mut DuplexStream, index: usize) -> Packet {
+    select! {
+        packet = Packet::read_from(stream, usize::MAX).fuse() => packet
+            .unwrap_or_else(|e| panic!("step {index}: the client closed the connection or sent a malformed packet: {e:?}")),
+        _ = FutureExt::fuse(Timer::after(EXPECT_TIMEOUT)) => panic!("step {index}: timed out waiting for the client"),
+    }
+}
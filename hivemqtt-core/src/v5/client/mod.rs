@@ -1,19 +1,57 @@
-use std::{num::NonZero, u32};
+use core::num::NonZero;
 
+use alloc::{string::String, vec::Vec};
 use bytes::Bytes;
 
-use super::packet::connect::will::Will;
+use super::{commons::version::Version, packet::connect::will::Will};
 
-pub(crate) mod client;
+#[cfg(feature = "asyncx")]
+pub mod capture;
+#[cfg(feature = "std")]
+mod client;
+#[cfg(feature = "asyncx")]
+pub mod connector;
+#[cfg(feature = "std")]
+pub mod error;
+#[cfg(feature = "std")]
 pub mod handler;
+#[cfg(feature = "std")]
+pub mod interceptor;
+#[cfg(all(test, feature = "asyncx"))]
+pub(crate) mod mock;
+#[cfg(feature = "std")]
 pub mod network;
-pub(crate) mod packet_id;
-pub(crate) mod state;
+pub mod packet_id;
+#[cfg(feature = "serde")]
+pub mod payload;
+#[cfg(feature = "std")]
+mod routes;
+pub mod state;
+#[cfg(feature = "std")]
+pub mod stats;
+#[cfg(feature = "std")]
+mod subscription;
+
+#[cfg(feature = "std")]
+pub use client::MqttClient;
+#[cfg(feature = "std")]
+pub use subscription::{Message, Subscription};
 
 #[derive(Debug)]
 pub struct ConnectOptions {
-    /// Whether the user want's to handle all acks manually, or they want us to do this for them
+    /// Protocol version used on the first CONNECT attempt
+    pub version: Version,
+    /// Retry with MQTT 3.1.1 if the server refuses MQTT 5.0 with `UnsupportedProtocolVersion`.
+    /// The server closes the connection after refusing it, so this only applies when the client was created with a [`Connector`](connector::Connector)
+    pub fallback_to_v311: bool,
+    /// Whether the application acknowledges the QoS 1 and QoS 2 messages it receives itself, with
+    /// [`MqttClient::ack`] (e.g. once they are stored), rather than as soon as they are received.
+    /// The rest of the QoS 2 exchanges (PUBREL, PUBCOMP) is always handled by the client
     pub manual_ack: bool,
+    /// Report every protocol violation the client detects, on packets it sends or receives, as an
+    /// [`MQTTError::Conformance`](crate::v5::commons::error::MQTTError::Conformance) naming the normative statement broken (e.g. `MQTT-3.3.2-8`).
+    /// Also checks a few rules that are otherwise left alone (e.g. the DUP flag of QoS 0 PUBLISH packets)
+    pub strict: bool,
     pub clean_start: bool,
     /// 3.1.2.11.2
     pub session_expiry_interval: Option<u32>, // default value is 0
@@ -47,14 +85,23 @@ pub struct ConnectOptions {
     pub user_property: Vec<(String, String)>,
     pub authentication_method: Option<String>,
     pub authentication_data: Option<Bytes>,
+
+    /// Number of packets the clients can hand to the network before they have to wait for it to send them
+    pub outgoing_queue_size: NonZero<usize>,
+    /// Number of messages a subscription holds until the application takes them. Once it is full, the oldest message
+    /// is dropped to make room for the next one
+    pub incoming_queue_size: NonZero<usize>,
 }
 
 impl Default for ConnectOptions {
     fn default() -> Self {
         Self {
+            version: Version::V5,
+            fallback_to_v311: false,
             inbound_topic_alias_max: 0,
             outbound_topic_alias_max: 0,
             manual_ack: false,
+            strict: false,
             clean_start: true,
             session_expiry_interval: Some(0),
             client_receive_max: NonZero::<u16>::MAX, // connect
@@ -75,6 +122,9 @@ impl Default for ConnectOptions {
             user_property: Vec::with_capacity(0),
             authentication_method: None,
             authentication_data: None,
+
+            outgoing_queue_size: NonZero::new(100).unwrap(),
+            incoming_queue_size: NonZero::new(100).unwrap(),
         }
     }
 }
@@ -8,6 +8,8 @@ use super::{packet_id::PacketIdManager, ConnectOptions};
 
 #[cfg(feature = "asyncx")]
 pub mod asyncx;
+#[cfg(feature = "asyncx")]
+pub mod runtime;
 #[cfg(feature = "syncx")]
 pub mod syncx;
 
@@ -1,20 +1,63 @@
-use std::{sync::Arc, time::Instant};
-
-use async_channel::Receiver;
-use futures::{select, AsyncReadExt, AsyncWriteExt, FutureExt};
-
-use crate::v5::{
-    client::{client::MqttClient, handler::AsyncHandler, state::State, ConnectOptions},
-    commons::{error::MQTTError, packet::Packet, packet_type::PacketType},
-    packet::{
-        connack::{reason_code::ConnAckReasonCode, ConnAck},
-        connect::Connect,
-        ping::PingReq,
+use std::{
+    collections::{HashMap, VecDeque},
+    io,
+    marker::PhantomData,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use async_channel::{Receiver, Sender};
+use bytes::{Bytes, BytesMut};
+use futures::{future, select, AsyncRead, AsyncWrite, FutureExt};
+
+use crate::{
+    v311,
+    v5::{
+        client::{
+            client::MqttClient,
+            connector::Connector,
+            error::{ConnectError, RunError, SubscribeError},
+            handler::AsyncHandler,
+            interceptor::Interceptors,
+            routes::Routes,
+            state::State,
+            stats::{Direction, Exchange, ReconnectReason, Stats, StatsRecorder},
+            ConnectOptions, Message,
+        },
+        commons::{
+            conformance,
+            error::{DecodeError, MQTTError},
+            fixed_header::FixedHeader,
+            packet::{decode_frame, split_frame, Packet},
+            packet_type::PacketType,
+            qos::QoS,
+            version::Version,
+        },
+        packet::{
+            connack::{reason_code::ConnAckReasonCode, ConnAck},
+            connect::Connect,
+            disconnect::{Disconnect, DisconnectProperties, DisconnectReasonCode},
+            ping::PingReq,
+            puback::PubAck,
+            pubcomp::PubComp,
+            publish::Publish,
+            pubrec::PubRec,
+            suback::SubAckReasonCode,
+        },
+        traits::{
+            bufferio::BufferIO,
+            pkid_mgr::{PacketIdAlloc, PacketIdRelease},
+            utils::Utils,
+        },
     },
-    traits::streamio::StreamIO,
 };
 
-use super::PacketIdManager;
+#[cfg(feature = "tokio")]
+use super::runtime::Tokio;
+use super::{
+    runtime::{Futures, Runtime},
+    PacketIdManager,
+};
 
 pub enum NetworkStatus {
     IncomingDisconnect,
@@ -22,136 +65,2294 @@ pub enum NetworkStatus {
     Timeout,
 }
 
+/// A connection established again by [`Network::reconnect`]
 #[derive(Debug)]
-pub struct Network<S> {
+pub struct Reconnection {
+    pub connack: ConnAck,
+    /// The subscriptions made again because the server lost the session (Session Present is 0), one per topic filter
+    pub resubscribed: Vec<Resubscription>,
+}
+
+/// What became of a subscription made again after the server lost the session
+#[derive(Debug)]
+pub struct Resubscription {
+    pub filter: String,
+    /// The Reason Code the server answered with in the SUBACK, or why the SUBSCRIBE could not be sent
+    pub result: Result<SubAckReasonCode, SubscribeError>,
+}
+
+/// Runs the MQTT connection over a stream `S`, driven by the [`Runtime`] `R`: [`Futures`] for the streams implementing
+/// the `futures` IO traits, or, with the `tokio` feature, `Tokio` for those implementing tokio's
+#[derive(Debug)]
+pub struct Network<S, R = Futures> {
     stream: S,
     options: ConnectOptions,
+    /// The protocol version in use, this is only different from `options.version` after falling back to MQTT 3.1.1
+    version: Version,
     state: State<PacketIdManager>,
     rx: Receiver<Packet>,
+    /// Bytes received from the server, and not decoded yet
+    read_buf: BytesMut,
+    /// Every event of the connection is recorded within this span
+    #[cfg(feature = "tracing")]
+    span: tracing::Span,
+    stats: Arc<StatsRecorder>,
+    /// When the QoS 1 and QoS 2 PUBLISH packets awaiting their PUBACK or PUBCOMP were sent
+    publish_sent: HashMap<u16, Instant>,
+    /// How the last run of the network ended (this is the reason of the next reconnection), shared with the clients
+    exit: Arc<Mutex<Option<ReconnectReason>>>,
+    /// Packets received while waiting for the SUBACKs of a resubscription, handled by the next run
+    held: VecDeque<Packet>,
+    /// The subscribe calls of the clients, to deliver the messages received to
+    routes: Arc<Routes>,
+    /// Registered by the clients, run on the packets exchanged with the server and the messages delivered
+    interceptors: Arc<Interceptors>,
+    runtime: PhantomData<R>,
 }
 
 impl<S> Network<S>
 where
-    S: AsyncReadExt + AsyncWriteExt + Unpin,
+    S: AsyncRead + AsyncWrite + Unpin,
 {
     pub async fn new(
         options: ConnectOptions,
         stream: S,
-    ) -> Result<(Self, MqttClient<PacketIdManager>), MQTTError> {
-        let max_size = options.server_max_size.get() as usize;
+    ) -> Result<(Self, MqttClient), ConnectError> {
+        Self::start(options, stream).await
+    }
+
+    /// Opens a stream with the `connector`, and establishes the MQTT connection over it.
+    /// Falls back to MQTT 3.1.1 if `options.fallback_to_v311` is set and the server does not support MQTT 5.0
+    pub async fn connect_with<C>(
+        options: ConnectOptions,
+        connector: &C,
+    ) -> Result<(Self, MqttClient), ConnectError>
+    where
+        C: Connector<Stream = S>,
+    {
+        Self::start_with(options, connector).await
+    }
+}
+
+#[cfg(feature = "tokio")]
+impl<S> Network<S, Tokio>
+where
+    S: tokio::io::AsyncRead + tokio::io::AsyncWrite + Unpin,
+{
+    /// Same as [`Network::new`], over a stream implementing tokio's `AsyncRead` and `AsyncWrite` (e.g.
+    /// `tokio::net::TcpStream`). The network runs on tokio's timers, within a tokio runtime
+    pub async fn new_tokio(
+        options: ConnectOptions,
+        stream: S,
+    ) -> Result<(Self, MqttClient), ConnectError> {
+        Self::start(options, stream).await
+    }
+
+    /// Same as [`Network::connect_with`], with a connector of tokio streams (e.g.
+    /// [`TokioTcpConnector`](crate::v5::client::connector::TokioTcpConnector)), which [`Network::reconnect`] takes too
+    pub async fn connect_tokio<C>(
+        options: ConnectOptions,
+        connector: &C,
+    ) -> Result<(Self, MqttClient), ConnectError>
+    where
+        C: Connector<Stream = S>,
+    {
+        Self::start_with(options, connector).await
+    }
+}
+
+impl<S, R> Network<S, R>
+where
+    R: Runtime<S>,
+{
+    fn with_stream(options: ConnectOptions, stream: S) -> (Self, Sender<Packet>) {
         let state = State::from(&options);
 
-        let (tx, rx) = async_channel::bounded::<Packet>(100); // receive_max + send_max
+        let (tx, rx) = async_channel::bounded::<Packet>(options.outgoing_queue_size.get());
+        let routes = Arc::new(Routes::new(options.incoming_queue_size.get()));
+        #[cfg(feature = "tracing")]
+        let span = tracing::info_span!(
+            "mqtt",
+            client_id = %options.client_id,
+            broker = tracing::field::Empty,
+            version = ?options.version,
+        );
 
-        let mut network = Self {
+        let network = Self {
             stream,
+            version: options.version,
             options,
             // pkids,
             state,
             rx,
+            read_buf: BytesMut::new(),
+            #[cfg(feature = "tracing")]
+            span,
+            stats: Arc::default(),
+            publish_sent: HashMap::new(),
+            exit: Arc::default(),
+            held: VecDeque::new(),
+            routes,
+            interceptors: Arc::default(),
+            runtime: PhantomData,
         };
 
-        let connack = network.connect().await?;
+        (network, tx)
+    }
+
+    fn client(&mut self, tx: Sender<Packet>, connack: &ConnAck) -> MqttClient {
         let server_receive_max = connack.properties.receive_maximum.unwrap_or(100);
         let pkids = Arc::new(PacketIdManager::new(server_receive_max));
 
-        network.state.pkid_mgr = Some(pkids.clone());
+        self.state.set_pkid_mgr(pkids.clone());
+
+        let (stats, exit, routes) = (self.stats.clone(), self.exit.clone(), self.routes.clone());
+        let interceptors = self.interceptors.clone();
+        MqttClient::new(tx, pkids, stats, exit, routes, interceptors, &self.options)
+    }
+
+    async fn start(options: ConnectOptions, stream: S) -> Result<(Self, MqttClient), ConnectError> {
+        let (mut network, tx) = Self::with_stream(options, stream);
+
+        let connack = network.connect().await?;
+        let client = network.client(tx, &connack);
+
+        Ok((network, client))
+    }
+
+    async fn start_with<C>(
+        options: ConnectOptions,
+        connector: &C,
+    ) -> Result<(Self, MqttClient), ConnectError>
+    where
+        C: Connector<Stream = S>,
+    {
+        let stream = connector.connect().await?;
+        let (mut network, tx) = Self::with_stream(options, stream);
+        #[cfg(feature = "tracing")]
+        network.span.record("broker", connector.broker().as_deref());
+
+        let connack = network.connect_or_fallback(connector).await?;
+        let client = network.client(tx, &connack);
+
+        Ok((network, client))
+    }
+
+    /// Replaces the current stream with a fresh one from the `connector` and sends a new CONNECT on it.
+    /// The state of the previous connection (in-flight packets, topic aliases, packet identifiers) is retained.
+    /// If the server resumed the session, the PUBLISH and PUBREL packets it did not acknowledge are sent again.
+    /// If it lost the session, every subscription is made again, and this returns once the server acknowledged them
+    pub async fn reconnect<C>(&mut self, connector: &C) -> Result<Reconnection, ConnectError>
+    where
+        C: Connector<Stream = S>,
+    {
+        let reason = self.exit.lock().unwrap().unwrap_or(ReconnectReason::Unknown);
+        self.stats.reconnect(reason);
+
+        self.stream = connector.connect().await?;
+        self.held.clear();
+        let connack = self.connect_or_fallback(connector).await?;
+        let resubscribed = match connack.session_present {
+            true => {
+                self.retransmit().await?;
+                Vec::new()
+            }
+            false => instrument!(self.span, self.resubscribe(&connack)).await?,
+        };
+        *self.exit.lock().unwrap() = None;
+        self.open_pkids();
+
+        Ok(Reconnection {
+            connack,
+            resubscribed,
+        })
+    }
+
+    /// Sends the PUBLISH and PUBREL packets of a resumed session again, see [`State::retransmit`]
+    async fn retransmit(&mut self) -> Result<(), ConnectError> {
+        let packets = self.state.retransmit();
+        if packets.is_empty() {
+            return Ok(());
+        }
+        debug!(packets = packets.len(), "session resumed, sending the packets in flight again");
 
-        Ok((network, MqttClient::new(tx, pkids, max_size)))
+        for packet in packets {
+            // encoded first, so that IO errors keep their source
+            let mut buf = BytesMut::new();
+            encode_packet(self.version, &packet, &mut buf)?;
+            packet!("outgoing", &packet, buf.len());
+            self.stats.packet(Direction::Outgoing, packet.packet_type(), buf.len());
+            R::write_all(&mut self.stream, &buf).await?;
+        }
+        R::flush(&mut self.stream).await?;
+        Ok(())
     }
 
-    async fn connect(&mut self) -> Result<ConnAck, MQTTError> {
-        Packet::Connect(Connect::from(&self.options))
-            .write(&mut self.stream)
-            .await?;
+    /// Sends the SUBSCRIBE packets making the subscriptions of a lost session again, and waits for their SUBACK.
+    /// Whatever else the server sends in the meantime is held for the next run
+    async fn resubscribe(&mut self, connack: &ConnAck) -> Result<Vec<Resubscription>, ConnectError> {
+        let max_size = self.options.server_max_size.get();
+        let max_size = connack.properties.maximum_packet_size.map_or(max_size, |m| m.min(max_size));
+        let batches = self.state.resubscribe(max_size as usize);
+        self.publish_sent.clear();
+        if !batches.is_empty() {
+            debug!(packets = batches.len(), "session lost, subscribing again");
+        }
+
+        let mut resubscribed = Vec::new();
+        let mut awaiting = HashMap::new();
+        for mut subscribe in batches {
+            let filters = subscribe.payload.iter().map(|(filter, _)| filter.clone());
+            let filters = filters.collect::<Vec<_>>();
+            let pkid = match (subscribe.is_valid(max_size as usize), self.state.pkid_mgr()) {
+                (Err(error), _) => Err(error),
+                // the clients waiting for a packet identifier come after the subscriptions
+                (Ok(()), Some(pkids)) => pkids.take().ok_or(MQTTError::PacketIdGenerationError),
+                (Ok(()), None) => Err(MQTTError::PacketIdGenerationError),
+            };
+            let pkid = match pkid {
+                Ok(pkid) => pkid,
+                Err(error) => {
+                    warn!(%error, "could not subscribe again");
+                    let error = || match error {
+                        MQTTError::MaxPacketSizeExceed(size) => SubscribeError::TooLarge(size),
+                        _ => SubscribeError::PacketIdsExhausted,
+                    };
+                    resubscribed.extend(filters.into_iter().map(|filter| Resubscription {
+                        filter,
+                        result: Err(error()),
+                    }));
+                    continue;
+                }
+            };
+            subscribe.pkid = pkid;
+
+            // the interceptors see it like any other SUBSCRIBE, they may drop it or refuse it
+            let subscribe = match self.intercept_outgoing(Packet::Subscribe(subscribe)) {
+                Ok(Some(subscribe)) => subscribe,
+                intercepted => {
+                    let reason = match intercepted {
+                        Err(RunError::Protocol(error)) => match *error {
+                            MQTTError::Intercepted(reason) => reason,
+                            error => error.to_string(),
+                        },
+                        Err(error) => error.to_string(),
+                        Ok(_) => String::from("the SUBSCRIBE was dropped"),
+                    };
+                    warn!(%reason, "an interceptor stopped subscribing again");
+                    resubscribed.extend(filters.into_iter().map(|filter| {
+                        let error = MQTTError::Intercepted(reason.clone());
+                        Resubscription {
+                            filter,
+                            result: Err(SubscribeError::Other(Box::new(error))),
+                        }
+                    }));
+                    continue;
+                }
+            };
+
+            // encoded first, so that IO errors keep their source
+            let mut buf = BytesMut::new();
+            encode_packet(self.version, &subscribe, &mut buf)?;
+            packet!("outgoing", &subscribe, buf.len());
+            self.stats.packet(Direction::Outgoing, PacketType::Subscribe, buf.len());
+            R::write_all(&mut self.stream, &buf).await?;
+            self.state.handle_outgoing_packet(subscribe)?;
+            awaiting.insert(pkid, filters);
+        }
+        R::flush(&mut self.stream).await?;
+
+        let max_size = self.options.client_max_size.get() as usize;
+        while !awaiting.is_empty() {
+            let (header, body) = self
+                .read_frame(max_size)
+                .await
+                .map_err(|e| self.strict_decode_error(e))?;
+            let mut packet = decode_packet(self.version, &self.stats, header, body)
+                .map_err(|e| self.strict_decode_error(e.into()))?;
+
+            let Packet::SubAck(suback) = &packet else {
+                self.held.push_back(packet);
+                continue;
+            };
+            let Some(filters) = awaiting.remove(&suback.pkid) else {
+                self.held.push_back(packet);
+                continue;
+            };
 
-        let packet = Packet::read(&mut self.stream).await?;
+            let reasons = suback.payload.clone();
+            self.state.handle_incoming_packet(&mut packet)?;
+            resubscribed.extend(filters.into_iter().zip(reasons).map(|(filter, reason)| {
+                Resubscription {
+                    filter,
+                    result: Ok(reason),
+                }
+            }));
+        }
+
+        Ok(resubscribed)
+    }
+
+    /// The protocol version negotiated with the server
+    pub fn version(&self) -> Version {
+        self.version
+    }
+
+    /// A snapshot of the statistics of the client
+    pub fn stats(&self) -> Stats {
+        self.stats.snapshot(self.rx.len())
+    }
+
+    async fn connect_or_fallback<C>(&mut self, connector: &C) -> Result<ConnAck, ConnectError>
+    where
+        C: Connector<Stream = S>,
+    {
+        match self.connect().await {
+            Err(ConnectError::Refused(connack))
+                if connack.reason == ConnAckReasonCode::UnsupportedProtocolVersion
+                    && self.version == Version::V5
+                    && self.options.fallback_to_v311 =>
+            {
+                // the server closes the network connection after refusing the protocol version
+                self.version = Version::V4;
+                #[cfg(feature = "tracing")]
+                self.span.record("version", tracing::field::debug(self.version));
+                debug!(parent: &self.span, "falling back to MQTT 3.1.1");
+                self.stream = connector.connect().await?;
+                self.connect().await
+            }
+            result => result,
+        }
+    }
+
+    async fn connect(&mut self) -> Result<ConnAck, ConnectError> {
+        instrument!(self.span, self.handshake()).await
+    }
 
-        let Packet::ConnAck(connack) = packet else {
-            return Err(MQTTError::ConnectionError); // this needs to be return an Error that contains the packet received
+    async fn handshake(&mut self) -> Result<ConnAck, ConnectError> {
+        let mut connect = Connect::from(&self.options);
+        connect.version = self.version;
+        self.read_buf.clear();
+
+        // encoded first, so that IO errors keep their source
+        let mut buf = BytesMut::new();
+        let connect = Packet::Connect(connect);
+        encode_packet(self.version, &connect, &mut buf)?;
+        packet!("outgoing", &connect, buf.len());
+        self.stats.packet(Direction::Outgoing, PacketType::Connect, buf.len());
+        R::write_all(&mut self.stream, &buf).await?;
+        R::flush(&mut self.stream).await?;
+
+        let max_size = self.options.client_max_size.get() as usize;
+        let (header, body) = self
+            .read_frame(max_size)
+            .await
+            .map_err(|e| self.strict_decode_error(e))?;
+
+        // An MQTT 3.1.1 server answers an MQTT 5.0 CONNECT with its own CONNACK, with the return code 0x01 (unacceptable protocol version)
+        if self.version == Version::V5
+            && header.packet_type == PacketType::ConnAck
+            && body.len() == 2
+            && body[1] == 0x01
+        {
+            return Err(ConnectError::Refused(Box::new(ConnAck {
+                reason: ConnAckReasonCode::UnsupportedProtocolVersion,
+                ..Default::default()
+            })));
+        }
+
+        let packet = decode_packet(self.version, &self.stats, header, body)
+            .map_err(|e| self.strict_decode_error(e.into()))?;
+        let connack = match packet {
+            Packet::ConnAck(connack) => connack,
+            packet => return Err(ConnectError::UnexpectedPacket(Box::new(packet))),
         };
 
         if connack.reason == ConnAckReasonCode::Success {
+            debug!(session_present = connack.session_present, "connected");
+            // Subscription Identifiers are supported unless the server says otherwise (3.2.2.3.12)
+            let subscription_ids = connack.properties.subscription_identifiers_available;
+            let subscription_ids = self.version == Version::V5 && subscription_ids != Some(false);
+            self.routes.connected(subscription_ids);
             return Ok(connack);
         }
 
-        Err(MQTTError::ConnectionRefused(connack.reason.into()))
+        warn!(reason_code = %connack.reason, "connection refused");
+        Err(ConnectError::Refused(Box::new(connack)))
+    }
+
+    /// Reads off the stream until the read buffer holds a whole packet, and splits it off.
+    /// Packets larger than `max_size` bytes are refused as soon as their fixed header is received
+    async fn read_frame(&mut self, max_size: usize) -> Result<(FixedHeader, Bytes), DecodeError> {
+        let mut chunk = [0u8; 4096];
+        loop {
+            if let Some(frame) = split_frame(&mut self.read_buf, max_size)? {
+                return Ok(frame);
+            }
+            match R::read(&mut self.stream, &mut chunk).await? {
+                0 => return Err(io::Error::from(io::ErrorKind::UnexpectedEof).into()),
+                n => self.read_buf.extend_from_slice(&chunk[..n]),
+            }
+        }
+    }
+
+    /// Decodes the next packet of the read buffer, if it was received whole
+    fn next_packet(&mut self, max_size: usize) -> Result<Option<Packet>, MQTTError> {
+        let frame = split_frame(&mut self.read_buf, max_size).map_err(|e| match e {
+            MQTTError::MaxPacketSizeExceed(_) if self.options.strict => {
+                conformance::SERVER_PACKET_TOO_LARGE.into()
+            }
+            e => self.strict_error(e),
+        })?;
+
+        frame
+            .map(|(header, body)| decode_packet(self.version, &self.stats, header, body))
+            .transpose()
+            .map_err(|e| self.strict_error(e))
+    }
+
+    /// In strict mode, a packet that could not be decoded is reported as the normative statement it breaks, if any
+    fn strict_error(&self, error: MQTTError) -> MQTTError {
+        match error.broken_rule() {
+            Some(rule) if self.options.strict => rule.into(),
+            _ => error,
+        }
+    }
+
+    /// [`Self::strict_error`], for the packets read off the stream as a whole
+    fn strict_decode_error(&self, error: DecodeError) -> DecodeError {
+        match error {
+            DecodeError::Malformed(error) | DecodeError::Protocol(error) => self.strict_error(error).into(),
+            error => error,
+        }
+    }
+
+    /// Tells the server why the connection is about to be closed (4.13), and closes it.
+    /// `error` is handed back, to be reported to the application
+    async fn disconnect_with(&mut self, error: MQTTError) -> RunError {
+        warn!(
+            reason_code = %DisconnectReasonCode::from(&error),
+            %error,
+            "protocol error, disconnecting"
+        );
+
+        // MQTT 3.1.1 has no reason codes, a DISCONNECT would only tell the server to discard the Will Message
+        if self.version == Version::V5 {
+            let mut disconnect = Disconnect {
+                reason_code: DisconnectReasonCode::from(&error),
+                properties: DisconnectProperties {
+                    reason_string: Some(error.to_string()),
+                    ..Default::default()
+                },
+            };
+            // The sender MUST NOT send this Property if it would increase the size of the DISCONNECT packet beyond the Maximum Packet Size specified by the receiver [MQTT-3.14.2-3]
+            if disconnect.is_valid(self.options.server_max_size.get() as usize).is_err() {
+                disconnect.properties.reason_string = None;
+            }
+
+            // the connection is closed either way, an interceptor dropping the DISCONNECT, or failing to say why,
+            // changes nothing
+            if let Ok(Some(disconnect)) = self.intercept_outgoing(Packet::Disconnect(disconnect)) {
+                let (version, stats) = (self.version, &self.stats);
+                let _ = write_packet::<S, R>(&mut self.stream, version, stats, &disconnect).await;
+            }
+        }
+        let _ = R::close(&mut self.stream).await;
+
+        error.into()
+    }
+
+    /// Runs the interceptors on a packet about to be sent, and releases the packet identifier of a packet they drop.
+    /// What they hand on is checked like the packets of the client are: it must not be over the Maximum Packet Size of
+    /// the server, nor publish to a topic with wildcards, and it keeps the packet identifier it was allocated
+    fn intercept_outgoing(&self, packet: Packet) -> Result<Option<Packet>, RunError> {
+        let pkid = allocated_pkid(&packet);
+        let intercepted = self
+            .interceptors
+            .outgoing(packet)
+            .map_err(RunError::from)
+            .and_then(|packet| match packet {
+                Some(packet) => self.check_intercepted(&packet, pkid).map(|_| Some(packet)),
+                None => Ok(None),
+            });
+        if let (Ok(None) | Err(_), Some(pkid), Some(pkids)) = (&intercepted, pkid, self.state.pkid_mgr()) {
+            pkids.release(pkid);
+        }
+        intercepted
+    }
+
+    /// [`Self::intercept_outgoing`], for the packets the network sends on its own (the acknowledgements it answers the
+    /// server with, the PINGREQ of the keep alive): one refused by an interceptor closes the connection with a DISCONNECT
+    async fn intercept_or_disconnect(&mut self, packet: Packet) -> Result<Option<Packet>, RunError> {
+        match self.intercept_outgoing(packet) {
+            Err(RunError::Protocol(error)) => Err(self.disconnect_with(*error).await),
+            intercepted => intercepted,
+        }
     }
 
-    pub async fn run<H>(&mut self, handler: &mut H) -> Result<NetworkStatus, MQTTError>
+    fn check_intercepted(&self, packet: &Packet, pkid: Option<u16>) -> Result<(), RunError> {
+        if allocated_pkid(packet) != pkid {
+            let reason = "an interceptor changed the Packet Identifier of a packet";
+            return Err(MQTTError::Intercepted(String::from(reason)).into());
+        }
+
+        let max_size = self.options.server_max_size.get() as usize;
+        let strict = self.options.strict;
+        let valid = match packet {
+            Packet::Connect(packet) => packet.is_valid(max_size),
+            Packet::ConnAck(packet) => packet.is_valid(max_size),
+            Packet::Publish(packet) => packet.is_valid(max_size),
+            Packet::PubAck(packet) => packet.is_valid(max_size),
+            Packet::PubRec(packet) => packet.is_valid(max_size),
+            Packet::PubRel(packet) => packet.is_valid(max_size),
+            Packet::PubComp(packet) => packet.is_valid(max_size),
+            Packet::Subscribe(packet) => packet.is_valid(max_size),
+            Packet::SubAck(packet) => packet.is_valid(max_size),
+            Packet::UnSubscribe(packet) => packet.is_valid(max_size),
+            Packet::UnSubAck(packet) => packet.is_valid(max_size),
+            Packet::PingReq(packet) => packet.is_valid(max_size),
+            Packet::PingResp(packet) => packet.is_valid(max_size),
+            Packet::Disconnect(packet) => packet.is_valid(max_size),
+            Packet::Auth(packet) => packet.is_valid(max_size),
+        };
+        valid.map_err(|e| match e {
+            MQTTError::MaxPacketSizeExceed(_) if strict => conformance::CLIENT_PACKET_TOO_LARGE.into(),
+            e => e,
+        })?;
+
+        if let Packet::Publish(publish) = packet {
+            publish.validate_topic(&publish.topic).map_err(|e| match strict {
+                true => conformance::TOPIC_NAME_WILDCARD.into(),
+                false => e,
+            })?;
+        }
+        Ok(())
+    }
+
+    /// The UNSUBSCRIBE packets of dropped subscriptions the network was left to send, see [`Routes::take_deferred`]
+    fn take_deferred(&self) -> impl Iterator<Item = Packet> {
+        let pkids = self.state.pkid_mgr();
+        let deferred = self.routes.take_deferred(|| pkids?.allocate().ok());
+        deferred.into_iter().map(Packet::UnSubscribe)
+    }
+
+    /// Sends a packet handed to the network, unless the interceptors drop it or refuse it, and tells whether it was
+    /// sent. A packet refused by an interceptor is dropped alone, the connection is kept
+    async fn send(&mut self, packet: Packet) -> Result<bool, RunError> {
+        let packet = match self.intercept_outgoing(packet) {
+            Ok(Some(packet)) => packet,
+            Ok(None) => return Ok(false),
+            #[cfg_attr(not(feature = "tracing"), allow(unused_variables))]
+            Err(RunError::Protocol(error)) => {
+                warn!(%error, "an interceptor refused a packet, it is not sent");
+                return Ok(false);
+            }
+            Err(error) => return Err(error),
+        };
+        write_packet::<S, R>(&mut self.stream, self.version, &self.stats, &packet).await?;
+        self.time_exchange(&packet, Direction::Outgoing);
+        self.state.handle_outgoing_packet(packet)?;
+        Ok(true)
+    }
+
+    pub async fn run<H>(&mut self, handler: &mut H) -> Result<NetworkStatus, RunError>
     where
         H: AsyncHandler,
     {
-        let mut last_ping = if self.options.keep_alive > 0 {
-            Some(Instant::now())
-        } else {
-            None
+        *self.exit.lock().unwrap() = None;
+        self.open_pkids();
+        let result = instrument!(self.span, self.run_loop(handler)).await;
+
+        *self.exit.lock().unwrap() = Some(match &result {
+            Ok(NetworkStatus::IncomingDisconnect) => ReconnectReason::ServerDisconnect,
+            Ok(NetworkStatus::OutgoingDisconnect) => ReconnectReason::ClientDisconnect,
+            Ok(NetworkStatus::Timeout) => ReconnectReason::KeepAliveTimeout,
+            Err(RunError::Io(_)) => ReconnectReason::ConnectionLost,
+            Err(_) => ReconnectReason::ProtocolError,
+        });
+        // nothing releases a packet identifier until the network runs again
+        if let Some(pkids) = self.state.pkid_mgr() {
+            pkids.close();
+        }
+
+        result
+    }
+
+    fn open_pkids(&self) {
+        if let Some(pkids) = self.state.pkid_mgr() {
+            pkids.open();
+        }
+    }
+
+    /// Starts the clock of the exchanges whose latency is measured, and stops it once they are over
+    fn time_exchange(&mut self, packet: &Packet, direction: Direction) {
+        let ended = match (direction, packet) {
+            (Direction::Outgoing, Packet::Publish(publish)) => {
+                if let Some(pkid) = publish.pkid {
+                    self.publish_sent.insert(pkid, Instant::now());
+                }
+                None
+            }
+            (Direction::Incoming, Packet::PubAck(PubAck { pkid, .. }))
+            | (Direction::Incoming, Packet::PubComp(PubComp { pkid, .. })) => {
+                self.publish_sent.remove(pkid)
+            }
+            // the exchange ends early if the PUBLISH is refused
+            (Direction::Incoming, Packet::PubRec(pubrec)) if pubrec.reason_code.is_error() => {
+                self.publish_sent.remove(&pubrec.pkid);
+                None
+            }
+            _ => None,
         };
 
-        let keep_alive = self.options.keep_alive;
-        let mut expecting_pingresp = false;
-        let max_timeout = keep_alive as u64 * (3 / 2);
+        if let Some(sent) = ended {
+            self.stats.latency(Exchange::Publish, sent.elapsed());
+        }
+    }
 
-        // let result = self.state.handle_incoming_packet(&mut data);
-        // result.unwrap().unwrap().write(&mut self.stream);
+    async fn run_loop<H>(&mut self, handler: &mut H) -> Result<NetworkStatus, RunError>
+    where
+        H: AsyncHandler,
+    {
+        let keep_alive = Duration::from_secs(self.options.keep_alive as u64);
+        // If the Client does not receive a PINGRESP packet within a reasonable amount of time after it has sent a PINGREQ,
+        // it SHOULD close the Network Connection to the Server (3.1.2.10)
+        let max_timeout = keep_alive * 3 / 2;
+        let max_size = self.options.client_max_size.get() as usize;
 
-        loop {
-            select! {
-                // receiving incoming packets
-                incoming = Packet::read(&mut self.stream).fuse() => {
-                    let mut packet = incoming?;
+        let mut last_sent = Instant::now();
+        let mut pingreq_sent: Option<Instant> = None;
+        let mut chunk = [0u8; 4096];
 
-                    match packet {
-                        Packet::PingResp(_) => {
-                            handler.handle(packet).await;
-                            expecting_pingresp = false;
-                            last_ping = Some(Instant::now());
+        loop {
+            // packets already received are handled before waiting on anything else
+            let incoming = match self.held.pop_front() {
+                Some(packet) => Ok(Some(packet)),
+                None => self.next_packet(max_size),
+            };
+            let incoming = match incoming {
+                Ok(incoming) => incoming,
+                Err(error) => return Err(self.disconnect_with(error).await),
+            };
+            if let Some(packet) = incoming {
+                // an acknowledgement the interceptors drop is hidden from the handler only, the session state still
+                // releases the packet identifier it acknowledges
+                let copy = copy_acknowledgement(&packet);
+                let (mut packet, hidden) = match self.interceptors.incoming(packet) {
+                    Ok(Some(packet)) => (packet, false),
+                    Ok(None) => match copy {
+                        Some(packet) => (packet, true),
+                        None => continue,
+                    },
+                    Err(error) => return Err(self.disconnect_with(error.into()).await),
+                };
+                self.time_exchange(&packet, Direction::Incoming);
+                match packet {
+                    Packet::PingResp(_) => {
+                        handler.handle(packet).await;
+                        if let Some(sent) = pingreq_sent.take() {
+                            self.stats.latency(Exchange::Ping, sent.elapsed());
                         }
-                        Packet::Disconnect(_) => {
+                    }
+                    Packet::Disconnect(_) => {
+                        handler.handle(packet).await;
+                        return Ok(NetworkStatus::IncomingDisconnect);
+                    }
+                    _ => {
+                        let duplicate = self.state.is_duplicate_publish(&packet);
+                        let result = match self.state.handle_incoming_packet(&mut packet) {
+                            Ok(result) => result,
+                            Err(error) => return Err(self.disconnect_with(error).await),
+                        };
+                        let (outgoing, incoming) = self.state.in_flight();
+                        self.stats.in_flight(outgoing, incoming);
+                        // a message refused for a payload that is not what its format indicator says is not delivered
+                        let refused = match &packet {
+                            Packet::Publish(publish) => publish.check_payload_format().is_err(),
+                            _ => false,
+                        };
+                        let delivered = match packet {
+                            _ if duplicate || refused || hidden => None,
+                            Packet::Publish(publish) => {
+                                let ack = acknowledgement(&publish);
+                                match self.interceptors.message(Message::from(publish)) {
+                                    Ok(Some(message)) => {
+                                        Some(Packet::Publish(message.into_publish()))
+                                    }
+                                    Ok(None) => {
+                                        // the application cannot acknowledge a message it never gets
+                                        if let Some(ack) = ack.filter(|_| self.options.manual_ack) {
+                                            for ack in self.state.order_ack(ack) {
+                                                self.send(ack).await?;
+                                            }
+                                            R::flush(&mut self.stream).await?;
+                                            last_sent = Instant::now();
+                                        }
+                                        None
+                                    }
+                                    Err(error) => {
+                                        return Err(self.disconnect_with(error.into()).await)
+                                    }
+                                }
+                            }
+                            packet => Some(packet),
+                        };
+                        if let Some(packet) = delivered {
+                            if let Packet::Publish(publish) = &packet {
+                                // a subscription the application does not read from does not hold back the
+                                // network: its oldest message makes room. A dropped receiver is no longer interested
+                                for (tx, copy) in self.routes.deliver(publish) {
+                                    if let Ok(Some(_)) = tx.force_send(copy) {
+                                        warn!(
+                                            topic = %publish.topic,
+                                            "subscription full, its oldest message was dropped"
+                                        );
+                                    }
+                                }
+                            }
                             handler.handle(packet).await;
-                            return Ok(NetworkStatus::IncomingDisconnect)
                         }
-                        _ => {
-                            let result = self.state.handle_incoming_packet(&mut packet)?;
-                            last_ping = Some(Instant::now());
-                            handler.handle(packet).await;
 
-                            if let Some(response) = result {
-                                response.write(&mut self.stream).await?;
+                        if let Some(response) = result {
+                            if let Some(response) = self.intercept_or_disconnect(response).await? {
+                                let (version, stats) = (self.version, &self.stats);
+                                write_packet::<S, R>(&mut self.stream, version, stats, &response).await?;
+                                R::flush(&mut self.stream).await?;
+                                last_sent = Instant::now();
+                            }
+                        }
+
+                        // an acknowledgement may have released the packet identifier a dropped subscription waits
+                        // for to be unsubscribed
+                        if self.rx.is_empty() {
+                            let mut sent = false;
+                            for packet in self.take_deferred().collect::<Vec<_>>() {
+                                sent |= self.send(packet).await?;
+                            }
+                            if sent {
+                                R::flush(&mut self.stream).await?;
+                                last_sent = Instant::now();
                             }
                         }
                     }
+                }
+                continue;
+            }
+
+            // a Keep Alive of 0 turns the keep alive mechanism off (3.1.2.10)
+            let keep_alive_timer = async {
+                match pingreq_sent {
+                    _ if keep_alive.is_zero() => future::pending().await,
+                    Some(sent) => R::sleep_until(sent + max_timeout).await,
+                    None => R::sleep_until(last_sent + keep_alive).await,
+                }
+            };
+
+            select! {
+                // a single read is cancel safe: nothing is lost when another branch completes first
+                read = R::read(&mut self.stream, &mut chunk).fuse() => {
+                    match read? {
+                        0 => return Err(io::Error::from(io::ErrorKind::UnexpectedEof).into()),
+                        n => self.read_buf.extend_from_slice(&chunk[..n]),
+                    }
                 },
                 outgoing = self.rx.recv().fuse() => {
                     let packet = outgoing?;
+                    self.stats.queued(self.rx.len());
 
-                    let disconnect = packet.packet_type() == PacketType::Disconnect;
-
-                    packet.write(&mut self.stream).await?;
-                    self.state.handle_outgoing_packet(packet)?;
-                    last_ping = Some(Instant::now());
+                    // acknowledgements sent by the application are held until they can be sent in order
+                    let mut packets = self.state.order_ack(packet);
+                    // the UNSUBSCRIBE packets of dropped subscriptions that did not fit in the queue follow the
+                    // packets that were in it
+                    if self.rx.is_empty() {
+                        packets.extend(self.take_deferred());
+                    }
+                    let mut disconnect = false;
+                    for packet in packets {
+                        let packet_type = packet.packet_type();
+                        if self.send(packet).await? {
+                            disconnect |= packet_type == PacketType::Disconnect;
+                            last_sent = Instant::now();
+                        }
+                    }
+                    R::flush(&mut self.stream).await?;
+                    let (outgoing, incoming) = self.state.in_flight();
+                    self.stats.in_flight(outgoing, incoming);
 
                     if disconnect {
                         return Ok(NetworkStatus::OutgoingDisconnect)
                     }
                 },
-                 default => {
-                     let Some(last_time) = last_ping else { continue; };
-                     let since = last_time.elapsed().as_secs();
-                    if expecting_pingresp {
-                        if since >= max_timeout {
-                            return Ok(NetworkStatus::Timeout);
-                        }
-                        continue;
+                _ = keep_alive_timer.fuse() => {
+                    if pingreq_sent.is_some() {
+                        warn!(
+                            keep_alive = self.options.keep_alive,
+                            "no PINGRESP received, keep alive timed out"
+                        );
+                        return Ok(NetworkStatus::Timeout);
+                    }
+
+                    let pingreq = Packet::PingReq(PingReq::default());
+                    if let Some(pingreq) = self.intercept_or_disconnect(pingreq).await? {
+                        write_packet::<S, R>(&mut self.stream, self.version, &self.stats, &pingreq).await?;
+                        R::flush(&mut self.stream).await?;
+                    }
+                    last_sent = Instant::now();
+                    pingreq_sent = Some(last_sent);
+                },
+            };
+        }
+    }
+}
+
+impl<S, R> Drop for Network<S, R> {
+    /// The clients waiting for a packet identifier would wait forever
+    fn drop(&mut self) {
+        if let Some(pkids) = self.state.pkid_mgr() {
+            pkids.close();
+        }
+    }
+}
+
+/// The acknowledgement of a QoS 1 (PUBACK) or QoS 2 (PUBREC) message
+fn acknowledgement(publish: &Publish) -> Option<Packet> {
+    match (publish.qos, publish.pkid) {
+        (QoS::One, Some(pkid)) => Some(Packet::PubAck(PubAck {
+            pkid,
+            ..Default::default()
+        })),
+        (QoS::Two, Some(pkid)) => Some(Packet::PubRec(PubRec {
+            pkid,
+            ..Default::default()
+        })),
+        _ => None,
+    }
+}
+
+fn decode_packet(
+    version: Version,
+    stats: &StatsRecorder,
+    header: FixedHeader,
+    mut body: Bytes,
+) -> Result<Packet, MQTTError> {
+    let size = 1 + header.header_len + header.remaining_length;
+
+    let packet = match version {
+        Version::V5 => decode_frame(header, body),
+        Version::V4 => v311::codec::decode(header, &mut body),
+    }?;
+
+    packet!("incoming", &packet, size);
+    stats.packet(Direction::Incoming, packet.packet_type(), size);
+    Ok(packet)
+}
+
+/// The packet identifier the client allocated to a packet, the one its acknowledgement releases
+fn allocated_pkid(packet: &Packet) -> Option<u16> {
+    match packet {
+        Packet::Publish(publish) => publish.pkid,
+        Packet::Subscribe(subscribe) => Some(subscribe.pkid),
+        Packet::UnSubscribe(unsubscribe) => Some(unsubscribe.pkid),
+        _ => None,
+    }
+}
+
+/// A copy of a packet acknowledging one the client sent, whose packet identifier is released once the session state
+/// handles it
+fn copy_acknowledgement(packet: &Packet) -> Option<Packet> {
+    match packet {
+        Packet::PubAck(puback) => Some(Packet::PubAck(puback.clone())),
+        Packet::PubRec(pubrec) => Some(Packet::PubRec(pubrec.clone())),
+        Packet::PubComp(pubcomp) => Some(Packet::PubComp(pubcomp.clone())),
+        Packet::SubAck(suback) => Some(Packet::SubAck(suback.clone())),
+        Packet::UnSubAck(unsuback) => Some(Packet::UnSubAck(unsuback.clone())),
+        _ => None,
+    }
+}
+
+async fn write_packet<S, R>(
+    stream: &mut S,
+    version: Version,
+    stats: &StatsRecorder,
+    packet: &Packet,
+) -> Result<(), RunError>
+where
+    R: Runtime<S>,
+{
+    let mut buf = BytesMut::new();
+    encode_packet(version, packet, &mut buf)?;
+    packet!("outgoing", packet, buf.len());
+    stats.packet(Direction::Outgoing, packet.packet_type(), buf.len());
+    R::write_all(stream, &buf).await?;
+    Ok(())
+}
+
+fn encode_packet(version: Version, packet: &Packet, buf: &mut BytesMut) -> Result<(), MQTTError> {
+    match version {
+        Version::V5 => packet.encode(buf),
+        Version::V4 => v311::codec::encode(packet, buf),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bytes::{Bytes, BytesMut};
+    use futures::{executor::block_on, join, AsyncReadExt, AsyncWriteExt};
+
+    use super::*;
+    use crate::v5::{
+        client::{
+            connector::{DuplexStream, MemoryConnector, MemoryListener},
+            error::{InterceptError, PublishError},
+            mock::MockBroker,
+        },
+        commons::{fixed_header::FixedHeader, packet::read_frame, qos::QoS},
+        packet::connack::ConnAckProperties,
+        traits::bufferio::BufferIO,
+    };
+
+    struct Ignore;
+
+    impl AsyncHandler for Ignore {
+        async fn handle(&mut self, _packet: Packet) {}
+    }
+
+    /// Plays the broker's side of the handshake on the next accepted connection, and returns the CONNECT it received
+    async fn accept(listener: &MemoryListener, session_present: bool) -> (DuplexStream, Connect) {
+        let mut stream = listener.accept().await.unwrap();
+
+        let mut header = [0u8; 2];
+        stream.read_exact(&mut header).await.unwrap();
+        let mut body = vec![0u8; header[1] as usize];
+        stream.read_exact(&mut body).await.unwrap();
+
+        let mut frame = Bytes::from_iter(header.into_iter().chain(body));
+        let fixed_header = <FixedHeader as BufferIO>::read(&mut frame).unwrap();
+        assert_eq!(fixed_header.packet_type, PacketType::Connect);
+        let connect = <Connect as BufferIO>::read(&mut frame).unwrap();
+
+        let mut buf = BytesMut::new();
+        let connack = ConnAck {
+            session_present,
+            ..Default::default()
+        };
+        BufferIO::write(&connack, &mut buf).unwrap();
+        stream.write_all(&buf).await.unwrap();
+
+        (stream, connect)
+    }
+
+    #[test]
+    fn connects_and_reconnects_through_a_connector() {
+        block_on(async {
+            let (connector, listener) = MemoryConnector::new(1024);
+            let options = ConnectOptions {
+                client_id: String::from("reconnecting-client"),
+                ..Default::default()
+            };
+
+            let (client, server) = join!(
+                Network::connect_with(options, &connector),
+                accept(&listener, false)
+            );
+            let (mut network, _client) = client.unwrap();
+            let (first_stream, connect) = server;
+            assert_eq!(connect.client_id, "reconnecting-client");
+
+            drop(first_stream);
+            let error = network.run(&mut Ignore).await.err().unwrap();
+            assert!(matches!(error, RunError::Io(ref e) if e.kind() == io::ErrorKind::UnexpectedEof));
+            assert!(error.is_retryable());
+
+            let (reconnection, (_, connect)) = join!(
+                network.reconnect(&connector),
+                accept(&listener, true)
+            );
+            let reconnection = reconnection.unwrap();
+            assert!(reconnection.connack.session_present);
+            assert!(reconnection.resubscribed.is_empty());
+            assert_eq!(connect.client_id, "reconnecting-client");
+
+            let reconnects = network.stats().reconnects;
+            let reconnects = reconnects.into_iter().collect::<Vec<_>>();
+            assert_eq!(reconnects, [(ReconnectReason::ConnectionLost, 1)]);
+        });
+    }
+
+    #[test]
+    fn sends_the_packets_in_flight_again_when_the_session_is_resumed() {
+        use crate::v5::packet::{pubrel::PubRel, publish::Publish};
+
+        async fn recv(stream: &mut DuplexStream) -> Packet {
+            let (header, body) = read_frame(&mut *stream, usize::MAX).await.unwrap();
+            decode_packet(Version::V5, &StatsRecorder::default(), header, body).unwrap()
+        }
+
+        async fn send(stream: &mut DuplexStream, packet: Packet) {
+            let mut buf = BytesMut::new();
+            encode_packet(Version::V5, &packet, &mut buf).unwrap();
+            stream.write_all(&buf).await.unwrap();
+        }
+
+        let topics = ["sensors/temperature", "sensors/humidity"];
+        let publish = |topic: usize, qos, pkid| Publish {
+            topic: String::from(topics[topic]),
+            qos,
+            pkid: Some(pkid),
+            payload: "21.5".into(),
+            ..Default::default()
+        };
+        let pubrel = Packet::PubRel(PubRel {
+            pkid: 1,
+            ..Default::default()
+        });
+
+        block_on(async {
+            let (connector, listener) = MemoryConnector::new(1024);
+            let (client, (mut stream, _)) = join!(
+                Network::connect_with(ConnectOptions::default(), &connector),
+                accept(&listener, true)
+            );
+            let (mut network, client) = client.unwrap();
+
+            client.publish(topics[0], QoS::Two, false, "21.5", None).await.unwrap();
+            client.publish(topics[1], QoS::One, false, "21.5", None).await.unwrap();
+            let broker = async {
+                assert_eq!(recv(&mut stream).await, Packet::Publish(publish(0, QoS::Two, 1)));
+                assert_eq!(recv(&mut stream).await, Packet::Publish(publish(1, QoS::One, 2)));
+                let pubrec = PubRec {
+                    pkid: 1,
+                    ..Default::default()
+                };
+                send(&mut stream, Packet::PubRec(pubrec)).await;
+                assert_eq!(recv(&mut stream).await, pubrel);
+                // the connection is lost before the PUBACK and the PUBCOMP are sent
+                drop(stream);
+            };
+            let mut ignore = Ignore;
+            let (run, ()) = join!(network.run(&mut ignore), broker);
+            assert!(matches!(run, Err(RunError::Io(_))));
+
+            let (reconnection, (mut stream, _)) =
+                join!(network.reconnect(&connector), accept(&listener, true));
+            reconnection.unwrap();
+            let resent = Publish {
+                dup: true,
+                ..publish(1, QoS::One, 2)
+            };
+            assert_eq!(recv(&mut stream).await, Packet::Publish(resent));
+            assert_eq!(recv(&mut stream).await, pubrel);
+
+            // acknowledged this time, the packet identifiers are released
+            let puback = PubAck {
+                pkid: 2,
+                ..Default::default()
+            };
+            send(&mut stream, Packet::PubAck(puback)).await;
+            let pubcomp = PubComp {
+                pkid: 1,
+                ..Default::default()
+            };
+            send(&mut stream, Packet::PubComp(pubcomp)).await;
+            send(&mut stream, Packet::Disconnect(Disconnect::default())).await;
+            let status = network.run(&mut Ignore).await.unwrap();
+            assert!(matches!(status, NetworkStatus::IncomingDisconnect));
+            assert_eq!(network.state.in_flight(), (0, 0));
+        });
+    }
+
+    #[test]
+    fn subscribes_again_when_the_server_lost_the_session() {
+        use crate::v5::packet::{
+            publish::Publish,
+            suback::SubAck,
+            subscribe::{Subscribe, SubscriptionOptions},
+        };
+
+        /// Keeps the PUBLISH packets handed to the application
+        #[derive(Default)]
+        struct Messages(Vec<Publish>);
+
+        impl AsyncHandler for Messages {
+            async fn handle(&mut self, packet: Packet) {
+                if let Packet::Publish(publish) = packet {
+                    self.0.push(publish);
+                }
+            }
+        }
+
+        async fn send(stream: &mut DuplexStream, packet: Packet) {
+            let mut buf = BytesMut::new();
+            encode_packet(Version::V5, &packet, &mut buf).unwrap();
+            stream.write_all(&buf).await.unwrap();
+        }
+
+        /// Answers the next SUBSCRIBE with a SUBACK granting every topic filter, after sending `before`
+        async fn suback(stream: &mut DuplexStream, before: Vec<Packet>) -> Subscribe {
+            let (header, body) = read_frame(&mut *stream, usize::MAX).await.unwrap();
+            let stats = StatsRecorder::default();
+            let Ok(Packet::Subscribe(subscribe)) = decode_packet(Version::V5, &stats, header, body)
+            else {
+                panic!("expected a SUBSCRIBE");
+            };
+            for packet in before {
+                send(stream, packet).await;
+            }
+            let payload = vec![SubAckReasonCode::GrantedQoS1; subscribe.payload.len()];
+            let pkid = subscribe.pkid;
+            send(stream, Packet::SubAck(SubAck { pkid, payload, ..Default::default() })).await;
+            send(stream, Packet::Disconnect(Disconnect::default())).await;
+            subscribe
+        }
+
+        block_on(async {
+            let (connector, listener) = MemoryConnector::new(1024);
+            let (client, (mut stream, _)) = join!(
+                Network::connect_with(ConnectOptions::default(), &connector),
+                accept(&listener, false)
+            );
+            let (mut network, client) = client.unwrap();
+
+            let options = SubscriptionOptions {
+                qos: QoS::One,
+                ..Default::default()
+            };
+            let filters = vec![(String::from("sensors/#"), options)];
+            let _subscription = client.subscribe("sensors/#", options).await.unwrap();
+            let mut ignore = Ignore;
+            let (status, _) = join!(network.run(&mut ignore), suback(&mut stream, vec![]));
+            assert!(matches!(status, Ok(NetworkStatus::IncomingDisconnect)));
+
+            // a retained message may be sent before the SUBACK
+            let retained = Publish {
+                topic: String::from("sensors/temperature"),
+                retain: true,
+                payload: "21.5".into(),
+                ..Default::default()
+            };
+            let broker = async {
+                let (mut stream, _) = accept(&listener, false).await;
+                let before = vec![Packet::Publish(retained.clone())];
+                let subscribe = suback(&mut stream, before).await;
+                (stream, subscribe)
+            };
+            let (reconnection, (_stream, subscribe)) =
+                join!(network.reconnect(&connector), broker);
+            let reconnection = reconnection.unwrap();
+            assert_eq!(subscribe.payload, filters);
+            let [resubscribed] = &reconnection.resubscribed[..] else {
+                panic!("expected a single subscription: {:?}", reconnection.resubscribed);
+            };
+            assert_eq!(resubscribed.filter, "sensors/#");
+            assert!(matches!(resubscribed.result, Ok(SubAckReasonCode::GrantedQoS1)));
+
+            let mut messages = Messages::default();
+            let status = network.run(&mut messages).await.unwrap();
+            assert!(matches!(status, NetworkStatus::IncomingDisconnect));
+            assert_eq!(messages.0, [retained]);
+        });
+    }
+
+    #[test]
+    fn delivers_messages_to_the_subscriptions_they_were_sent_for() {
+        use futures::StreamExt;
+
+        use crate::v5::packet::{
+            publish::{Publish, PublishProperties},
+            suback::SubAck,
+            subscribe::{Subscribe, SubscribeProperties, SubscriptionOptions},
+        };
+
+        let subscribe = |pkid, filter: &str, subscription_id| {
+            Packet::Subscribe(Subscribe {
+                pkid,
+                payload: vec![(String::from(filter), SubscriptionOptions::default())],
+                properties: SubscribeProperties {
+                    subscription_id: Some(subscription_id),
+                    ..Default::default()
+                },
+            })
+        };
+        let suback = |pkid| {
+            Packet::SubAck(SubAck {
+                pkid,
+                payload: vec![SubAckReasonCode::GrantedQoS0],
+                ..Default::default()
+            })
+        };
+        let publish = |topic: &str, subscription_identifier| {
+            Packet::Publish(Publish {
+                topic: String::from(topic),
+                payload: "21.5".into(),
+                properties: PublishProperties {
+                    subscription_identifier,
+                    ..Default::default()
+                },
+                ..Default::default()
+            })
+        };
+        let (stream, mock) = MockBroker::new()
+            .handshake(ConnAck::default())
+            .expect(subscribe(1, "sensors/#", 1))
+            .expect(subscribe(2, "sensors/+/temp", 2))
+            .send(suback(1))
+            .send(suback(2))
+            // a single PUBLISH for both subscriptions, then one for the first only
+            .send(publish("sensors/kitchen/temp", vec![1, 2]))
+            .send(publish("sensors/kitchen/temp", vec![1]))
+            .send(Packet::Disconnect(Disconnect::default()))
+            .spawn();
+
+        block_on(async {
+            let options = ConnectOptions {
+                keep_alive: 0,
+                ..Default::default()
+            };
+            let (mut network, client) = Network::new(options, stream).await.unwrap();
+            let options = SubscriptionOptions::default();
+            let all = client.subscribe("sensors/#", options).await.unwrap();
+            let temperatures = client.subscribe("sensors/+/temp", options).await.unwrap();
+
+            let status = network.run(&mut ()).await.unwrap();
+            assert!(matches!(status, NetworkStatus::IncomingDisconnect));
+            drop(network);
+            drop(client);
+            let all = all.take(2).collect::<Vec<_>>().await;
+            assert_eq!(all.len(), 2);
+            let temperatures = temperatures.take(1).collect::<Vec<_>>().await;
+            assert_eq!(temperatures[0].properties.subscription_identifier, [1, 2]);
+        });
+        mock.join().unwrap();
+    }
+
+    #[test]
+    fn drops_the_oldest_message_of_a_full_subscription() {
+        use futures::StreamExt;
+
+        use crate::v5::packet::{
+            publish::{Publish, PublishProperties},
+            suback::SubAck,
+            subscribe::{Subscribe, SubscribeProperties, SubscriptionOptions},
+        };
+
+        let publish = |payload: &'static str| {
+            Packet::Publish(Publish {
+                topic: String::from("sensors/temperature"),
+                payload: payload.into(),
+                properties: PublishProperties {
+                    subscription_identifier: vec![1],
+                    ..Default::default()
+                },
+                ..Default::default()
+            })
+        };
+        let (stream, mock) = MockBroker::new()
+            .handshake(ConnAck::default())
+            .expect(Packet::Subscribe(Subscribe {
+                pkid: 1,
+                payload: vec![(String::from("sensors/#"), SubscriptionOptions::default())],
+                properties: SubscribeProperties {
+                    subscription_id: Some(1),
+                    ..Default::default()
+                },
+            }))
+            .send(Packet::SubAck(SubAck {
+                pkid: 1,
+                payload: vec![SubAckReasonCode::GrantedQoS0],
+                ..Default::default()
+            }))
+            .send(publish("21.5"))
+            .send(publish("22.0"))
+            .send(publish("22.5"))
+            .send(Packet::Disconnect(Disconnect::default()))
+            .spawn();
+
+        block_on(async {
+            let options = ConnectOptions {
+                keep_alive: 0,
+                incoming_queue_size: 2.try_into().unwrap(),
+                ..Default::default()
+            };
+            let (mut network, client) = Network::new(options, stream).await.unwrap();
+            let options = SubscriptionOptions::default();
+            let subscription = client.subscribe("sensors/#", options).await.unwrap();
+
+            // nobody reads the subscription while the network runs
+            let status = network.run(&mut ()).await.unwrap();
+            assert!(matches!(status, NetworkStatus::IncomingDisconnect));
+            drop(network);
+            // the subscription keeps the channel open, only what it holds is ready
+            let messages = subscription.ready_chunks(3).next().await.unwrap();
+            let payloads = messages.iter().map(|message| &message.payload[..]);
+            assert!(payloads.eq([&b"22.0"[..], b"22.5"]));
+        });
+        mock.join().unwrap();
+    }
+
+    #[test]
+    fn unsubscribes_once_the_last_handle_of_a_topic_filter_is_dropped() {
+        use crate::v5::packet::{
+            suback::SubAck,
+            subscribe::{Subscribe, SubscribeProperties, SubscriptionOptions},
+            unsuback::{UnSubAck, UnSubAckReasonCode},
+            unsubscribe::UnSubscribe,
+        };
+
+        let (stream, mock) = MockBroker::new()
+            .handshake(ConnAck::default())
+            .expect(Packet::Subscribe(Subscribe {
+                pkid: 1,
+                payload: vec![(String::from("sensors/#"), SubscriptionOptions::default())],
+                properties: SubscribeProperties {
+                    subscription_id: Some(1),
+                    ..Default::default()
+                },
+            }))
+            .expect(Packet::UnSubscribe(UnSubscribe {
+                pkid: 2,
+                payload: vec![String::from("sensors/#")],
+                ..Default::default()
+            }))
+            .send(Packet::SubAck(SubAck {
+                pkid: 1,
+                payload: vec![SubAckReasonCode::GrantedQoS0],
+                ..Default::default()
+            }))
+            .send(Packet::UnSubAck(UnSubAck {
+                pkid: 2,
+                payload: vec![UnSubAckReasonCode::Success],
+                ..Default::default()
+            }))
+            .send(Packet::Disconnect(Disconnect::default()))
+            .spawn();
+
+        block_on(async {
+            let options = ConnectOptions {
+                keep_alive: 0,
+                ..Default::default()
+            };
+            let (mut network, client) = Network::new(options, stream).await.unwrap();
+            let options = SubscriptionOptions::default();
+            let first = client.subscribe("sensors/#", options).await.unwrap();
+            let second = client.subscribe("sensors/#", options).await.unwrap();
+            assert_eq!(second.filter(), "sensors/#");
+
+            // the subscription is still used by the second handle
+            drop(first);
+            drop(second);
+
+            let status = network.run(&mut ()).await.unwrap();
+            assert!(matches!(status, NetworkStatus::IncomingDisconnect));
+            assert_eq!(client.stats().sent.packets(PacketType::Subscribe), 1);
+        });
+        mock.join().unwrap();
+    }
+
+    #[test]
+    fn shares_the_outgoing_queue_between_clones_of_the_client() {
+        use futures::FutureExt;
+
+        use crate::v5::packet::publish::Publish;
+
+        let publish = |payload: &'static str| {
+            Packet::Publish(Publish {
+                topic: String::from("sensors/temperature"),
+                payload: payload.into(),
+                ..Default::default()
+            })
+        };
+        let (stream, mock) = MockBroker::new()
+            .handshake(ConnAck::default())
+            .expect(publish("21.5"))
+            .expect(publish("22.0"))
+            .send(Packet::Disconnect(Disconnect::default()))
+            .spawn();
+
+        block_on(async {
+            let options = ConnectOptions {
+                outgoing_queue_size: 2.try_into().unwrap(),
+                ..Default::default()
+            };
+            let (mut network, client) = Network::new(options, stream).await.unwrap();
+            let clone = client.clone();
+
+            let topic = "sensors/temperature";
+            client.publish(topic, QoS::Zero, false, "21.5", None).await.unwrap();
+            clone.publish(topic, QoS::Zero, false, "22.0", None).await.unwrap();
+            // the queue is full until the network sends them
+            let third = clone.publish(topic, QoS::Zero, false, "22.5", None);
+            assert!(third.now_or_never().is_none());
+            assert!(client.is_alive());
+            assert_eq!(client.exit_reason(), None);
+
+            let status = network.run(&mut Ignore).await.unwrap();
+            assert!(matches!(status, NetworkStatus::IncomingDisconnect));
+            assert!(!clone.is_alive());
+            assert_eq!(clone.exit_reason(), Some(ReconnectReason::ServerDisconnect));
+
+            drop(network);
+            assert_eq!(client.exit_reason(), Some(ReconnectReason::ServerDisconnect));
+        });
+        mock.join().unwrap();
+    }
+
+    #[cfg(feature = "json")]
+    #[test]
+    fn publishes_typed_payloads_with_their_content_type() {
+        use std::collections::BTreeMap;
+
+        use crate::v5::{
+            client::{error::PayloadError, payload::Json},
+            packet::publish::{Publish, PublishProperties},
+        };
+
+        let utf8 = |content_type: Option<&str>| PublishProperties {
+            payload_format_indicator: Some(1),
+            content_type: content_type.map(String::from),
+            ..Default::default()
+        };
+        let (stream, mock) = MockBroker::new()
+            .handshake(ConnAck::default())
+            .expect(Packet::Publish(Publish {
+                topic: String::from("sensors/temperature"),
+                payload: r#"{"celsius":21.5}"#.into(),
+                properties: utf8(Some("application/json")),
+                ..Default::default()
+            }))
+            .send(Packet::Disconnect(Disconnect::default()))
+            .spawn();
+
+        block_on(async {
+            let (mut network, client) = Network::new(ConnectOptions::default(), stream)
+                .await
+                .unwrap();
+
+            let topic = "sensors/temperature";
+            let reading = BTreeMap::from([("celsius", 21.5)]);
+            let typed = client.publish_typed(topic, QoS::Zero, false, &reading, &Json, None);
+            typed.await.unwrap();
+            // the payload is not what its indicator says, it is not sent
+            let payload = &b"\xc3\x28"[..];
+            let invalid = client.publish(topic, QoS::One, false, payload, Some(utf8(None)));
+            assert!(matches!(
+                invalid.await,
+                Err(PublishError::Payload(PayloadError::InvalidUtf8(_)))
+            ));
+
+            let status = network.run(&mut Ignore).await.unwrap();
+            assert!(matches!(status, NetworkStatus::IncomingDisconnect));
+        });
+        mock.join().unwrap();
+    }
+
+    #[test]
+    fn runs_the_interceptors_between_the_session_state_and_the_stream() {
+        use crate::v5::{
+            client::interceptor::Interceptor,
+            packet::publish::{Publish, PublishProperties},
+        };
+
+        /// Tags the messages and the DISCONNECT it sends, hides the messages on `private/` topics, and refuses the ones
+        /// on `forbidden`
+        struct Gate;
+
+        impl Interceptor for Gate {
+            fn outgoing(&self, packet: Packet) -> Result<Option<Packet>, InterceptError> {
+                let tag = (String::from("origin"), String::from("gate"));
+                let packet = match packet {
+                    Packet::Publish(mut publish) => {
+                        publish.properties.user_property.push(tag);
+                        Packet::Publish(publish)
+                    }
+                    Packet::Disconnect(mut disconnect) => {
+                        disconnect.properties.user_property.push(tag);
+                        Packet::Disconnect(disconnect)
                     }
+                    packet => packet,
+                };
+                Ok(Some(packet))
+            }
 
-                    if last_ping.is_some_and(|t| t.elapsed().as_secs() >= keep_alive as u64) {
-                        Packet::PingReq(PingReq::default()).write(&mut self.stream).await?;
-                        last_ping = Some(Instant::now());
-                        expecting_pingresp = true;
+            fn incoming(&self, packet: Packet) -> Result<Option<Packet>, InterceptError> {
+                match &packet {
+                    Packet::Publish(publish) if publish.topic == "forbidden" => {
+                        Err(InterceptError(String::from("forbidden topic")))
                     }
+                    _ => Ok(Some(packet)),
+                }
+            }
+
+            fn message(&self, message: Message) -> Result<Option<Message>, InterceptError> {
+                Ok(Some(message).filter(|message| !message.topic.starts_with("private/")))
+            }
+        }
+
+        /// Keeps the topics of the messages handed to the application
+        #[derive(Default)]
+        struct Topics(Vec<String>);
+
+        impl AsyncHandler for Topics {
+            async fn handle(&mut self, packet: Packet) {
+                if let Packet::Publish(publish) = packet {
+                    self.0.push(publish.topic);
+                }
+            }
+        }
+
+        let message = |topic: &str, qos: QoS, pkid: Option<u16>| {
+            Packet::Publish(Publish {
+                topic: String::from(topic),
+                qos,
+                pkid,
+                payload: "21.5".into(),
+                ..Default::default()
+            })
+        };
+        let (stream, mock) = MockBroker::new()
+            .handshake(ConnAck::default())
+            .expect(Packet::Publish(Publish {
+                topic: String::from("sensors/temperature"),
+                payload: "21.5".into(),
+                properties: PublishProperties {
+                    user_property: vec![(String::from("origin"), String::from("gate"))],
+                    ..Default::default()
+                },
+                ..Default::default()
+            }))
+            // the application never gets it, the network acknowledges it
+            .send(message("private/key", QoS::One, Some(1)))
+            .expect(Packet::PubAck(PubAck {
+                pkid: 1,
+                ..Default::default()
+            }))
+            .send(message("sensors/humidity", QoS::Zero, None))
+            .send(message("forbidden", QoS::One, Some(2)))
+            .expect(Packet::Disconnect(Disconnect {
+                reason_code: DisconnectReasonCode::ImplementationSpecificError,
+                properties: DisconnectProperties {
+                    reason_string: Some(String::from("Intercepted: forbidden topic")),
+                    user_property: vec![(String::from("origin"), String::from("gate"))],
+                    ..Default::default()
+                },
+            }))
+            .spawn();
+
+        block_on(async {
+            let options = ConnectOptions {
+                manual_ack: true,
+                ..Default::default()
+            };
+            let (mut network, client) = Network::new(options, stream).await.unwrap();
+            client.intercept(Gate);
+            let topic = "sensors/temperature";
+            client.publish(topic, QoS::Zero, false, "21.5", None).await.unwrap();
+
+            let mut topics = Topics::default();
+            let error = network.run(&mut topics).await.err();
+            let forbidden = MQTTError::Intercepted(String::from("forbidden topic"));
+            assert!(matches!(error, Some(RunError::Protocol(e)) if *e == forbidden));
+            assert_eq!(topics.0, ["sensors/humidity"]);
+        });
+        mock.join().unwrap();
+    }
+
+    #[test]
+    fn checks_the_packets_the_interceptors_hand_on() {
+        use crate::v5::client::interceptor::Interceptor;
+
+        /// Gives the messages it sends another packet identifier, or a topic with a wildcard
+        struct Rewrite;
+
+        impl Interceptor for Rewrite {
+            fn outgoing(&self, packet: Packet) -> Result<Option<Packet>, InterceptError> {
+                let Packet::Publish(mut publish) = packet else {
+                    return Ok(Some(packet));
+                };
+                match publish.qos {
+                    QoS::Zero => publish.topic = String::from("sensors/#"),
+                    _ => publish.pkid = Some(5),
+                }
+                Ok(Some(Packet::Publish(publish)))
+            }
+        }
+
+        let connack = ConnAck {
+            properties: ConnAckProperties {
+                receive_maximum: Some(1),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        // neither message reaches the server
+        let (stream, mock) = MockBroker::new()
+            .handshake(connack)
+            .expect(Packet::Disconnect(Disconnect::default()))
+            .spawn();
+
+        block_on(async {
+            let options = ConnectOptions {
+                keep_alive: 0,
+                strict: true,
+                ..Default::default()
+            };
+            let (mut network, client) = Network::new(options, stream).await.unwrap();
+            client.intercept(Rewrite);
+            let publish = |qos| client.publish("sensors/temperature", qos, false, "21.5", None);
+
+            // each refused packet is dropped alone, the connection is kept
+            publish(QoS::One).await.unwrap();
+            publish(QoS::Zero).await.unwrap();
+            client.disconnect().await.unwrap();
+            let status = network.run(&mut Ignore).await.unwrap();
+            assert!(matches!(status, NetworkStatus::OutgoingDisconnect));
+            // the packet identifier of the packet that was not sent is released
+            let pkids = network.state.pkid_mgr().unwrap();
+            assert_eq!(pkids.allocate(), Ok(1));
+        });
+        mock.join().unwrap();
+    }
+
+    #[test]
+    fn releases_the_packet_identifier_of_an_acknowledgement_an_interceptor_drops() {
+        use crate::v5::client::interceptor::Interceptor;
+
+        /// Drops every PUBACK
+        struct NoPubAcks;
+
+        impl Interceptor for NoPubAcks {
+            fn incoming(&self, packet: Packet) -> Result<Option<Packet>, InterceptError> {
+                Ok(Some(packet).filter(|packet| !matches!(packet, Packet::PubAck(_))))
+            }
+        }
+
+        /// Keeps the types of the packets handed to the application
+        #[derive(Default)]
+        struct Types(Vec<PacketType>);
+
+        impl AsyncHandler for Types {
+            async fn handle(&mut self, packet: Packet) {
+                self.0.push(packet.packet_type());
+            }
+        }
+
+        let publish = Publish {
+            topic: String::from("sensors/temperature"),
+            qos: QoS::One,
+            pkid: Some(1),
+            payload: "21.5".into(),
+            ..Default::default()
+        };
+        let connack = ConnAck {
+            properties: ConnAckProperties {
+                receive_maximum: Some(1),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let (stream, mock) = MockBroker::new()
+            .handshake(connack)
+            .expect(Packet::Publish(publish))
+            .send(Packet::PubAck(PubAck {
+                pkid: 1,
+                ..Default::default()
+            }))
+            .send(Packet::Disconnect(Disconnect::default()))
+            .spawn();
+
+        block_on(async {
+            let options = ConnectOptions {
+                keep_alive: 0,
+                ..Default::default()
+            };
+            let (mut network, client) = Network::new(options, stream).await.unwrap();
+            client.intercept(NoPubAcks);
+            let topic = "sensors/temperature";
+            client.publish(topic, QoS::One, false, "21.5", None).await.unwrap();
+
+            let mut types = Types::default();
+            let status = network.run(&mut types).await.unwrap();
+            assert!(matches!(status, NetworkStatus::IncomingDisconnect));
+            assert_eq!(types.0, [PacketType::Disconnect]);
+            assert_eq!(client.stats().in_flight_outgoing, 0);
+            let pkids = network.state.pkid_mgr().unwrap();
+            assert_eq!(pkids.allocate(), Ok(1));
+        });
+        mock.join().unwrap();
+    }
+
+    #[test]
+    fn disconnects_when_an_interceptor_refuses_a_packet_the_network_sends() {
+        use crate::v5::client::interceptor::Interceptor;
+
+        /// Refuses every acknowledgement
+        struct NoAcks;
+
+        impl Interceptor for NoAcks {
+            fn outgoing(&self, packet: Packet) -> Result<Option<Packet>, InterceptError> {
+                match packet {
+                    Packet::PubAck(_) => Err(InterceptError(String::from("no acknowledgements"))),
+                    packet => Ok(Some(packet)),
+                }
+            }
+        }
+
+        let (stream, mock) = MockBroker::new()
+            .handshake(ConnAck::default())
+            .send(Packet::Publish(Publish {
+                topic: String::from("sensors/temperature"),
+                qos: QoS::One,
+                pkid: Some(1),
+                payload: "21.5".into(),
+                ..Default::default()
+            }))
+            .expect(Packet::Disconnect(Disconnect {
+                reason_code: DisconnectReasonCode::ImplementationSpecificError,
+                properties: DisconnectProperties {
+                    reason_string: Some(String::from("Intercepted: no acknowledgements")),
+                    ..Default::default()
+                },
+            }))
+            .spawn();
+
+        block_on(async {
+            let options = ConnectOptions {
+                keep_alive: 0,
+                ..Default::default()
+            };
+            let (mut network, client) = Network::new(options, stream).await.unwrap();
+            client.intercept(NoAcks);
+
+            let error = network.run(&mut Ignore).await.err();
+            let refused = MQTTError::Intercepted(String::from("no acknowledgements"));
+            assert!(matches!(error, Some(RunError::Protocol(e)) if *e == refused));
+        });
+        mock.join().unwrap();
+    }
+
+    #[test]
+    fn counts_packets_and_times_their_acknowledgement() {
+        use crate::v5::packet::publish::Publish;
+
+        let publish = Publish {
+            topic: String::from("sensors/temperature"),
+            qos: QoS::One,
+            pkid: Some(1),
+            payload: "21.5".into(),
+            ..Default::default()
+        };
+        let (stream, mock) = MockBroker::new()
+            .handshake(ConnAck::default())
+            .expect(Packet::Publish(publish))
+            .send(Packet::PubAck(PubAck {
+                pkid: 1,
+                ..Default::default()
+            }))
+            .send(Packet::Disconnect(Disconnect::default()))
+            .spawn();
+
+        block_on(async {
+            let options = ConnectOptions::default();
+            let (mut network, client) = Network::new(options, stream).await.unwrap();
+            client
+                .publish("sensors/temperature", QoS::One, false, "21.5", None)
+                .await
+                .unwrap();
+            assert_eq!(client.stats().queued, 1);
+
+            let status = network.run(&mut Ignore).await.unwrap();
+            assert!(matches!(status, NetworkStatus::IncomingDisconnect));
+
+            let stats = client.stats();
+            assert_eq!(stats.sent.packets(PacketType::Connect), 1);
+            assert_eq!(stats.sent.packets(PacketType::Publish), 1);
+            assert_eq!(stats.sent.bytes(PacketType::Publish), 30);
+            assert_eq!(stats.received.packets(PacketType::PubAck), 1);
+            assert_eq!(stats.received.total_packets(), 3);
+            assert_eq!(stats.in_flight_outgoing, 0);
+            assert_eq!(stats.queued, 0);
+            assert_eq!(stats.publish_latency.count, 1);
+            assert_eq!(stats.ping_latency.count, 0);
+        });
+        mock.join().unwrap();
+    }
+
+    #[test]
+    fn falls_back_to_mqtt_311_when_the_server_does_not_support_mqtt_5() {
+        block_on(async {
+            let (connector, listener) = MemoryConnector::new(1024);
+            let options = ConnectOptions {
+                client_id: String::from("legacy-device"),
+                fallback_to_v311: true,
+                ..Default::default()
+            };
+
+            let server = async {
+                // An MQTT 3.1.1 server refuses the MQTT 5.0 CONNECT, and closes the connection
+                let mut stream = listener.accept().await.unwrap();
+                let (header, _) = read_frame(&mut stream, usize::MAX).await.unwrap();
+                assert_eq!(header.packet_type, PacketType::Connect);
+                stream.write_all(b"\x20\x02\x00\x01").await.unwrap();
+                drop(stream);
+
+                let mut stream = listener.accept().await.unwrap();
+                let (header, mut body) = read_frame(&mut stream, usize::MAX).await.unwrap();
+                let Packet::Connect(connect) = v311::codec::decode(header, &mut body).unwrap()
+                else {
+                    panic!("expected a CONNECT");
+                };
+                stream.write_all(b"\x20\x02\x00\x00").await.unwrap();
+                (stream, connect)
+            };
+
+            let (client, (mut stream, connect)) =
+                join!(Network::connect_with(options, &connector), server);
+            let (mut network, client) = client.unwrap();
+            assert_eq!(network.version(), Version::V4);
+            assert_eq!(connect.version, Version::V4);
+            assert_eq!(connect.client_id, "legacy-device");
+
+            client.disconnect().await.unwrap();
+            let status = network.run(&mut Ignore).await.unwrap();
+            assert!(matches!(status, NetworkStatus::OutgoingDisconnect));
+
+            let mut disconnect = [0u8; 2];
+            stream.read_exact(&mut disconnect).await.unwrap();
+            assert_eq!(disconnect, [0xe0, 0x00]);
+        });
+    }
+
+    #[test]
+    fn does_not_fall_back_unless_asked_to() {
+        block_on(async {
+            let (connector, listener) = MemoryConnector::new(1024);
+
+            let server = async {
+                let mut stream = listener.accept().await.unwrap();
+                read_frame(&mut stream, usize::MAX).await.unwrap();
+                stream.write_all(b"\x20\x02\x00\x01").await.unwrap();
+            };
+
+            let (client, _) = join!(
+                Network::connect_with(ConnectOptions::default(), &connector),
+                server
+            );
+            let error = client.err().unwrap();
+            assert_eq!(
+                error.reason_code(),
+                Some(ConnAckReasonCode::UnsupportedProtocolVersion)
+            );
+            assert!(!error.is_retryable());
+        });
+    }
+
+    #[test]
+    fn keeps_the_connack_of_a_refused_connection() {
+        let connack = ConnAck {
+            reason: ConnAckReasonCode::BadUserNameOrPassword,
+            properties: ConnAckProperties {
+                reason_string: Some(String::from("unknown user")),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let (stream, mock) = MockBroker::new().handshake(connack).spawn();
+
+        block_on(async {
+            let error = Network::new(ConnectOptions::default(), stream).await.err().unwrap();
+            let ConnectError::Refused(connack) = error else {
+                panic!("expected a refused connection, found: {error:?}");
+            };
+            assert_eq!(connack.reason, ConnAckReasonCode::BadUserNameOrPassword);
+            assert_eq!(connack.properties.reason_string.as_deref(), Some("unknown user"));
+        });
+        mock.join().unwrap();
+    }
+
+    #[test]
+    fn reports_what_the_server_answered_instead_of_a_connack() {
+        block_on(async {
+            let (connector, listener) = MemoryConnector::new(1024);
+
+            let server = async {
+                let mut stream = listener.accept().await.unwrap();
+                read_frame(&mut stream, usize::MAX).await.unwrap();
+                stream.write_all(b"\xd0\x00").await.unwrap();
+                stream
+            };
+
+            let (client, _stream) = join!(
+                Network::connect_with(ConnectOptions::default(), &connector),
+                server
+            );
+            let error = client.err().unwrap();
+            assert!(matches!(error, ConnectError::UnexpectedPacket(ref p) if matches!(**p, Packet::PingResp(_))));
+        });
+    }
+
+    #[test]
+    fn keeps_the_io_error_of_a_lost_connection() {
+        block_on(async {
+            let (connector, listener) = MemoryConnector::new(1024);
+
+            let server = async {
+                let mut stream = listener.accept().await.unwrap();
+                read_frame(&mut stream, usize::MAX).await.unwrap();
+            };
+
+            let (client, _) = join!(
+                Network::connect_with(ConnectOptions::default(), &connector),
+                server
+            );
+            let error = client.err().unwrap();
+            assert!(matches!(error, ConnectError::Io(ref e) if e.kind() == io::ErrorKind::UnexpectedEof));
+            assert!(error.is_retryable());
+        });
+    }
+
+    #[test]
+    fn waits_for_a_packet_identifier_once_the_receive_maximum_is_reached() {
+        let publish = |topic: &str| {
+            Packet::Publish(Publish {
+                topic: String::from(topic),
+                qos: QoS::One,
+                pkid: Some(1),
+                payload: "21.5".into(),
+                ..Default::default()
+            })
+        };
+        let connack = ConnAck {
+            properties: ConnAckProperties {
+                receive_maximum: Some(1),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let (stream, mock) = MockBroker::new()
+            .handshake(connack)
+            .expect(publish("sensors/temperature"))
+            .send(Packet::PubAck(PubAck {
+                pkid: 1,
+                ..Default::default()
+            }))
+            .expect(publish("sensors/humidity"))
+            .send(Packet::Disconnect(Disconnect::default()))
+            .spawn();
+
+        block_on(async {
+            let options = ConnectOptions {
+                keep_alive: 0,
+                ..Default::default()
+            };
+            let (mut network, client) = Network::new(options, stream).await.unwrap();
+            let publish = |topic| client.publish(topic, QoS::One, false, "21.5", None);
+
+            publish("sensors/temperature").await.unwrap();
+            // the only packet identifier is released by the PUBACK the network receives
+            let mut ignore = Ignore;
+            let (status, second) = join!(network.run(&mut ignore), publish("sensors/humidity"));
+            assert!(matches!(status, Ok(NetworkStatus::IncomingDisconnect)));
+            second.unwrap();
+        });
+        mock.join().unwrap();
+    }
+
+    #[test]
+    fn stops_waiting_for_a_packet_identifier_once_the_network_is_dropped() {
+        let connack = ConnAck {
+            properties: ConnAckProperties {
+                receive_maximum: Some(1),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let (stream, mock) = MockBroker::new().handshake(connack).spawn();
+
+        block_on(async {
+            let (network, client) = Network::new(ConnectOptions::default(), stream).await.unwrap();
+            let publish = |topic| client.publish(topic, QoS::One, false, "21.5", None);
+
+            publish("sensors/temperature").await.unwrap();
+            // the second publish waits for the only packet identifier, which the network will never release
+            let (second, ()) = join!(publish("sensors/humidity"), async move { drop(network) });
+            let Err(PublishError::Disconnected(packet)) = second else {
+                panic!("expected a disconnected network, found: {second:?}");
+            };
+            assert!(matches!(*packet, Packet::Publish(publish) if publish.topic == "sensors/humidity"));
+        });
+        mock.join().unwrap();
+    }
+
+    #[test]
+    fn unsubscribes_a_dropped_subscription_once_a_packet_identifier_is_released() {
+        use crate::v5::packet::{
+            suback::SubAck,
+            subscribe::{Subscribe, SubscribeProperties, SubscriptionOptions},
+            unsuback::{UnSubAck, UnSubAckReasonCode},
+            unsubscribe::UnSubscribe,
+        };
+
+        let connack = ConnAck {
+            properties: ConnAckProperties {
+                receive_maximum: Some(1),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let (stream, mock) = MockBroker::new()
+            .handshake(connack)
+            .expect(Packet::Subscribe(Subscribe {
+                pkid: 1,
+                payload: vec![(String::from("sensors/#"), SubscriptionOptions::default())],
+                properties: SubscribeProperties {
+                    subscription_id: Some(1),
+                    ..Default::default()
                 },
+            }))
+            .send(Packet::SubAck(SubAck {
+                pkid: 1,
+                payload: vec![SubAckReasonCode::GrantedQoS0],
+                ..Default::default()
+            }))
+            .expect(Packet::UnSubscribe(UnSubscribe {
+                pkid: 1,
+                payload: vec![String::from("sensors/#")],
+                ..Default::default()
+            }))
+            .send(Packet::UnSubAck(UnSubAck {
+                pkid: 1,
+                payload: vec![UnSubAckReasonCode::Success],
+                ..Default::default()
+            }))
+            .send(Packet::Disconnect(Disconnect::default()))
+            .spawn();
+
+        block_on(async {
+            let options = ConnectOptions {
+                keep_alive: 0,
+                ..Default::default()
             };
+            let (mut network, client) = Network::new(options, stream).await.unwrap();
+            let options = SubscriptionOptions::default();
+            // the only packet identifier is taken by the SUBSCRIBE until its SUBACK is received
+            let subscription = client.subscribe("sensors/#", options).await.unwrap();
+            drop(subscription);
+
+            let status = network.run(&mut ()).await.unwrap();
+            assert!(matches!(status, NetworkStatus::IncomingDisconnect));
+            assert_eq!(client.stats().sent.packets(PacketType::UnSubscribe), 1);
+        });
+        mock.join().unwrap();
+    }
+
+    #[test]
+    fn hands_back_packets_the_network_can_no_longer_send() {
+        let (stream, mock) = MockBroker::new().handshake(ConnAck::default()).spawn();
+
+        block_on(async {
+            let (network, client) = Network::new(ConnectOptions::default(), stream).await.unwrap();
+            drop(network);
+
+            let error = client
+                .publish("sensors/temperature", QoS::One, false, "21.5", None)
+                .await
+                .unwrap_err();
+            let PublishError::Disconnected(packet) = error else {
+                panic!("expected a disconnected network, found: {error:?}");
+            };
+            assert!(matches!(*packet, Packet::Publish(ref p) if p.topic == "sensors/temperature"));
+        });
+        mock.join().unwrap();
+    }
+
+    #[cfg(feature = "tracing")]
+    #[test]
+    fn traces_packets_without_their_payload_nor_credentials() {
+        use std::sync::{
+            atomic::{AtomicU64, Ordering},
+            Mutex,
+        };
+
+        use tracing::{
+            field::{Field, Visit},
+            span, Event, Metadata, Subscriber,
+        };
+
+        use crate::v5::packet::{disconnect::Disconnect, puback::PubAck, publish::Publish};
+
+        /// Keeps every field of every span and event, formatted as `name=value`
+        #[derive(Default)]
+        struct Recorder {
+            fields: Arc<Mutex<Vec<String>>>,
+            ids: AtomicU64,
         }
+
+        impl Visit for &Recorder {
+            fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+                let field = format!("{}={value:?}", field.name());
+                self.fields.lock().unwrap().push(field);
+            }
+        }
+
+        impl Subscriber for Recorder {
+            fn enabled(&self, _: &Metadata<'_>) -> bool {
+                true
+            }
+            fn new_span(&self, span: &span::Attributes<'_>) -> span::Id {
+                span.record(&mut &*self);
+                span::Id::from_u64(self.ids.fetch_add(1, Ordering::Relaxed) + 1)
+            }
+            fn record(&self, _: &span::Id, values: &span::Record<'_>) {
+                values.record(&mut &*self);
+            }
+            fn record_follows_from(&self, _: &span::Id, _: &span::Id) {}
+            fn event(&self, event: &Event<'_>) {
+                event.record(&mut &*self);
+            }
+            fn enter(&self, _: &span::Id) {}
+            fn exit(&self, _: &span::Id) {}
+        }
+
+        let (stream, mock) = MockBroker::new()
+            .handshake(ConnAck::default())
+            .send(Packet::Publish(Publish {
+                topic: String::from("sensors/temperature"),
+                qos: QoS::One,
+                pkid: Some(7),
+                payload: "secret payload".into(),
+                ..Default::default()
+            }))
+            .expect(Packet::PubAck(PubAck {
+                pkid: 7,
+                ..Default::default()
+            }))
+            .send(Packet::Disconnect(Disconnect::default()))
+            .spawn();
+
+        let recorder = Recorder::default();
+        let fields = recorder.fields.clone();
+        let options = ConnectOptions {
+            username: Some(String::from("secret user")),
+            password: Some(String::from("secret password")),
+            ..Default::default()
+        };
+        tracing::subscriber::with_default(recorder, || {
+            block_on(async {
+                let (mut network, _client) = Network::new(options, stream).await.unwrap();
+                let status = network.run(&mut Ignore).await.unwrap();
+                assert!(matches!(status, NetworkStatus::IncomingDisconnect));
+            })
+        });
+        mock.join().unwrap();
+
+        let fields = fields.lock().unwrap();
+        assert!(fields.contains(&String::from("client_id=UniqueClientId")));
+        for r#type in ["CONNECT", "CONNACK", "PUBLISH", "PUBACK", "DISCONNECT"] {
+            assert!(
+                fields.iter().any(|field| field == &format!("packet_type={type}")),
+                "no event for {type}: {fields:?}"
+            );
+        }
+        assert!(fields.contains(&String::from("pkid=7")));
+        assert!(fields.iter().all(|field| !field.contains("secret")), "{fields:?}");
+    }
+
+    #[cfg(feature = "tokio")]
+    #[test]
+    fn runs_over_a_tokio_stream() {
+        use futures::{SinkExt, StreamExt};
+        use tokio_util::codec::Framed;
+
+        use crate::v5::{codec::MqttCodec, packet::disconnect::Disconnect};
+
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap();
+
+        runtime.block_on(async {
+            let (client, server) = tokio::io::duplex(1024);
+            let mut server = Framed::new(server, MqttCodec::default());
+
+            let broker = async {
+                let connect = server.next().await.unwrap().unwrap();
+                assert!(matches!(connect, Packet::Connect(_)));
+                server.send(Packet::ConnAck(ConnAck::default())).await.unwrap();
+            };
+            let (network, _) = join!(Network::new_tokio(ConnectOptions::default(), client), broker);
+            let (mut network, client) = network.unwrap();
+
+            client.disconnect().await.unwrap();
+            let status = network.run(&mut Ignore).await.unwrap();
+            assert!(matches!(status, NetworkStatus::OutgoingDisconnect));
+            assert!(matches!(
+                server.next().await,
+                Some(Ok(Packet::Disconnect(Disconnect { .. })))
+            ));
+        });
+    }
+
+    #[cfg(feature = "tokio")]
+    #[test]
+    fn keeps_alive_and_reconnects_on_tokio() {
+        use std::net::Ipv4Addr;
+
+        use futures::{SinkExt, StreamExt};
+        use tokio::net::TcpListener;
+        use tokio_util::codec::Framed;
+
+        use crate::v5::{
+            client::connector::TokioTcpConnector,
+            codec::MqttCodec,
+            packet::{disconnect::Disconnect, ping::PingResp},
+        };
+
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap();
+
+        runtime.block_on(async {
+            let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).await.unwrap();
+            let port = listener.local_addr().unwrap().port();
+            let connector = TokioTcpConnector::new("127.0.0.1", port);
+            let accept = || async {
+                let (stream, _) = listener.accept().await.unwrap();
+                let mut stream = Framed::new(stream, MqttCodec::default());
+                let connect = stream.next().await.unwrap().unwrap();
+                assert!(matches!(connect, Packet::Connect(_)));
+                stream.send(Packet::ConnAck(ConnAck::default())).await.unwrap();
+                stream
+            };
+
+            let options = ConnectOptions {
+                keep_alive: 1,
+                ..Default::default()
+            };
+            let (network, mut server) = join!(Network::connect_tokio(options, &connector), accept());
+            let (mut network, client) = network.unwrap();
+
+            // the keep alive runs on tokio's timers, then the server goes away
+            let broker = async {
+                let pingreq = server.next().await.unwrap().unwrap();
+                assert!(matches!(pingreq, Packet::PingReq(_)));
+                server.send(Packet::PingResp(PingResp)).await.unwrap();
+                drop(server);
+            };
+            let mut ignore = Ignore;
+            let (error, _) = join!(network.run(&mut ignore), broker);
+            assert!(matches!(error, Err(RunError::Io(_))));
+            assert_eq!(client.stats().ping_latency.count, 1);
+
+            let (reconnection, mut server) = join!(network.reconnect(&connector), accept());
+            assert!(reconnection.is_ok());
+
+            client.disconnect().await.unwrap();
+            let status = network.run(&mut Ignore).await.unwrap();
+            assert!(matches!(status, NetworkStatus::OutgoingDisconnect));
+            assert!(matches!(
+                server.next().await,
+                Some(Ok(Packet::Disconnect(Disconnect { .. })))
+            ));
+        });
     }
 }
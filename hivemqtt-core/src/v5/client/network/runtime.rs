@@ -0,0 +1,106 @@
+//! What the [`Network`](super::asyncx::Network) needs from an async runtime: reading and writing its stream, and
+//! waiting on the keep alive.
+//!
+//! [`Futures`] drives the streams implementing the `futures` IO traits (async-std, smol, or those of the
+//! [`Connector`](crate::v5::client::connector::Connector)s of this crate) on `async-io` timers. With the `tokio`
+//! feature, `Tokio` drives the streams implementing tokio's IO traits on tokio's timers, so that a
+//! `tokio::net::TcpStream` is handed over as is
+use std::{future::Future, io, time::Instant};
+
+use async_io::Timer;
+use futures::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+/// Drives the stream `S` of a network
+pub trait Runtime<S> {
+    /// Reads whatever is available into `buf`, 0 bytes once the stream is closed.
+    /// Cancel safe: nothing is lost when the future is dropped before it completes
+    fn read<'a>(
+        stream: &'a mut S,
+        buf: &'a mut [u8],
+    ) -> impl Future<Output = io::Result<usize>> + 'a;
+
+    fn write_all<'a>(
+        stream: &'a mut S,
+        buf: &'a [u8],
+    ) -> impl Future<Output = io::Result<()>> + 'a;
+
+    fn flush(stream: &mut S) -> impl Future<Output = io::Result<()>> + '_;
+
+    /// Closes the stream, once whatever was written to it is flushed
+    fn close(stream: &mut S) -> impl Future<Output = io::Result<()>> + '_;
+
+    /// Completes once `deadline` is reached
+    fn sleep_until(deadline: Instant) -> impl Future<Output = ()>;
+}
+
+/// The `futures` IO traits, and the timers of `async-io`
+#[derive(Debug)]
+pub struct Futures;
+
+impl<S> Runtime<S> for Futures
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    fn read<'a>(
+        stream: &'a mut S,
+        buf: &'a mut [u8],
+    ) -> impl Future<Output = io::Result<usize>> + 'a {
+        AsyncReadExt::read(stream, buf)
+    }
+
+    fn write_all<'a>(
+        stream: &'a mut S,
+        buf: &'a [u8],
+    ) -> impl Future<Output = io::Result<()>> + 'a {
+        AsyncWriteExt::write_all(stream, buf)
+    }
+
+    fn flush(stream: &mut S) -> impl Future<Output = io::Result<()>> + '_ {
+        AsyncWriteExt::flush(stream)
+    }
+
+    fn close(stream: &mut S) -> impl Future<Output = io::Result<()>> + '_ {
+        AsyncWriteExt::close(stream)
+    }
+
+    async fn sleep_until(deadline: Instant) {
+        Timer::at(deadline).await;
+    }
+}
+
+/// tokio's IO traits and timers. The network must run within a tokio runtime with the time driver enabled
+#[cfg(feature = "tokio")]
+#[derive(Debug)]
+pub struct Tokio;
+
+#[cfg(feature = "tokio")]
+impl<S> Runtime<S> for Tokio
+where
+    S: tokio::io::AsyncRead + tokio::io::AsyncWrite + Unpin,
+{
+    fn read<'a>(
+        stream: &'a mut S,
+        buf: &'a mut [u8],
+    ) -> impl Future<Output = io::Result<usize>> + 'a {
+        tokio::io::AsyncReadExt::read(stream, buf)
+    }
+
+    fn write_all<'a>(
+        stream: &'a mut S,
+        buf: &'a [u8],
+    ) -> impl Future<Output = io::Result<()>> + 'a {
+        tokio::io::AsyncWriteExt::write_all(stream, buf)
+    }
+
+    fn flush(stream: &mut S) -> impl Future<Output = io::Result<()>> + '_ {
+        tokio::io::AsyncWriteExt::flush(stream)
+    }
+
+    fn close(stream: &mut S) -> impl Future<Output = io::Result<()>> + '_ {
+        tokio::io::AsyncWriteExt::shutdown(stream)
+    }
+
+    async fn sleep_until(deadline: Instant) {
+        tokio::time::sleep_until(deadline.into()).await;
+    }
+}
@@ -1,24 +1,37 @@
-use std::sync::atomic::{AtomicU16, Ordering};
+use alloc::vec::Vec;
+use core::sync::atomic::{AtomicU16, Ordering};
 
 mod shard;
 use shard::PacketIdShard;
+#[cfg(feature = "std")]
+mod wait;
+#[cfg(feature = "std")]
+pub use wait::AllocateWait;
 
 use crate::v5::{
     commons::error::MQTTError,
     traits::pkid_mgr::{PacketIdAlloc, PacketIdRelease},
 };
 
+/// Hands out packet identifiers in turn: the search for a free one starts after the last one allocated, so an
+/// identifier that was just released is only reused once all the others have been tried. A late (duplicate)
+/// acknowledgement from the server is then unlikely to match a new packet.
 #[derive(Debug)]
 pub struct PacketIdManager {
     shards: Vec<PacketIdShard>,
     allocated: AtomicU16,
     max_packets: u16,
+    /// Index (packet identifier - 1) the next search starts from
+    next: AtomicU16,
+    #[cfg(feature = "std")]
+    waiters: wait::Waiters,
 }
 
 impl PacketIdManager {
     const BITS: usize = usize::BITS as usize;
 
-    pub(crate) fn new(max_packets: u16) -> Self {
+    /// Allocates packet identifiers from 1 to `max_packets`, the Receive Maximum of the server (3.2.2.3.3)
+    pub fn new(max_packets: u16) -> Self {
         let num_shards = (max_packets as usize + Self::BITS - 1) / Self::BITS;
         let shards = (0..num_shards)
             .map(|_| PacketIdShard::default())
@@ -27,48 +40,101 @@ impl PacketIdManager {
             shards,
             allocated: AtomicU16::new(0),
             max_packets,
+            next: AtomicU16::new(0),
+            #[cfg(feature = "std")]
+            waiters: wait::Waiters::default(),
         }
     }
-}
 
-impl PacketIdAlloc for PacketIdManager {
-    fn allocate(&self) -> Result<u16, MQTTError> {
+    /// Waits until a packet identifier is available, instead of failing like [`allocate`](PacketIdAlloc::allocate)
+    /// does when all of them are in use.
+    ///
+    /// Waiters are served in the order they started waiting, and [`allocate`](PacketIdAlloc::allocate) does not take
+    /// an identifier while anyone is waiting. Dropping the returned future gives up its turn to the next waiter.
+    /// It resolves to `None` if the manager is [closed](Self::close) before an identifier is free.
+    #[cfg(feature = "std")]
+    pub fn allocate_wait(&self) -> AllocateWait<'_> {
+        AllocateWait::new(self)
+    }
+
+    /// Sends away the tasks waiting for a packet identifier, and those that would have to wait, until
+    /// [`open`](Self::open): the network that releases the identifiers is not running
+    #[cfg(feature = "std")]
+    pub(crate) fn close(&self) {
+        self.waiters.close();
+    }
+
+    #[cfg(feature = "std")]
+    pub(crate) fn open(&self) {
+        self.waiters.open();
+    }
+
+    /// Reserves a free packet identifier, regardless of the waiters: for the network itself, which cannot wait behind
+    /// the clients for an identifier only it can release
+    pub(crate) fn take(&self) -> Option<u16> {
         let allocated = self.allocated.fetch_add(1, Ordering::AcqRel);
         if allocated >= self.max_packets {
             // rollback
             self.allocated.fetch_sub(1, Ordering::Release);
-            return Err(MQTTError::PacketIdGenerationError);
+            return None;
         }
 
-        for (shard_index, shard) in self.shards.iter().enumerate() {
-            if let Some(id) = shard.allocate() {
+        let max_packets = self.max_packets as usize;
+        let start = self.next.load(Ordering::Relaxed) as usize % max_packets;
+        let (first, offset) = (start / Self::BITS, start % Self::BITS);
+        let num_shards = self.shards.len();
+
+        // from `start` to the end, then from the beginning back to `start`: the shard `start` is in is visited twice
+        for step in 0..=num_shards {
+            let shard_index = (first + step) % num_shards;
+            let mut mask = self.valid_ids(shard_index);
+            if step == 0 {
+                mask &= usize::MAX << offset;
+            } else if step == num_shards {
+                mask &= !(usize::MAX << offset);
+            }
+
+            if let Some(id) = self.shards[shard_index].allocate_in(mask) {
+                let index = shard_index * Self::BITS + id as usize;
+                self.next
+                    .store(((index + 1) % max_packets) as u16, Ordering::Relaxed);
                 // packet must always be non-zero
-                let packet_id = (shard_index * (Self::BITS) + id as usize) + 1;
-                return Ok(packet_id as u16);
+                return Some(index as u16 + 1);
             }
         }
 
         // It should be almost impossible for this to occur, but its better taken care of than not!
         self.allocated.fetch_sub(1, Ordering::Release);
-        return Err(MQTTError::PacketIdGenerationError);
+        None
+    }
+
+    /// Bits of the shard that stand for packet identifiers up to `max_packets`
+    fn valid_ids(&self, shard_index: usize) -> usize {
+        let ids = self.max_packets as usize - shard_index * Self::BITS;
+        if ids >= Self::BITS {
+            usize::MAX
+        } else {
+            (1 << ids) - 1
+        }
     }
 }
 
-impl PacketIdRelease for PacketIdManager {
-    /// Returns whether the packetId is in use or free (Not yet tested)
-    fn is_occupied(&self, id: u16) -> bool {
-        let id = (id - 1) as usize;
-        let shard_index = id / Self::BITS;
-        let actual_index_in_shard = (id % Self::BITS) as u8;
-        let result = self
-            .shards
-            .get(shard_index)
-            .and_then(|shard| Some(shard.release(actual_index_in_shard)));
-        return result.is_some();
+impl PacketIdAlloc for PacketIdManager {
+    fn allocate(&self) -> Result<u16, MQTTError> {
+        #[cfg(feature = "std")]
+        if self.waiters.any() {
+            return Err(MQTTError::PacketIdGenerationError);
+        }
+
+        self.take().ok_or(MQTTError::PacketIdGenerationError)
     }
+}
 
+impl PacketIdRelease for PacketIdManager {
     fn release(&self, id: u16) {
-        let id = (id - 1) as usize;
+        let Some(id) = id.checked_sub(1).map(usize::from) else {
+            return;
+        };
         let shard_index = id / Self::BITS;
         let actual_index_in_shard = (id % Self::BITS) as u8;
         let result = self
@@ -79,15 +145,15 @@ impl PacketIdRelease for PacketIdManager {
             let _ = self
                 .allocated
                 .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |n| n.checked_sub(1));
+            #[cfg(feature = "std")]
+            self.waiters.wake_first();
         }
     }
 }
 
-// use futures::execut
-
 #[cfg(test)]
 mod test {
-    use std::sync::atomic::Ordering;
+    use core::sync::atomic::Ordering;
 
     use super::*;
 
@@ -137,5 +203,25 @@ mod test {
 
         mgr.release(67);
         assert_eq!(mgr.allocated.load(Ordering::Relaxed), 0);
+
+        mgr.release(0);
+        assert_eq!(mgr.allocated.load(Ordering::Relaxed), 0);
+    }
+
+    #[test]
+    fn does_not_reuse_a_released_packet_id_straight_away() {
+        let mgr = PacketIdManager::new(100);
+        let ids = (0..70).map(|_| mgr.allocate().unwrap()).collect::<Vec<_>>();
+        assert_eq!(ids, (1..=70).collect::<Vec<_>>());
+
+        mgr.release(3);
+        mgr.release(70);
+        assert_eq!(mgr.allocate(), Ok(71));
+
+        // wraps around once the last packet identifier has been handed out, skipping those still in use
+        (72..=100).for_each(|id| assert_eq!(mgr.allocate(), Ok(id)));
+        assert_eq!(mgr.allocate(), Ok(3));
+        assert_eq!(mgr.allocate(), Ok(70));
+        assert_eq!(mgr.allocate(), Err(MQTTError::PacketIdGenerationError));
     }
 }
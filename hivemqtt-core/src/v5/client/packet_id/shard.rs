@@ -1,6 +1,6 @@
 // The #[cfg(target_has_atomic)]
 
-use std::sync::atomic::{AtomicUsize, Ordering};
+use core::sync::atomic::{AtomicUsize, Ordering};
 
 /// Depending on the target architecture, this can either be 64 bits or 32 bits
 #[derive(Debug, Default)]
@@ -8,10 +8,16 @@ pub(super) struct PacketIdShard(pub(super) AtomicUsize); // Each bit manages 64
 
 impl PacketIdShard {
     // Allocate an available packet ID
+    #[cfg(test)]
     pub(super) fn allocate(&self) -> Option<u16> {
+        self.allocate_in(usize::MAX)
+    }
+
+    /// Allocate the lowest available packet ID among the bits set in `mask`
+    pub(super) fn allocate_in(&self, mask: usize) -> Option<u16> {
         let mut bitmap = self.0.load(Ordering::Relaxed);
         loop {
-            let free_index = (!bitmap).trailing_zeros();
+            let free_index = (!bitmap & mask).trailing_zeros();
             if free_index >= usize::BITS { return None; } // break here
 
             let new_bitmap = bitmap | (1 << free_index);
@@ -0,0 +1,213 @@
+use std::{
+    collections::VecDeque,
+    future::Future,
+    pin::Pin,
+    sync::Mutex,
+    task::{Context, Poll, Waker},
+};
+
+use super::PacketIdManager;
+
+/// Tasks waiting for a packet identifier, first come first served
+#[derive(Debug, Default)]
+pub(super) struct Waiters(Mutex<Queue>);
+
+#[derive(Debug, Default)]
+struct Queue {
+    waiters: VecDeque<(u64, Waker)>,
+    next_ticket: u64,
+    /// No packet identifier will be released until the queue is opened again: nobody is let in to wait
+    closed: bool,
+}
+
+impl Waiters {
+    pub(super) fn any(&self) -> bool {
+        !self.0.lock().unwrap().waiters.is_empty()
+    }
+
+    /// Sends every waiter away, and those to come until [`open`](Self::open)
+    pub(super) fn close(&self) {
+        let mut queue = self.0.lock().unwrap();
+        queue.closed = true;
+        queue.waiters.drain(..).for_each(|(_, waker)| waker.wake());
+    }
+
+    pub(super) fn open(&self) {
+        self.0.lock().unwrap().closed = false;
+    }
+
+    /// Lets the first waiter try again, e.g. once a packet identifier has been released
+    pub(super) fn wake_first(&self) {
+        if let Some((_, waker)) = self.0.lock().unwrap().waiters.front() {
+            waker.wake_by_ref();
+        }
+    }
+}
+
+/// Future returned by [`PacketIdManager::allocate_wait`]
+#[derive(Debug)]
+#[must_use = "futures do nothing unless polled"]
+pub struct AllocateWait<'a> {
+    manager: &'a PacketIdManager,
+    /// Place in the queue, once the future had to wait
+    ticket: Option<u64>,
+}
+
+impl<'a> AllocateWait<'a> {
+    pub(super) fn new(manager: &'a PacketIdManager) -> Self {
+        Self {
+            manager,
+            ticket: None,
+        }
+    }
+}
+
+impl Future for AllocateWait<'_> {
+    /// `None` if the queue was closed before a packet identifier was free
+    type Output = Option<u16>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<u16>> {
+        let manager = self.manager;
+        // held while trying, so that a release in the meantime cannot wake the waiter before it registered its waker
+        let mut queue = manager.waiters.0.lock().unwrap();
+
+        let Some(ticket) = self.ticket else {
+            if queue.waiters.is_empty() {
+                if let Some(id) = manager.take() {
+                    return Poll::Ready(Some(id));
+                }
+            }
+            if queue.closed {
+                return Poll::Ready(None);
+            }
+            let ticket = queue.next_ticket;
+            queue.next_ticket += 1;
+            queue.waiters.push_back((ticket, cx.waker().clone()));
+            self.ticket = Some(ticket);
+            return Poll::Pending;
+        };
+
+        let first = queue.waiters.front().is_some_and(|(t, _)| *t == ticket);
+        if first {
+            if let Some(id) = manager.take() {
+                queue.waiters.pop_front();
+                self.ticket = None;
+                // more identifiers may be free, the next waiter finds out
+                if let Some((_, waker)) = queue.waiters.front() {
+                    waker.wake_by_ref();
+                }
+                return Poll::Ready(Some(id));
+            }
+        }
+
+        match queue.waiters.iter_mut().find(|(t, _)| *t == ticket) {
+            Some((_, waker)) => waker.clone_from(cx.waker()),
+            // sent away by `close`
+            None => {
+                self.ticket = None;
+                return Poll::Ready(None);
+            }
+        }
+        Poll::Pending
+    }
+}
+
+impl Drop for AllocateWait<'_> {
+    fn drop(&mut self) {
+        let Some(ticket) = self.ticket else {
+            return;
+        };
+        let mut queue = self.manager.waiters.0.lock().unwrap();
+        let Some(position) = queue.waiters.iter().position(|(t, _)| *t == ticket) else {
+            return;
+        };
+        queue.waiters.remove(position);
+        // it may have been woken for an identifier it will never take
+        if position == 0 {
+            if let Some((_, waker)) = queue.waiters.front() {
+                waker.wake_by_ref();
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::{executor::block_on, task::noop_waker, FutureExt};
+
+    use super::*;
+    use crate::v5::traits::pkid_mgr::{PacketIdAlloc, PacketIdRelease};
+
+    fn poll(future: &mut AllocateWait<'_>) -> Poll<Option<u16>> {
+        future.poll_unpin(&mut Context::from_waker(&noop_waker()))
+    }
+
+    #[test]
+    fn waits_for_a_packet_id_to_be_released() {
+        let mgr = PacketIdManager::new(1);
+        assert_eq!(block_on(mgr.allocate_wait()), Some(1));
+
+        let mut waiting = mgr.allocate_wait();
+        assert_eq!(poll(&mut waiting), Poll::Pending);
+        assert!(mgr.allocate().is_err());
+
+        mgr.release(1);
+        // the waiter comes first
+        assert!(mgr.allocate().is_err());
+        assert_eq!(poll(&mut waiting), Poll::Ready(Some(1)));
+        assert!(!mgr.waiters.any());
+    }
+
+    #[test]
+    fn serves_waiters_in_order() {
+        let mgr = PacketIdManager::new(2);
+        let (first, second) = (mgr.allocate().unwrap(), mgr.allocate().unwrap());
+
+        let mut a = mgr.allocate_wait();
+        let mut b = mgr.allocate_wait();
+        assert_eq!(poll(&mut a), Poll::Pending);
+        assert_eq!(poll(&mut b), Poll::Pending);
+
+        mgr.release(second);
+        assert_eq!(poll(&mut b), Poll::Pending);
+        assert_eq!(poll(&mut a), Poll::Ready(Some(second)));
+
+        mgr.release(first);
+        assert_eq!(poll(&mut b), Poll::Ready(Some(first)));
+    }
+
+    #[test]
+    fn passes_the_turn_on_when_a_waiter_is_dropped() {
+        let mgr = PacketIdManager::new(1);
+        let id = mgr.allocate().unwrap();
+
+        let mut a = mgr.allocate_wait();
+        let mut b = mgr.allocate_wait();
+        assert_eq!(poll(&mut a), Poll::Pending);
+        assert_eq!(poll(&mut b), Poll::Pending);
+
+        mgr.release(id);
+        drop(a);
+        assert_eq!(poll(&mut b), Poll::Ready(Some(id)));
+        assert!(!mgr.waiters.any());
+    }
+
+    #[test]
+    fn sends_the_waiters_away_once_closed() {
+        let mgr = PacketIdManager::new(1);
+        let id = mgr.allocate().unwrap();
+
+        let mut waiting = mgr.allocate_wait();
+        assert_eq!(poll(&mut waiting), Poll::Pending);
+        mgr.close();
+        assert_eq!(poll(&mut waiting), Poll::Ready(None));
+        assert_eq!(block_on(mgr.allocate_wait()), None);
+
+        // a free packet identifier is still handed out
+        mgr.release(id);
+        assert_eq!(block_on(mgr.allocate_wait()), Some(id));
+        mgr.open();
+        let mut waiting = mgr.allocate_wait();
+        assert_eq!(poll(&mut waiting), Poll::Pending);
+    }
+}
@@ -0,0 +1,397 @@
+//! Typed payloads. A [`PayloadCodec`] turns values into payloads and back, and tells the Content Type (3.3.2.3.9) and
+//! the Payload Format Indicator (3.3.2.3.2) of the messages it encodes:
+//! ```ignore
+//! client.publish_typed("sensors/temperature", QoS::One, false, &reading, &Json, None).await?;
+//! ...
+//! let reading: Reading = message.decode()?;
+//! ```
+//! [`Publish::decode`] picks the codec by the Content Type of the message: [`Json`] for `application/json` (and the
+//! `+json` types), [`Cbor`] for `application/cbor` (and the `+cbor` types), [`Text`] for the `text/` types, or for a
+//! message without Content Type whose Payload Format Indicator says it is UTF-8.
+use bytes::Bytes;
+use serde::{
+    de::{value::Error as TextError, DeserializeOwned, IntoDeserializer},
+    ser::{self, Impossible},
+    Serialize, Serializer,
+};
+
+use super::error::PayloadError;
+use crate::v5::packet::publish::Publish;
+
+pub trait PayloadCodec {
+    /// Content Type of the messages it encodes
+    fn content_type(&self) -> &str;
+    /// Whether the payloads it encodes are UTF-8 Encoded Character Data, set as their Payload Format Indicator
+    fn is_utf8(&self) -> bool;
+    fn encode<T: Serialize + ?Sized>(&self, value: &T) -> Result<Bytes, PayloadError>;
+    fn decode<T: DeserializeOwned>(&self, payload: &[u8]) -> Result<T, PayloadError>;
+}
+
+/// JSON payloads, with serde_json
+#[cfg(feature = "json")]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Json;
+
+#[cfg(feature = "json")]
+impl PayloadCodec for Json {
+    fn content_type(&self) -> &str {
+        "application/json"
+    }
+
+    /// JSON text exchanged between systems is UTF-8 encoded (RFC 8259)
+    fn is_utf8(&self) -> bool {
+        true
+    }
+
+    fn encode<T: Serialize + ?Sized>(&self, value: &T) -> Result<Bytes, PayloadError> {
+        let payload = serde_json::to_vec(value).map_err(|e| PayloadError::Encode(Box::new(e)))?;
+        Ok(Bytes::from(payload))
+    }
+
+    fn decode<T: DeserializeOwned>(&self, payload: &[u8]) -> Result<T, PayloadError> {
+        serde_json::from_slice(payload).map_err(|e| PayloadError::Decode(Box::new(e)))
+    }
+}
+
+/// CBOR payloads (RFC 8949), with ciborium
+#[cfg(feature = "cbor")]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Cbor;
+
+#[cfg(feature = "cbor")]
+impl PayloadCodec for Cbor {
+    fn content_type(&self) -> &str {
+        "application/cbor"
+    }
+
+    fn is_utf8(&self) -> bool {
+        false
+    }
+
+    fn encode<T: Serialize + ?Sized>(&self, value: &T) -> Result<Bytes, PayloadError> {
+        let mut payload = Vec::new();
+        ciborium::into_writer(value, &mut payload)
+            .map_err(|e| PayloadError::Encode(Box::new(e)))?;
+        Ok(Bytes::from(payload))
+    }
+
+    fn decode<T: DeserializeOwned>(&self, payload: &[u8]) -> Result<T, PayloadError> {
+        ciborium::from_reader(payload).map_err(|e| PayloadError::Decode(Box::new(e)))
+    }
+}
+
+/// UTF-8 text payloads, for the values serialized as a string (`String`, `char`, unit enum variants, and the newtypes
+/// around them)
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Text;
+
+impl PayloadCodec for Text {
+    fn content_type(&self) -> &str {
+        "text/plain; charset=utf-8"
+    }
+
+    fn is_utf8(&self) -> bool {
+        true
+    }
+
+    fn encode<T: Serialize + ?Sized>(&self, value: &T) -> Result<Bytes, PayloadError> {
+        let text = value
+            .serialize(TextSerializer)
+            .map_err(|e| PayloadError::Encode(Box::new(e)))?;
+        Ok(Bytes::from(text))
+    }
+
+    fn decode<T: DeserializeOwned>(&self, payload: &[u8]) -> Result<T, PayloadError> {
+        let text = std::str::from_utf8(payload)?;
+        let deserializer = IntoDeserializer::<TextError>::into_deserializer(text);
+        T::deserialize(deserializer).map_err(|e| PayloadError::Decode(Box::new(e)))
+    }
+}
+
+impl Publish {
+    /// Decodes the payload with the codec of its Content Type (see [`payload`](self))
+    pub fn decode<T: DeserializeOwned>(&self) -> Result<T, PayloadError> {
+        let content_type = self.properties.content_type.as_deref();
+        // parameters (e.g. `; charset=utf-8`) do not change the codec
+        let essence = content_type.map(|content_type| {
+            let essence = content_type
+                .split_once(';')
+                .map_or(content_type, |(e, _)| e);
+            essence.trim().to_ascii_lowercase()
+        });
+
+        match essence.as_deref() {
+            #[cfg(feature = "json")]
+            Some(essence) if essence == "application/json" || essence.ends_with("+json") => {
+                self.decode_with(&Json)
+            }
+            #[cfg(feature = "cbor")]
+            Some(essence) if essence == "application/cbor" || essence.ends_with("+cbor") => {
+                self.decode_with(&Cbor)
+            }
+            Some(essence) if essence.starts_with("text/") => self.decode_with(&Text),
+            None if self.properties.payload_format_indicator == Some(1) => self.decode_with(&Text),
+            _ => Err(PayloadError::UnknownContentType(
+                content_type.map(String::from),
+            )),
+        }
+    }
+
+    /// Decodes the payload with `codec`, whatever the Content Type of the message
+    pub fn decode_with<C, T>(&self, codec: &C) -> Result<T, PayloadError>
+    where
+        C: PayloadCodec,
+        T: DeserializeOwned,
+    {
+        self.check_payload_format()?;
+        codec.decode(&self.payload)
+    }
+}
+
+/// Serializes the values [`Text`] encodes, and refuses the others
+struct TextSerializer;
+
+fn not_text() -> TextError {
+    ser::Error::custom("the Text codec only encodes strings")
+}
+
+macro_rules! not_text {
+    ($($method:ident($($arg:ty),*)),* $(,)?) => {
+        $(
+            fn $method(self, $(_: $arg),*) -> Result<String, TextError> {
+                Err(not_text())
+            }
+        )*
+    };
+}
+
+impl Serializer for TextSerializer {
+    type Ok = String;
+    type Error = TextError;
+    type SerializeSeq = Impossible<String, TextError>;
+    type SerializeTuple = Impossible<String, TextError>;
+    type SerializeTupleStruct = Impossible<String, TextError>;
+    type SerializeTupleVariant = Impossible<String, TextError>;
+    type SerializeMap = Impossible<String, TextError>;
+    type SerializeStruct = Impossible<String, TextError>;
+    type SerializeStructVariant = Impossible<String, TextError>;
+
+    fn serialize_str(self, v: &str) -> Result<String, TextError> {
+        Ok(String::from(v))
+    }
+
+    fn serialize_char(self, v: char) -> Result<String, TextError> {
+        Ok(String::from(v))
+    }
+
+    fn serialize_unit_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+    ) -> Result<String, TextError> {
+        Ok(String::from(variant))
+    }
+
+    fn serialize_newtype_struct<T: Serialize + ?Sized>(
+        self,
+        _name: &'static str,
+        value: &T,
+    ) -> Result<String, TextError> {
+        value.serialize(self)
+    }
+
+    not_text! {
+        serialize_bool(bool),
+        serialize_i8(i8),
+        serialize_i16(i16),
+        serialize_i32(i32),
+        serialize_i64(i64),
+        serialize_u8(u8),
+        serialize_u16(u16),
+        serialize_u32(u32),
+        serialize_u64(u64),
+        serialize_f32(f32),
+        serialize_f64(f64),
+        serialize_bytes(&[u8]),
+        serialize_none(),
+        serialize_unit(),
+        serialize_unit_struct(&'static str),
+    }
+
+    fn serialize_some<T: Serialize + ?Sized>(self, _value: &T) -> Result<String, TextError> {
+        Err(not_text())
+    }
+
+    fn serialize_newtype_variant<T: Serialize + ?Sized>(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        _variant: &'static str,
+        _value: &T,
+    ) -> Result<String, TextError> {
+        Err(not_text())
+    }
+
+    fn serialize_seq(self, _len: Option<usize>) -> Result<Self::SerializeSeq, TextError> {
+        Err(not_text())
+    }
+
+    fn serialize_tuple(self, _len: usize) -> Result<Self::SerializeTuple, TextError> {
+        Err(not_text())
+    }
+
+    fn serialize_tuple_struct(
+        self,
+        _name: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeTupleStruct, TextError> {
+        Err(not_text())
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        _variant: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeTupleVariant, TextError> {
+        Err(not_text())
+    }
+
+    fn serialize_map(self, _len: Option<usize>) -> Result<Self::SerializeMap, TextError> {
+        Err(not_text())
+    }
+
+    fn serialize_struct(
+        self,
+        _name: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeStruct, TextError> {
+        Err(not_text())
+    }
+
+    fn serialize_struct_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        _variant: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeStructVariant, TextError> {
+        Err(not_text())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde::Deserialize;
+
+    use super::*;
+    use crate::v5::packet::publish::PublishProperties;
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Reading {
+        sensor: String,
+        celsius: f64,
+    }
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    enum Status {
+        Online,
+        Offline,
+    }
+
+    fn encoded<C: PayloadCodec, T: Serialize + ?Sized>(codec: &C, value: &T) -> Publish {
+        Publish {
+            payload: codec.encode(value).unwrap(),
+            properties: PublishProperties {
+                content_type: Some(String::from(codec.content_type())),
+                payload_format_indicator: codec.is_utf8().then_some(1),
+                ..Default::default()
+            },
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn encodes_strings_as_text() {
+        let publish = encoded(&Text, &Status::Online);
+        assert_eq!(publish.payload, "Online");
+        assert_eq!(publish.decode::<Status>().unwrap(), Status::Online);
+        assert_eq!(encoded(&Text, "21.5").decode::<String>().unwrap(), "21.5");
+
+        let reading = Reading {
+            sensor: String::from("a"),
+            celsius: 21.5,
+        };
+        assert!(matches!(
+            Text.encode(&reading),
+            Err(PayloadError::Encode(_))
+        ));
+        assert!(matches!(Text.encode(&21.5), Err(PayloadError::Encode(_))));
+    }
+
+    #[test]
+    fn refuses_a_payload_that_is_not_utf8_as_its_indicator_says() {
+        let mut publish = encoded(&Text, "21.5");
+        publish.payload = Bytes::from_static(b"\xc3\x28");
+        assert!(matches!(
+            publish.decode::<String>(),
+            Err(PayloadError::InvalidUtf8(_))
+        ));
+    }
+
+    #[test]
+    fn picks_the_codec_by_content_type() {
+        let mut publish = encoded(&Text, "21.5");
+        publish.properties.content_type = Some(String::from("Text/Plain"));
+        assert_eq!(publish.decode::<String>().unwrap(), "21.5");
+
+        // without Content Type, only a UTF-8 payload is known to be text
+        publish.properties.content_type = None;
+        assert_eq!(publish.decode::<String>().unwrap(), "21.5");
+        publish.properties.payload_format_indicator = None;
+        assert!(matches!(
+            publish.decode::<String>(),
+            Err(PayloadError::UnknownContentType(None))
+        ));
+
+        publish.properties.content_type = Some(String::from("application/xml"));
+        assert!(matches!(
+            publish.decode::<String>(),
+            Err(PayloadError::UnknownContentType(Some(_)))
+        ));
+        assert_eq!(publish.decode_with::<_, String>(&Text).unwrap(), "21.5");
+    }
+
+    #[cfg(feature = "json")]
+    #[test]
+    fn round_trips_json() {
+        let reading = Reading {
+            sensor: String::from("a"),
+            celsius: 21.5,
+        };
+        let mut publish = encoded(&Json, &reading);
+        assert_eq!(publish.payload, r#"{"sensor":"a","celsius":21.5}"#);
+        assert_eq!(publish.decode::<Reading>().unwrap(), reading);
+
+        publish.properties.content_type = Some(String::from("application/vnd.sensor+json; v=2"));
+        assert_eq!(publish.decode::<Reading>().unwrap(), reading);
+        assert!(matches!(
+            publish.decode::<Status>(),
+            Err(PayloadError::Decode(_))
+        ));
+    }
+
+    #[cfg(feature = "cbor")]
+    #[test]
+    fn round_trips_cbor() {
+        let reading = Reading {
+            sensor: String::from("a"),
+            celsius: 21.5,
+        };
+        let publish = encoded(&Cbor, &reading);
+        assert_eq!(publish.properties.payload_format_indicator, None);
+        assert_eq!(publish.decode::<Reading>().unwrap(), reading);
+    }
+}
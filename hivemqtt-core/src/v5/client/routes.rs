@@ -0,0 +1,328 @@
+//! Delivery of the messages received to the [`Subscription`](super::Subscription) handles they were subscribed for.
+//!
+//! When the server supports Subscription Identifiers (3.2.2.3.12), every SUBSCRIBE gets one, and a PUBLISH is delivered
+//! to the handles whose identifiers it carries (3.3.2.3.8): once per matching subscription, without matching its topic
+//! again. Otherwise, it is delivered to every handle with a topic filter matching its topic.
+//! Handles of the same topic filter share a single subscription with the server, and the same identifier.
+use std::{collections::VecDeque, sync::Mutex};
+
+use async_channel::{Receiver, Sender};
+
+use crate::v5::{
+    packet::{publish::Publish, subscribe::SubscribeProperties, unsubscribe::UnSubscribe},
+    utils::topic::matches,
+};
+
+/// Highest Subscription Identifier, the largest Variable Byte Integer (3.8.2.1.2)
+const MAX_SUBSCRIPTION_ID: usize = 268_435_455;
+
+#[derive(Debug)]
+struct Route {
+    /// Tells the handles apart
+    key: u64,
+    subscription_id: Option<usize>,
+    filter: String,
+    tx: Sender<Publish>,
+}
+
+impl Route {
+    fn matches(&self, topic: &str) -> bool {
+        // the topic filter of a shared subscription follows `$share/{ShareName}/` (4.8.2)
+        let filter = match self.filter.strip_prefix("$share/") {
+            Some(shared) => shared.split_once('/').map_or(shared, |(_, filter)| filter),
+            None => &self.filter,
+        };
+        matches(filter, topic)
+    }
+}
+
+#[derive(Debug)]
+struct Inner {
+    /// Whether the server of the current connection supports Subscription Identifiers
+    subscription_ids: bool,
+    next_id: usize,
+    next_key: u64,
+    routes: Vec<Route>,
+    /// UNSUBSCRIBE packets of dropped handles that did not fit in the queue of outgoing packets, or got no packet
+    /// identifier (0) as every one was in use, along with the Subscription Identifier of the subscription they end
+    deferred: VecDeque<(UnSubscribe, Option<usize>)>,
+}
+
+/// A handle registered by [`Routes::add`]
+#[derive(Debug)]
+pub(crate) struct Added {
+    pub(crate) key: u64,
+    pub(crate) messages: Receiver<Publish>,
+    /// Whether no other handle has the same topic filter, the server has to be asked for the subscription
+    pub(crate) first: bool,
+    /// Packet identifier of the UNSUBSCRIBE that was about to end the subscription, it is not sent anymore
+    pub(crate) cancelled: Option<u16>,
+}
+
+/// Where the client registers its [`Subscription`](super::Subscription) handles, and the network finds whom to deliver
+/// the messages it receives to
+#[derive(Debug)]
+pub(crate) struct Routes {
+    inner: Mutex<Inner>,
+    /// Messages a handle holds, before the oldest ones are dropped to make room
+    capacity: usize,
+}
+
+impl Routes {
+    pub(crate) fn new(capacity: usize) -> Self {
+        Self {
+            inner: Mutex::new(Inner {
+                subscription_ids: false,
+                next_id: 1,
+                next_key: 0,
+                routes: Vec::new(),
+                deferred: VecDeque::new(),
+            }),
+            capacity,
+        }
+    }
+
+    /// Records whether the server the network (re)connected to supports Subscription Identifiers
+    pub(crate) fn connected(&self, subscription_ids: bool) {
+        self.inner.lock().unwrap().subscription_ids = subscription_ids;
+    }
+
+    /// Registers a handle for `filter`. The first one of a topic filter gets a Subscription Identifier if the server
+    /// supports them and `properties` does not set one already, the others share it
+    pub(crate) fn add(&self, filter: &str, properties: &mut SubscribeProperties) -> Added {
+        let mut inner = self.inner.lock().unwrap();
+        let key = inner.next_key;
+        inner.next_key += 1;
+
+        let (tx, messages) = async_channel::bounded(self.capacity);
+        let shared = inner.routes.iter().find(|route| route.filter == filter);
+        let shared = shared.map(|route| (route.subscription_id, None));
+        // the subscription of the last handle dropped is still there, as long as its UNSUBSCRIBE was not sent
+        let deferred = || {
+            let index = inner.deferred.iter().position(|(unsubscribe, _)| {
+                unsubscribe
+                    .payload
+                    .iter()
+                    .any(|unsubscribed| unsubscribed == filter)
+            })?;
+            let (unsubscribe, subscription_id) = inner.deferred.remove(index)?;
+            Some((
+                subscription_id,
+                Some(unsubscribe.pkid).filter(|pkid| *pkid != 0),
+            ))
+        };
+        if let Some((subscription_id, cancelled)) = shared.or_else(deferred) {
+            inner.routes.push(Route {
+                key,
+                subscription_id,
+                filter: String::from(filter),
+                tx,
+            });
+            return Added {
+                key,
+                messages,
+                first: false,
+                cancelled,
+            };
+        }
+
+        if inner.subscription_ids && properties.subscription_id.is_none() {
+            let mut id = inner.next_id;
+            while inner
+                .routes
+                .iter()
+                .any(|route| route.subscription_id == Some(id))
+            {
+                id = id % MAX_SUBSCRIPTION_ID + 1;
+            }
+            inner.next_id = id % MAX_SUBSCRIPTION_ID + 1;
+            properties.subscription_id = Some(id);
+        }
+
+        inner.routes.push(Route {
+            key,
+            subscription_id: properties.subscription_id,
+            filter: String::from(filter),
+            tx,
+        });
+        Added {
+            key,
+            messages,
+            first: true,
+            cancelled: None,
+        }
+    }
+
+    /// Forgets the handle. If it was the last one of its topic filter, returns the Subscription Identifier of the
+    /// subscription, which has to be ended
+    pub(crate) fn remove(&self, key: u64) -> Option<Option<usize>> {
+        let mut inner = self.inner.lock().unwrap();
+        let index = inner.routes.iter().position(|route| route.key == key)?;
+        let route = inner.routes.remove(index);
+        let last = inner
+            .routes
+            .iter()
+            .all(|other| other.filter != route.filter);
+        last.then_some(route.subscription_id)
+    }
+
+    /// Keeps the UNSUBSCRIBE of a dropped handle until the network takes it
+    pub(crate) fn defer(&self, unsubscribe: UnSubscribe, subscription_id: Option<usize>) {
+        let mut inner = self.inner.lock().unwrap();
+        inner.deferred.push_back((unsubscribe, subscription_id));
+    }
+
+    /// The deferred UNSUBSCRIBE packets, in order, as long as those without a packet identifier get one from
+    /// `allocate`. The others wait for a packet identifier to be released
+    pub(crate) fn take_deferred(
+        &self,
+        mut allocate: impl FnMut() -> Option<u16>,
+    ) -> Vec<UnSubscribe> {
+        let mut inner = self.inner.lock().unwrap();
+        let mut taken = Vec::new();
+        while let Some((unsubscribe, _)) = inner.deferred.front_mut() {
+            if unsubscribe.pkid == 0 {
+                let Some(pkid) = allocate() else {
+                    break;
+                };
+                unsubscribe.pkid = pkid;
+            }
+            let (unsubscribe, _) = inner.deferred.pop_front().unwrap();
+            taken.push(unsubscribe);
+        }
+        taken
+    }
+
+    /// The handles `publish` is to be delivered to, each one with its own copy of it
+    pub(crate) fn deliver(&self, publish: &Publish) -> Vec<(Sender<Publish>, Publish)> {
+        let inner = self.inner.lock().unwrap();
+        let ids = &publish.properties.subscription_identifier;
+        inner
+            .routes
+            .iter()
+            .filter(|route| match route.subscription_id {
+                Some(id) => ids.contains(&id),
+                // a PUBLISH without identifiers was not sent for a subscription that had one
+                None => ids.is_empty() && route.matches(&publish.topic),
+            })
+            .map(|route| (route.tx.clone(), publish.clone()))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::v5::packet::publish::PublishProperties;
+
+    fn publish(topic: &str, subscription_identifier: Vec<usize>) -> Publish {
+        Publish {
+            topic: String::from(topic),
+            properties: PublishProperties {
+                subscription_identifier,
+                ..Default::default()
+            },
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn delivers_once_per_subscription_identifier() {
+        let routes = Routes::new(10);
+        routes.connected(true);
+
+        let mut properties = SubscribeProperties::default();
+        let all = routes.add("sensors/#", &mut properties);
+        assert_eq!(properties.subscription_id, Some(1));
+
+        let mut properties = SubscribeProperties::default();
+        let temperatures = routes.add("sensors/+/temp", &mut properties);
+        assert_eq!(properties.subscription_id, Some(2));
+
+        // the topic is not matched again, the identifiers are enough
+        let deliveries = routes.deliver(&publish("sensors/a/temp", vec![1, 2]));
+        assert_eq!(deliveries.len(), 2);
+        assert_eq!(routes.deliver(&publish("sensors/a/temp", vec![2])).len(), 1);
+        assert_eq!(routes.deliver(&publish("sensors/a/temp", vec![])).len(), 0);
+
+        assert_eq!(routes.remove(temperatures.key), Some(Some(2)));
+        let deliveries = routes.deliver(&publish("sensors/a/temp", vec![1, 2]));
+        assert_eq!(deliveries.len(), 1);
+        assert_eq!(routes.remove(all.key), Some(Some(1)));
+    }
+
+    #[test]
+    fn shares_a_subscription_between_the_handles_of_a_topic_filter() {
+        let routes = Routes::new(10);
+        routes.connected(true);
+
+        let mut properties = SubscribeProperties::default();
+        let first = routes.add("sensors/#", &mut properties);
+        let mut properties = SubscribeProperties::default();
+        let second = routes.add("sensors/#", &mut properties);
+        assert!(first.first);
+        assert!(!second.first);
+        assert_eq!(properties.subscription_id, None);
+
+        assert_eq!(routes.deliver(&publish("sensors/a/temp", vec![1])).len(), 2);
+        assert_eq!(routes.remove(first.key), None);
+        assert_eq!(routes.remove(second.key), Some(Some(1)));
+        assert_eq!(routes.remove(second.key), None);
+
+        // subscribing again before the UNSUBSCRIBE was sent keeps the subscription
+        let unsubscribe = UnSubscribe {
+            pkid: 7,
+            payload: vec![String::from("sensors/#")],
+            ..Default::default()
+        };
+        routes.defer(unsubscribe, Some(1));
+        let mut properties = SubscribeProperties::default();
+        let third = routes.add("sensors/#", &mut properties);
+        assert!(!third.first);
+        assert_eq!(third.cancelled, Some(7));
+        assert!(routes.take_deferred(|| None).is_empty());
+        assert_eq!(routes.deliver(&publish("sensors/a/temp", vec![1])).len(), 1);
+    }
+
+    #[test]
+    fn gives_packet_identifiers_to_deferred_unsubscribes_in_order() {
+        let routes = Routes::new(10);
+        for (pkid, filter) in [(0, "sensors/#"), (5, "alerts/#"), (0, "status")] {
+            let unsubscribe = UnSubscribe {
+                pkid,
+                payload: vec![String::from(filter)],
+                ..Default::default()
+            };
+            routes.defer(unsubscribe, None);
+        }
+
+        assert!(routes.take_deferred(|| None).is_empty());
+        let mut pkids = [3].into_iter();
+        let taken = routes.take_deferred(|| pkids.next());
+        let taken = taken
+            .iter()
+            .map(|unsubscribe| unsubscribe.pkid)
+            .collect::<Vec<_>>();
+        assert_eq!(taken, [3, 5]);
+
+        // subscribing again cancels an UNSUBSCRIBE without a packet identifier, none is released
+        let mut properties = SubscribeProperties::default();
+        let added = routes.add("status", &mut properties);
+        assert!(!added.first);
+        assert_eq!(added.cancelled, None);
+    }
+
+    #[test]
+    fn matches_topics_without_subscription_identifiers() {
+        let routes = Routes::new(10);
+
+        let mut properties = SubscribeProperties::default();
+        let _sensors = routes.add("sensors/#", &mut properties);
+        let _alerts = routes.add("$share/group/alerts/+", &mut properties);
+        assert_eq!(properties.subscription_id, None);
+
+        assert_eq!(routes.deliver(&publish("sensors/a/temp", vec![])).len(), 1);
+        assert_eq!(routes.deliver(&publish("alerts/fire", vec![])).len(), 1);
+        assert_eq!(routes.deliver(&publish("status", vec![])).len(), 0);
+    }
+}
@@ -1,20 +1,34 @@
-use std::sync::{Arc, Mutex};
+use alloc::{
+    borrow::ToOwned,
+    collections::{BTreeMap, VecDeque},
+    string::String,
+    sync::Arc,
+    vec,
+    vec::Vec,
+};
+use core::{cell::RefCell, mem};
 
 use crate::v5::{
-    commons::{error::MQTTError, packet::Packet, packet_type::PacketType, qos::QoS},
+    commons::{
+        conformance::{self, ConformanceError},
+        error::MQTTError,
+        packet::Packet,
+        packet_type::PacketType,
+        qos::QoS,
+    },
     packet::{
         disconnect::Disconnect,
-        puback::PubAck,
+        puback::{PubAck, PubAckReasonCode},
         pubcomp::{PubComp, PubCompReasonCode},
         publish::Publish,
         pubrec::{properties::PubRecReasonCode, PubRec},
         pubrel::PubRel,
         suback::SubAck,
-        subscribe::Subscribe,
-        unsuback::UnSubAck,
+        subscribe::{Subscribe, SubscribeProperties, SubscriptionOptions},
+        unsuback::{UnSubAck, UnSubAckReasonCode},
         unsubscribe::UnSubscribe,
     },
-    traits::pkid_mgr::PacketIdRelease,
+    traits::{bufferio::BufferIO, pkid_mgr::PacketIdRelease},
     utils::topic::parse_alias,
 };
 
@@ -28,32 +42,119 @@ enum Direction {
 
 #[derive(Debug, Default)]
 struct TopicAlias {
-    outgoing: Mutex<Vec<Option<String>>>,
-    incoming: Mutex<Vec<Option<String>>>,
+    outgoing: RefCell<Vec<Option<String>>>,
+    incoming: RefCell<Vec<Option<String>>>,
+}
+
+/// The packet awaited next for every packet identifier in flight, indexed by any packet identifier, along with how
+/// many of them are in flight
+#[derive(Debug)]
+struct PacketTable {
+    slots: Vec<Option<PacketType>>,
+    in_flight: usize,
+}
+
+impl PacketTable {
+    fn new() -> Self {
+        Self {
+            slots: vec![None; u16::MAX as usize + 1],
+            in_flight: 0,
+        }
+    }
+
+    fn get(&self, pkid: u16) -> Option<PacketType> {
+        self.slots[pkid as usize]
+    }
+
+    fn set(&mut self, pkid: u16, packet_type: PacketType) {
+        if self.slots[pkid as usize].replace(packet_type).is_none() {
+            self.in_flight += 1;
+        }
+    }
+
+    /// Releases `pkid` if it awaits a packet of `packet_type`
+    fn take_if(&mut self, pkid: u16, packet_type: PacketType) -> Option<PacketType> {
+        let prev = self.slots[pkid as usize].take_if(|pt| *pt == packet_type);
+        if prev.is_some() {
+            self.in_flight -= 1;
+        }
+        prev
+    }
+
+    fn release(&mut self, pkid: u16) {
+        if self.slots[pkid as usize].take().is_some() {
+            self.in_flight -= 1;
+        }
+    }
 }
 
 #[derive(Debug)]
 struct ActivePkids {
     /// all pkids generated by the server and received by us(client)
-    server: Mutex<Vec<Option<PacketType>>>,
+    server: RefCell<PacketTable>,
     /// all pkids generated by us (the client), and sent to the server
-    client: Mutex<Vec<Option<PacketType>>>,
-    unacked_publish: Mutex<Vec<Option<Publish>>>,
+    client: RefCell<PacketTable>,
+    /// the QoS 1 and QoS 2 PUBLISH packets sent until they are acknowledged by a PUBACK or PUBREC, and the PUBREL
+    /// packets sent until they are acknowledged by a PUBCOMP, in the order they were sent (4.6)
+    unacked: RefCell<VecDeque<Packet>>,
+    /// With manual acknowledgements, the QoS 1 and QoS 2 PUBLISH packets received and not acknowledged yet, in the order
+    /// they were received, along with their PUBACK or PUBREC once the application sent it
+    unsent_acks: RefCell<VecDeque<(u16, Option<Packet>)>>,
 }
 
+/// A topic filter the client subscribed to, along with the options and properties of its SUBSCRIBE
+#[derive(Debug)]
+struct Subscription {
+    filter: String,
+    options: SubscriptionOptions,
+    properties: SubscribeProperties,
+}
+
+/// The subscriptions of the session, as the server last acknowledged them, so that they can be made again if the
+/// server loses the session
+#[derive(Debug, Default)]
+struct Subscriptions {
+    active: RefCell<Vec<Subscription>>,
+    /// SUBSCRIBE packets sent and not acknowledged yet, by packet identifier
+    subscribing: RefCell<BTreeMap<u16, Subscribe>>,
+    /// Topic filters of the UNSUBSCRIBE packets sent and not acknowledged yet, by packet identifier
+    unsubscribing: RefCell<BTreeMap<u16, Vec<String>>>,
+}
+
+impl Subscriptions {
+    /// A new subscription replaces the one with the same topic filter (3.8.4)
+    fn insert(&self, subscription: Subscription) {
+        let mut active = self.active.borrow_mut();
+        active.retain(|s| s.filter != subscription.filter);
+        active.push(subscription);
+    }
+
+    fn remove(&self, filter: &str) {
+        self.active.borrow_mut().retain(|s| s.filter != filter);
+    }
+}
+
+/// The client's side of an MQTT session, without any IO (sans-IO): the [`Network`](super::network::asyncx::Network) feeds it
+/// every packet it sends and receives, and sends back whatever it answers with.
+/// It only needs `alloc`, so it can be driven by hand on `no_std` targets
 // Todo!!: All hashsets needs to be changed to vec/VecDeque to improve performance/caching?
 #[derive(Debug)]
-pub(crate) struct State<T> {
+pub struct State<T> {
     inbound_topic_alias_max: u16,
     outbound_topic_alias_max: u16,
     topic_aliases: TopicAlias,
+    /// 3.1.2.11.3 QoS 1 and QoS 2 publications from the server we process concurrently
+    client_receive_max: u16,
 
-    /// Manually or Automatically acknolwedge pubs/subs - this should be removed eventually?
+    /// The application acknowledges the QoS 1 and QoS 2 PUBLISH packets it receives (see [`ConnectOptions::manual_ack`])
     manual_ack: bool,
+    /// Report protocol violations as the normative statement they break (see [`ConnectOptions::strict`])
+    strict: bool,
     clean_start: bool,
-    pub(crate) pkid_mgr: Option<Arc<T>>,
+    pkid_mgr: Option<Arc<T>>,
 
     active_packets: ActivePkids,
+    subscriptions: Subscriptions,
 }
 
 impl<T> From<&ConnectOptions> for State<T>
@@ -61,22 +162,24 @@ where
     T: PacketIdRelease,
 {
     fn from(value: &ConnectOptions) -> Self {
-        let outgoing_max = value.server_receive_max.get() as usize;
-        let incoming_max = value.client_receive_max.get() as usize;
-
         Self {
             topic_aliases: TopicAlias {
-                outgoing: Mutex::new(vec![None; value.outbound_topic_alias_max as usize]),
-                incoming: Mutex::new(vec![None; value.outbound_topic_alias_max as usize]),
+                outgoing: RefCell::new(vec![None; value.outbound_topic_alias_max as usize]),
+                incoming: RefCell::new(vec![None; value.inbound_topic_alias_max as usize]),
             },
 
             active_packets: ActivePkids {
-                server: Mutex::new(vec![None; incoming_max]),
-                client: Mutex::new(vec![None; outgoing_max]),
-                unacked_publish: Mutex::new(vec![None; outgoing_max]),
+                server: RefCell::new(PacketTable::new()),
+                client: RefCell::new(PacketTable::new()),
+                unacked: RefCell::new(VecDeque::new()),
+                unsent_acks: RefCell::new(VecDeque::new()),
             },
 
+            subscriptions: Subscriptions::default(),
+
             manual_ack: value.manual_ack,
+            strict: value.strict,
+            client_receive_max: value.client_receive_max.get(),
             inbound_topic_alias_max: value.inbound_topic_alias_max,
             outbound_topic_alias_max: value.outbound_topic_alias_max,
             clean_start: value.clean_start,
@@ -89,6 +192,33 @@ impl<T> State<T>
 where
     T: PacketIdRelease,
 {
+    /// `rule` in strict mode, `error` otherwise
+    fn violation(&self, rule: ConformanceError, error: MQTTError) -> MQTTError {
+        if self.strict {
+            return rule.into();
+        }
+        error
+    }
+
+    /// Rules that are only checked in strict mode, the rest of the client gets along without them
+    fn check_publish(&self, packet: &Publish) -> Result<(), ConformanceError> {
+        if !self.strict {
+            return Ok(());
+        }
+
+        match (packet.qos, packet.pkid) {
+            (QoS::Zero, Some(_)) => return Err(conformance::PUBLISH_QOS0_PACKET_ID),
+            (QoS::Zero, None) if packet.dup => return Err(conformance::PUBLISH_QOS0_DUP),
+            _ => {}
+        }
+
+        if packet.topic.contains(['+', '#']) {
+            return Err(conformance::TOPIC_NAME_WILDCARD);
+        }
+
+        Ok(())
+    }
+
     fn parse_topic_and_try_update(
         &self,
         packet: &Publish,
@@ -97,15 +227,27 @@ where
         let topic = &packet.topic;
         let alias = packet.properties.topic_alias;
 
+        let parse_alias = |alias: u16, max: u16| {
+            parse_alias(alias, max).map_err(|e| match (alias, &direction) {
+                (0, _) => self.violation(conformance::TOPIC_ALIAS_ZERO, e),
+                (_, Direction::InBound) => {
+                    self.violation(conformance::SERVER_TOPIC_ALIAS_TOO_LARGE, e)
+                }
+                (_, Direction::OutBound) => {
+                    self.violation(conformance::CLIENT_TOPIC_ALIAS_TOO_LARGE, e)
+                }
+            })
+        };
+
         let (max, mut record) = if direction == Direction::InBound {
             (
                 self.inbound_topic_alias_max,
-                self.topic_aliases.incoming.lock().unwrap(),
+                self.topic_aliases.incoming.borrow_mut(),
             )
         } else {
             (
                 self.outbound_topic_alias_max,
-                self.topic_aliases.outgoing.lock().unwrap(),
+                self.topic_aliases.outgoing.borrow_mut(),
             )
         };
 
@@ -113,49 +255,71 @@ where
             (topic, None) if topic.len() > 0 => Ok(topic.to_owned()),
             (topic, Some(alias)) if topic.len() > 0 => {
                 let alias = parse_alias(alias, max)?;
-                record.insert(alias as usize, Some(topic.clone()));
+                record[alias as usize - 1] = Some(topic.clone());
+                trace!(?direction, alias, topic, "topic alias assigned");
                 Ok(topic.to_owned())
             }
             (topic, Some(alias)) if topic.len() == 0 => {
                 let alias = parse_alias(alias, max)?;
-                let value = record[alias as usize].clone();
-                value.ok_or(MQTTError::UnknownData(format!(
-                    "Unrecognized Topic Alias {alias}"
-                )))
+                let value = record[alias as usize - 1].clone();
+                value.ok_or(MQTTError::UnknownTopicAlias(alias))
             }
-            _ => Err(MQTTError::UnknownData(format!(
-                "Expected Topic or Alias but found none"
-            ))),
+            // 3.3.2.3.4 a Topic Name of zero length without a Topic Alias
+            _ => Err(MQTTError::ProtocolError("Expected Topic or Alias but found none")),
         }
     }
 
     fn handle_outgoing_publish(&self, packet: Publish) -> Result<(), MQTTError> {
-        // Confirm that the packet identifier is not a duplicate before we proceed with anything
+        // Confirm that the packet identifier is not already in flight before we proceed with anything
+        self.check_publish(&packet)?;
         if let Some(pid) = packet.pkid {
-            if self.pkid_mgr.as_ref().unwrap().is_occupied(pid) {
-                return Err(MQTTError::PacketIdConflict(pid));
+            if (self.strict && pid == 0)
+                || self.active_packets.client.borrow().get(pid).is_some()
+            {
+                let error = MQTTError::PacketIdConflict(pid);
+                return Err(self.violation(conformance::CLIENT_PACKET_ID_IN_USE, error));
             }
         }
 
         self.parse_topic_and_try_update(&packet, Direction::OutBound)?;
 
         if packet.qos != QoS::Zero {
-            self.active_packets.client.lock().unwrap()[packet.pkid.unwrap() as usize]
-                .replace(PacketType::Publish);
-            self.active_packets.unacked_publish.lock().unwrap()[packet.pkid.unwrap() as usize]
-                .replace(packet);
+            trace!(
+                pkid = packet.pkid,
+                "in-flight slot taken by an outgoing PUBLISH"
+            );
+            self.active_packets
+                .client
+                .borrow_mut()
+                .set(packet.pkid.unwrap(), PacketType::Publish);
+            self.active_packets
+                .unacked
+                .borrow_mut()
+                .push_back(Packet::Publish(packet));
         }
 
         Ok(())
     }
 
     fn handle_incoming_publish(&self, packet: &mut Publish) -> Result<Option<Packet>, MQTTError> {
+        self.check_publish(packet)?;
         let topic = self.parse_topic_and_try_update(&packet, Direction::InBound)?;
         packet.topic = topic;
 
+        if self.is_duplicate(packet) {
+            // the PUBREC got lost, the PUBLISH is acknowledged again without being delivered a second time
+            return Ok(Some(Packet::PubRec(PubRec {
+                pkid: packet.pkid.unwrap(),
+                ..Default::default()
+            })));
+        }
+
         if let Some(pid) = packet.pkid {
-            if self.active_packets.server.lock().unwrap()[pid as usize].is_some() {
-                return Err(MQTTError::PacketIdConflict(pid));
+            if (self.strict && pid == 0)
+                || self.active_packets.server.borrow().get(pid).is_some()
+            {
+                let error = MQTTError::PacketIdConflict(pid);
+                return Err(self.violation(conformance::SERVER_PACKET_ID_IN_USE, error));
             }
         }
 
@@ -163,9 +327,34 @@ where
             return Ok(None);
         }
 
+        // The Server MUST NOT send more than Receive Maximum QoS 1 and QoS 2 PUBLISH packets for which it has not received PUBACK, PUBCOMP, or PUBREC with a Reason Code of 128 or greater from the Client [MQTT-3.3.4-9]
+        if self.incoming_in_flight() >= self.client_receive_max as usize {
+            let error = MQTTError::ReceiveMaximumExceeded(self.client_receive_max);
+            return Err(self.violation(conformance::SERVER_RECEIVE_MAXIMUM_EXCEEDED, error));
+        }
+
         let pkid = packet.pkid.unwrap();
+        // a payload that is not what its Payload Format Indicator says MAY be refused with 0x99 (3.3.2.3.2)
+        if packet.check_payload_format().is_err() {
+            debug!(pkid, "refused a PUBLISH whose payload is not UTF-8, as its indicator says");
+            return Ok(self.refuse_payload(pkid, packet.qos));
+        }
+
         if packet.qos == QoS::Two && !self.manual_ack {
-            self.active_packets.server.lock().unwrap()[pkid as usize] = Some(PacketType::PubRec);
+            trace!(pkid, "in-flight slot taken by an incoming PUBLISH");
+            self.active_packets.server.borrow_mut().set(pkid, PacketType::PubRec);
+        }
+
+        if self.manual_ack {
+            let mut unsent_acks = self.active_packets.unsent_acks.borrow_mut();
+            // a PUBLISH sent again before it was acknowledged is still waiting for the same acknowledgement
+            if unsent_acks.iter().all(|(id, _)| *id != pkid) {
+                trace!(
+                    pkid,
+                    "in-flight slot taken until the application acknowledges"
+                );
+                unsent_acks.push_back((pkid, None));
+            }
         }
 
         let result = match (packet.qos, self.manual_ack) {
@@ -184,6 +373,110 @@ where
         Ok(result)
     }
 
+    /// The acknowledgement of a QoS 1 or QoS 2 PUBLISH with an invalid payload, which ends its exchange. With manual
+    /// acknowledgements, it waits for the messages received before it to be acknowledged [MQTT-4.6.0-2]
+    fn refuse_payload(&self, pkid: u16, qos: QoS) -> Option<Packet> {
+        let ack = match qos {
+            QoS::Zero => return None,
+            QoS::One => Packet::PubAck(PubAck {
+                pkid,
+                reason_code: PubAckReasonCode::PayloadFormatInvalid,
+                ..Default::default()
+            }),
+            QoS::Two => Packet::PubRec(PubRec {
+                pkid,
+                reason_code: PubRecReasonCode::PayloadFormatInvalid,
+                ..Default::default()
+            }),
+        };
+
+        let mut unsent_acks = self.active_packets.unsent_acks.borrow_mut();
+        if !self.manual_ack || unsent_acks.is_empty() {
+            return Some(ack);
+        }
+        if unsent_acks.iter().all(|(id, _)| *id != pkid) {
+            unsent_acks.push_back((pkid, Some(ack)));
+        }
+        None
+    }
+
+    /// Packet identifiers are released to `pkids` once the server acknowledges the packets they were allocated for.
+    /// This must be set before any packet with a packet identifier is sent
+    pub fn set_pkid_mgr(&mut self, pkids: Arc<T>) {
+        self.pkid_mgr = Some(pkids);
+    }
+
+    pub(crate) fn pkid_mgr(&self) -> Option<&Arc<T>> {
+        self.pkid_mgr.as_ref()
+    }
+
+    /// Number of packets in flight: the QoS 1 and QoS 2 packets sent and not acknowledged yet, and the QoS 1 and QoS 2
+    /// PUBLISH packets received and not acknowledged yet (or whose PUBREL has not been received yet)
+    pub fn in_flight(&self) -> (u16, u16) {
+        let outgoing = self.active_packets.client.borrow().in_flight;
+        (outgoing as u16, self.incoming_in_flight() as u16)
+    }
+
+    fn incoming_in_flight(&self) -> usize {
+        let server = self.active_packets.server.borrow().in_flight;
+        server + self.active_packets.unsent_acks.borrow().len()
+    }
+
+    /// With manual acknowledgements, holds `ack` (a PUBACK or a PUBREC sent by the application) until every PUBLISH
+    /// received before the one it acknowledges is acknowledged too, and returns the acknowledgements that can be sent,
+    /// in order. The Client MUST send PUBACK packets in the order in which the corresponding PUBLISH packets were
+    /// received (QoS 1 messages) [MQTT-4.6.0-2], as well as PUBREC packets (QoS 2 messages) [MQTT-4.6.0-3].
+    /// Acknowledgements of packets that are not waiting for one are dropped
+    pub fn order_ack(&self, ack: Packet) -> Vec<Packet> {
+        let pkid = match &ack {
+            Packet::PubAck(PubAck { pkid, .. }) | Packet::PubRec(PubRec { pkid, .. })
+                if self.manual_ack =>
+            {
+                *pkid
+            }
+            _ => return vec![ack],
+        };
+
+        let mut unsent_acks = self.active_packets.unsent_acks.borrow_mut();
+        match unsent_acks
+            .iter_mut()
+            .find(|(id, unsent)| *id == pkid && unsent.is_none())
+        {
+            Some((_, unsent)) => *unsent = Some(ack),
+            None => {
+                trace!(
+                    pkid,
+                    "dropped the acknowledgement of a PUBLISH that is not waiting for one"
+                );
+                return Vec::new();
+            }
+        }
+
+        let mut ready = Vec::new();
+        while let Some((_, Some(_))) = unsent_acks.front() {
+            let (_, ack) = unsent_acks.pop_front().unwrap();
+            ready.extend(ack);
+        }
+        ready
+    }
+
+    /// Whether `packet` is a QoS 2 PUBLISH that was already received, and is still waiting for its PUBREL (4.3.3)
+    pub fn is_duplicate_publish(&self, packet: &Packet) -> bool {
+        match packet {
+            Packet::Publish(publish) => self.is_duplicate(publish),
+            _ => false,
+        }
+    }
+
+    fn is_duplicate(&self, packet: &Publish) -> bool {
+        match (packet.qos, packet.pkid) {
+            (QoS::Two, Some(pkid)) => {
+                self.active_packets.server.borrow().get(pkid) == Some(PacketType::PubRec)
+            }
+            _ => false,
+        }
+    }
+
     // we don't need to confirm anything locally
     pub(crate) fn handle_outgoing_pubacki(&self, _p: PubAck) -> Result<(), MQTTError> {
         Ok(())
@@ -198,28 +491,33 @@ where
         &self,
         packet: &PubAck,
     ) -> Result<Option<Packet>, MQTTError> {
-        let pkid = packet.pkid as usize;
+        let pkid = packet.pkid;
 
         // release the pkid, and remove the packet from the state
-        let prev =
-            self.active_packets.client.lock().unwrap()[pkid].take_if(|p| *p == PacketType::Publish);
+        let prev = self.active_packets.client.borrow_mut().take_if(pkid, PacketType::Publish);
 
         if prev.is_none() {
-            return Err(MQTTError::UnknownData(format!(
-                "Unknown Pakcet Id: {}",
-                packet.pkid
-            )));
+            return Err(MQTTError::UnknownPacketId(packet.pkid));
         }
 
         self.pkid_mgr.as_ref().unwrap().release(packet.pkid);
-        self.active_packets.unacked_publish.lock().unwrap()[pkid] = None;
+        self.acknowledged(pkid);
+        trace!(pkid, "in-flight slot released by a PUBACK");
 
         return Ok(None);
     }
 
     // we don't need to confirm anything locally
     pub(crate) fn handle_outgoing_pubrec(&self, packet: PubRec) -> Result<(), MQTTError> {
-        self.active_packets.server.lock().unwrap()[packet.pkid as usize] = Some(PacketType::PubRec);
+        // a PUBREC with a Reason Code of 128 or greater ends the exchange, no PUBREL follows
+        if packet.reason_code.is_error() {
+            return Ok(());
+        }
+        trace!(
+            pkid = packet.pkid,
+            "in-flight slot taken by an incoming PUBLISH"
+        );
+        self.active_packets.server.borrow_mut().set(packet.pkid, PacketType::PubRec);
         Ok(())
     }
 
@@ -227,43 +525,45 @@ where
         &self,
         packet: &PubRec,
     ) -> Result<Option<Packet>, MQTTError> {
-        let pkid = packet.pkid as usize;
-
-        match self.active_packets.client.lock().unwrap().get_mut(pkid) {
-            Some(pt) if *pt == Some(PacketType::Publish) => {
-                // MUST NOT re-send the PUBLISH once it has sent the corresponding PUBREL packet [MQTT-4.3.3-6].
-                self.active_packets.client.lock().unwrap()[pkid] = None;
-                if packet.reason_code == PubRecReasonCode::NoMatchingSubscribers
-                    || packet.reason_code == PubRecReasonCode::Success
-                {
-                    *pt = Some(PacketType::PubRel);
-                } else {
-                    *pt = None;
-                    return Ok(None);
-                }
-            }
-            _ => {
-                return Err(MQTTError::UnknownData(format!(
-                    "Unknown pubrec Pakcet Id: {}",
-                    packet.pkid
-                )));
-            }
-        };
+        let pkid = packet.pkid;
+        let mut client = self.active_packets.client.borrow_mut();
 
-        if self.manual_ack {
+        if client.take_if(pkid, PacketType::Publish).is_none() {
+            return Err(MQTTError::UnknownPacketId(pkid));
+        }
+        // MUST NOT re-send the PUBLISH once it has sent the corresponding PUBREL packet [MQTT-4.3.3-6].
+        self.acknowledged(pkid);
+        if packet.reason_code == PubRecReasonCode::NoMatchingSubscribers
+            || packet.reason_code == PubRecReasonCode::Success
+        {
+            client.set(pkid, PacketType::PubRel);
+        } else {
+            if let Some(pkid_mgr) = &self.pkid_mgr {
+                pkid_mgr.release(pkid);
+            }
+            trace!(
+                pkid,
+                reason_code = %packet.reason_code,
+                "in-flight slot released by a failed PUBREC"
+            );
             return Ok(None);
         }
 
         // MUST treat the PUBREL packet as “unacknowledged” until it has received the corresponding PUBCOMP packet from the receiver [MQTT-4.3.3-5].
-        return Ok(Some(Packet::PubRel(PubRel {
+        let pubrel = PubRel {
             pkid: packet.pkid,
             ..Default::default()
-        })));
+        };
+        let unacked = Packet::PubRel(pubrel.clone());
+        self.active_packets.unacked.borrow_mut().push_back(unacked);
+        return Ok(Some(Packet::PubRel(pubrel)));
     }
 
     // we don't need to confirm anything locally, if it's autohandled good, if it's not, then it's up to the user
     pub(crate) fn handle_outgoing_pubrel(&self, packet: PubRel) -> Result<(), MQTTError> {
-        self.active_packets.client.lock().unwrap()[packet.pkid as usize] = Some(PacketType::PubRel);
+        self.active_packets.client.borrow_mut().set(packet.pkid, PacketType::PubRel);
+        self.acknowledged(packet.pkid);
+        self.active_packets.unacked.borrow_mut().push_back(Packet::PubRel(packet));
         Ok(())
     }
 
@@ -271,13 +571,11 @@ where
         &self,
         packet: &PubRel,
     ) -> Result<Option<Packet>, MQTTError> {
-        let pkid = packet.pkid as usize;
+        let pkid = packet.pkid;
 
-        let prev = self.active_packets.server.lock().unwrap()[pkid]
-            .take_if(|pt| *pt == PacketType::PubRec);
-
-        if self.manual_ack {
-            return Ok(None);
+        let prev = self.active_packets.server.borrow_mut().take_if(pkid, PacketType::PubRec);
+        if prev.is_some() {
+            trace!(pkid, "in-flight slot released by a PUBREL");
         }
 
         if prev.is_none() {
@@ -299,61 +597,109 @@ where
     }
 
     fn handle_incoming_pubcomp(&self, packet: &PubComp) -> Result<Option<Packet>, MQTTError> {
-        let pkid = packet.pkid as usize;
-        let prev = self.active_packets.client.lock().unwrap()[pkid]
-            .take_if(|pt| *pt == PacketType::PubRel);
+        let pkid = packet.pkid;
+        let prev = self.active_packets.client.borrow_mut().take_if(pkid, PacketType::PubRel);
 
         if prev.is_none() {
-            return Err(MQTTError::UnknownData(format!(
-                "Unknown Packet Id: {}",
-                packet.pkid
-            )));
+            return Err(MQTTError::UnknownPacketId(packet.pkid));
         }
 
         self.pkid_mgr.as_ref().unwrap().release(packet.pkid);
+        self.acknowledged(pkid);
+        trace!(pkid, "in-flight slot released by a PUBCOMP");
 
         return Ok(None);
     }
 
     fn handle_outgoing_subscribe(&self, packet: Subscribe) -> Result<(), MQTTError> {
-        self.active_packets.client.lock().unwrap()[packet.pkid as usize] =
-            Some(PacketType::Subscribe);
+        if self.strict && packet.pkid == 0 {
+            return Err(conformance::CLIENT_PACKET_ID_IN_USE.into());
+        }
+        self.active_packets.client.borrow_mut().set(packet.pkid, PacketType::Subscribe);
+        trace!(pkid = packet.pkid, "in-flight slot taken by a SUBSCRIBE");
+        self.subscriptions
+            .subscribing
+            .borrow_mut()
+            .insert(packet.pkid, packet);
 
         Ok(())
     }
 
     fn handle_incoming_suback(&self, packet: &SubAck) -> Result<Option<Packet>, MQTTError> {
-        let prev = self.active_packets.client.lock().unwrap()[packet.pkid as usize]
-            .take_if(|pt| *pt == PacketType::Subscribe);
+        let prev = self
+            .active_packets
+            .client
+            .borrow_mut()
+            .take_if(packet.pkid, PacketType::Subscribe);
 
         if prev.is_none() {
-            return Err(MQTTError::UnknownData(format!(
-                "Unknown Suback Packet Id: {}",
-                packet.pkid
-            )));
+            return Err(MQTTError::UnknownPacketId(packet.pkid));
         }
 
         self.pkid_mgr.as_ref().unwrap().release(packet.pkid);
+        trace!(pkid = packet.pkid, "in-flight slot released by a SUBACK");
+
+        let subscribe = self
+            .subscriptions
+            .subscribing
+            .borrow_mut()
+            .remove(&packet.pkid);
+        if let Some(subscribe) = subscribe {
+            // the server does not hold the subscriptions it refused
+            for ((filter, options), reason) in subscribe.payload.into_iter().zip(&packet.payload) {
+                match (*reason as u8) < 0x80 {
+                    true => self.subscriptions.insert(Subscription {
+                        filter,
+                        options,
+                        properties: subscribe.properties.clone(),
+                    }),
+                    false => self.subscriptions.remove(&filter),
+                }
+            }
+        }
         return Ok(None);
     }
 
     fn handle_outgoing_unsubscribe(&self, packet: UnSubscribe) -> Result<(), MQTTError> {
-        self.active_packets.client.lock().unwrap()[packet.pkid as usize] =
-            Some(PacketType::UnSubscribe);
+        if self.strict && packet.pkid == 0 {
+            return Err(conformance::CLIENT_PACKET_ID_IN_USE.into());
+        }
+        self.active_packets.client.borrow_mut().set(packet.pkid, PacketType::UnSubscribe);
+        trace!(pkid = packet.pkid, "in-flight slot taken by an UNSUBSCRIBE");
+        self.subscriptions
+            .unsubscribing
+            .borrow_mut()
+            .insert(packet.pkid, packet.payload);
 
         Ok(())
     }
 
     fn handle_incoming_unsuback(&self, packet: &UnSubAck) -> Result<Option<Packet>, MQTTError> {
-        let prev = self.active_packets.client.lock().unwrap()[packet.pkid as usize];
+        let prev = self
+            .active_packets
+            .client
+            .borrow_mut()
+            .take_if(packet.pkid, PacketType::UnSubscribe);
         if prev.is_none() {
-            return Err(MQTTError::UnknownData(format!(
-                "Unknown Suback Packet Id: {}",
-                packet.pkid
-            )));
+            return Err(MQTTError::UnknownPacketId(packet.pkid));
         }
 
         self.pkid_mgr.as_ref().unwrap().release(packet.pkid);
+        trace!(pkid = packet.pkid, "in-flight slot released by an UNSUBACK");
+
+        let filters = self
+            .subscriptions
+            .unsubscribing
+            .borrow_mut()
+            .remove(&packet.pkid);
+        for (filter, reason) in filters.into_iter().flatten().zip(&packet.payload) {
+            if matches!(
+                reason,
+                UnSubAckReasonCode::Success | UnSubAckReasonCode::NoSubscriptionExisted
+            ) {
+                self.subscriptions.remove(&filter);
+            }
+        }
         return Ok(None);
     }
 
@@ -361,10 +707,8 @@ where
         Ok(())
     }
 
-    pub(crate) fn handle_incoming_packet(
-        &self,
-        packet: &mut Packet,
-    ) -> Result<Option<Packet>, MQTTError> {
+    /// Records a packet received from the server, and returns the packet to answer with (if any)
+    pub fn handle_incoming_packet(&self, packet: &mut Packet) -> Result<Option<Packet>, MQTTError> {
         match packet {
             Packet::Publish(packet) => self.handle_incoming_publish(packet),
             Packet::PubAck(packet) => self.handle_incoming_puback(packet),
@@ -377,7 +721,8 @@ where
         }
     }
 
-    pub(crate) fn handle_outgoing_packet(&self, packet: Packet) -> Result<(), MQTTError> {
+    /// Records a packet about to be sent to the server
+    pub fn handle_outgoing_packet(&self, packet: Packet) -> Result<(), MQTTError> {
         match packet {
             Packet::Publish(packet) => self.handle_outgoing_publish(packet),
             Packet::PubAck(packet) => self.handle_outgoing_puback(packet),
@@ -392,9 +737,1011 @@ where
         }
     }
 
-    /// should be used in the case of clean_start=0,
-    /// dup flag on publish packets must be set to 1 in this case
-    /// see: 4.3.3 QoS 2: Exactly once delivery
-    /// for more information
-    pub(crate) fn retransmit_all() {}
+    /// To be called when the server lost the session (Session Present is 0 in the CONNACK of a reconnection): discards
+    /// the Session State [MQTT-3.2.2-5], i.e. releases the packet identifiers of the PUBLISH, PUBREL, SUBSCRIBE and
+    /// UNSUBSCRIBE packets still awaiting an acknowledgement and forgets the QoS 2 messages received and awaiting their
+    /// PUBREL, and returns the SUBSCRIBE packets that make every subscription again (including those that were
+    /// awaiting one).
+    /// Subscriptions sharing the same properties are batched into as few packets as `max_size` (the Maximum Packet
+    /// Size of the server) allows, a subscription too large to fit on its own gets a packet of its own anyway.
+    /// The packets are left without packet identifiers (0)
+    pub fn resubscribe(&self, max_size: usize) -> Vec<Subscribe> {
+        let subscribing = mem::take(&mut *self.subscriptions.subscribing.borrow_mut());
+        let unsubscribing = mem::take(&mut *self.subscriptions.unsubscribing.borrow_mut());
+
+        let mut client = self.active_packets.client.borrow_mut();
+        let pkids = (1..=u16::MAX).filter(|pkid| client.get(*pkid).is_some());
+        for pkid in pkids.collect::<Vec<_>>() {
+            client.release(pkid);
+            if let Some(pkid_mgr) = &self.pkid_mgr {
+                pkid_mgr.release(pkid);
+            }
+        }
+        drop(client);
+        self.active_packets.unacked.borrow_mut().clear();
+        *self.active_packets.server.borrow_mut() = PacketTable::new();
+        self.active_packets.unsent_acks.borrow_mut().clear();
+
+        for subscribe in subscribing.into_values() {
+            for (filter, options) in subscribe.payload {
+                let properties = subscribe.properties.clone();
+                self.subscriptions.insert(Subscription {
+                    filter,
+                    options,
+                    properties,
+                });
+            }
+        }
+        unsubscribing
+            .values()
+            .flatten()
+            .for_each(|filter| self.subscriptions.remove(filter));
+
+        let mut batches: Vec<Subscribe> = Vec::new();
+        for subscription in self.subscriptions.active.borrow().iter() {
+            let entry = (subscription.filter.clone(), subscription.options);
+            let batched = batches.iter_mut().any(|batch| {
+                if batch.properties != subscription.properties {
+                    return false;
+                }
+                batch.payload.push(entry.clone());
+                let fits = batch.is_valid(max_size).is_ok();
+                if !fits {
+                    batch.payload.pop();
+                }
+                fits
+            });
+
+            if !batched {
+                batches.push(Subscribe {
+                    pkid: 0,
+                    properties: subscription.properties.clone(),
+                    payload: vec![entry],
+                });
+            }
+        }
+        batches
+    }
+
+    /// Forgets the PUBLISH or PUBREL packet sent with `pkid`, it no longer needs to be sent again
+    fn acknowledged(&self, pkid: u16) {
+        let mut unacked = self.active_packets.unacked.borrow_mut();
+        unacked.retain(|packet| match packet {
+            Packet::Publish(publish) => publish.pkid != Some(pkid),
+            Packet::PubRel(pubrel) => pubrel.pkid != pkid,
+            _ => true,
+        });
+    }
+
+    /// To be called when the server resumed the session (Session Present is 1 in the CONNACK of a reconnection):
+    /// returns the PUBLISH packets not acknowledged yet, with their DUP flag set, and the PUBREL packets not
+    /// acknowledged yet, in the order they were first sent [MQTT-4.4.0-1]. They are still in flight, and are not to be
+    /// handed to [`handle_outgoing_packet`](Self::handle_outgoing_packet) again
+    pub fn retransmit(&self) -> Vec<Packet> {
+        let unacked = self.active_packets.unacked.borrow();
+        unacked
+            .iter()
+            .filter_map(|packet| match packet {
+                Packet::Publish(publish) => Some(Packet::Publish(Publish {
+                    dup: true,
+                    ..publish.clone()
+                })),
+                Packet::PubRel(pubrel) => Some(Packet::PubRel(pubrel.clone())),
+                _ => None,
+            })
+            .collect()
+    }
+}
+
+#[cfg(all(test, feature = "asyncx"))]
+mod tests {
+    use std::{num::NonZero, sync::Arc};
+
+    use futures::executor::block_on;
+
+    use super::State;
+    use crate::v5::{
+        client::{
+            error::{AckError, RunError},
+            handler::AsyncHandler,
+            mock::MockBroker,
+            network::asyncx::{Network, NetworkStatus},
+            packet_id::PacketIdManager,
+            ConnectOptions,
+        },
+        commons::{conformance, error::MQTTError, packet::Packet, qos::QoS},
+        packet::{
+            connack::ConnAck,
+            disconnect::{Disconnect, DisconnectProperties, DisconnectReasonCode},
+            puback::{PubAck, PubAckReasonCode},
+            pubcomp::{PubComp, PubCompReasonCode},
+            publish::{Publish, PublishProperties},
+            pubrec::PubRec,
+            pubrel::PubRel,
+        },
+        traits::{bufferio::BufferIO, pkid_mgr::PacketIdAlloc},
+    };
+
+    /// Keeps every packet handed to the application
+    #[derive(Default)]
+    struct Recorder(Vec<Packet>);
+
+    impl AsyncHandler for Recorder {
+        async fn handle(&mut self, packet: Packet) {
+            self.0.push(packet);
+        }
+    }
+
+    fn options() -> ConnectOptions {
+        ConnectOptions {
+            keep_alive: 0,
+            ..Default::default()
+        }
+    }
+
+    fn publish(pkid: u16) -> Packet {
+        Packet::Publish(Publish {
+            topic: String::from("sensors/temperature"),
+            qos: QoS::One,
+            pkid: Some(pkid),
+            payload: "21.5".into(),
+            ..Default::default()
+        })
+    }
+
+    fn server_disconnect() -> Packet {
+        Packet::Disconnect(Disconnect {
+            reason_code: DisconnectReasonCode::ServerShuttingDown,
+            ..Default::default()
+        })
+    }
+
+    #[test]
+    fn answers_a_pubrel_for_an_unknown_packet_id() {
+        let (stream, mock) = MockBroker::new()
+            .handshake(ConnAck::default())
+            .send(Packet::PubRel(PubRel {
+                pkid: 5,
+                ..Default::default()
+            }))
+            .expect(Packet::PubComp(PubComp {
+                pkid: 5,
+                reason_code: PubCompReasonCode::PacketIdentifierNotFound,
+                ..Default::default()
+            }))
+            .send(server_disconnect())
+            .spawn();
+
+        block_on(async {
+            let (mut network, _client) = Network::new(options(), stream).await.unwrap();
+            let mut handler = Recorder::default();
+
+            let status = network.run(&mut handler).await.unwrap();
+            assert!(matches!(status, NetworkStatus::IncomingDisconnect));
+            assert_eq!(handler.0.last(), Some(&server_disconnect()));
+        });
+        mock.join().unwrap();
+    }
+
+    #[test]
+    fn accepts_acknowledgements_out_of_order() {
+        let (stream, mock) = MockBroker::new()
+            .handshake(ConnAck::default())
+            .expect(publish(1))
+            .expect(publish(2))
+            .send(Packet::PubAck(PubAck {
+                pkid: 2,
+                ..Default::default()
+            }))
+            .send(Packet::PubAck(PubAck {
+                pkid: 1,
+                ..Default::default()
+            }))
+            .send(server_disconnect())
+            .spawn();
+
+        block_on(async {
+            let (mut network, client) = Network::new(options(), stream).await.unwrap();
+            for _ in 0..2 {
+                client
+                    .publish("sensors/temperature", QoS::One, false, "21.5", None)
+                    .await
+                    .unwrap();
+            }
+            let mut handler = Recorder::default();
+
+            let status = network.run(&mut handler).await.unwrap();
+            assert!(matches!(status, NetworkStatus::IncomingDisconnect));
+            let acked = handler
+                .0
+                .iter()
+                .filter_map(|packet| match packet {
+                    Packet::PubAck(puback) => Some(puback.pkid),
+                    _ => None,
+                })
+                .collect::<Vec<_>>();
+            assert_eq!(acked, [2, 1]);
+        });
+        mock.join().unwrap();
+    }
+
+    #[test]
+    fn acknowledges_a_duplicate_qos2_publish_without_delivering_it_twice() {
+        let message = Publish {
+            topic: String::from("sensors/temperature"),
+            qos: QoS::Two,
+            pkid: Some(3),
+            payload: "21.5".into(),
+            ..Default::default()
+        };
+        let (stream, mock) = MockBroker::new()
+            .handshake(ConnAck::default())
+            .send(Packet::Publish(message.clone()))
+            .expect(Packet::PubRec(PubRec {
+                pkid: 3,
+                ..Default::default()
+            }))
+            .send(Packet::Publish(Publish {
+                dup: true,
+                ..message
+            }))
+            .expect(Packet::PubRec(PubRec {
+                pkid: 3,
+                ..Default::default()
+            }))
+            .send(server_disconnect())
+            .spawn();
+
+        block_on(async {
+            let (mut network, _client) = Network::new(options(), stream).await.unwrap();
+            let mut handler = Recorder::default();
+
+            let status = network.run(&mut handler).await.unwrap();
+            assert!(matches!(status, NetworkStatus::IncomingDisconnect));
+            let delivered = handler.0.iter().filter(|p| matches!(p, Packet::Publish(_)));
+            assert_eq!(delivered.count(), 1);
+        });
+        mock.join().unwrap();
+    }
+
+    #[test]
+    fn refuses_a_payload_that_is_not_utf8_as_its_indicator_says() {
+        let utf8 = |pkid: u16, payload: &'static [u8]| {
+            Packet::Publish(Publish {
+                topic: String::from("sensors/temperature"),
+                qos: QoS::One,
+                pkid: Some(pkid),
+                payload: payload.into(),
+                properties: PublishProperties {
+                    payload_format_indicator: Some(1),
+                    ..Default::default()
+                },
+                ..Default::default()
+            })
+        };
+        let (stream, mock) = MockBroker::new()
+            .handshake(ConnAck::default())
+            .send(utf8(1, b"\xc3\x28"))
+            .expect(Packet::PubAck(PubAck {
+                pkid: 1,
+                reason_code: PubAckReasonCode::PayloadFormatInvalid,
+                ..Default::default()
+            }))
+            .send(utf8(2, b"21.5"))
+            .expect(Packet::PubAck(PubAck {
+                pkid: 2,
+                ..Default::default()
+            }))
+            .send(server_disconnect())
+            .spawn();
+
+        block_on(async {
+            let (mut network, _client) = Network::new(options(), stream).await.unwrap();
+            let mut handler = Recorder::default();
+
+            let status = network.run(&mut handler).await.unwrap();
+            assert!(matches!(status, NetworkStatus::IncomingDisconnect));
+            let delivered = handler.0.iter().filter(|p| matches!(p, Packet::Publish(_)));
+            assert_eq!(delivered.collect::<Vec<_>>(), [&utf8(2, b"21.5")]);
+        });
+        mock.join().unwrap();
+    }
+
+    #[test]
+    fn resolves_topic_aliases_set_by_the_server() {
+        let aliased = |topic: &str| {
+            Packet::Publish(Publish {
+                topic: topic.to_string(),
+                properties: PublishProperties {
+                    topic_alias: Some(2),
+                    ..Default::default()
+                },
+                payload: "21.5".into(),
+                ..Default::default()
+            })
+        };
+        let (stream, mock) = MockBroker::new()
+            .handshake(ConnAck::default())
+            .send(aliased("sensors/temperature"))
+            .send(aliased(""))
+            .send(server_disconnect())
+            .spawn();
+
+        block_on(async {
+            let options = ConnectOptions {
+                inbound_topic_alias_max: 2,
+                ..options()
+            };
+            let (mut network, _client) = Network::new(options, stream).await.unwrap();
+            let mut handler = Recorder::default();
+
+            network.run(&mut handler).await.unwrap();
+            let topics = handler
+                .0
+                .iter()
+                .filter_map(|packet| match packet {
+                    Packet::Publish(publish) => Some(publish.topic.as_str()),
+                    _ => None,
+                })
+                .collect::<Vec<_>>();
+            assert_eq!(topics, ["sensors/temperature", "sensors/temperature"]);
+        });
+        mock.join().unwrap();
+    }
+
+    #[test]
+    fn refuses_an_unknown_topic_alias() {
+        let (stream, mock) = MockBroker::new()
+            .handshake(ConnAck::default())
+            .send(Packet::Publish(Publish {
+                properties: PublishProperties {
+                    topic_alias: Some(1),
+                    ..Default::default()
+                },
+                ..Default::default()
+            }))
+            .spawn();
+
+        block_on(async {
+            let options = ConnectOptions {
+                inbound_topic_alias_max: 2,
+                ..options()
+            };
+            let (mut network, _client) = Network::new(options, stream).await.unwrap();
+
+            let result = network.run(&mut Recorder::default()).await;
+            assert!(matches!(result, Err(RunError::Protocol(e)) if *e == MQTTError::UnknownTopicAlias(1)));
+        });
+        mock.join().unwrap();
+    }
+
+    #[test]
+    fn disconnects_with_a_reason_code_on_an_invalid_topic_alias() {
+        let error = MQTTError::InvalidTopicAlias(3, 2);
+        let (stream, mock) = MockBroker::new()
+            .handshake(ConnAck::default())
+            .send(Packet::Publish(Publish {
+                topic: String::from("sensors/temperature"),
+                properties: PublishProperties {
+                    topic_alias: Some(3),
+                    ..Default::default()
+                },
+                ..Default::default()
+            }))
+            .expect(Packet::Disconnect(Disconnect {
+                reason_code: DisconnectReasonCode::TopicAliasInvalid,
+                properties: DisconnectProperties {
+                    reason_string: Some(error.to_string()),
+                    ..Default::default()
+                },
+            }))
+            .spawn();
+
+        block_on(async {
+            let options = ConnectOptions {
+                inbound_topic_alias_max: 2,
+                ..options()
+            };
+            let (mut network, _client) = Network::new(options, stream).await.unwrap();
+
+            let result = network.run(&mut Recorder::default()).await;
+            assert!(matches!(result, Err(RunError::Protocol(e)) if *e == error));
+        });
+        mock.join().unwrap();
+    }
+
+    #[test]
+    fn refuses_publish_packets_over_the_receive_maximum() {
+        let state = State::<PacketIdManager>::from(&ConnectOptions {
+            client_receive_max: 1.try_into().unwrap(),
+            ..options()
+        });
+        let qos2 = |pkid| {
+            let Packet::Publish(mut publish) = publish(pkid) else {
+                unreachable!()
+            };
+            publish.qos = QoS::Two;
+            state.handle_incoming_packet(&mut Packet::Publish(publish))
+        };
+
+        assert!(qos2(1).is_ok());
+        assert_eq!(qos2(2), Err(MQTTError::ReceiveMaximumExceeded(1)));
+        assert_eq!(state.in_flight(), (0, 1));
+
+        let mut pubrel = Packet::PubRel(PubRel {
+            pkid: 1,
+            ..Default::default()
+        });
+        state.handle_incoming_packet(&mut pubrel).unwrap();
+        assert_eq!(state.in_flight(), (0, 0));
+        assert!(qos2(2).is_ok());
+    }
+
+    #[test]
+    fn strict_mode_names_the_rule_a_publish_over_the_receive_maximum_breaks() {
+        let state = State::<PacketIdManager>::from(&ConnectOptions {
+            client_receive_max: 1.try_into().unwrap(),
+            strict: true,
+            ..options()
+        });
+        let receive = |pkid, qos| {
+            let Packet::Publish(mut publish) = publish(pkid) else {
+                unreachable!()
+            };
+            publish.qos = qos;
+            state.handle_incoming_packet(&mut Packet::Publish(publish)).err()
+        };
+
+        // without manual acknowledgements, a QoS 1 PUBLISH is acknowledged right away
+        assert_eq!(receive(1, QoS::One), None);
+        assert_eq!(state.in_flight(), (0, 0));
+
+        assert_eq!(receive(2, QoS::Two), None);
+        let error = receive(3, QoS::One).unwrap();
+        assert_eq!(error, conformance::SERVER_RECEIVE_MAXIMUM_EXCEEDED.into());
+        assert_eq!(
+            DisconnectReasonCode::from(&error),
+            DisconnectReasonCode::ReceiveMaximumExceeded
+        );
+    }
+
+    #[test]
+    fn decodes_packets_received_in_fragments() {
+        let mut frame = bytes::BytesMut::new();
+        let publish = Packet::Publish(Publish {
+            topic: String::from("sensors/temperature"),
+            payload: "21.5".into(),
+            ..Default::default()
+        });
+        BufferIO::write(&publish, &mut frame).unwrap();
+        let (head, tail) = frame.split_at(3);
+
+        let (stream, mock) = MockBroker::new()
+            .handshake(ConnAck::default())
+            .send_raw(head)
+            .send_raw(tail)
+            .send(server_disconnect())
+            .spawn();
+
+        block_on(async {
+            let (mut network, _client) = Network::new(options(), stream).await.unwrap();
+            let mut handler = Recorder::default();
+
+            network.run(&mut handler).await.unwrap();
+            assert_eq!(handler.0.first(), Some(&publish));
+        });
+        mock.join().unwrap();
+    }
+
+    #[test]
+    fn refuses_an_acknowledgement_for_an_unknown_packet_id() {
+        let (stream, mock) = MockBroker::new()
+            .handshake(ConnAck::default())
+            .send(Packet::PubAck(PubAck {
+                pkid: 9,
+                ..Default::default()
+            }))
+            .spawn();
+
+        block_on(async {
+            let (mut network, _client) = Network::new(options(), stream).await.unwrap();
+
+            let result = network.run(&mut Recorder::default()).await;
+            assert!(matches!(result, Err(RunError::Protocol(e)) if *e == MQTTError::UnknownPacketId(9)));
+        });
+        mock.join().unwrap();
+    }
+
+    #[test]
+    fn refuses_acknowledgements_for_packet_ids_past_the_receive_maximum() {
+        let options = ConnectOptions {
+            server_receive_max: NonZero::new(2).unwrap(),
+            ..options()
+        };
+        let state = State::<PacketIdManager>::from(&options);
+
+        for pkid in [2, 3, u16::MAX] {
+            let mut puback = Packet::PubAck(PubAck {
+                pkid,
+                ..Default::default()
+            });
+            let mut pubcomp = Packet::PubComp(PubComp {
+                pkid,
+                ..Default::default()
+            });
+            for packet in [&mut puback, &mut pubcomp] {
+                let result = state.handle_incoming_packet(packet);
+                assert_eq!(result, Err(MQTTError::UnknownPacketId(pkid)));
+            }
+            let error = MQTTError::UnknownPacketId(pkid);
+            assert_eq!(DisconnectReasonCode::from(&error), DisconnectReasonCode::ProtocolError);
+        }
+    }
+
+    #[test]
+    fn fails_on_a_reserved_packet_type() {
+        let (stream, mock) = MockBroker::new()
+            .handshake(ConnAck::default())
+            .send_raw([0x00, 0x00])
+            .spawn();
+
+        block_on(async {
+            let (mut network, _client) = Network::new(options(), stream).await.unwrap();
+
+            let result = network.run(&mut Recorder::default()).await;
+            assert!(result.is_err());
+        });
+        mock.join().unwrap();
+    }
+
+    #[test]
+    fn releases_packet_ids_once_acknowledged_without_a_network() {
+        let mut state = State::from(&options());
+        let pkids = Arc::new(PacketIdManager::new(1));
+        state.set_pkid_mgr(pkids.clone());
+
+        let pkid = pkids.allocate().unwrap();
+        state.handle_outgoing_packet(publish(pkid)).unwrap();
+        assert!(pkids.allocate().is_err());
+
+        let mut puback = Packet::PubAck(PubAck {
+            pkid,
+            ..Default::default()
+        });
+        assert_eq!(state.handle_incoming_packet(&mut puback), Ok(None));
+        assert_eq!(pkids.allocate(), Ok(pkid));
+    }
+
+    #[test]
+    fn discards_the_messages_in_flight_when_the_session_is_lost() {
+        let mut state = State::from(&options());
+        let pkids = Arc::new(PacketIdManager::new(2));
+        state.set_pkid_mgr(pkids.clone());
+
+        // a QoS 1 PUBLISH awaiting its PUBACK, and a QoS 2 PUBLISH whose PUBREL awaits its PUBCOMP
+        let (first, second) = (pkids.allocate().unwrap(), pkids.allocate().unwrap());
+        state.handle_outgoing_packet(publish(first)).unwrap();
+        let Packet::Publish(mut qos2) = publish(second) else {
+            unreachable!()
+        };
+        qos2.qos = QoS::Two;
+        state.handle_outgoing_packet(Packet::Publish(qos2)).unwrap();
+        let mut pubrec = Packet::PubRec(PubRec {
+            pkid: second,
+            ..Default::default()
+        });
+        assert!(state.handle_incoming_packet(&mut pubrec).unwrap().is_some());
+
+        // a QoS 2 PUBLISH received, awaiting its PUBREL
+        let Packet::Publish(mut received) = publish(7) else {
+            unreachable!()
+        };
+        received.qos = QoS::Two;
+        state.handle_incoming_packet(&mut Packet::Publish(received)).unwrap();
+        assert_eq!(state.in_flight(), (2, 1));
+
+        assert!(state.resubscribe(usize::MAX).is_empty());
+        assert_eq!(state.in_flight(), (0, 0));
+        assert!(state.retransmit().is_empty());
+        assert_eq!((pkids.allocate(), pkids.allocate()), (Ok(1), Ok(2)));
+    }
+
+    #[test]
+    fn makes_the_subscriptions_of_a_lost_session_again() {
+        use crate::v5::packet::{
+            suback::{SubAck, SubAckReasonCode},
+            subscribe::{Subscribe, SubscribeProperties, SubscriptionOptions},
+            unsuback::{UnSubAck, UnSubAckReasonCode},
+            unsubscribe::UnSubscribe,
+        };
+
+        let mut state = State::from(&options());
+        let pkids = Arc::new(PacketIdManager::new(3));
+        state.set_pkid_mgr(pkids.clone());
+        let filter = |filter: &str, qos| {
+            let options = SubscriptionOptions {
+                qos,
+                ..Default::default()
+            };
+            (String::from(filter), options)
+        };
+
+        let subscribe = Packet::Subscribe(Subscribe {
+            pkid: pkids.allocate().unwrap(),
+            payload: vec![
+                filter("sensors/#", QoS::One),
+                filter("alerts/+", QoS::Zero),
+                filter("admin/#", QoS::Two),
+                filter("devices/+", QoS::One),
+            ],
+            ..Default::default()
+        });
+        state.handle_outgoing_packet(subscribe).unwrap();
+        let mut suback = Packet::SubAck(SubAck {
+            pkid: 1,
+            payload: vec![
+                SubAckReasonCode::GrantedQoS1,
+                SubAckReasonCode::GrantedQoS0,
+                SubAckReasonCode::NotAuthorized,
+                SubAckReasonCode::GrantedQoS1,
+            ],
+            ..Default::default()
+        });
+        state.handle_incoming_packet(&mut suback).unwrap();
+
+        let unsubscribe = Packet::UnSubscribe(UnSubscribe {
+            pkid: pkids.allocate().unwrap(),
+            payload: vec![String::from("alerts/+")],
+            ..Default::default()
+        });
+        state.handle_outgoing_packet(unsubscribe).unwrap();
+        let mut unsuback = Packet::UnSubAck(UnSubAck {
+            pkid: 2,
+            payload: vec![UnSubAckReasonCode::Success],
+            ..Default::default()
+        });
+        state.handle_incoming_packet(&mut unsuback).unwrap();
+
+        // never acknowledged, the session was lost before the SUBACK
+        let properties = SubscribeProperties {
+            subscription_id: Some(7),
+            ..Default::default()
+        };
+        let subscribe = Packet::Subscribe(Subscribe {
+            pkid: pkids.allocate().unwrap(),
+            payload: vec![filter("logs/#", QoS::Zero)],
+            properties: properties.clone(),
+        });
+        state.handle_outgoing_packet(subscribe).unwrap();
+        assert_eq!(state.in_flight(), (1, 0));
+
+        let resubscribe = state.resubscribe(usize::MAX);
+        assert_eq!(
+            resubscribe,
+            [
+                Subscribe {
+                    pkid: 0,
+                    payload: vec![filter("sensors/#", QoS::One), filter("devices/+", QoS::One)],
+                    ..Default::default()
+                },
+                Subscribe {
+                    pkid: 0,
+                    payload: vec![filter("logs/#", QoS::Zero)],
+                    properties,
+                },
+            ]
+        );
+        assert_eq!(state.in_flight(), (0, 0));
+        assert!((0..3).all(|_| pkids.allocate().is_ok()));
+
+        // 17 bytes for a SUBSCRIBE with a single one of these topic filters
+        let resubscribe = state.resubscribe(20);
+        let filters = resubscribe
+            .iter()
+            .map(|s| s.payload.len())
+            .collect::<Vec<_>>();
+        assert_eq!(filters, [1, 1, 1]);
+        assert!(resubscribe.iter().all(|s| s.is_valid(20).is_ok()));
+    }
+
+    #[test]
+    fn answers_a_pubrec_with_a_pubrel_without_a_network() {
+        let mut state = State::from(&options());
+        let pkids = Arc::new(PacketIdManager::new(1));
+        state.set_pkid_mgr(pkids.clone());
+
+        let pkid = pkids.allocate().unwrap();
+        let Packet::Publish(mut publish) = publish(pkid) else {
+            unreachable!()
+        };
+        publish.qos = QoS::Two;
+        state
+            .handle_outgoing_packet(Packet::Publish(publish))
+            .unwrap();
+
+        let mut pubrec = Packet::PubRec(PubRec {
+            pkid,
+            ..Default::default()
+        });
+        assert_eq!(
+            state.handle_incoming_packet(&mut pubrec),
+            Ok(Some(Packet::PubRel(PubRel {
+                pkid,
+                ..Default::default()
+            })))
+        );
+
+        let mut pubcomp = Packet::PubComp(PubComp {
+            pkid,
+            ..Default::default()
+        });
+        assert_eq!(state.handle_incoming_packet(&mut pubcomp), Ok(None));
+        assert_eq!(pkids.allocate(), Ok(pkid));
+    }
+
+    #[test]
+    fn strict_mode_names_the_rule_an_invalid_topic_alias_breaks() {
+        let aliased = |alias| Publish {
+            topic: String::from("sensors/temperature"),
+            properties: PublishProperties {
+                topic_alias: Some(alias),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let lenient = ConnectOptions {
+            inbound_topic_alias_max: 2,
+            ..options()
+        };
+        let strict = ConnectOptions {
+            strict: true,
+            inbound_topic_alias_max: 2,
+            ..options()
+        };
+
+        let state = State::<PacketIdManager>::from(&lenient);
+        let result = state.handle_incoming_packet(&mut Packet::Publish(aliased(0)));
+        assert_eq!(result, Err(MQTTError::InvalidTopicAlias(0, 2)));
+
+        let state = State::<PacketIdManager>::from(&strict);
+        let result = state.handle_incoming_packet(&mut Packet::Publish(aliased(0)));
+        assert_eq!(result, Err(conformance::TOPIC_ALIAS_ZERO.into()));
+        let result = state.handle_incoming_packet(&mut Packet::Publish(aliased(3)));
+        assert_eq!(
+            result,
+            Err(conformance::SERVER_TOPIC_ALIAS_TOO_LARGE.into())
+        );
+        let result = state.handle_outgoing_packet(Packet::Publish(aliased(1)));
+        assert_eq!(
+            result,
+            Err(conformance::CLIENT_TOPIC_ALIAS_TOO_LARGE.into())
+        );
+    }
+
+    #[test]
+    fn strict_mode_checks_qos0_publish_packets() {
+        let state = State::<PacketIdManager>::from(&ConnectOptions {
+            strict: true,
+            ..options()
+        });
+
+        let mut wildcard = Packet::Publish(Publish {
+            topic: String::from("sensors/+"),
+            ..Default::default()
+        });
+        let result = state.handle_incoming_packet(&mut wildcard);
+        assert_eq!(result, Err(conformance::TOPIC_NAME_WILDCARD.into()));
+
+        let mut dup = Packet::Publish(Publish {
+            topic: String::from("sensors/temperature"),
+            dup: true,
+            ..Default::default()
+        });
+        let result = state.handle_incoming_packet(&mut dup);
+        assert_eq!(result, Err(conformance::PUBLISH_QOS0_DUP.into()));
+
+        let with_pkid = Packet::Publish(Publish {
+            topic: String::from("sensors/temperature"),
+            pkid: Some(1),
+            ..Default::default()
+        });
+        let result = state.handle_outgoing_packet(with_pkid);
+        assert_eq!(result, Err(conformance::PUBLISH_QOS0_PACKET_ID.into()));
+    }
+
+    #[test]
+    fn strict_mode_reports_packets_over_the_maximum_size() {
+        let large = Packet::Publish(Publish {
+            topic: String::from("sensors/temperature"),
+            payload: vec![0u8; 200].into(),
+            ..Default::default()
+        });
+        let (stream, mock) = MockBroker::new()
+            .handshake(ConnAck::default())
+            .send(large)
+            .spawn();
+
+        block_on(async {
+            let options = ConnectOptions {
+                strict: true,
+                client_max_size: 128.try_into().unwrap(),
+                ..options()
+            };
+            let (mut network, _client) = Network::new(options, stream).await.unwrap();
+
+            let result = network.run(&mut Recorder::default()).await;
+            let error = match result {
+                Err(RunError::Protocol(error)) => *error,
+                result => panic!("expected a protocol error, found: {:?}", result.err()),
+            };
+            let MQTTError::Conformance(rule) = error else {
+                panic!("expected a conformance error, found: {error:?}");
+            };
+            assert_eq!(rule.id(), "MQTT-3.1.2-24");
+        });
+        mock.join().unwrap();
+    }
+
+    #[test]
+    fn strict_mode_names_the_rule_a_packet_that_cannot_be_decoded_breaks() {
+        let frames: [(&[u8], &str); 3] = [
+            // a Remaining Length of 0, on 2 bytes
+            (b"\xd0\x80\x00", "MQTT-1.5.5-1"),
+            // a Topic Name of "a\0"
+            (b"\x30\x05\x00\x02a\x00\x00", "MQTT-1.5.4-2"),
+            // both QoS bits set
+            (b"\x36\x06\x00\x01a\x00\x01\x00", "MQTT-3.3.1-4"),
+        ];
+
+        for (frame, id) in frames {
+            let (stream, mock) = MockBroker::new()
+                .handshake(ConnAck::default())
+                .send_raw(frame)
+                .spawn();
+
+            block_on(async {
+                let options = ConnectOptions {
+                    strict: true,
+                    ..options()
+                };
+                let (mut network, _client) = Network::new(options, stream).await.unwrap();
+
+                let result = network.run(&mut Recorder::default()).await;
+                let error = match result {
+                    Err(RunError::Protocol(error)) => *error,
+                    result => panic!("expected a protocol error, found: {:?}", result.err()),
+                };
+                let MQTTError::Conformance(rule) = error else {
+                    panic!("expected a conformance error, found: {error:?}");
+                };
+                assert_eq!(rule.id(), id);
+                assert_eq!(DisconnectReasonCode::from(&error), DisconnectReasonCode::MalformedPacket);
+            });
+            mock.join().unwrap();
+        }
+    }
+
+    #[test]
+    fn sends_manual_acknowledgements_in_the_order_the_messages_were_received() {
+        let options = ConnectOptions {
+            manual_ack: true,
+            ..options()
+        };
+        let state = State::<PacketIdManager>::from(&options);
+
+        let Packet::Publish(mut qos2) = publish(2) else {
+            unreachable!()
+        };
+        qos2.qos = QoS::Two;
+        for mut packet in [publish(1), Packet::Publish(qos2), publish(3)] {
+            assert_eq!(state.handle_incoming_packet(&mut packet), Ok(None));
+        }
+        assert_eq!(state.in_flight(), (0, 3));
+
+        let puback = |pkid, reason_code| {
+            Packet::PubAck(PubAck {
+                pkid,
+                reason_code,
+                ..Default::default()
+            })
+        };
+        let pubrec = || {
+            Packet::PubRec(PubRec {
+                pkid: 2,
+                ..Default::default()
+            })
+        };
+
+        // the first message is acknowledged last
+        assert_eq!(state.order_ack(puback(3, PubAckReasonCode::Success)), []);
+        assert_eq!(state.order_ack(pubrec()), []);
+        assert_eq!(
+            state.order_ack(puback(1, PubAckReasonCode::ImplementationSpecificError)),
+            [
+                puback(1, PubAckReasonCode::ImplementationSpecificError),
+                pubrec(),
+                puback(3, PubAckReasonCode::Success)
+            ]
+        );
+        // acknowledged twice
+        assert_eq!(state.order_ack(puback(3, PubAckReasonCode::Success)), []);
+    }
+
+    #[test]
+    fn acknowledges_messages_once_the_application_is_done_with_them() {
+        use crate::v5::{client::MqttClient, commons::reason_code::ReasonCode};
+
+        /// Acknowledges the messages it receives in the reverse order
+        struct Committer(MqttClient, Vec<Publish>);
+
+        impl AsyncHandler for Committer {
+            async fn handle(&mut self, packet: Packet) {
+                let Packet::Publish(publish) = packet else {
+                    return;
+                };
+                self.1.push(publish);
+                if self.1.len() < 2 {
+                    return;
+                }
+                while let Some(publish) = self.1.pop() {
+                    let reason = match publish.pkid {
+                        Some(1) => ReasonCode::Success,
+                        _ => ReasonCode::ImplementationSpecificError,
+                    };
+                    self.0.ack(&publish, reason).await.unwrap();
+                }
+            }
+        }
+
+        let (stream, mock) = MockBroker::new()
+            .handshake(ConnAck::default())
+            .send(publish(1))
+            .send(publish(2))
+            .expect(Packet::PubAck(PubAck {
+                pkid: 1,
+                ..Default::default()
+            }))
+            .expect(Packet::PubAck(PubAck {
+                pkid: 2,
+                reason_code: PubAckReasonCode::ImplementationSpecificError,
+                ..Default::default()
+            }))
+            .send(server_disconnect())
+            .spawn();
+
+        block_on(async {
+            let options = ConnectOptions {
+                manual_ack: true,
+                ..options()
+            };
+            let (mut network, client) = Network::new(options, stream).await.unwrap();
+
+            let Packet::Publish(qos0) = publish(1) else {
+                unreachable!()
+            };
+            let qos0 = Publish {
+                qos: QoS::Zero,
+                pkid: None,
+                ..qos0
+            };
+            let error = client.ack(&qos0, ReasonCode::Success).await.unwrap_err();
+            assert!(matches!(error, AckError::QoS0));
+            let Packet::Publish(qos1) = publish(1) else {
+                unreachable!()
+            };
+            let error = client
+                .ack(&qos1, ReasonCode::GrantedQoS1)
+                .await
+                .unwrap_err();
+            assert!(matches!(
+                error,
+                AckError::InvalidReasonCode(ReasonCode::GrantedQoS1, 1)
+            ));
+
+            let mut handler = Committer(client, Vec::new());
+            let status = network.run(&mut handler).await.unwrap();
+            assert!(matches!(status, NetworkStatus::IncomingDisconnect));
+        });
+        mock.join().unwrap();
+    }
 }
@@ -0,0 +1,343 @@
+//! What a client has been doing: the packets and bytes it exchanged, how many packets are in flight or waiting to be
+//! sent, how long the server takes to acknowledge them, and why it had to reconnect.
+//!
+//! [`MqttClient::stats`](super::MqttClient::stats) (or [`Network::stats`](super::network::asyncx::Network::stats))
+//! takes a snapshot of them:
+//! ```ignore
+//! let stats = client.stats();
+//! println!("{} PUBLISH sent, {} bytes received", stats.sent.packets(PacketType::Publish), stats.received.total_bytes());
+//! println!("PUBLISH acknowledged in {:?} on average", stats.publish_latency.mean());
+//! ```
+//! With the `metrics` feature, everything is also reported through the [`metrics`](https://docs.rs/metrics) facade as it
+//! happens, to whichever recorder (exporter) the application installed:
+//! - `mqtt_packets_total` and `mqtt_bytes_total` counters, labelled with the `direction` and the packet `type`
+//! - `mqtt_in_flight` gauge, labelled with the `direction`
+//! - `mqtt_queued` gauge, the packets waiting to be sent
+//! - `mqtt_latency_seconds` histogram, labelled with the `exchange` (`publish` or `ping`)
+//! - `mqtt_reconnects_total` counter, labelled with the `reason`
+use std::{
+    collections::BTreeMap,
+    sync::{
+        atomic::{AtomicU16, AtomicU64, Ordering},
+        Mutex,
+    },
+    time::Duration,
+};
+
+use crate::v5::commons::packet_type::PacketType;
+
+/// Upper bounds of the buckets of the latency histograms, the last bucket holds everything above them
+const LATENCY_BUCKETS: [Duration; 12] = [
+    Duration::from_millis(1),
+    Duration::from_millis(2),
+    Duration::from_millis(5),
+    Duration::from_millis(10),
+    Duration::from_millis(25),
+    Duration::from_millis(50),
+    Duration::from_millis(100),
+    Duration::from_millis(250),
+    Duration::from_millis(500),
+    Duration::from_secs(1),
+    Duration::from_secs(5),
+    Duration::from_secs(10),
+];
+
+/// Index of `packet_type` in the counters, the packet type is the high nibble of the fixed header (2.1.2)
+fn index(packet_type: PacketType) -> usize {
+    (packet_type as u8 >> 4) as usize
+}
+
+/// Packets and bytes exchanged in one direction
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Traffic {
+    packets: [u64; 16],
+    bytes: [u64; 16],
+}
+
+impl Traffic {
+    /// Number of packets of this type
+    pub fn packets(&self, packet_type: PacketType) -> u64 {
+        self.packets[index(packet_type)]
+    }
+
+    /// Size of the packets of this type, fixed headers included
+    pub fn bytes(&self, packet_type: PacketType) -> u64 {
+        self.bytes[index(packet_type)]
+    }
+
+    pub fn total_packets(&self) -> u64 {
+        self.packets.iter().sum()
+    }
+
+    pub fn total_bytes(&self) -> u64 {
+        self.bytes.iter().sum()
+    }
+}
+
+/// Distribution of the time taken by an exchange (e.g. PINGREQ → PINGRESP)
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Histogram {
+    /// Number of exchanges measured
+    pub count: u64,
+    /// Time taken by all of them
+    pub sum: Duration,
+    pub max: Duration,
+    buckets: [u64; LATENCY_BUCKETS.len() + 1],
+}
+
+impl Histogram {
+    fn record(&mut self, latency: Duration) {
+        let bucket = LATENCY_BUCKETS.partition_point(|bound| *bound < latency);
+        self.buckets[bucket] += 1;
+        self.count += 1;
+        self.sum += latency;
+        self.max = self.max.max(latency);
+    }
+
+    pub fn mean(&self) -> Option<Duration> {
+        // `Duration` only divides by a `u32`, which `count` outgrows
+        (self.count > 0).then(|| {
+            Duration::from_nanos((self.sum.as_nanos() / self.count as u128) as u64)
+        })
+    }
+
+    /// Number of exchanges that took at most `bound`, for each bound. The last bucket (`None`) is not bounded
+    pub fn buckets(&self) -> impl Iterator<Item = (Option<Duration>, u64)> + '_ {
+        LATENCY_BUCKETS
+            .iter()
+            .copied()
+            .map(Some)
+            .chain([None])
+            .zip(self.buckets.iter().copied())
+    }
+}
+
+/// How a run of the network ended, which is why the client reconnected (see [`MqttClient::exit_reason`](super::MqttClient::exit_reason))
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, derive_more::Display)]
+pub enum ReconnectReason {
+    /// The server sent a DISCONNECT
+    #[display("server_disconnect")]
+    ServerDisconnect,
+    /// The client sent a DISCONNECT
+    #[display("client_disconnect")]
+    ClientDisconnect,
+    /// No PINGRESP was received in time
+    #[display("keep_alive_timeout")]
+    KeepAliveTimeout,
+    /// The network connection failed
+    #[display("connection_lost")]
+    ConnectionLost,
+    /// The server broke the protocol, and the client closed the connection
+    #[display("protocol_error")]
+    ProtocolError,
+    /// The previous connection was not run, or the network was dropped while running it
+    #[display("unknown")]
+    Unknown,
+}
+
+/// A snapshot of the statistics of a client
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Stats {
+    pub sent: Traffic,
+    pub received: Traffic,
+    /// QoS 1 and QoS 2 packets sent, and not acknowledged yet
+    pub in_flight_outgoing: u16,
+    /// QoS 2 packets received, whose PUBREL has not been received yet
+    pub in_flight_incoming: u16,
+    /// Packets handed to the client, and not sent yet (e.g. while the network is reconnecting)
+    pub queued: usize,
+    /// PUBLISH → PUBACK (QoS 1), or PUBLISH → PUBCOMP (QoS 2)
+    pub publish_latency: Histogram,
+    /// PINGREQ → PINGRESP
+    pub ping_latency: Histogram,
+    pub reconnects: BTreeMap<ReconnectReason, u64>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Direction {
+    Outgoing,
+    Incoming,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Exchange {
+    Publish,
+    Ping,
+}
+
+#[derive(Debug, Default)]
+struct Counters {
+    packets: [AtomicU64; 16],
+    bytes: [AtomicU64; 16],
+}
+
+impl Counters {
+    fn snapshot(&self) -> Traffic {
+        Traffic {
+            packets: self
+                .packets
+                .each_ref()
+                .map(|count| count.load(Ordering::Relaxed)),
+            bytes: self
+                .bytes
+                .each_ref()
+                .map(|count| count.load(Ordering::Relaxed)),
+        }
+    }
+}
+
+/// Where the network records what it does, shared with the client for snapshots
+#[derive(Debug, Default)]
+pub(crate) struct StatsRecorder {
+    sent: Counters,
+    received: Counters,
+    in_flight_outgoing: AtomicU16,
+    in_flight_incoming: AtomicU16,
+    latencies: Mutex<(Histogram, Histogram)>,
+    reconnects: Mutex<BTreeMap<ReconnectReason, u64>>,
+}
+
+impl StatsRecorder {
+    /// `size` is the size of the whole packet on the wire
+    pub(crate) fn packet(&self, direction: Direction, packet_type: PacketType, size: usize) {
+        let counters = match direction {
+            Direction::Outgoing => &self.sent,
+            Direction::Incoming => &self.received,
+        };
+        counters.packets[index(packet_type)].fetch_add(1, Ordering::Relaxed);
+        counters.bytes[index(packet_type)].fetch_add(size as u64, Ordering::Relaxed);
+
+        #[cfg(feature = "metrics")]
+        {
+            let labels = [
+                ("direction", direction.label().to_string()),
+                ("type", packet_type.to_string()),
+            ];
+            metrics::counter!("mqtt_packets_total", &labels).increment(1);
+            metrics::counter!("mqtt_bytes_total", &labels).increment(size as u64);
+        }
+    }
+
+    pub(crate) fn in_flight(&self, outgoing: u16, incoming: u16) {
+        self.in_flight_outgoing.store(outgoing, Ordering::Relaxed);
+        self.in_flight_incoming.store(incoming, Ordering::Relaxed);
+
+        #[cfg(feature = "metrics")]
+        {
+            metrics::gauge!("mqtt_in_flight", "direction" => "outgoing").set(f64::from(outgoing));
+            metrics::gauge!("mqtt_in_flight", "direction" => "incoming").set(f64::from(incoming));
+        }
+    }
+
+    #[cfg_attr(not(feature = "metrics"), allow(unused_variables))]
+    pub(crate) fn queued(&self, queued: usize) {
+        #[cfg(feature = "metrics")]
+        metrics::gauge!("mqtt_queued").set(queued as f64);
+    }
+
+    pub(crate) fn latency(&self, exchange: Exchange, latency: Duration) {
+        let mut latencies = self.latencies.lock().unwrap();
+        match exchange {
+            Exchange::Publish => latencies.0.record(latency),
+            Exchange::Ping => latencies.1.record(latency),
+        }
+
+        #[cfg(feature = "metrics")]
+        metrics::histogram!("mqtt_latency_seconds", "exchange" => exchange.label())
+            .record(latency.as_secs_f64());
+    }
+
+    pub(crate) fn reconnect(&self, reason: ReconnectReason) {
+        *self.reconnects.lock().unwrap().entry(reason).or_default() += 1;
+
+        #[cfg(feature = "metrics")]
+        metrics::counter!("mqtt_reconnects_total", "reason" => reason.to_string()).increment(1);
+    }
+
+    /// `queued` is only known to whoever holds the sending half of the channel
+    pub(crate) fn snapshot(&self, queued: usize) -> Stats {
+        let (publish_latency, ping_latency) = self.latencies.lock().unwrap().clone();
+
+        Stats {
+            sent: self.sent.snapshot(),
+            received: self.received.snapshot(),
+            in_flight_outgoing: self.in_flight_outgoing.load(Ordering::Relaxed),
+            in_flight_incoming: self.in_flight_incoming.load(Ordering::Relaxed),
+            queued,
+            publish_latency,
+            ping_latency,
+            reconnects: self.reconnects.lock().unwrap().clone(),
+        }
+    }
+}
+
+#[cfg(feature = "metrics")]
+impl Direction {
+    fn label(&self) -> &'static str {
+        match self {
+            Self::Outgoing => "outgoing",
+            Self::Incoming => "incoming",
+        }
+    }
+}
+
+#[cfg(feature = "metrics")]
+impl Exchange {
+    fn label(&self) -> &'static str {
+        match self {
+            Self::Publish => "publish",
+            Self::Ping => "ping",
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sorts_latencies_into_buckets() {
+        let mut histogram = Histogram::default();
+        assert_eq!(histogram.mean(), None);
+
+        histogram.record(Duration::from_millis(1));
+        histogram.record(Duration::from_millis(3));
+        histogram.record(Duration::from_secs(60));
+
+        let buckets = histogram.buckets().collect::<Vec<_>>();
+        assert_eq!(buckets[0], (Some(Duration::from_millis(1)), 1));
+        assert_eq!(buckets[2], (Some(Duration::from_millis(5)), 1));
+        assert_eq!(buckets[12], (None, 1));
+        assert_eq!(histogram.count, 3);
+        assert_eq!(histogram.max, Duration::from_secs(60));
+        assert_eq!(histogram.mean(), Some(Duration::from_nanos(20_001_333_333)));
+    }
+
+    #[test]
+    fn averages_more_exchanges_than_a_u32_counts() {
+        let histogram = Histogram {
+            count: u32::MAX as u64 + 1,
+            sum: Duration::from_millis(u32::MAX as u64 + 1),
+            ..Default::default()
+        };
+        assert_eq!(histogram.mean(), Some(Duration::from_millis(1)));
+    }
+
+    #[test]
+    fn counts_packets_and_bytes_per_type() {
+        let stats = StatsRecorder::default();
+        stats.packet(Direction::Outgoing, PacketType::Publish, 20);
+        stats.packet(Direction::Outgoing, PacketType::Publish, 30);
+        stats.packet(Direction::Incoming, PacketType::PubAck, 4);
+        stats.reconnect(ReconnectReason::KeepAliveTimeout);
+
+        let snapshot = stats.snapshot(2);
+        assert_eq!(snapshot.sent.packets(PacketType::Publish), 2);
+        assert_eq!(snapshot.sent.bytes(PacketType::Publish), 50);
+        assert_eq!(snapshot.sent.packets(PacketType::PubAck), 0);
+        assert_eq!(snapshot.received.total_packets(), 1);
+        assert_eq!(snapshot.received.total_bytes(), 4);
+        assert_eq!(snapshot.queued, 2);
+        assert_eq!(snapshot.reconnects[&ReconnectReason::KeepAliveTimeout], 1);
+    }
+}
@@ -0,0 +1,89 @@
+use std::{
+    ops::Deref,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use async_channel::Receiver;
+use futures::{Stream, StreamExt};
+
+use super::MqttClient;
+use crate::v5::packet::publish::Publish;
+
+/// A message received for a [`Subscription`], it derefs to the PUBLISH packet it was received with (e.g. to
+/// [`MqttClient::ack`] it)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Message(Publish);
+
+impl Message {
+    pub fn into_publish(self) -> Publish {
+        self.0
+    }
+}
+
+impl Deref for Message {
+    type Target = Publish;
+
+    fn deref(&self) -> &Publish {
+        &self.0
+    }
+}
+
+impl From<Publish> for Message {
+    fn from(publish: Publish) -> Self {
+        Self(publish)
+    }
+}
+
+/// The messages received for a topic filter, returned by [`MqttClient::subscribe`].
+///
+/// Handles of the same topic filter share a single subscription with the server: only the first one sends a
+/// SUBSCRIBE (the options of the others are left unused, and they do not receive the retained messages sent for it),
+/// and dropping the last one sends an UNSUBSCRIBE
+#[derive(Debug)]
+pub struct Subscription {
+    filter: String,
+    /// Of the handle, in the routes of the client
+    key: u64,
+    messages: Pin<Box<Receiver<Publish>>>,
+    client: MqttClient,
+}
+
+impl Subscription {
+    pub(crate) fn new(
+        filter: String,
+        key: u64,
+        messages: Receiver<Publish>,
+        client: MqttClient,
+    ) -> Self {
+        Self {
+            filter,
+            key,
+            messages: Box::pin(messages),
+            client,
+        }
+    }
+
+    pub fn filter(&self) -> &str {
+        &self.filter
+    }
+}
+
+impl Stream for Subscription {
+    type Item = Message;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Message>> {
+        self.messages
+            .poll_next_unpin(cx)
+            .map(|publish| publish.map(Message))
+    }
+}
+
+impl Drop for Subscription {
+    fn drop(&mut self) {
+        if let Some(subscription_id) = self.client.routes.remove(self.key) {
+            let filter = std::mem::take(&mut self.filter);
+            self.client.unsubscribe_dropped(filter, subscription_id);
+        }
+    }
+}
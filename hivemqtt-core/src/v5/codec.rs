@@ -0,0 +1,307 @@
+//! Encoding and decoding of MQTT 5.0 packets, to and from bytes.
+//!
+//! This is the codec the client itself runs on, for those who need the packet model without the client
+//! (proxies, test fixtures, broker plugins, ...):
+//! ```
+//! use bytes::{Bytes, BytesMut};
+//! use hivemqtt_core::v5::{codec::Decoded, commons::packet::Packet, packet::ping::PingReq};
+//!
+//! let packet = Packet::PingReq(PingReq {});
+//! let mut buf = BytesMut::new();
+//! packet.encode(&mut buf).unwrap();
+//! assert_eq!(buf.len(), packet.encoded_len());
+//!
+//! let mut bytes = buf.freeze();
+//! assert_eq!(Packet::decode(&mut bytes.slice(..1)).unwrap(), Decoded::Incomplete(1));
+//! assert_eq!(Packet::decode(&mut bytes).unwrap(), Decoded::Packet(packet));
+//! assert!(bytes.is_empty());
+//! ```
+//! With the `tokio` feature, [`MqttCodec`] plugs the same codec into `tokio_util`'s `Framed`, `FramedRead` and `FramedWrite`.
+use bytes::{Buf, Bytes, BytesMut};
+
+use super::{
+    commons::{
+        error::{DecodeError, MQTTError},
+        packet::{decode_frame, peek_header, Packet},
+    },
+    traits::bufferio::BufferIO,
+};
+
+/// Outcome of [`Packet::decode`]
+#[derive(Debug, PartialEq, Eq)]
+#[allow(clippy::large_enum_variant)] // moved out right away, boxing would only add an allocation per packet
+pub enum Decoded {
+    /// A whole packet was decoded, and removed from the buffer
+    Packet(Packet),
+    /// The buffer does not hold a whole packet yet, and needs at least this many more bytes.
+    /// Only the fixed header tells how long a packet is, so this is a lower bound until the fixed header is complete
+    Incomplete(usize),
+}
+
+impl Packet {
+    /// Appends the packet (fixed header included) to `buf`
+    pub fn encode(&self, buf: &mut BytesMut) -> Result<(), MQTTError> {
+        BufferIO::write(self, buf)
+    }
+
+    /// Decodes the packet at the start of `buf`.
+    /// `buf` is only advanced when a packet is decoded: it is left untouched when the packet is incomplete or malformed
+    pub fn decode(buf: &mut Bytes) -> Result<Decoded, DecodeError> {
+        let Some(header) = peek_header(buf)? else {
+            return Ok(Decoded::Incomplete(1));
+        };
+
+        let size = 1 + header.header_len + header.remaining_length;
+        if buf.len() < size {
+            return Ok(Decoded::Incomplete(size - buf.len()));
+        }
+
+        let packet = decode_frame(header, buf.slice(1 + header.header_len..size))?;
+        buf.advance(size);
+
+        Ok(Decoded::Packet(packet))
+    }
+
+    /// Number of bytes [`Packet::encode`] writes for this packet, fixed header included
+    pub fn encoded_len(&self) -> usize {
+        match self {
+            Self::Connect(packet) => frame_len(packet),
+            Self::ConnAck(packet) => frame_len(packet),
+            Self::Publish(packet) => frame_len(packet),
+            Self::PubAck(packet) => frame_len(packet),
+            Self::PubRec(packet) => frame_len(packet),
+            Self::PubRel(packet) => frame_len(packet),
+            Self::PubComp(packet) => frame_len(packet),
+            Self::Subscribe(packet) => frame_len(packet),
+            Self::SubAck(packet) => frame_len(packet),
+            Self::UnSubscribe(packet) => frame_len(packet),
+            Self::UnSubAck(packet) => frame_len(packet),
+            Self::PingReq(packet) => frame_len(packet),
+            Self::PingResp(packet) => frame_len(packet),
+            Self::Disconnect(packet) => frame_len(packet),
+            Self::Auth(packet) => frame_len(packet),
+        }
+    }
+}
+
+/// The packet type byte, the Remaining Length, and the Remaining Length bytes themselves
+fn frame_len<T: BufferIO>(packet: &T) -> usize {
+    1 + packet.variable_length() + packet.length()
+}
+
+#[cfg(feature = "tokio")]
+pub use tokio_codec::MqttCodec;
+
+#[cfg(feature = "tokio")]
+mod tokio_codec {
+    use bytes::BytesMut;
+    use tokio_util::codec::{Decoder, Encoder};
+
+    use super::*;
+    use crate::v5::commons::packet::split_frame;
+
+    /// MQTT 5.0 codec for [`tokio_util::codec`]
+    /// ```ignore
+    /// let mut framed = Framed::new(stream, MqttCodec::new(64 * 1024));
+    /// while let Some(packet) = framed.next().await { ... }
+    /// ```
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub struct MqttCodec {
+        max_size: usize,
+    }
+
+    impl MqttCodec {
+        /// Packets larger than `max_size` bytes (fixed header included) are refused with [`DecodeError::TooLarge`],
+        /// as soon as their fixed header is received
+        pub fn new(max_size: usize) -> Self {
+            Self { max_size }
+        }
+
+        pub fn max_size(&self) -> usize {
+            self.max_size
+        }
+    }
+
+    impl Default for MqttCodec {
+        /// Same as the default `client_max_size` in [`ConnectOptions`](crate::v5::client::ConnectOptions)
+        fn default() -> Self {
+            Self::new(u32::MAX as usize)
+        }
+    }
+
+    impl Decoder for MqttCodec {
+        type Item = Packet;
+        type Error = DecodeError;
+
+        fn decode(&mut self, src: &mut BytesMut) -> Result<Option<Packet>, DecodeError> {
+            let Some((header, body)) = split_frame(src, self.max_size)? else {
+                return Ok(None);
+            };
+
+            Ok(Some(decode_frame(header, body)?))
+        }
+    }
+
+    impl Encoder<Packet> for MqttCodec {
+        type Error = MQTTError;
+
+        /// Packets larger than the maximum size are refused, the same way they would be by the other side
+        fn encode(&mut self, packet: Packet, dst: &mut BytesMut) -> Result<(), MQTTError> {
+            let size = packet.encoded_len();
+            if size > self.max_size {
+                return Err(MQTTError::MaxPacketSizeExceed(size));
+            }
+
+            dst.reserve(size);
+            packet.encode(dst)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::v5::{
+        commons::qos::QoS,
+        packet::{
+            connect::Connect,
+            disconnect::{Disconnect, DisconnectReasonCode},
+            puback::PubAck,
+            publish::Publish,
+            subscribe::{Subscribe, SubscriptionOptions},
+        },
+    };
+
+    fn packets() -> Vec<Packet> {
+        vec![
+            Packet::Connect(Connect::default()),
+            Packet::Publish(Publish {
+                topic: String::from("sensors/temperature"),
+                qos: QoS::One,
+                pkid: Some(7),
+                payload: Bytes::from(vec![0u8; 300]),
+                ..Default::default()
+            }),
+            Packet::PubAck(PubAck {
+                pkid: 7,
+                ..Default::default()
+            }),
+            Packet::Subscribe(Subscribe {
+                pkid: 8,
+                payload: vec![(String::from("sensors/#"), SubscriptionOptions::default())],
+                ..Default::default()
+            }),
+            Packet::Disconnect(Disconnect {
+                reason_code: DisconnectReasonCode::ServerShuttingDown,
+                ..Default::default()
+            }),
+        ]
+    }
+
+    #[test]
+    fn encoded_len_matches_the_encoding() {
+        for packet in packets() {
+            let mut buf = BytesMut::new();
+            packet.encode(&mut buf).unwrap();
+            assert_eq!(buf.len(), packet.encoded_len(), "{packet:?}");
+        }
+    }
+
+    #[test]
+    fn decodes_back_to_back_packets() {
+        let mut buf = BytesMut::new();
+        for packet in packets() {
+            packet.encode(&mut buf).unwrap();
+        }
+        let mut buf = buf.freeze();
+
+        for packet in packets() {
+            assert_eq!(Packet::decode(&mut buf).unwrap(), Decoded::Packet(packet));
+        }
+        assert_eq!(Packet::decode(&mut buf).unwrap(), Decoded::Incomplete(1));
+    }
+
+    #[test]
+    fn reports_how_many_bytes_are_missing() {
+        let mut buf = BytesMut::new();
+        packets()[1].encode(&mut buf).unwrap();
+        let total = buf.len();
+
+        let mut partial = buf.freeze().slice(..10);
+        assert_eq!(Packet::decode(&mut partial).unwrap(), Decoded::Incomplete(total - 10));
+        assert_eq!(partial.len(), 10);
+    }
+
+    #[test]
+    fn leaves_malformed_packets_in_the_buffer() {
+        let mut buf = Bytes::from_static(b"\x00\x00");
+        assert!(matches!(Packet::decode(&mut buf), Err(DecodeError::Malformed(_))));
+        assert_eq!(buf.len(), 2);
+    }
+
+    #[test]
+    fn refuses_bytes_left_over_after_the_packet() {
+        // a PINGREQ has no body, a PUBACK ends with its properties
+        for frame in [&b"\xc0\x01\x00"[..], b"\x40\x05\x00\x07\x00\x00\xff"] {
+            let mut buf = Bytes::from_static(frame);
+            assert!(
+                matches!(Packet::decode(&mut buf), Err(DecodeError::Malformed(MQTTError::MalformedPacket))),
+                "{frame:x?}"
+            );
+        }
+    }
+
+    #[test]
+    fn decode_errors_close_with_the_reason_code_of_their_error() {
+        let cases = [
+            (MQTTError::MalformedPacket, DisconnectReasonCode::MalformedPacket),
+            (MQTTError::InvalidTopic("#"), DisconnectReasonCode::TopicNameInvalid),
+            (MQTTError::PacketIdConflict(7), DisconnectReasonCode::ProtocolError),
+            (MQTTError::ReceiveMaximumExceeded(10), DisconnectReasonCode::ReceiveMaximumExceeded),
+            (MQTTError::MaxPacketSizeExceed(300), DisconnectReasonCode::PacketTooLarge),
+        ];
+        for (error, reason_code) in cases {
+            assert_eq!(DisconnectReasonCode::from(&error), reason_code, "{error:?}");
+            assert_eq!(DecodeError::from(error).reason_code(), reason_code);
+        }
+    }
+
+    #[cfg(feature = "tokio")]
+    #[test]
+    fn tokio_codec_waits_for_whole_packets() {
+        use tokio_util::codec::{Decoder, Encoder};
+
+        let mut codec = MqttCodec::default();
+        let mut buf = BytesMut::new();
+        for packet in packets() {
+            codec.encode(packet, &mut buf).unwrap();
+        }
+
+        // the first packet, and the start of the second one
+        let mut src = buf.split_to(packets()[0].encoded_len() + 3);
+        assert_eq!(codec.decode(&mut src).unwrap(), Some(packets().remove(0)));
+        assert_eq!(codec.decode(&mut src).unwrap(), None);
+
+        src.unsplit(buf);
+        for packet in packets().into_iter().skip(1) {
+            assert_eq!(codec.decode(&mut src).unwrap(), Some(packet));
+        }
+        assert!(src.is_empty());
+    }
+
+    #[cfg(feature = "tokio")]
+    #[test]
+    fn tokio_codec_refuses_packets_over_the_maximum_size() {
+        use tokio_util::codec::{Decoder, Encoder};
+
+        let size = packets()[1].encoded_len();
+        let mut codec = MqttCodec::new(size - 1);
+
+        let mut buf = BytesMut::new();
+        assert_eq!(codec.encode(packets().remove(1), &mut buf), Err(MQTTError::MaxPacketSizeExceed(size)));
+        assert!(buf.is_empty());
+
+        packets()[1].encode(&mut buf).unwrap();
+        assert!(matches!(codec.decode(&mut buf), Err(DecodeError::TooLarge(n)) if n == size));
+    }
+}
@@ -0,0 +1,105 @@
+//! The normative statements of the MQTT 5.0 specification the client checks in strict mode
+//! ([`ConnectOptions::strict`](crate::v5::client::ConnectOptions::strict)).
+//!
+//! Each [`ConformanceError`] carries the identifier of the statement it was raised for, so that whoever broke it (the
+//! server, or the application) can be pointed at the exact rule:
+//! ```
+//! use hivemqtt_core::v5::commons::conformance::{self, ConformanceError};
+//!
+//! let err: ConformanceError = conformance::TOPIC_ALIAS_ZERO;
+//! assert_eq!(err.id(), "MQTT-3.3.2-8");
+//! ```
+
+/// A protocol violation, and the normative statement (e.g. `MQTT-3.3.2-8`) of the specification it breaks
+#[derive(Debug, Clone, Copy, PartialEq, Eq, thiserror::Error)]
+#[error("{description} [{id}]")]
+pub struct ConformanceError {
+    id: &'static str,
+    description: &'static str,
+}
+
+impl ConformanceError {
+    const fn new(id: &'static str, description: &'static str) -> Self {
+        Self { id, description }
+    }
+
+    /// Identifier of the normative statement, as written in the specification (e.g. `MQTT-3.3.2-8`)
+    pub fn id(&self) -> &'static str {
+        self.id
+    }
+
+    /// The normative statement itself
+    pub fn description(&self) -> &'static str {
+        self.description
+    }
+}
+
+pub const UTF8_NULL_CHARACTER: ConformanceError = ConformanceError::new(
+    "MQTT-1.5.4-2",
+    "A UTF-8 Encoded String MUST NOT include an encoding of the null character U+0000",
+);
+
+pub const VARINT_NOT_MINIMAL: ConformanceError = ConformanceError::new(
+    "MQTT-1.5.5-1",
+    "The encoded value MUST use the minimum number of bytes necessary to represent the value",
+);
+
+pub const PUBLISH_QOS0_PACKET_ID: ConformanceError = ConformanceError::new(
+    "MQTT-2.2.1-2",
+    "A PUBLISH packet MUST NOT contain a Packet Identifier if its QoS value is set to 0",
+);
+
+pub const CLIENT_PACKET_ID_IN_USE: ConformanceError = ConformanceError::new(
+    "MQTT-2.2.1-3",
+    "Each time a Client sends a new SUBSCRIBE, UNSUBSCRIBE, or PUBLISH (where QoS > 0) MQTT Control Packet it MUST assign it a non-zero Packet Identifier that is currently unused",
+);
+
+pub const SERVER_PACKET_ID_IN_USE: ConformanceError = ConformanceError::new(
+    "MQTT-2.2.1-4",
+    "Each time a Server sends a new PUBLISH (with QoS > 0) MQTT Control Packet it MUST assign it a non zero Packet Identifier that is currently unused",
+);
+
+pub const SERVER_PACKET_TOO_LARGE: ConformanceError = ConformanceError::new(
+    "MQTT-3.1.2-24",
+    "The Server MUST NOT send packets exceeding Maximum Packet Size to the Client",
+);
+
+pub const CLIENT_PACKET_TOO_LARGE: ConformanceError = ConformanceError::new(
+    "MQTT-3.2.2-15",
+    "The Client MUST NOT send packets exceeding Maximum Packet Size to the Server",
+);
+
+pub const PUBLISH_QOS0_DUP: ConformanceError = ConformanceError::new(
+    "MQTT-3.3.1-2",
+    "The DUP flag MUST be set to 0 for all QoS 0 messages",
+);
+
+pub const PUBLISH_QOS3: ConformanceError = ConformanceError::new(
+    "MQTT-3.3.1-4",
+    "A PUBLISH Packet MUST NOT have both QoS bits set to 1",
+);
+
+pub const TOPIC_NAME_WILDCARD: ConformanceError = ConformanceError::new(
+    "MQTT-3.3.2-2",
+    "The Topic Name in the PUBLISH packet MUST NOT contain wildcard characters",
+);
+
+pub const TOPIC_ALIAS_ZERO: ConformanceError = ConformanceError::new(
+    "MQTT-3.3.2-8",
+    "A sender MUST NOT send a PUBLISH packet containing a Topic Alias which has the value 0",
+);
+
+pub const CLIENT_TOPIC_ALIAS_TOO_LARGE: ConformanceError = ConformanceError::new(
+    "MQTT-3.3.2-9",
+    "A Client MUST NOT send a PUBLISH packet with a Topic Alias greater than the Topic Alias Maximum value returned by the Server in the CONNACK packet",
+);
+
+pub const SERVER_TOPIC_ALIAS_TOO_LARGE: ConformanceError = ConformanceError::new(
+    "MQTT-3.3.2-11",
+    "A Server MUST NOT send a PUBLISH packet with a Topic Alias greater than the Topic Alias Maximum value sent by the Client in the CONNECT packet",
+);
+
+pub const SERVER_RECEIVE_MAXIMUM_EXCEEDED: ConformanceError = ConformanceError::new(
+    "MQTT-3.3.4-9",
+    "The Server MUST NOT send more than Receive Maximum QoS 1 and QoS 2 PUBLISH packets for which it has not received PUBACK, PUBCOMP, or PUBREC with a Reason Code of 128 or greater from the Client",
+);
@@ -1,8 +1,12 @@
-use std::string::FromUtf8Error;
+use alloc::string::String;
 
+#[cfg(feature = "std")]
 use async_channel::{RecvError, SendError};
 
+use super::conformance::{self, ConformanceError};
+#[cfg(feature = "std")]
 use super::packet::Packet;
+use crate::v5::packet::disconnect::DisconnectReasonCode;
 
 #[derive(Debug, thiserror::Error, PartialEq, Eq)]
 pub enum MQTTError {
@@ -14,14 +18,20 @@ pub enum MQTTError {
     IncompletePacket,
     #[error("Received QoS: {0} which is unsupported")]
     UnsupportedQoS(u8),
+    /// A Variable Byte Integer encoded with more bytes than needed (1.5.5)
+    #[error("Variable Byte Integer not encoded with the minimum number of bytes")]
+    NonMinimalVarInt,
+    /// A UTF-8 Encoded String that includes the null character U+0000 (1.5.4)
+    #[error("UTF-8 Encoded String includes the null character U+0000")]
+    NullCharacter,
     #[error("Incomplete Data: {0} Expected {1} bytes but found {2}")]
     IncompleteData(&'static str, usize, usize),
     #[error("Received an Unknown Property: {0}")]
     UnknownProperty(u8),
-    #[error("Multiple instances of {0} Property found")]
-    DuplicateProperty(String), // property converted to string
-    #[error("Error generating utf-8 string from {0}")]
-    Utf8Error(FromUtf8Error),
+    /// The property, and the packet it was found more than once on
+    #[error("Multiple instances of {0} Property found on: {1}")]
+    DuplicateProperty(String, String),
+    /// The property, and the packet it is not allowed on
     #[error("{0} is not allowed on: {1}")]
     UnexpectedProperty(String, String),
     #[error("Version {0} not supported")]
@@ -38,6 +48,18 @@ pub enum MQTTError {
     PacketIdConflict(u16),
     #[error("Invalid Property: {0}")]
     InvalidProperty(String),
+    /// The alias, and the Topic Alias Maximum it was checked against
+    #[error("Topic Alias must be non-zero and less than or equal to the Topic Alias Maximum {1}, but got {0}")]
+    InvalidTopicAlias(u16, u16),
+    /// An acknowledgement for a packet identifier that is not in flight
+    #[error("Unknown Packet Id: {0}")]
+    UnknownPacketId(u16),
+    /// A PUBLISH without a Topic Name, whose Topic Alias was never mapped to one (3.3.2.3.4)
+    #[error("Unknown Topic Alias: {0}")]
+    UnknownTopicAlias(u16),
+    /// More QoS 1 and QoS 2 PUBLISH packets are awaiting acknowledgement than the Receive Maximum allows
+    #[error("Receive Maximum exceeded: {0}")]
+    ReceiveMaximumExceeded(u16),
     #[error("Packet Id required")]
     PacketIdRequired,
     #[error("UnImplemented")]
@@ -49,17 +71,12 @@ pub enum MQTTError {
     #[error("IO Error: {0}")]
     IoError(String),
 
-    // to be moved
-    #[error("Connection Error")]
-    ConnectionError,
-    #[error("Connection Refused {0}")]
-    ConnectionRefused(u8),
-
     #[error("Timeout Error")]
     TimeoutError,
 
     #[error("Incoming Disconnect")]
     IncomingDisconnect,
+    #[cfg(feature = "std")]
     #[error("No more outgoing packets {0}")]
     NoOutgoingPackets(#[from] RecvError),
 
@@ -71,10 +88,90 @@ pub enum MQTTError {
     #[error("Invalid Topic contains: {0}")]
     InvalidTopic(&'static str),
 
+    /// An interceptor refused the packet or the message, see [`Interceptor`](crate::v5::client::interceptor::Interceptor)
+    #[error("Intercepted: {0}")]
+    Intercepted(String),
+
+    /// Only raised in strict mode ([`ConnectOptions::strict`](crate::v5::client::ConnectOptions::strict)),
+    /// in place of the error the violation would otherwise be reported with
+    #[error("Conformance Error: {0}")]
+    Conformance(#[from] ConformanceError),
+
+    #[cfg(feature = "std")]
     #[error("Channel Error: Channel Closed")]
     ChannelClosed(#[from] SendError<Packet>),
 }
 
+impl MQTTError {
+    /// The normative statement an incoming packet breaks, when it could not be decoded because of this error.
+    /// The client only receives QoS bits on PUBLISH packets
+    pub(crate) fn broken_rule(&self) -> Option<ConformanceError> {
+        match self {
+            Self::NonMinimalVarInt => Some(conformance::VARINT_NOT_MINIMAL),
+            Self::NullCharacter => Some(conformance::UTF8_NULL_CHARACTER),
+            Self::UnsupportedQoS(3) => Some(conformance::PUBLISH_QOS3),
+            _ => None,
+        }
+    }
+}
+
+/// Why a packet could not be decoded
+#[derive(Debug, thiserror::Error)]
+pub enum DecodeError {
+    /// The bytes are not a valid MQTT packet
+    #[error("Malformed Packet: {0}")]
+    Malformed(MQTTError),
+    /// The packet is well formed, but breaks a rule of the protocol (e.g. it carries a property it is not allowed to)
+    #[error("Protocol Error: {0}")]
+    Protocol(MQTTError),
+    /// The size of the packet (fixed header included) is over the maximum accepted
+    #[error("Packet too large: {0} bytes")]
+    TooLarge(usize),
+    /// The stream the packet was read from failed
+    #[cfg(feature = "std")]
+    #[error("IO Error: {0}")]
+    Io(#[from] std::io::Error),
+}
+
+impl DecodeError {
+    /// The reason code to close the connection with, when the packet was received from the other side
+    pub fn reason_code(&self) -> DisconnectReasonCode {
+        match self {
+            Self::Malformed(error) | Self::Protocol(error) => DisconnectReasonCode::from(error),
+            Self::TooLarge(_) => DisconnectReasonCode::PacketTooLarge,
+            #[cfg(feature = "std")]
+            Self::Io(_) => DisconnectReasonCode::UnspecifiedError,
+        }
+    }
+}
+
+impl From<MQTTError> for DecodeError {
+    /// Errors are told apart by the reason code [`DisconnectReasonCode::from`] gives them, so that both always agree
+    fn from(error: MQTTError) -> Self {
+        match error {
+            MQTTError::MaxPacketSizeExceed(size) => Self::TooLarge(size),
+            #[cfg(feature = "std")]
+            MQTTError::IoError(message) => Self::Io(std::io::Error::other(message)),
+            error => match DisconnectReasonCode::from(&error) {
+                DisconnectReasonCode::MalformedPacket => Self::Malformed(error),
+                _ => Self::Protocol(error),
+            },
+        }
+    }
+}
+
+impl From<DecodeError> for MQTTError {
+    fn from(error: DecodeError) -> Self {
+        match error {
+            DecodeError::Malformed(error) | DecodeError::Protocol(error) => error,
+            DecodeError::TooLarge(size) => Self::MaxPacketSizeExceed(size),
+            #[cfg(feature = "std")]
+            DecodeError::Io(error) => error.into(),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
 impl From<std::io::Error> for MQTTError {
     fn from(value: std::io::Error) -> Self {
         Self::IoError(value.to_string())
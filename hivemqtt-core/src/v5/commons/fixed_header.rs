@@ -32,6 +32,8 @@ impl FixedHeader {
 }
 
 pub(crate) mod syncx {
+    use alloc::format;
+
     use crate::v5::commons::fixed_header::FixedHeader;
     use crate::v5::commons::packet_type::PacketType;
     use crate::v5::traits::bufferio::BufferIO;
@@ -48,7 +50,7 @@ pub(crate) mod syncx {
 
         fn write(&self, buf: &mut BytesMut) -> Result<(), MQTTError> {
             // let f = self.flags.unwrap_or(0);
-            ((self.packet_type as u8) | &self.flags.unwrap_or(0)).write(buf);
+            ((self.packet_type as u8) | &self.flags.unwrap_or(0)).write(buf)?;
             self.encode(buf)?;
 
             Ok(())
@@ -76,52 +78,3 @@ pub(crate) mod syncx {
         }
     }
 }
-
-pub(crate) mod asyncx {
-    use crate::v5::commons::fixed_header::FixedHeader;
-    use crate::v5::commons::packet_type::PacketType;
-    use crate::v5::traits::streamio::StreamIO;
-    use crate::v5::{
-        commons::error::MQTTError,
-        traits::asyncx::{read::Read, write::Write},
-    };
-    use futures::{AsyncReadExt, AsyncWriteExt};
-
-    impl StreamIO for FixedHeader {
-        fn length(&self) -> usize {
-            0
-        }
-
-        async fn read<R>(stream: &mut R) -> Result<Self, MQTTError>
-        where
-            R: AsyncReadExt + Unpin,
-        {
-            let byte0 = u8::read(stream).await?;
-            let packet = byte0 & 0b11110000;
-            let packet_type = PacketType::try_from(packet).map_err(|_| {
-                MQTTError::UnknownData(format!("Unexpected packet type: {}", packet))
-            })?;
-
-            let (remaining_length, header_len) = Self::decode(stream).await?;
-
-            Ok(Self {
-                packet_type,
-                flags: Some(byte0 & 0b00001111).filter(|n| *n != 0),
-                remaining_length,
-                header_len,
-            })
-        }
-
-        async fn write<W>(&self, stream: &mut W) -> Result<(), MQTTError>
-        where
-            W: AsyncWriteExt + Unpin,
-        {
-            let byte0 = (self.packet_type as u8) | &self.flags.unwrap_or(0);
-            (byte0 as u8).write(stream).await?;
-
-            self.encode(stream).await?;
-
-            Ok(())
-        }
-    }
-}
@@ -1,9 +1,11 @@
+pub mod conformance;
 pub mod packet;
 pub mod packet_type;
+pub mod primitives;
 pub mod property;
 pub mod qos;
 pub mod reason_code;
 
-pub(crate) mod error;
+pub mod error;
 pub(crate) mod fixed_header;
-pub(crate) mod version; // good
+pub mod version; // good
@@ -1,3 +1,5 @@
+use alloc::format;
+
 use crate::v5::{
     packet::{
         auth::Auth,
@@ -65,6 +67,32 @@ impl Packet {
             Self::Auth(_) => PacketType::Auth,
         }
     }
+
+    /// The Packet Identifier, for the packets that carry one
+    #[cfg(all(feature = "std", feature = "tracing"))]
+    pub(crate) fn pkid(&self) -> Option<u16> {
+        match self {
+            Self::Publish(packet) => packet.pkid,
+            Self::PubAck(packet) => Some(packet.pkid),
+            Self::PubRec(packet) => Some(packet.pkid),
+            Self::PubRel(packet) => Some(packet.pkid),
+            Self::PubComp(packet) => Some(packet.pkid),
+            Self::Subscribe(packet) => Some(packet.pkid),
+            Self::SubAck(packet) => Some(packet.pkid),
+            Self::UnSubscribe(packet) => Some(packet.pkid),
+            Self::UnSubAck(packet) => Some(packet.pkid),
+            _ => None,
+        }
+    }
+
+    /// The QoS of PUBLISH packets
+    #[cfg(all(feature = "std", feature = "tracing"))]
+    pub(crate) fn qos(&self) -> Option<super::qos::QoS> {
+        match self {
+            Self::Publish(packet) => Some(packet.qos),
+            _ => None,
+        }
+    }
 }
 
 impl ReadData for Packet {}
@@ -95,9 +123,14 @@ pub(crate) mod syncx {
         }
 
         fn read(buf: &mut bytes::Bytes) -> Result<Self, MQTTError> {
-            // let x = Self::Connect(Connect::default());
-
             let header = FixedHeader::read(buf)?;
+            Self::read_with_fixedheader(buf, header)
+        }
+
+        fn read_with_fixedheader(
+            buf: &mut bytes::Bytes,
+            header: FixedHeader,
+        ) -> Result<Self, MQTTError> {
             match header.packet_type {
                 PacketType::Connect => Ok(Packet::Connect(Connect::read(buf)?)),
                 PacketType::ConnAck => Ok(Packet::ConnAck(ConnAck::read(buf)?)),
@@ -133,75 +166,169 @@ pub(crate) mod syncx {
     }
 }
 
-mod asyncx {
-    use crate::v5::traits::streamio::StreamIO;
+pub(crate) use framed::{decode_frame, peek_header};
+#[cfg(feature = "std")]
+pub(crate) use framed::{read_frame, split_frame};
+
+/// Frame-first IO: a whole packet is read off (or written to) the stream at once, and decoded (or encoded) with [`BufferIO`](crate::v5::traits::bufferio::BufferIO)
+mod framed {
+    use bytes::Bytes;
+    #[cfg(feature = "std")]
+    use bytes::{Buf, BytesMut};
+    #[cfg(feature = "std")]
+    use futures::{AsyncReadExt, AsyncWriteExt};
+
+    #[cfg(feature = "std")]
+    use crate::v5::commons::error::DecodeError;
+    use crate::v5::traits::bufferio::BufferIO;
 
     use super::*;
 
-    impl StreamIO for Packet {
-        async fn write<W>(&self, stream: &mut W) -> Result<(), MQTTError>
+    /// Parses the fixed header at the start of `buf`, once all of it has been received
+    pub(crate) fn peek_header(buf: &[u8]) -> Result<Option<FixedHeader>, MQTTError> {
+        // 1.5.5 The Variable Byte Integer is encoded with at most 4 bytes
+        let Some(end) = buf.iter().skip(1).take(4).position(|byte| byte & 0x80 == 0) else {
+            return match buf.len() {
+                ..=4 => Ok(None),
+                _ => Err(MQTTError::MalformedPacket),
+            };
+        };
+
+        let header = <FixedHeader as BufferIO>::read(&mut Bytes::copy_from_slice(&buf[..end + 2]))?;
+        Ok(Some(header))
+    }
+
+    /// Decodes the packet in the body of a frame. The packet must take up the whole body (its Remaining Length),
+    /// bytes left over make it a Malformed Packet
+    #[allow(clippy::result_large_err)] // the error every decoder returns
+    pub(crate) fn decode_frame(header: FixedHeader, mut body: Bytes) -> Result<Packet, MQTTError> {
+        let packet = <Packet as BufferIO>::read_with_fixedheader(&mut body, header)?;
+        if !body.is_empty() {
+            return Err(MQTTError::MalformedPacket);
+        }
+        Ok(packet)
+    }
+
+    /// Splits the first packet off `buf`, once all of it has been received.
+    /// Packets larger than `max_size` bytes are refused as soon as their fixed header is known
+    #[cfg(feature = "std")]
+    pub(crate) fn split_frame(
+        buf: &mut BytesMut,
+        max_size: usize,
+    ) -> Result<Option<(FixedHeader, Bytes)>, MQTTError> {
+        let Some(header) = peek_header(buf)? else {
+            return Ok(None);
+        };
+
+        let size = 1 + header.header_len + header.remaining_length;
+        if size > max_size {
+            return Err(MQTTError::MaxPacketSizeExceed(size));
+        }
+        if buf.len() < size {
+            return Ok(None);
+        }
+
+        let mut frame = buf.split_to(size).freeze();
+        frame.advance(1 + header.header_len);
+        Ok(Some((header, frame)))
+    }
+
+    /// Reads a whole packet (fixed header, variable header and payload) off the stream, before anything gets decoded.
+    /// Packets larger than `max_size` bytes are refused without reading their body.
+    /// Nothing past the packet is read, but the read is not cancel safe: dropping the future loses whatever was read so far
+    #[cfg(feature = "std")]
+    pub(crate) async fn read_frame<R>(
+        stream: &mut R,
+        max_size: usize,
+    ) -> Result<(FixedHeader, Bytes), DecodeError>
+    where
+        R: AsyncReadExt + Unpin,
+    {
+        let mut buf = BytesMut::with_capacity(5);
+        let mut byte = [0u8; 1];
+
+        // the packet type, then the Remaining Length (1.5.5 Variable Byte Integer, at most 4 bytes)
+        loop {
+            stream.read_exact(&mut byte).await?;
+            buf.extend_from_slice(&byte);
+
+            if buf.len() > 1 && byte[0] & 0x80 == 0 {
+                break;
+            }
+            if buf.len() == 5 {
+                return Err(DecodeError::Malformed(MQTTError::MalformedPacket));
+            }
+        }
+
+        let header = <FixedHeader as BufferIO>::read(&mut buf.freeze())?;
+        let size = 1 + header.header_len + header.remaining_length;
+        if size > max_size {
+            return Err(DecodeError::TooLarge(size));
+        }
+
+        let mut body = BytesMut::zeroed(header.remaining_length);
+        stream.read_exact(&mut body).await?;
+
+        Ok((header, body.freeze()))
+    }
+
+    #[cfg(feature = "std")]
+    impl Packet {
+        /// Reads the next MQTT 5.0 packet off the stream, refusing packets larger than `max_size` bytes
+        pub async fn read_from<R>(stream: &mut R, max_size: usize) -> Result<Self, DecodeError>
         where
-            W: futures::AsyncWriteExt + Unpin,
+            R: AsyncReadExt + Unpin,
         {
-            match self {
-                Self::Connect(packet) => packet.write(stream).await,
-                Self::ConnAck(packet) => packet.write(stream).await,
-                Self::Publish(packet) => packet.write(stream).await,
-                Self::PubAck(packet) => packet.write(stream).await,
-                Self::PubRec(packet) => packet.write(stream).await,
-                Self::PubRel(packet) => packet.write(stream).await,
-                Self::PubComp(packet) => packet.write(stream).await,
-                Self::Subscribe(packet) => packet.write(stream).await,
-                Self::SubAck(packet) => packet.write(stream).await,
-                Self::UnSubscribe(packet) => packet.write(stream).await,
-                Self::UnSubAck(packet) => packet.write(stream).await,
-                Self::PingReq(packet) => packet.write(stream).await,
-                Self::PingResp(packet) => packet.write(stream).await,
-                Self::Disconnect(packet) => packet.write(stream).await,
-                Self::Auth(packet) => packet.write(stream).await,
-                _ => Ok(()),
-            }
+            let (header, body) = read_frame(stream, max_size).await?;
+            Ok(decode_frame(header, body)?)
         }
 
-        async fn read<R>(stream: &mut R) -> Result<Self, MQTTError>
+        /// Writes the packet to the stream, without flushing it
+        pub async fn write_to<W>(&self, stream: &mut W) -> Result<(), MQTTError>
         where
-            R: futures::AsyncReadExt + Unpin,
-            Self: Default,
+            W: AsyncWriteExt + Unpin,
         {
-            let header = FixedHeader::read(stream).await?;
-            match header.packet_type {
-                PacketType::Connect => Ok(Packet::Connect(Connect::read(stream).await?)),
-                PacketType::ConnAck => Ok(Packet::ConnAck(ConnAck::read(stream).await?)),
-                PacketType::Publish => Ok(Packet::Publish(
-                    Publish::read_with_fixedheader(stream, &header).await?,
-                )),
-                PacketType::PubAck => Ok(Packet::PubAck(
-                    PubAck::read_with_fixedheader(stream, &header).await?,
-                )),
-                PacketType::PubRec => Ok(Packet::PubRec(
-                    PubRec::read_with_fixedheader(stream, &header).await?,
-                )),
-                PacketType::PubRel => Ok(Packet::PubRel(
-                    PubRel::read_with_fixedheader(stream, &header).await?,
-                )),
-                PacketType::PubComp => Ok(Packet::PubComp(
-                    PubComp::read_with_fixedheader(stream, &header).await?,
-                )),
-                PacketType::Subscribe => Ok(Packet::Subscribe(Subscribe::read(stream).await?)),
-                PacketType::SubAck => Ok(Packet::SubAck(SubAck::read(stream).await?)),
-                PacketType::UnSubscribe => {
-                    Ok(Packet::UnSubscribe(UnSubscribe::read(stream).await?))
-                }
-                PacketType::UnSubAck => Ok(Packet::UnSubAck(UnSubAck::read(stream).await?)),
-                PacketType::PingReq => Ok(Packet::PingReq(PingReq::read(stream).await?)),
-                PacketType::PingResp => Ok(Packet::PingResp(PingResp::read(stream).await?)),
-                PacketType::Auth => Ok(Packet::Auth(Auth::read(stream).await?)),
-                PacketType::Disconnect => Ok(Packet::Disconnect(Disconnect::read(stream).await?)),
-                _ => Err(MQTTError::UnknownData(format!(
-                    "Unexpected Packet type {:?}",
-                    header.packet_type
-                ))),
-            }
+            let mut buf = BytesMut::new();
+            BufferIO::write(self, &mut buf)?;
+            stream.write_all(&buf).await?;
+            Ok(())
         }
     }
 }
+
+#[cfg(all(test, feature = "std"))]
+mod tests {
+    use bytes::BytesMut;
+
+    use super::*;
+
+    #[test]
+    fn splits_frames_only_once_they_are_complete() {
+        let mut buf = BytesMut::from(&b"\x30\x82"[..]);
+        assert_eq!(split_frame(&mut buf, usize::MAX), Ok(None));
+
+        // a 130 bytes body, followed by the first byte of the next packet
+        buf.extend_from_slice(&[0x01]);
+        buf.extend_from_slice(&[0u8; 129]);
+        assert_eq!(split_frame(&mut buf, usize::MAX), Ok(None));
+        buf.extend_from_slice(&[0u8, 0xC0]);
+
+        let (header, body) = split_frame(&mut buf, usize::MAX).unwrap().unwrap();
+        assert_eq!(header.packet_type, PacketType::Publish);
+        assert_eq!(header.remaining_length, 130);
+        assert_eq!(body.len(), 130);
+        assert_eq!(&buf[..], b"\xC0");
+    }
+
+    #[test]
+    fn refuses_frames_larger_than_the_maximum_packet_size() {
+        let mut buf = BytesMut::from(&b"\x30\x82\x01"[..]);
+        assert_eq!(
+            split_frame(&mut buf, 100),
+            Err(MQTTError::MaxPacketSizeExceed(133))
+        );
+
+        let mut buf = BytesMut::from(&b"\x30\xff\xff\xff\xff\x7f"[..]);
+        assert_eq!(split_frame(&mut buf, usize::MAX), Err(MQTTError::MalformedPacket));
+    }
+}
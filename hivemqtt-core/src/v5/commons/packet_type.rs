@@ -5,25 +5,41 @@ use crate::v5::traits::bufferio::BufferIO;
 
 use super::error::MQTTError;
 
+/// Type of an MQTT Control Packet (2.1.2), the high nibble of its fixed header
 #[allow(dead_code)]
-#[derive(Debug, Clone, Copy, PartialEq, Eq, FromU8, Default)]
-pub(crate) enum PacketType {
+#[derive(Debug, Clone, Copy, PartialEq, Eq, FromU8, Default, derive_more::Display)]
+pub enum PacketType {
     #[default]
+    #[display("CONNECT")]
     Connect = 0x10, // 0b0001_0000
-    ConnAck = 0x20,     // 0b0010_0000
-    Publish = 0x30,     // 0b0011_0000
-    PubAck = 0x40,      // 0b0100_0000
-    PubRec = 0x50,      // 0b0101_0000
-    PubRel = 0x60,      // 0b0110_0000
-    PubComp = 0x70,     // 0b0111_0000
-    Subscribe = 0x80,   // 0b1000_0000
-    SubAck = 0x90,      // 0b1001_0000
+    #[display("CONNACK")]
+    ConnAck = 0x20, // 0b0010_0000
+    #[display("PUBLISH")]
+    Publish = 0x30, // 0b0011_0000
+    #[display("PUBACK")]
+    PubAck = 0x40, // 0b0100_0000
+    #[display("PUBREC")]
+    PubRec = 0x50, // 0b0101_0000
+    #[display("PUBREL")]
+    PubRel = 0x60, // 0b0110_0000
+    #[display("PUBCOMP")]
+    PubComp = 0x70, // 0b0111_0000
+    #[display("SUBSCRIBE")]
+    Subscribe = 0x80, // 0b1000_0000
+    #[display("SUBACK")]
+    SubAck = 0x90, // 0b1001_0000
+    #[display("UNSUBSCRIBE")]
     UnSubscribe = 0xA0, // 0b1010_0000
-    UnSubAck = 0xB0,    // 0b1011_0000
-    PingReq = 0xC0,     // 0b1100_0000
-    PingResp = 0xD0,    // 0b1101_0000
-    Disconnect = 0xE0,  // 0b1110_0000
-    Auth = 0xF0,        // 0b1111_0000
+    #[display("UNSUBACK")]
+    UnSubAck = 0xB0, // 0b1011_0000
+    #[display("PINGREQ")]
+    PingReq = 0xC0, // 0b1100_0000
+    #[display("PINGRESP")]
+    PingResp = 0xD0, // 0b1101_0000
+    #[display("DISCONNECT")]
+    Disconnect = 0xE0, // 0b1110_0000
+    #[display("AUTH")]
+    Auth = 0xF0, // 0b1111_0000
 }
 
 impl PacketType {
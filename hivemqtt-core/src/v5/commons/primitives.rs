@@ -0,0 +1,423 @@
+//! The data types every MQTT 5.0 packet is built from (1.5 Data representation).
+//!
+//! Each type checks the rules of the specification when it is built and when it is decoded, so that anything received that
+//! breaks them is refused as a Malformed Packet, instead of being half-decoded (or panicking).
+use alloc::string::String;
+use core::{fmt, ops::Deref};
+
+use bytes::{BufMut, Bytes, BytesMut};
+
+use crate::v5::{
+    commons::error::MQTTError,
+    traits::syncx::{read::Read, write::Write},
+};
+
+/// 1.5.5 Variable Byte Integer: a number from 0 to 268,435,455, encoded with 1 to 4 bytes
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct VarInt(u32);
+
+impl VarInt {
+    /// The largest number a Variable Byte Integer can hold
+    pub const MAX: u32 = 268_435_455;
+
+    pub fn new(value: u32) -> Result<Self, MQTTError> {
+        if value > Self::MAX {
+            return Err(MQTTError::PayloadTooLong);
+        }
+        Ok(Self(value))
+    }
+
+    pub fn get(self) -> u32 {
+        self.0
+    }
+
+    /// Number of bytes of the encoding, from 1 to 4
+    pub fn encoded_len(self) -> usize {
+        match self.0 {
+            0..=127 => 1,
+            128..=16_383 => 2,
+            16_384..=2_097_151 => 3,
+            _ => 4,
+        }
+    }
+}
+
+impl TryFrom<usize> for VarInt {
+    type Error = MQTTError;
+
+    fn try_from(value: usize) -> Result<Self, Self::Error> {
+        u32::try_from(value)
+            .map_err(|_| MQTTError::PayloadTooLong)
+            .and_then(Self::new)
+    }
+}
+
+impl From<VarInt> for usize {
+    fn from(value: VarInt) -> Self {
+        value.0 as usize
+    }
+}
+
+impl Read for VarInt {
+    fn read(buf: &mut Bytes) -> Result<Self, MQTTError> {
+        let mut value = 0;
+
+        for i in 0..4 {
+            let byte = u8::read(buf).map_err(|_| MQTTError::MalformedPacket)?;
+            value |= ((byte & 0x7F) as u32) << (7 * i);
+
+            if byte & 0x80 == 0 {
+                // the encoded value MUST use the minimum number of bytes necessary to represent the value [MQTT-1.5.5-1]
+                if i > 0 && byte == 0 {
+                    return Err(MQTTError::NonMinimalVarInt);
+                }
+                return Ok(Self(value));
+            }
+        }
+
+        Err(MQTTError::MalformedPacket)
+    }
+}
+
+impl Write for VarInt {
+    fn write(&self, buf: &mut BytesMut) -> Result<(), MQTTError> {
+        let mut value = self.0;
+
+        loop {
+            let mut byte = (value % 128) as u8;
+            value /= 128;
+            if value > 0 {
+                byte |= 128;
+            }
+
+            buf.put_u8(byte);
+            if value == 0 {
+                return Ok(());
+            }
+        }
+    }
+}
+
+/// 1.5.4 UTF-8 Encoded String: well-formed UTF-8 of at most 65,535 bytes, without the null character U+0000
+#[derive(Debug, Clone, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Utf8String(String);
+
+impl Utf8String {
+    pub fn new(value: impl Into<String>) -> Result<Self, MQTTError> {
+        let value = value.into();
+        Self::validate(&value)?;
+        Ok(Self(value))
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+
+    pub fn into_inner(self) -> String {
+        self.0
+    }
+
+    /// Number of bytes of the encoding, the 2 bytes length prefix included
+    pub fn encoded_len(&self) -> usize {
+        2 + self.0.len()
+    }
+
+    /// `str` is always well-formed UTF-8 (without surrogates), only its length and the null character are left to check
+    pub(crate) fn validate(value: &str) -> Result<(), MQTTError> {
+        if value.len() > u16::MAX as usize {
+            return Err(MQTTError::MalformedPacket);
+        }
+        // A UTF-8 Encoded String MUST NOT include an encoding of the null character U+0000 [MQTT-1.5.4-2]
+        if value.contains('\0') {
+            return Err(MQTTError::NullCharacter);
+        }
+        Ok(())
+    }
+
+    pub(crate) fn write_str(value: &str, buf: &mut BytesMut) -> Result<(), MQTTError> {
+        Self::validate(value)?;
+        buf.put_u16(value.len() as u16);
+        buf.extend_from_slice(value.as_bytes());
+        Ok(())
+    }
+}
+
+impl Deref for Utf8String {
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        &self.0
+    }
+}
+
+impl fmt::Display for Utf8String {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl TryFrom<String> for Utf8String {
+    type Error = MQTTError;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        Self::new(value)
+    }
+}
+
+impl TryFrom<&str> for Utf8String {
+    type Error = MQTTError;
+
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        Self::new(value)
+    }
+}
+
+impl From<Utf8String> for String {
+    fn from(value: Utf8String) -> Self {
+        value.0
+    }
+}
+
+impl Read for Utf8String {
+    fn read(buf: &mut Bytes) -> Result<Self, MQTTError> {
+        let data = BinaryData::read(buf)?;
+        // ill-formed UTF-8 is a Malformed Packet [MQTT-1.5.4-1]
+        let value = core::str::from_utf8(&data).map_err(|_| MQTTError::MalformedPacket)?;
+        Self::new(value)
+    }
+}
+
+impl Write for Utf8String {
+    fn write(&self, buf: &mut BytesMut) -> Result<(), MQTTError> {
+        Self::write_str(&self.0, buf)
+    }
+}
+
+/// 1.5.6 Binary Data: at most 65,535 bytes
+#[derive(Debug, Clone, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct BinaryData(Bytes);
+
+impl BinaryData {
+    pub fn new(value: impl Into<Bytes>) -> Result<Self, MQTTError> {
+        let value = value.into();
+        Self::validate(&value)?;
+        Ok(Self(value))
+    }
+
+    pub fn into_inner(self) -> Bytes {
+        self.0
+    }
+
+    /// Number of bytes of the encoding, the 2 bytes length prefix included
+    pub fn encoded_len(&self) -> usize {
+        2 + self.0.len()
+    }
+
+    pub(crate) fn validate(value: &[u8]) -> Result<(), MQTTError> {
+        if value.len() > u16::MAX as usize {
+            return Err(MQTTError::MalformedPacket);
+        }
+        Ok(())
+    }
+
+    pub(crate) fn write_slice(value: &[u8], buf: &mut BytesMut) -> Result<(), MQTTError> {
+        Self::validate(value)?;
+        buf.put_u16(value.len() as u16);
+        buf.extend_from_slice(value);
+        Ok(())
+    }
+}
+
+impl Deref for BinaryData {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+impl TryFrom<Bytes> for BinaryData {
+    type Error = MQTTError;
+
+    fn try_from(value: Bytes) -> Result<Self, Self::Error> {
+        Self::new(value)
+    }
+}
+
+impl From<BinaryData> for Bytes {
+    fn from(value: BinaryData) -> Self {
+        value.0
+    }
+}
+
+impl Read for BinaryData {
+    fn read(buf: &mut Bytes) -> Result<Self, MQTTError> {
+        let len = u16::read(buf).map_err(|_| MQTTError::MalformedPacket)? as usize;
+        if len > buf.len() {
+            return Err(MQTTError::MalformedPacket);
+        }
+        Ok(Self(buf.split_to(len)))
+    }
+}
+
+impl Write for BinaryData {
+    fn write(&self, buf: &mut BytesMut) -> Result<(), MQTTError> {
+        Self::write_slice(&self.0, buf)
+    }
+}
+
+/// 1.5.7 UTF-8 String Pair, used by the User Property
+#[derive(Debug, Clone, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct StringPair {
+    pub name: Utf8String,
+    pub value: Utf8String,
+}
+
+impl StringPair {
+    pub fn new(name: impl Into<String>, value: impl Into<String>) -> Result<Self, MQTTError> {
+        Ok(Self {
+            name: Utf8String::new(name)?,
+            value: Utf8String::new(value)?,
+        })
+    }
+
+    /// Number of bytes of the encoding, the length prefixes of both strings included
+    pub fn encoded_len(&self) -> usize {
+        self.name.encoded_len() + self.value.encoded_len()
+    }
+}
+
+impl TryFrom<(String, String)> for StringPair {
+    type Error = MQTTError;
+
+    fn try_from((name, value): (String, String)) -> Result<Self, Self::Error> {
+        Self::new(name, value)
+    }
+}
+
+impl From<StringPair> for (String, String) {
+    fn from(value: StringPair) -> Self {
+        (value.name.into(), value.value.into())
+    }
+}
+
+impl Read for StringPair {
+    fn read(buf: &mut Bytes) -> Result<Self, MQTTError> {
+        Ok(Self {
+            name: Utf8String::read(buf)?,
+            value: Utf8String::read(buf)?,
+        })
+    }
+}
+
+impl Write for StringPair {
+    fn write(&self, buf: &mut BytesMut) -> Result<(), MQTTError> {
+        self.name.write(buf)?;
+        self.value.write(buf)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn encode<T: Write>(value: &T) -> Bytes {
+        let mut buf = BytesMut::new();
+        value.write(&mut buf).unwrap();
+        buf.freeze()
+    }
+
+    #[test]
+    fn round_trips_variable_byte_integers_at_every_boundary() {
+        let cases: [(u32, &[u8]); 8] = [
+            (0, b"\x00"),
+            (127, b"\x7f"),
+            (128, b"\x80\x01"),
+            (16_383, b"\xff\x7f"),
+            (16_384, b"\x80\x80\x01"),
+            (2_097_151, b"\xff\xff\x7f"),
+            (2_097_152, b"\x80\x80\x80\x01"),
+            (VarInt::MAX, b"\xff\xff\xff\x7f"),
+        ];
+
+        for (value, encoding) in cases {
+            let varint = VarInt::new(value).unwrap();
+            assert_eq!(&encode(&varint)[..], encoding);
+            assert_eq!(varint.encoded_len(), encoding.len());
+            assert_eq!(VarInt::read(&mut Bytes::from_static(encoding)), Ok(varint));
+        }
+        assert_eq!(VarInt::new(VarInt::MAX + 1), Err(MQTTError::PayloadTooLong));
+    }
+
+    #[test]
+    fn refuses_malformed_variable_byte_integers() {
+        for encoding in [&b""[..], b"\x80", b"\xff\xff\xff\xff\x01"] {
+            assert_eq!(
+                VarInt::read(&mut Bytes::from_static(encoding)),
+                Err(MQTTError::MalformedPacket),
+                "{encoding:?}"
+            );
+        }
+        // 0 and 1 with more bytes than needed [MQTT-1.5.5-1]
+        for encoding in [&b"\x80\x00"[..], b"\x81\x80\x00"] {
+            assert_eq!(
+                VarInt::read(&mut Bytes::from_static(encoding)),
+                Err(MQTTError::NonMinimalVarInt),
+                "{encoding:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn refuses_strings_the_specification_forbids() {
+        let malformed: [&[u8]; 3] = [
+            b"\x00\x02\xc3\x28",     // ill-formed UTF-8
+            b"\x00\x03\xed\xa0\x80", // an encoded surrogate (U+D800)
+            b"\x00\x05abc",          // shorter than its length prefix
+        ];
+        for encoding in malformed {
+            assert_eq!(
+                Utf8String::read(&mut Bytes::from_static(encoding)),
+                Err(MQTTError::MalformedPacket),
+                "{encoding:?}"
+            );
+        }
+        assert_eq!(
+            Utf8String::read(&mut Bytes::from_static(b"\x00")),
+            Err(MQTTError::MalformedPacket)
+        );
+        // U+0000 [MQTT-1.5.4-2]
+        assert_eq!(
+            Utf8String::read(&mut Bytes::from_static(b"\x00\x03a\x00b")),
+            Err(MQTTError::NullCharacter)
+        );
+
+        assert_eq!(Utf8String::new("a\0b"), Err(MQTTError::NullCharacter));
+        let oversized = "a".repeat(u16::MAX as usize + 1);
+        assert_eq!(Utf8String::new(oversized), Err(MQTTError::MalformedPacket));
+        assert_eq!(
+            BinaryData::new(vec![0u8; u16::MAX as usize + 1]),
+            Err(MQTTError::MalformedPacket)
+        );
+    }
+
+    #[test]
+    fn round_trips_strings_binary_data_and_string_pairs() {
+        let string = Utf8String::new("sensors/température").unwrap();
+        let mut encoded = encode(&string);
+        assert_eq!(encoded.len(), string.encoded_len());
+        assert_eq!(Utf8String::read(&mut encoded), Ok(string));
+
+        let longest = Utf8String::new("a".repeat(u16::MAX as usize)).unwrap();
+        assert_eq!(Utf8String::read(&mut encode(&longest)), Ok(longest));
+
+        let data = BinaryData::new(Bytes::from_static(b"\x00\xff")).unwrap();
+        assert_eq!(&encode(&data)[..], b"\x00\x02\x00\xff");
+        assert_eq!(BinaryData::read(&mut encode(&data)), Ok(data));
+
+        let pair = StringPair::new("unit", "celsius").unwrap();
+        let mut encoded = encode(&pair);
+        assert_eq!(encoded.len(), pair.encoded_len());
+        assert_eq!(StringPair::read(&mut encoded), Ok(pair));
+    }
+}
@@ -1,45 +1,73 @@
-use std::borrow::Cow;
-use std::fmt::Display;
-use std::future::Future;
+use alloc::{
+    borrow::Cow,
+    string::{String, ToString},
+};
 
 use bytes::{Bytes, BytesMut};
-use futures::AsyncWriteExt;
 
 use crate::v5::commons::error::MQTTError;
+use crate::v5::commons::packet_type::PacketType;
+use crate::v5::commons::primitives::{StringPair, VarInt};
 use crate::v5::traits::read_data::ReadData;
-use crate::v5::traits::utils::Utils;
 use crate::v5::traits::{self, syncx::read::Read};
 
 /// Must be encoded using the VBI
-#[derive(Debug, Clone, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq, derive_more::Display)]
 #[repr(u8)]
 pub(crate) enum Property<'a> {
+    #[display("Payload Format Indicator")]
     PayloadFormatIndicator(Option<u8>) = 1,
+    #[display("Message Expiry Interval")]
     MessageExpiryInterval(Option<u32>) = 2,
+    #[display("Content Type")]
     ContentType(Option<Cow<'a, str>>) = 3,
+    #[display("Response Topic")]
     ResponseTopic(Option<Cow<'a, str>>) = 8,
+    #[display("Correlation Data")]
     CorrelationData(Option<Cow<'a, [u8]>>) = 9,
+    #[display("Subscription Identifier")]
     SubscriptionIdentifier(Cow<'a, usize>) = 11,
+    #[display("Session Expiry Interval")]
     SessionExpiryInterval(Option<u32>) = 17,
+    #[display("Assigned Client Identifier")]
     AssignedClientIdentifier(Option<Cow<'a, str>>) = 18,
+    #[display("Server Keep Alive")]
     ServerKeepAlive(Option<u16>) = 19,
+    #[display("Authentication Method")]
     AuthenticationMethod(Option<Cow<'a, str>>) = 21,
+    #[display("Authentication Data")]
     AuthenticationData(Option<Cow<'a, [u8]>>) = 22,
+    #[display("Request Problem Information")]
     RequestProblemInformation(Option<u8>) = 23,
+    #[display("Will Delay Interval")]
     WillDelayInterval(Option<u32>) = 24,
+    #[display("Request Response Information")]
     RequestResponseInformation(Option<u8>) = 25,
+    #[display("Response Information")]
     ResponseInformation(Option<Cow<'a, str>>) = 26,
+    #[display("Server Reference")]
     ServerReference(Option<Cow<'a, str>>) = 28,
+    #[display("Reason String")]
     ReasonString(Option<Cow<'a, str>>) = 31,
+    #[display("Receive Maximum")]
     ReceiveMaximum(Option<u16>) = 33,
+    #[display("Topic Alias Maximum")]
     TopicAliasMaximum(Option<u16>) = 34,
+    #[display("Topic Alias")]
     TopicAlias(Option<u16>) = 35,
+    #[display("Maximum QoS")]
     MaximumQoS(Option<u8>) = 36,
+    #[display("Retain Available")]
     RetainAvailable(Option<u8>) = 37,
+    #[display("User Property")]
     UserProperty(Cow<'a, (String, String)>) = 38,
+    #[display("Maximum Packet Size")]
     MaximumPacketSize(Option<u32>) = 39,
+    #[display("Wildcard Subscription Available")]
     WildCardSubscription(Option<u8>) = 40,
+    #[display("Subscription Identifier Available")]
     SubscriptionIdentifierAvailable(Option<u8>) = 41,
+    #[display("Shared Subscription Available")]
     SharedSubscriptionAvailable(Option<u8>) = 42,
 }
 
@@ -119,36 +147,13 @@ impl<'a> TryFrom<u8> for Property<'a> {
 }
 
 impl<'a> Property<'a> {
-    fn with_id<F>(&self, buf: &mut BytesMut, func: F)
+    fn with_id<F>(&self, buf: &mut BytesMut, func: F) -> Result<(), MQTTError>
     where
-        F: Fn(&mut BytesMut),
+        F: Fn(&mut BytesMut) -> Result<(), MQTTError>,
     {
         use traits::syncx::write::Write;
-        u8::from(self).write(buf);
-        // buf.put_u8(u8::from(self));
-        func(buf);
-    }
-
-    async fn write_to_stream<S, T>(&self, stream: &mut S, value: &T) -> Result<(), MQTTError>
-    where
-        S: AsyncWriteExt + Unpin,
-        T: traits::asyncx::write::Write<S>,
-    {
-        use traits::asyncx::write::Write;
-        u8::from(self).write(stream).await?;
-        value.write(stream).await
-    }
-
-    async fn write_async<'b, S, F, Fut>(&self, stream: &'b mut S, func: F) -> Result<(), MQTTError>
-    where
-        S: AsyncWriteExt + Unpin + 'b,
-        F: FnOnce(&'b mut S) -> Fut,
-        Fut: Future<Output = Result<(), MQTTError>> + 'b,
-    {
-        use traits::asyncx::write::Write;
-        u8::from(self).write(stream).await?;
-
-        func(stream).await
+        u8::from(self).write(buf)?;
+        func(buf)
     }
 
     pub(crate) fn read(buf: &mut Bytes) -> Result<Self, MQTTError> {
@@ -167,7 +172,7 @@ impl<'a> Property<'a> {
                 Bytes::read(buf)?.to_vec(),
             )))),
             11 => Ok(Property::SubscriptionIdentifier(Cow::Owned(
-                Self::decode(buf)?.0,
+                VarInt::read(buf)?.into(),
             ))),
             17 => Ok(Property::SessionExpiryInterval(Some(u32::read(buf)?))),
             18 => Ok(Property::AssignedClientIdentifier(Some(Cow::Owned(
@@ -195,10 +200,9 @@ impl<'a> Property<'a> {
             35 => Ok(Property::TopicAlias(Some(u16::read(buf)?))),
             36 => Ok(Property::MaximumQoS(Some(u8::read(buf)?))),
             37 => Ok(Property::RetainAvailable(Some(u8::read(buf)?))),
-            38 => Ok(Property::UserProperty(Cow::Owned((
-                String::read(buf)?,
-                String::read(buf)?,
-            )))),
+            38 => Ok(Property::UserProperty(Cow::Owned(
+                StringPair::read(buf)?.into(),
+            ))),
             39 => Ok(Property::MaximumPacketSize(Some(u32::read(buf)?))),
             40 => Ok(Property::WildCardSubscription(Some(u8::read(buf)?))),
             41 => Ok(Property::SubscriptionIdentifierAvailable(Some(u8::read(
@@ -210,21 +214,161 @@ impl<'a> Property<'a> {
     }
 }
 
-/// this would eventually be changed to use derive_more lib
-impl<'a> Display for Property<'a> {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(
-            f,
-            "this would be changed eventually to use derive_more::Error"
-        )
+/// What a set of properties belongs to: the Variable Header of a packet, or the Will Properties of a CONNECT packet (3.1.3.2)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, derive_more::Display)]
+pub(crate) enum PropertyOwner {
+    Packet(PacketType),
+    #[display("Will Properties")]
+    Will,
+}
+
+impl From<PacketType> for PropertyOwner {
+    fn from(value: PacketType) -> Self {
+        Self::Packet(value)
+    }
+}
+
+impl<'a> Property<'a> {
+    /// 2.2.2.2 Property: the packets each property can be sent on
+    fn is_allowed_on(&self, owner: PropertyOwner) -> bool {
+        use PacketType::*;
+
+        let PropertyOwner::Packet(packet) = owner else {
+            return matches!(
+                self,
+                Self::PayloadFormatIndicator(_)
+                    | Self::MessageExpiryInterval(_)
+                    | Self::ContentType(_)
+                    | Self::ResponseTopic(_)
+                    | Self::CorrelationData(_)
+                    | Self::WillDelayInterval(_)
+                    | Self::UserProperty(_)
+            );
+        };
+
+        match self {
+            Self::PayloadFormatIndicator(_)
+            | Self::MessageExpiryInterval(_)
+            | Self::ContentType(_)
+            | Self::ResponseTopic(_)
+            | Self::CorrelationData(_)
+            | Self::TopicAlias(_) => packet == Publish,
+            Self::SubscriptionIdentifier(_) => matches!(packet, Publish | Subscribe),
+            Self::SessionExpiryInterval(_) => matches!(packet, Connect | ConnAck | Disconnect),
+            Self::AuthenticationMethod(_) | Self::AuthenticationData(_) => {
+                matches!(packet, Connect | ConnAck | Auth)
+            }
+            Self::RequestProblemInformation(_) | Self::RequestResponseInformation(_) => {
+                packet == Connect
+            }
+            Self::ReceiveMaximum(_) | Self::TopicAliasMaximum(_) | Self::MaximumPacketSize(_) => {
+                matches!(packet, Connect | ConnAck)
+            }
+            Self::AssignedClientIdentifier(_)
+            | Self::ServerKeepAlive(_)
+            | Self::ResponseInformation(_)
+            | Self::MaximumQoS(_)
+            | Self::RetainAvailable(_)
+            | Self::WildCardSubscription(_)
+            | Self::SubscriptionIdentifierAvailable(_)
+            | Self::SharedSubscriptionAvailable(_) => packet == ConnAck,
+            Self::ServerReference(_) => matches!(packet, ConnAck | Disconnect),
+            Self::ReasonString(_) => matches!(
+                packet,
+                ConnAck
+                    | PubAck
+                    | PubRec
+                    | PubRel
+                    | PubComp
+                    | SubAck
+                    | UnSubAck
+                    | Disconnect
+                    | Auth
+            ),
+            Self::UserProperty(_) => !matches!(packet, PingReq | PingResp),
+            Self::WillDelayInterval(_) => false,
+        }
+    }
+
+    /// Only the User Property, and the Subscription Identifier of a PUBLISH packet (3.3.2.3.8), can be included more than once
+    fn is_repeatable_on(&self, owner: PropertyOwner) -> bool {
+        match self {
+            Self::UserProperty(_) => true,
+            Self::SubscriptionIdentifier(_) => owner == PropertyOwner::Packet(PacketType::Publish),
+            _ => false,
+        }
+    }
+}
+
+/// Checks the properties of a packet against 2.2.2.2, as they are read or written:
+/// a property the packet cannot carry, or a second instance of a property that can only be included once, is a Protocol Error
+#[derive(Debug)]
+pub(crate) struct PropertyCheck {
+    owner: PropertyOwner,
+    /// bit `n` is set once the property with identifier `n` has been seen
+    seen: u64,
+}
+
+impl PropertyCheck {
+    pub(crate) fn new(owner: impl Into<PropertyOwner>) -> Self {
+        Self {
+            owner: owner.into(),
+            seen: 0,
+        }
+    }
+
+    pub(crate) fn check(&mut self, property: &Property) -> Result<(), MQTTError> {
+        if !property.is_allowed_on(self.owner) {
+            return Err(self.unexpected(property));
+        }
+
+        let id = 1 << u8::from(property);
+        if self.seen & id != 0 && !property.is_repeatable_on(self.owner) {
+            return Err(MQTTError::DuplicateProperty(
+                property.to_string(),
+                self.owner.to_string(),
+            ));
+        }
+        self.seen |= id;
+
+        Ok(())
+    }
+
+    /// The error for a `property` the packet cannot carry
+    pub(crate) fn unexpected(&self, property: &Property) -> MQTTError {
+        MQTTError::UnexpectedProperty(property.to_string(), self.owner.to_string())
+    }
+
+    /// Reads the next property off `buf`, and checks it
+    pub(crate) fn read<'a>(&mut self, buf: &mut Bytes) -> Result<Property<'a>, MQTTError> {
+        let property = Property::read(buf)?;
+        self.check(&property)?;
+        Ok(property)
+    }
+
+    /// Writes `property` into `buf` (absent properties are not written, nor checked)
+    pub(crate) fn write(
+        &mut self,
+        property: Property,
+        buf: &mut BytesMut,
+    ) -> Result<(), MQTTError> {
+        use crate::v5::traits::bufferio::BufferIO;
+
+        let len = buf.len();
+        property.write(buf)?;
+        if buf.len() > len {
+            self.check(&property)?;
+        }
+        Ok(())
     }
 }
 
 pub(crate) mod synx {
+    use alloc::{borrow::Cow, string::String};
     use bytes::{Bytes, BytesMut};
-    use std::borrow::Cow;
 
     use crate::v5::commons::error::MQTTError;
+    use crate::v5::commons::primitives::{BinaryData, StringPair, Utf8String, VarInt};
     use crate::v5::traits::bufferio::BufferIO;
     use crate::v5::traits::{syncx::read::Read, syncx::write::Write};
 
@@ -232,13 +376,6 @@ pub(crate) mod synx {
 
     // #[cfg(feature = "sync")]
     impl<'a> BufferIO for Property<'a> {
-        fn length(&self) -> usize {
-            match self {
-                Self::SubscriptionIdentifier(length) => **length,
-                _ => 0,
-            }
-        }
-
         fn write(&self, buf: &mut BytesMut) -> Result<(), MQTTError> {
             match self {
                 Self::SessionExpiryInterval(Some(p)) => self.with_id(buf, |b| p.write(b)),
@@ -248,42 +385,41 @@ pub(crate) mod synx {
                 Self::TopicAlias(Some(p)) => self.with_id(buf, |b| p.write(b)),
                 Self::RequestResponseInformation(Some(p)) => self.with_id(buf, |b| p.write(b)),
                 Self::RequestProblemInformation(Some(p)) => self.with_id(buf, |b| p.write(b)),
-                Self::UserProperty(Cow::Borrowed((ref k, ref v))) => self.with_id(buf, |b| {
-                    k.write(b);
-                    v.write(b);
+                Self::UserProperty(pair) => self.with_id(buf, |b| {
+                    pair.0.write(b)?;
+                    pair.1.write(b)
                 }),
                 Self::AuthenticationMethod(Some(data)) => {
-                    self.with_id(buf, |b| Bytes::from_iter(data.as_bytes().to_vec()).write(b))
+                    self.with_id(buf, |b| Utf8String::write_str(data, b))
                 }
                 Self::AuthenticationData(Some(p)) => {
-                    self.with_id(buf, |b| Bytes::from_iter(p.to_vec()).write(b))
+                    self.with_id(buf, |b| BinaryData::write_slice(p, b))
                 }
                 Self::WillDelayInterval(Some(p)) => self.with_id(buf, |b| p.write(b)),
                 Self::PayloadFormatIndicator(Some(p)) => self.with_id(buf, |b| p.write(b)),
                 Self::MessageExpiryInterval(Some(p)) => self.with_id(buf, |b| p.write(b)),
                 Self::ContentType(Some(data)) => {
-                    self.with_id(buf, |b| Bytes::from_iter(data.as_bytes().to_vec()).write(b))
-                }
-                Self::ResponseTopic(Some(p)) => {
-                    self.with_id(buf, |b| Bytes::from_iter(p.as_bytes().to_vec()).write(b))
+                    self.with_id(buf, |b| Utf8String::write_str(data, b))
                 }
+                Self::ResponseTopic(Some(p)) => self.with_id(buf, |b| Utf8String::write_str(p, b)),
                 Self::CorrelationData(Some(p)) => {
-                    self.with_id(buf, |b| Bytes::from_iter(p.to_vec()).write(b))
+                    self.with_id(buf, |b| BinaryData::write_slice(p, b))
+                }
+                Self::SubscriptionIdentifier(id) => {
+                    self.with_id(buf, |b| VarInt::try_from(**id)?.write(b))
                 }
-                // NOTE: this needs to be tested for if this method of writing is correct or not!
-                Self::SubscriptionIdentifier(_) => self.with_id(buf, |b| self.encode(b).unwrap()),
                 Self::AssignedClientIdentifier(Some(data)) => {
-                    self.with_id(buf, |b| Bytes::from_iter(data.as_bytes().to_vec()).write(b))
+                    self.with_id(buf, |b| Utf8String::write_str(data, b))
                 }
                 Self::ServerKeepAlive(Some(p)) => self.with_id(buf, |b| p.write(b)),
                 Self::ResponseInformation(Some(data)) => {
-                    self.with_id(buf, |b| Bytes::from_iter(data.as_bytes().to_vec()).write(b))
+                    self.with_id(buf, |b| Utf8String::write_str(data, b))
                 }
                 Self::ServerReference(Some(data)) => {
-                    self.with_id(buf, |b| Bytes::from_iter(data.as_bytes().to_vec()).write(b))
+                    self.with_id(buf, |b| Utf8String::write_str(data, b))
                 }
                 Self::ReasonString(Some(data)) => {
-                    self.with_id(buf, |b| Bytes::from_iter(data.as_bytes().to_vec()).write(b))
+                    self.with_id(buf, |b| Utf8String::write_str(data, b))
                 }
                 Self::MaximumQoS(Some(i)) => self.with_id(buf, |b| i.write(b)),
                 Self::RetainAvailable(Some(i)) => self.with_id(buf, |b| i.write(b)),
@@ -291,10 +427,8 @@ pub(crate) mod synx {
                 Self::SubscriptionIdentifierAvailable(Some(i)) => self.with_id(buf, |b| i.write(b)),
                 Self::SharedSubscriptionAvailable(Some(i)) => self.with_id(buf, |b| i.write(b)),
                 // _ => {unreachable!("Unrecognized enum variant or argument!")}
-                _ => {}
+                _ => Ok(()),
             }
-
-            Ok(())
         }
 
         fn read(buf: &mut Bytes) -> Result<Self, MQTTError> {
@@ -313,7 +447,7 @@ pub(crate) mod synx {
                     Bytes::read(buf)?.to_vec(),
                 )))),
                 11 => Ok(Property::SubscriptionIdentifier(Cow::Owned(
-                    Self::decode(buf)?.0,
+                    VarInt::read(buf)?.into(),
                 ))),
                 17 => Ok(Property::SessionExpiryInterval(Some(u32::read(buf)?))),
                 18 => Ok(Property::AssignedClientIdentifier(Some(Cow::Owned(
@@ -341,10 +475,9 @@ pub(crate) mod synx {
                 35 => Ok(Property::TopicAlias(Some(u16::read(buf)?))),
                 36 => Ok(Property::MaximumQoS(Some(u8::read(buf)?))),
                 37 => Ok(Property::RetainAvailable(Some(u8::read(buf)?))),
-                38 => Ok(Property::UserProperty(Cow::Owned((
-                    String::read(buf)?,
-                    String::read(buf)?,
-                )))),
+                38 => Ok(Property::UserProperty(Cow::Owned(
+                    StringPair::read(buf)?.into(),
+                ))),
                 39 => Ok(Property::MaximumPacketSize(Some(u32::read(buf)?))),
                 40 => Ok(Property::WildCardSubscription(Some(u8::read(buf)?))),
                 41 => Ok(Property::SubscriptionIdentifierAvailable(Some(u8::read(
@@ -357,154 +490,117 @@ pub(crate) mod synx {
     }
 }
 
-pub(crate) mod asyncx {
-    use std::borrow::Cow;
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::v5::packet::disconnect::DisconnectReasonCode;
 
-    use crate::v5::commons::error::MQTTError;
-    use crate::v5::traits::asyncx::read::Read;
-    use crate::v5::traits::streamio::StreamIO;
+    #[test]
+    fn refuses_properties_the_packet_cannot_carry() {
+        let mut check = PropertyCheck::new(PacketType::PubAck);
+        check.check(&Property::ReasonString(None)).unwrap();
 
-    use super::Property;
+        let err = check.check(&Property::TopicAlias(Some(3))).unwrap_err();
+        assert_eq!(
+            err,
+            MQTTError::UnexpectedProperty("Topic Alias".into(), "PUBACK".into())
+        );
+        assert_eq!(
+            DisconnectReasonCode::from(&err),
+            DisconnectReasonCode::ProtocolError
+        );
 
-    impl<'a> StreamIO for Property<'a> {
-        fn length(&self) -> usize {
-            match self {
-                Self::SubscriptionIdentifier(length) => **length,
-                _ => 0,
-            }
-        }
+        let mut check = PropertyCheck::new(PropertyOwner::Will);
+        check.check(&Property::WillDelayInterval(Some(5))).unwrap();
+        let mut check = PropertyCheck::new(PacketType::Publish);
+        assert_eq!(
+            check.check(&Property::WillDelayInterval(Some(5))),
+            Err(MQTTError::UnexpectedProperty(
+                "Will Delay Interval".into(),
+                "PUBLISH".into()
+            ))
+        );
+    }
 
-        async fn read<R>(stream: &mut R) -> Result<Self, crate::v5::commons::error::MQTTError>
-        where
-            R: futures::AsyncReadExt + Unpin,
-        {
-            let property_id = u8::read(stream).await?;
+    #[test]
+    fn refuses_a_second_instance_of_single_instance_properties() {
+        let mut check = PropertyCheck::new(PacketType::Disconnect);
+        check.check(&Property::ReasonString(None)).unwrap();
+        let err = check.check(&Property::ReasonString(None)).unwrap_err();
+        assert_eq!(
+            err,
+            MQTTError::DuplicateProperty("Reason String".into(), "DISCONNECT".into())
+        );
+        assert_eq!(
+            DisconnectReasonCode::from(&err),
+            DisconnectReasonCode::ProtocolError
+        );
 
-            match property_id {
-                1 => Ok(Property::PayloadFormatIndicator(Some(
-                    u8::read(stream).await?,
-                ))),
-                2 => Ok(Property::MessageExpiryInterval(Some(
-                    u32::read(stream).await?,
-                ))),
-                3 => Ok(Property::ContentType(Some(Cow::Owned(
-                    String::read(stream).await?,
-                )))),
-                8 => Ok(Property::CorrelationData(Some(Cow::Owned(
-                    Vec::read(stream).await?,
-                )))),
-                11 => Ok(Property::SubscriptionIdentifier(Cow::Owned(
-                    Self::decode(stream).await?.0,
-                ))),
-                17 => Ok(Property::SessionExpiryInterval(Some(
-                    u32::read(stream).await?,
-                ))),
-                18 => Ok(Property::AssignedClientIdentifier(Some(Cow::Owned(
-                    String::read(stream).await?,
-                )))),
-                19 => Ok(Property::ServerKeepAlive(Some(u16::read(stream).await?))),
-                21 => Ok(Property::AuthenticationMethod(Some(Cow::Owned(
-                    String::read(stream).await?,
-                )))),
-                22 => Ok(Property::AuthenticationData(Some(Cow::Owned(
-                    Vec::read(stream).await?,
-                )))),
-                23 => Ok(Property::RequestProblemInformation(Some(
-                    u8::read(stream).await?,
-                ))),
-                24 => Ok(Property::WillDelayInterval(Some(u32::read(stream).await?))),
-                25 => Ok(Property::RequestResponseInformation(Some(
-                    u8::read(stream).await?,
-                ))),
-                26 => Ok(Property::ResponseInformation(Some(Cow::Owned(
-                    String::read(stream).await?,
-                )))),
-                28 => Ok(Property::ServerReference(Some(Cow::Owned(
-                    String::read(stream).await?,
-                )))),
-                31 => Ok(Property::ReasonString(Some(Cow::Owned(
-                    String::read(stream).await?,
-                )))),
-                33 => Ok(Property::ReceiveMaximum(Some(u16::read(stream).await?))),
-                34 => Ok(Property::TopicAliasMaximum(Some(u16::read(stream).await?))),
-                35 => Ok(Property::TopicAlias(Some(u16::read(stream).await?))),
-                36 => Ok(Property::MaximumQoS(Some(u8::read(stream).await?))),
-                37 => Ok(Property::RetainAvailable(Some(u8::read(stream).await?))),
-                38 => Ok(Property::UserProperty(Cow::Owned((
-                    String::read(stream).await?,
-                    String::read(stream).await?,
-                )))),
-                39 => Ok(Property::MaximumPacketSize(Some(u32::read(stream).await?))),
-                40 => Ok(Property::WildCardSubscription(Some(
-                    u8::read(stream).await?,
-                ))),
-                41 => Ok(Property::SubscriptionIdentifierAvailable(Some(
-                    u8::read(stream).await?,
-                ))),
-                42 => Ok(Property::SharedSubscriptionAvailable(Some(
-                    u8::read(stream).await?,
-                ))),
-                v => Err(MQTTError::UnknownProperty(v)),
-            }
+        let pair = (String::from("k"), String::from("v"));
+        for _ in 0..3 {
+            check
+                .check(&Property::UserProperty(Cow::Borrowed(&pair)))
+                .unwrap();
         }
+    }
 
-        async fn write<W>(&self, stream: &mut W) -> Result<(), MQTTError>
-        where
-            W: futures::AsyncWriteExt + Unpin,
-        {
-            match self {
-                Self::SessionExpiryInterval(Some(p)) => self.write_to_stream(stream, p).await?,
-                Self::ReceiveMaximum(Some(p)) => self.write_to_stream(stream, p).await?,
-                Self::MaximumPacketSize(Some(p)) => self.write_to_stream(stream, p).await?,
-                Self::TopicAliasMaximum(Some(p)) => self.write_to_stream(stream, p).await?,
-                Self::TopicAlias(Some(p)) => self.write_to_stream(stream, p).await?,
-                Self::RequestResponseInformation(Some(p)) => {
-                    self.write_to_stream(stream, p).await?
-                }
-                Self::RequestProblemInformation(Some(p)) => self.write_to_stream(stream, p).await?,
-                Self::UserProperty(Cow::Borrowed(p)) => self.write_to_stream(stream, *p).await?,
-                Self::AuthenticationMethod(Some(p)) => {
-                    self.write_to_stream(stream, &p.to_string()).await?
-                }
-                Self::AuthenticationData(Some(p)) => {
-                    self.write_to_stream(stream, &p.to_vec()).await?
-                }
-                Self::WillDelayInterval(Some(p)) => self.write_to_stream(stream, p).await?,
-                Self::PayloadFormatIndicator(Some(p)) => self.write_to_stream(stream, p).await?,
-                Self::MessageExpiryInterval(Some(p)) => self.write_to_stream(stream, p).await?,
-                Self::ContentType(Some(p)) => self.write_to_stream(stream, &p.to_string()).await?,
-                Self::ResponseTopic(Some(p)) => {
-                    self.write_to_stream(stream, &p.to_string()).await?
-                }
-                Self::CorrelationData(Some(p)) => self.write_to_stream(stream, &p.to_vec()).await?,
-                Self::SubscriptionIdentifier(_) => {
-                    self.write_async(stream, |s| async move { self.encode(s).await })
-                        .await?;
-                }
-                Self::AssignedClientIdentifier(Some(p)) => {
-                    self.write_to_stream(stream, &p.to_string()).await?
-                }
-                Self::ServerKeepAlive(Some(p)) => self.write_to_stream(stream, p).await?,
-                Self::ResponseInformation(Some(p)) => {
-                    self.write_to_stream(stream, &p.to_string()).await?
-                }
-                Self::ServerReference(Some(p)) => {
-                    self.write_to_stream(stream, &p.to_string()).await?
-                }
-                Self::ReasonString(Some(p)) => self.write_to_stream(stream, &p.to_string()).await?,
-                Self::MaximumQoS(Some(p)) => self.write_to_stream(stream, p).await?,
-                Self::RetainAvailable(Some(p)) => self.write_to_stream(stream, p).await?,
-                Self::WildCardSubscription(Some(p)) => self.write_to_stream(stream, p).await?,
-                Self::SubscriptionIdentifierAvailable(Some(p)) => {
-                    self.write_to_stream(stream, p).await?
-                }
-                Self::SharedSubscriptionAvailable(Some(p)) => {
-                    self.write_to_stream(stream, p).await?
-                }
-                _ => (),
-            }
-
-            Ok(())
+    #[test]
+    fn allows_several_subscription_identifiers_on_publish_only() {
+        let mut publish = PropertyCheck::new(PacketType::Publish);
+        let mut subscribe = PropertyCheck::new(PacketType::Subscribe);
+        for id in [1, 2] {
+            publish
+                .check(&Property::SubscriptionIdentifier(Cow::Owned(id)))
+                .unwrap();
         }
+
+        subscribe
+            .check(&Property::SubscriptionIdentifier(Cow::Owned(1)))
+            .unwrap();
+        assert_eq!(
+            subscribe.check(&Property::SubscriptionIdentifier(Cow::Owned(2))),
+            Err(MQTTError::DuplicateProperty(
+                "Subscription Identifier".into(),
+                "SUBSCRIBE".into()
+            ))
+        );
+    }
+
+    #[test]
+    fn checks_properties_as_they_are_decoded_and_encoded() {
+        use crate::v5::packet::puback::PubAckProperties;
+        use crate::v5::traits::read_data::ReadData;
+
+        // Reason String (31), then Topic Alias (35)
+        let mut data = Bytes::from_static(b"\x1f\x00\x02ok\x23\x00\x01");
+        assert_eq!(
+            PubAckProperties::read_data(&mut data),
+            Err(MQTTError::UnexpectedProperty(
+                "Topic Alias".into(),
+                "PUBACK".into()
+            ))
+        );
+
+        // absent properties are neither written nor counted
+        let mut check = PropertyCheck::new(PacketType::PubAck);
+        let mut buf = BytesMut::new();
+        check.write(Property::ReasonString(None), &mut buf).unwrap();
+        check
+            .write(Property::ReasonString(Some("ok".into())), &mut buf)
+            .unwrap();
+        assert_eq!(
+            check.write(Property::ReasonString(Some("ok".into())), &mut buf),
+            Err(MQTTError::DuplicateProperty(
+                "Reason String".into(),
+                "PUBACK".into()
+            ))
+        );
+        assert_eq!(
+            check.write(Property::MaximumQoS(Some(1)), &mut BytesMut::new()),
+            Err(MQTTError::UnexpectedProperty(
+                "Maximum QoS".into(),
+                "PUBACK".into()
+            ))
+        );
     }
 }
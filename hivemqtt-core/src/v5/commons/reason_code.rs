@@ -1,14 +1,33 @@
-#[allow(dead_code)]
-#[derive(derive_more::Display)]
-#[repr(u8)]
-pub(crate) enum ReasonCode {
-    /// CONNACK, PUBACK. PUBREC, PUBREL, PUBCOMP, UNSUBACK, AUTH (0x00)
+//! 2.4 Every Reason Code of the specification, whatever the packet carrying it.
+//!
+//! Each packet only allows some of them, and has its own enum for those (e.g. [`SubAckReasonCode`]): they all convert
+//! into a [`ReasonCode`], so that acknowledgements and disconnections can be interpreted the same way:
+//! ```
+//! use hivemqtt_core::v5::{commons::reason_code::ReasonCode, packet::suback::SubAckReasonCode};
+//!
+//! let code = ReasonCode::from(SubAckReasonCode::QuotaExceeded);
+//! assert!(code.is_error() && code.is_retryable());
+//! assert_eq!(code.to_string(), "Quota exceeded");
+//! ```
+use alloc::format;
+
+use super::error::MQTTError;
+use crate::v5::packet::{
+    auth::AuthReasonCode, connack::reason_code::ConnAckReasonCode,
+    disconnect::DisconnectReasonCode, puback::PubAckReasonCode, pubcomp::PubCompReasonCode,
+    pubrec::properties::PubRecReasonCode, pubrel::PubRelReasonCode, suback::SubAckReasonCode,
+    unsuback::UnSubAckReasonCode,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, derive_more::Display)]
+pub enum ReasonCode {
+    /// CONNACK, PUBACK, PUBREC, PUBREL, PUBCOMP, UNSUBACK, AUTH = 0x00
     #[display("Success")]
-    Success,  // 0x00
-    /// NormalDisconnection = 0x00
-    #[display("Normal Disconnection")]
+    Success,
+    /// DISCONNECT = 0x00
+    #[display("Normal disconnection")]
     NormalDisconnection,
-    /// SUBACK  = 0x00
+    /// SUBACK = 0x00
     #[display("Granted QoS 0")]
     GrantedQoS0,
     /// SUBACK = 0x01
@@ -24,7 +43,7 @@ pub(crate) enum ReasonCode {
     #[display("No matching subscribers")]
     NoMatchingSubscribers,
     /// UNSUBACK = 0x11
-    #[display("No Subscription existed")]
+    #[display("No subscription existed")]
     NoSubscriptionExisted,
     /// AUTH = 0x18
     #[display("Continue authentication")]
@@ -80,11 +99,11 @@ pub(crate) enum ReasonCode {
     /// SUBACK, UNSUBACK, DISCONNECT = 0x8F
     #[display("Topic Filter invalid")]
     TopicFilterInvalid,
-    /// CONNACK, PUBACK, PUBREC, DISCONNECT = -0x90
-    #[display("Topic name invalid")]
+    /// CONNACK, PUBACK, PUBREC, DISCONNECT = 0x90
+    #[display("Topic Name invalid")]
     TopicNameInvalid,
     /// PUBACK, PUBREC, SUBACK, UNSUBACK = 0x91
-    #[display("Packet Identidier in use")]
+    #[display("Packet Identifier in use")]
     PacketIdentifierInUse,
     /// PUBREL, PUBCOMP = 0x92
     #[display("Packet Identifier not found")]
@@ -123,7 +142,7 @@ pub(crate) enum ReasonCode {
     #[display("Server moved")]
     ServerMoved,
     /// SUBACK, DISCONNECT = 0x9E
-    #[display("Shared Subcriptions not supported")]
+    #[display("Shared Subscriptions not supported")]
     SharedSubscriptionsNotSupported,
     /// CONNACK, DISCONNECT = 0x9F
     #[display("Connection rate exceeded")]
@@ -139,11 +158,47 @@ pub(crate) enum ReasonCode {
     WildcardSubscriptionsNotSupported,
 }
 
+impl ReasonCode {
+    /// Reason Codes less than 0x80 indicate successful completion of an operation (2.4)
+    pub fn is_success(&self) -> bool {
+        u8::from(*self) < 0x80
+    }
+
+    /// Reason Code values of 0x80 or greater indicate failure (2.4)
+    pub fn is_error(&self) -> bool {
+        !self.is_success()
+    }
+
+    /// Errors caused by the current state of the server (its load, quotas, or whereabouts), the same operation may
+    /// succeed later, or on another server
+    pub fn is_retryable(&self) -> bool {
+        matches!(
+            self,
+            Self::UnspecifiedError
+                | Self::ServerUnavailable
+                | Self::ServerBusy
+                | Self::ServerShuttingDown
+                | Self::KeepAliveTimeout
+                | Self::PacketIdentifierInUse
+                | Self::ReceiveMaximumExceeded
+                | Self::MessageRateTooHigh
+                | Self::QuotaExceeded
+                | Self::UseAnotherServer
+                | Self::ServerMoved
+                | Self::ConnectionRateExceeded
+                | Self::MaximumConnectTime
+        )
+    }
+
+    /// Errors that repeating the same operation cannot fix (a malformed or unauthorized packet, an unsupported feature, ...)
+    pub fn is_permanent(&self) -> bool {
+        self.is_error() && !self.is_retryable()
+    }
+}
 
 impl From<ReasonCode> for u8 {
     fn from(value: ReasonCode) -> Self {
         match value {
-        
             ReasonCode::Success | ReasonCode::NormalDisconnection | ReasonCode::GrantedQoS0 => 0x00,
             ReasonCode::GrantedQoS1 => 0x01,
             ReasonCode::GrantedQoS2 => 0x02,
@@ -187,7 +242,158 @@ impl From<ReasonCode> for u8 {
             ReasonCode::MaximumConnectTime => 0xA0,
             ReasonCode::SubscriptionIdentifiersNotSupported => 0xA1,
             ReasonCode::WildcardSubscriptionsNotSupported => 0xA2,
-            _ => 0x80
         }
     }
-}
\ No newline at end of file
+}
+
+/// Conversions between a packet's Reason Codes and the [`ReasonCode`]s of the same name,
+/// along with the classification and spec wording of the latter
+macro_rules! packet_reason_code {
+    ($packet:ident: $($variant:ident),+ $(,)?) => {
+        impl From<$packet> for ReasonCode {
+            fn from(value: $packet) -> Self {
+                match value {
+                    $($packet::$variant => Self::$variant,)+
+                }
+            }
+        }
+
+        impl TryFrom<ReasonCode> for $packet {
+            type Error = MQTTError;
+
+            fn try_from(value: ReasonCode) -> Result<Self, MQTTError> {
+                match value {
+                    $(ReasonCode::$variant => Ok(Self::$variant),)+
+                    code => Err(MQTTError::UnknownData(format!(
+                        "{code} is not a Reason Code of {}",
+                        stringify!($packet)
+                    ))),
+                }
+            }
+        }
+
+        impl $packet {
+            /// See [`ReasonCode::is_success`]
+            pub fn is_success(&self) -> bool {
+                ReasonCode::from(*self).is_success()
+            }
+
+            /// See [`ReasonCode::is_error`]
+            pub fn is_error(&self) -> bool {
+                ReasonCode::from(*self).is_error()
+            }
+
+            /// See [`ReasonCode::is_retryable`]
+            pub fn is_retryable(&self) -> bool {
+                ReasonCode::from(*self).is_retryable()
+            }
+
+            /// See [`ReasonCode::is_permanent`]
+            pub fn is_permanent(&self) -> bool {
+                ReasonCode::from(*self).is_permanent()
+            }
+        }
+
+        impl core::fmt::Display for $packet {
+            fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+                ReasonCode::from(*self).fmt(f)
+            }
+        }
+    };
+}
+
+packet_reason_code!(ConnAckReasonCode:
+    Success, UnspecifiedError, MalformedPacket, ProtocolError, ImplementationSpecificError,
+    UnsupportedProtocolVersion, ClientIdentifierNotValid, BadUserNameOrPassword, NotAuthorized,
+    ServerUnavailable, ServerBusy, Banned, BadAuthenticationMethod, TopicNameInvalid, PacketTooLarge,
+    QuotaExceeded, PayloadFormatInvalid, RetainNotSupported, QoSNotSupported, UseAnotherServer,
+    ServerMoved, ConnectionRateExceeded,
+);
+packet_reason_code!(PubAckReasonCode:
+    Success, NoMatchingSubscribers, UnspecifiedError, ImplementationSpecificError, NotAuthorized,
+    TopicNameInvalid, PacketIdentifierInUse, QuotaExceeded, PayloadFormatInvalid,
+);
+packet_reason_code!(PubRecReasonCode:
+    Success, NoMatchingSubscribers, UnspecifiedError, ImplementationSpecificError, NotAuthorized,
+    TopicNameInvalid, PacketIdentifierInUse, QuotaExceeded, PayloadFormatInvalid,
+);
+packet_reason_code!(PubRelReasonCode: Success, PacketIdentifierNotFound);
+packet_reason_code!(PubCompReasonCode: Success, PacketIdentifierNotFound);
+packet_reason_code!(SubAckReasonCode:
+    GrantedQoS0, GrantedQoS1, GrantedQoS2, UnspecifiedError, ImplementationSpecificError,
+    NotAuthorized, TopicFilterInvalid, PacketIdentifierInUse, QuotaExceeded,
+    SharedSubscriptionsNotSupported, SubscriptionIdentifiersNotSupported,
+    WildcardSubscriptionsNotSupported,
+);
+packet_reason_code!(UnSubAckReasonCode:
+    Success, NoSubscriptionExisted, UnspecifiedError, ImplementationSpecificError, NotAuthorized,
+    TopicFilterInvalid, PacketIdentifierInUse,
+);
+packet_reason_code!(DisconnectReasonCode:
+    NormalDisconnection, DisconnectWithWillMessage, UnspecifiedError, MalformedPacket,
+    ProtocolError, ImplementationSpecificError, NotAuthorized, ServerBusy, ServerShuttingDown,
+    KeepAliveTimeout, SessionTakenOver, TopicFilterInvalid, TopicNameInvalid,
+    ReceiveMaximumExceeded, TopicAliasInvalid, PacketTooLarge, MessageRateTooHigh, QuotaExceeded,
+    AdministrativeAction, PayloadFormatInvalid, RetainNotSupported, QoSNotSupported,
+    UseAnotherServer, ServerMoved, SharedSubscriptionsNotSupported, ConnectionRateExceeded,
+    MaximumConnectTime, SubscriptionIdentifiersNotSupported, WildcardSubscriptionsNotSupported,
+);
+packet_reason_code!(AuthReasonCode: Success, ContinueAuthentication, ReAuthenticate);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Every byte `T` decodes must map to a [`ReasonCode`] of the same value, and back
+    fn round_trips<T>()
+    where
+        T: TryFrom<u8>
+            + TryFrom<ReasonCode>
+            + Into<ReasonCode>
+            + Into<u8>
+            + Copy
+            + PartialEq
+            + core::fmt::Debug,
+        <T as TryFrom<ReasonCode>>::Error: core::fmt::Debug,
+    {
+        for byte in 0..=u8::MAX {
+            let Ok(code) = T::try_from(byte) else {
+                continue;
+            };
+            let registry: ReasonCode = code.into();
+            assert_eq!(u8::from(registry), byte, "{code:?}");
+            assert_eq!(T::try_from(registry).unwrap(), code);
+        }
+    }
+
+    #[test]
+    fn packet_reason_codes_map_to_the_registry() {
+        round_trips::<ConnAckReasonCode>();
+        round_trips::<PubAckReasonCode>();
+        round_trips::<PubRecReasonCode>();
+        round_trips::<PubRelReasonCode>();
+        round_trips::<PubCompReasonCode>();
+        round_trips::<SubAckReasonCode>();
+        round_trips::<UnSubAckReasonCode>();
+        round_trips::<DisconnectReasonCode>();
+        round_trips::<AuthReasonCode>();
+    }
+
+    #[test]
+    fn refuses_reason_codes_the_packet_cannot_carry() {
+        assert!(PubAckReasonCode::try_from(ReasonCode::GrantedQoS1).is_err());
+        assert!(DisconnectReasonCode::try_from(ReasonCode::Success).is_err());
+    }
+
+    #[test]
+    fn classifies_reason_codes() {
+        assert!(SubAckReasonCode::GrantedQoS2.is_success());
+        assert!(DisconnectReasonCode::ServerBusy.is_retryable());
+        assert!(ConnAckReasonCode::BadUserNameOrPassword.is_permanent());
+        assert!(!PubAckReasonCode::NoMatchingSubscribers.is_error());
+        assert_eq!(
+            UnSubAckReasonCode::NoSubscriptionExisted.to_string(),
+            "No subscription existed"
+        );
+    }
+}
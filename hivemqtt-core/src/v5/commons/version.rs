@@ -2,8 +2,10 @@ use super::error::MQTTError;
 
 #[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
 #[repr(u8)]
-pub(crate) enum Version {
+pub enum Version {
+    /// MQTT 3.1.1
     V4 = 0b0000_0100,
+    /// MQTT 5.0
     #[default]
     V5 = 0b0000_0101
 }
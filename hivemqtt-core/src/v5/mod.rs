@@ -1,5 +1,6 @@
 pub mod packet;
+pub mod codec;
 pub mod commons;
 pub mod client;
 pub mod traits;
-pub(crate) mod utils;
\ No newline at end of file
+pub mod utils;
\ No newline at end of file
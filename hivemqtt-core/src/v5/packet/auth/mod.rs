@@ -9,8 +9,8 @@ use crate::v5::{
 
 #[derive(Debug, Default, PartialEq, Eq)]
 pub struct Auth {
-    reason_code: AuthReasonCode,
-    properties: AuthProperties,
+    pub(crate) reason_code: AuthReasonCode,
+    pub(crate) properties: AuthProperties,
 }
 
 impl ReadData for Auth {}
@@ -35,7 +35,7 @@ mod synx {
         fn write(&self, buf: &mut bytes::BytesMut) -> Result<(), MQTTError> {
             FixedHeader::new(PacketType::Auth, 0, self.length()).write(buf)?;
 
-            u8::from(self.reason_code).write(buf);
+            u8::from(self.reason_code).write(buf)?;
             self.properties.write(buf)?;
 
             Ok(())
@@ -58,47 +58,6 @@ mod synx {
     }
 }
 
-mod asyncx {
-    use crate::v5::commons::error::MQTTError;
-    use crate::v5::packet::auth::{AuthProperties, AuthReasonCode, FixedHeader, PacketType};
-    use crate::v5::traits::asyncx::{read::Read, write::Write};
-    use crate::v5::{packet::auth::Auth, traits::streamio::StreamIO};
-
-    impl StreamIO for Auth {
-        fn length(&self) -> usize {
-            1 + self.properties.length() + self.properties.variable_length()
-        }
-
-        async fn write<W>(&self, stream: &mut W) -> Result<(), crate::v5::commons::error::MQTTError>
-        where
-            W: futures::AsyncWriteExt + Unpin,
-        {
-            FixedHeader::new(PacketType::Auth, 0, self.length())
-                .write(stream)
-                .await?;
-            u8::from(self.reason_code).write(stream).await?;
-            self.properties.write(stream).await
-        }
-
-        async fn read<R>(stream: &mut R) -> Result<Self, crate::v5::commons::error::MQTTError>
-        where
-            R: futures::AsyncReadExt + Unpin,
-        {
-            let mut packet = Self::default();
-
-            let Ok(reason_code) = u8::read(stream).await else {
-                return Ok(packet);
-            };
-
-            packet.reason_code =
-                AuthReasonCode::try_from(reason_code).map_err(MQTTError::UnknownData)?;
-            packet.properties = AuthProperties::read(stream).await?;
-
-            Ok(Self::default())
-        }
-    }
-}
-
 // #[cfg(test)]
 // mod tests {
 //     use bytes::BytesMut;
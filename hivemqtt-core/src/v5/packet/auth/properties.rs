@@ -1,9 +1,18 @@
+use alloc::{
+    string::String,
+    vec::Vec,
+};
+
 use bytes::Bytes;
 use hivemqtt_macros::Length;
 
 use crate::v5::{
-    commons::{error::MQTTError, property::Property},
-    traits::{read_data::ReadData, utils::Utils},
+    commons::{
+        error::MQTTError,
+        packet_type::PacketType,
+        property::{Property, PropertyCheck},
+    },
+    traits::read_data::ReadData,
 };
 
 use hivemqtt_macros::FromU8;
@@ -19,24 +28,23 @@ pub struct AuthProperties {
 impl ReadData for AuthProperties {
     fn read_data(data: &mut Bytes) -> Result<Self, MQTTError> {
         let mut props = Self::default();
+        let mut check = PropertyCheck::new(PacketType::Auth);
 
         loop {
-            let property = Property::read(data)?;
+            let property = check.read(data)?;
             match property {
-                Property::AuthenticationMethod(ref v) => Self::try_update(
-                    &mut props.auth_method,
-                    v.as_deref().map(String::from),
-                )(property)?,
-                Property::AuthenticationData(ref value) => Self::try_update(
-                    &mut props.auth_data,
-                    value.as_deref().map(|x| Bytes::from_iter(x.to_vec())),
-                )(property)?,
-                Property::ReasonString(ref v) => Self::try_update(
-                    &mut props.reason_string,
-                    v.as_deref().map(String::from),
-                )(property)?,
+                Property::AuthenticationMethod(ref v) => {
+                    props.auth_method = v.as_deref().map(String::from)
+                }
+                Property::AuthenticationData(ref value) => {
+                    props.auth_data = value.as_deref().map(|x| Bytes::from_iter(x.to_vec()))
+                }
+                Property::ReasonString(ref v) => {
+                    props.reason_string = v.as_deref().map(String::from)
+                }
                 Property::UserProperty(v) => props.user_property.push(v.into_owned()),
-                p => return Err(MQTTError::UnexpectedProperty(p.to_string(), "".to_string())),
+                // anything else is refused by `PropertyCheck::read` already
+                p => return Err(check.unexpected(&p)),
             }
             if data.is_empty() {
                 break;
@@ -56,9 +64,9 @@ pub enum AuthReasonCode {
 }
 
 mod syncx {
-    use std::borrow::Cow;
+    use alloc::borrow::Cow;
 
-    use super::{AuthProperties, Property};
+    use super::{AuthProperties, PacketType, Property, PropertyCheck};
     use crate::v5::{commons::error::MQTTError, traits::bufferio::BufferIO};
 
     impl BufferIO for AuthProperties {
@@ -68,56 +76,23 @@ mod syncx {
 
         fn write(&self, buf: &mut bytes::BytesMut) -> Result<(), MQTTError> {
             self.encode(buf)?;
-
-            Property::AuthenticationMethod(self.auth_method.as_deref().map(Cow::Borrowed))
-                .write(buf)?;
-            Property::AuthenticationData(self.auth_data.as_deref().map(Cow::Borrowed))
-                .write(buf)?;
-            Property::ReasonString(self.reason_string.as_deref().map(Cow::Borrowed)).write(buf)?;
+            let mut check = PropertyCheck::new(PacketType::Auth);
+
+            check.write(
+                Property::AuthenticationMethod(self.auth_method.as_deref().map(Cow::Borrowed)),
+                buf,
+            )?;
+            check.write(
+                Property::AuthenticationData(self.auth_data.as_deref().map(Cow::Borrowed)),
+                buf,
+            )?;
+            check.write(
+                Property::ReasonString(self.reason_string.as_deref().map(Cow::Borrowed)),
+                buf,
+            )?;
             self.user_property
                 .iter()
-                .try_for_each(|up| Property::UserProperty(Cow::Borrowed(&up)).write(buf))?;
-            Ok(())
-        }
-    }
-}
-
-mod asyncx {
-    use std::borrow::Cow;
-
-    use crate::v5::commons::error::MQTTError;
-    use crate::v5::packet::auth::AuthProperties;
-    use crate::v5::traits::streamio::StreamIO;
-
-    use super::Property;
-
-    impl StreamIO for AuthProperties {
-        fn length(&self) -> usize {
-            self.len()
-        }
-
-        async fn write<W>(&self, stream: &mut W) -> Result<(), MQTTError>
-        where
-            W: futures::AsyncWriteExt + Unpin,
-        {
-            self.encode(stream).await?;
-
-            Property::AuthenticationMethod(self.auth_method.as_deref().map(Cow::Borrowed))
-                .write(stream)
-                .await?;
-            Property::AuthenticationData(self.auth_data.as_deref().map(Cow::Borrowed))
-                .write(stream)
-                .await?;
-            Property::ReasonString(self.reason_string.as_deref().map(Cow::Borrowed))
-                .write(stream)
-                .await?;
-
-            for up in &self.user_property {
-                Property::UserProperty(Cow::Borrowed(up))
-                    .write(stream)
-                    .await?
-            }
-
+                .try_for_each(|up| check.write(Property::UserProperty(Cow::Borrowed(&up)), buf))?;
             Ok(())
         }
     }
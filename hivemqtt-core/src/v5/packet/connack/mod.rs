@@ -1,11 +1,11 @@
 mod properties;
-pub(crate) mod reason_code;
+pub mod reason_code;
 
-use properties::ConnAckProperties;
+pub use properties::ConnAckProperties;
 use reason_code::ConnAckReasonCode;
 
 use crate::v5::{
-    commons::{fixed_header::FixedHeader, packet_type::PacketType},
+    commons::packet_type::PacketType,
     traits::read_data::ReadData,
 };
 
@@ -13,13 +13,15 @@ use crate::v5::{
 pub struct ConnAck {
     /// 3.2.2.1.1 Connect Acknowledge flag
     pub session_present: bool, // bit 0 of the COnnect Acknowledge flag
-    pub(crate) reason: ConnAckReasonCode,
+    pub reason: ConnAckReasonCode,
     pub properties: ConnAckProperties,
 }
 
 impl ReadData for ConnAck {}
 
 mod synx {
+    use alloc::format;
+
     use crate::v5::commons::{error::MQTTError, fixed_header::FixedHeader};
     use crate::v5::traits::{
         bufferio::BufferIO,
@@ -41,8 +43,8 @@ mod synx {
         fn write(&self, buf: &mut bytes::BytesMut) -> Result<(), MQTTError> {
             FixedHeader::new(PacketType::ConnAck, 0, self.length()).write(buf)?;
 
-            u8::from(self.session_present).write(buf);
-            (self.reason as u8).write(buf);
+            u8::from(self.session_present).write(buf)?;
+            (self.reason as u8).write(buf)?;
             self.properties.write(buf)?;
 
             Ok(())
@@ -63,60 +65,6 @@ mod synx {
     }
 }
 
-mod asyncx {
-    use crate::v5::{
-        commons::error::MQTTError,
-        traits::{
-            asyncx::{read::Read, write::Write},
-            streamio::StreamIO,
-        },
-    };
-
-    use super::{
-        properties::ConnAckProperties, ConnAck, ConnAckReasonCode, FixedHeader, PacketType,
-    };
-
-    impl StreamIO for ConnAck {
-        /// This is the length of the Variable Header
-        fn length(&self) -> usize {
-            let mut len = 1 + 1; // session present + reason code
-            len += self.properties.length();
-            len += self.properties.variable_length();
-            len
-        }
-
-        async fn read<R>(stream: &mut R) -> Result<Self, MQTTError>
-        where
-            R: futures::AsyncReadExt + Unpin,
-        {
-            let mut packet = Self::default();
-            packet.session_present = u8::read(stream).await? != 0;
-            let reason = u8::read(stream).await?;
-            packet.reason = ConnAckReasonCode::try_from(reason).map_err(|_| {
-                MQTTError::UnknownData(format!("Unrecognized reason code: {reason}"))
-            })?;
-            packet.properties = ConnAckProperties::read(stream).await?;
-
-            Ok(packet)
-        }
-
-        async fn write<W>(&self, stream: &mut W) -> Result<(), MQTTError>
-        where
-            W: futures::AsyncWriteExt + Unpin,
-        {
-            FixedHeader::new(PacketType::ConnAck, 0, self.length())
-                .write(stream)
-                .await?;
-
-            u8::from(self.session_present).write(stream).await?;
-            (self.reason as u8).write(stream).await?;
-            self.properties.write(stream).await?;
-
-            Ok(())
-        }
-    }
-}
-
 // #[cfg(test)]
 // mod tests {
 //     use bytes::{Bytes, BytesMut};
@@ -3,18 +3,18 @@ use hivemqtt_macros::FromU8;
 #[allow(dead_code)]
 #[derive(Debug, Default, Clone, Copy, FromU8, PartialEq, Eq)]
 #[repr(u8)]
-pub(crate) enum ConnAckReasonCode {
+pub enum ConnAckReasonCode {
     #[default]
     Success = 0,
     UnspecifiedError = 128,
     MalformedPacket = 129,
     ProtocolError = 130,
     ImplementationSpecificError =131,
-    UnSupportedProtocolVersion = 132,
+    UnsupportedProtocolVersion = 132,
     ClientIdentifierNotValid = 133,
     BadUserNameOrPassword = 134,
     NotAuthorized = 135,
-    ServerUnAvailable = 136,
+    ServerUnavailable = 136,
     ServerBusy = 137,
     Banned = 138,
     BadAuthenticationMethod = 140,
@@ -25,6 +25,6 @@ pub(crate) enum ConnAckReasonCode {
     RetainNotSupported = 154,
     QoSNotSupported = 155,
     UseAnotherServer = 156,
-    ServeMoved = 157,
+    ServerMoved = 157,
     ConnectionRateExceeded = 159,
 }
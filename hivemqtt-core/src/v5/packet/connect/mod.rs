@@ -1,8 +1,10 @@
+use alloc::string::String;
+
 mod properties;
 pub mod will;
 
 use hivemqtt_macros::Length;
-use properties::ConnectProperties;
+pub use properties::ConnectProperties;
 use will::Will;
 
 use crate::{
@@ -20,25 +22,23 @@ use crate::{
 #[derive(Debug, Length, PartialEq, Eq)]
 pub struct Connect {
     #[bytes(no_id)]
-    pub(crate) client_id: String,
+    pub client_id: String,
     #[bytes(no_id)]
-    pub(crate) username: Option<String>,
+    pub username: Option<String>,
     #[bytes(no_id)]
-    pub(crate) password: Option<String>,
+    pub password: Option<String>,
     #[bytes(ignore)]
-    pub(crate) version: Version,
+    pub version: Version,
     #[bytes(ignore)]
-    pub(crate) will: Option<Will>,
+    pub will: Option<Will>,
     #[bytes(ignore)]
-    pub(crate) clean_start: bool,
+    pub clean_start: bool,
     #[bytes(ignore)]
-    pub(crate) keep_alive: u16,
+    pub keep_alive: u16,
     #[bytes(ignore)] // Connection properties
-    pub(crate) properties: ConnectProperties,
+    pub properties: ConnectProperties,
 }
 
-#[cfg(feature = "asyncx")]
-pub(crate) use asyncx::*;
 #[cfg(feature = "syncx")]
 pub(crate) use syncx::*;
 
@@ -63,7 +63,7 @@ impl From<&ConnectOptions> for Connect {
             client_id: value.client_id.clone(),
             username: value.username.clone(),
             password: value.password.clone(),
-            version: Version::V5,
+            version: value.version,
             will: value.will.clone(),
             clean_start: value.clean_start,
             keep_alive: value.keep_alive,
@@ -85,6 +85,8 @@ impl From<&ConnectOptions> for Connect {
 impl ReadData for Connect {}
 
 mod syncx {
+    use alloc::string::{String, ToString};
+
     use bytes::Bytes;
 
     use crate::v5::{
@@ -118,8 +120,8 @@ mod syncx {
         fn write(&self, buf: &mut bytes::BytesMut) -> Result<(), MQTTError> {
             FixedHeader::new(PacketType::Connect, 0, self.length()).write(buf)?;
 
-            (PROTOCOL_NAME.to_string()).write(buf);
-            (self.version as u8).write(buf);
+            (PROTOCOL_NAME.to_string()).write(buf)?;
+            (self.version as u8).write(buf)?;
 
             let mut flags = ConnectFlags {
                 clean_start: self.clean_start,
@@ -134,19 +136,19 @@ mod syncx {
                 flags.will_qos = will.qos;
             }
 
-            u8::from(flags).write(buf); // 3.1.2.3
-            self.keep_alive.write(buf); // 3.1.2.10
+            u8::from(flags).write(buf)?; // 3.1.2.3
+            self.keep_alive.write(buf)?; // 3.1.2.10
             self.properties.write(buf)?; // 3.1.2.11
                                          // CONNECT Payload: length-prefixed fields
-            self.client_id.write(buf); // ClientId, willProperties, willTopic, willPayload, userName, password
+            self.client_id.write(buf)?; // ClientId, willProperties, willTopic, willPayload, userName, password
             if let Some(will) = &self.will {
                 will.write(buf)?;
             }
             if let Some(username) = &self.username {
-                username.write(buf);
+                username.write(buf)?;
             } // 3.1.3.5
             if let Some(password) = &self.password {
-                password.write(buf);
+                password.write(buf)?;
             } // 3.1.3.6
 
             Ok(())
@@ -165,6 +167,7 @@ mod syncx {
             packet.version = Version::try_from(u8::read(buf)?)?;
 
             let flags = ConnectFlags::try_from(u8::read(buf)?)?;
+            packet.clean_start = flags.clean_start;
             packet.keep_alive = u16::read(buf)?;
             packet.properties = ConnectProperties::read(buf)?;
             packet.client_id = String::read(buf)?;
@@ -173,7 +176,6 @@ mod syncx {
                 let mut will = Will::read(buf)?;
                 will.retain = flags.will_retain;
                 will.qos = flags.will_qos;
-                packet.clean_start = flags.will_retain;
                 packet.will = Some(will);
             }
 
@@ -189,117 +191,6 @@ mod syncx {
     }
 }
 
-mod asyncx {
-    use crate::v5::{
-        commons::error::MQTTError,
-        traits::{
-            asyncx::{read::Read, write::Write},
-            streamio::StreamIO,
-        },
-    };
-
-    use super::{
-        will::Will, Connect, ConnectFlags, ConnectProperties, FixedHeader, PacketType, Version,
-        PROTOCOL_NAME,
-    };
-
-    impl StreamIO for Connect {
-        /// Length of the Variable Header + the length of the Payload
-        fn length(&self) -> usize {
-            let mut len: usize = (2 + PROTOCOL_NAME.len()) + 1 + 1 + 2; // version + connect flags + keep alive
-
-            len += self.properties.length();
-            len += self.properties.variable_length();
-            if let Some(will) = &self.will {
-                len += will.length()
-            }
-            len += self.len(); // client id + username + password
-
-            len
-        }
-
-        async fn write<W>(&self, stream: &mut W) -> Result<(), MQTTError>
-        where
-            W: futures::AsyncWriteExt + Unpin,
-        {
-            FixedHeader::new(PacketType::Connect, 0, self.length())
-                .write(stream)
-                .await?;
-
-            (PROTOCOL_NAME.to_string()).write(stream).await?;
-            (self.version as u8).write(stream).await?;
-
-            let mut flags = ConnectFlags {
-                clean_start: self.clean_start,
-                password: self.password.is_some(),
-                username: self.username.is_some(),
-                ..Default::default()
-            };
-
-            if let Some(will) = &self.will {
-                flags.will_retain = will.retain;
-                flags.will_flag = true;
-                flags.will_qos = will.qos;
-            }
-
-            u8::from(flags).write(stream).await?; // 3.1.2.3
-            self.keep_alive.write(stream).await?; // 3.1.2.10
-            self.properties.write(stream).await?; // 3.1.2.11
-                                                  // CONNECT Payload: length-prefixed fields
-            self.client_id.write(stream).await?; // ClientId, willProperties, willTopic, willPayload, userName, password
-            if let Some(will) = &self.will {
-                will.write(stream).await?;
-            }
-            if let Some(username) = &self.username {
-                username.write(stream).await?;
-            } // 3.1.3.5
-            if let Some(password) = &self.password {
-                password.write(stream).await?;
-            } // 3.1.3.6
-
-            Ok(())
-        }
-
-        async fn read<R>(stream: &mut R) -> Result<Self, crate::v5::commons::error::MQTTError>
-        where
-            R: futures::AsyncReadExt + Unpin,
-        {
-            // Assumption is that the fixed header as been read already
-            String::read(stream).await.and_then(|x| {
-                if x == "MQTT".to_string() {
-                    return Ok(x);
-                }
-                return Err(MQTTError::MalformedPacket);
-            })?;
-
-            let mut packet = Self::default();
-            packet.version = Version::try_from(u8::read(stream).await?)?;
-
-            let flags = ConnectFlags::try_from(u8::read(stream).await?)?;
-            packet.keep_alive = u16::read(stream).await?;
-            packet.properties = ConnectProperties::read(stream).await?;
-            packet.client_id = String::read(stream).await?;
-
-            if flags.will_flag {
-                let mut will = Will::read(stream).await?;
-                will.retain = flags.will_retain;
-                will.qos = flags.will_qos;
-                packet.clean_start = flags.will_retain;
-                packet.will = Some(will);
-            }
-
-            if flags.username {
-                packet.username = Some(String::read(stream).await?)
-            };
-            if flags.password {
-                packet.password = Some(String::read(stream).await?)
-            };
-
-            Ok(packet)
-        }
-    }
-}
-
 #[derive(Debug, Default, Clone, Copy)]
 pub struct ConnectFlags {
     pub username: bool,
@@ -344,6 +235,36 @@ impl TryFrom<u8> for ConnectFlags {
     }
 }
 
+#[cfg(test)]
+mod tests {
+    use bytes::BytesMut;
+
+    use super::*;
+    use crate::v5::{codec::Decoded, commons::packet::Packet};
+
+    #[test]
+    fn decodes_the_clean_start_flag_whether_or_not_there_is_a_will() {
+        let connect = |clean_start, will_retain: Option<bool>| Connect {
+            clean_start,
+            will: will_retain.map(|retain| Will {
+                topic: String::from("status/sensor"),
+                retain,
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+
+        for (clean_start, will_retain) in [(true, None), (false, None), (true, Some(false)), (false, Some(true))] {
+            let mut buf = BytesMut::new();
+            Packet::Connect(connect(clean_start, will_retain)).encode(&mut buf).unwrap();
+            assert_eq!(
+                Packet::decode(&mut buf.freeze()).unwrap(),
+                Decoded::Packet(Packet::Connect(connect(clean_start, will_retain)))
+            );
+        }
+    }
+}
+
 // #[cfg(test)]
 // mod connect_packet {
 //     use super::*;
@@ -1,8 +1,16 @@
+use alloc::{
+    string::String,
+    vec::Vec,
+};
+
 use bytes::Bytes;
 use hivemqtt_macros::Length;
 
-use crate::v5::commons::{error::MQTTError, qos::QoS};
-use crate::v5::traits::utils::Utils;
+use crate::v5::commons::{
+    error::MQTTError,
+    property::{PropertyCheck, PropertyOwner},
+    qos::QoS,
+};
 
 use super::{Property, ReadData};
 
@@ -26,43 +34,41 @@ pub struct Will {
     #[bytes(no_id)]
     pub payload: Bytes,
     #[bytes(ignore)]
-    pub(super) qos: QoS,
+    pub qos: QoS,
     #[bytes(ignore)]
-    pub(super) retain: bool,
+    pub retain: bool,
 }
 
 impl ReadData for WillProperties {
     fn read_data(data: &mut Bytes) -> Result<Self, MQTTError> {
         let mut properties = Self::default();
+        let mut check = PropertyCheck::new(PropertyOwner::Will);
 
         loop {
-            let property = Property::read(data)?;
+            let property = check.read(data)?;
             match property {
-                Property::WillDelayInterval(value) => {
-                    Self::try_update(&mut properties.delay_interval, value)(property)?
-                }
+                Property::WillDelayInterval(value) => properties.delay_interval = value,
                 Property::PayloadFormatIndicator(value) => {
-                    Self::try_update(&mut properties.payload_format_indicator, value)(property)?
+                    properties.payload_format_indicator = value
                 }
                 Property::MessageExpiryInterval(value) => {
-                    Self::try_update(&mut properties.message_expiry_interval, value)(property)?
+                    properties.message_expiry_interval = value
+                }
+                Property::ContentType(ref value) => {
+                    properties.content_type = value.as_deref().map(|x| String::from(x))
+                }
+                Property::ResponseTopic(ref value) => {
+                    properties.response_topic = value.as_deref().map(|x| String::from(x))
+                }
+                Property::CorrelationData(ref value) => {
+                    properties.correlation_data =
+                        value.as_deref().map(|x| Bytes::from_iter(x.to_vec()))
                 }
-                Property::ContentType(ref value) => Self::try_update(
-                    &mut properties.content_type,
-                    value.as_deref().map(|x| String::from(x)),
-                )(property)?,
-                Property::ResponseTopic(ref value) => Self::try_update(
-                    &mut properties.response_topic,
-                    value.as_deref().map(|x| String::from(x)),
-                )(property)?,
-                Property::CorrelationData(ref value) => Self::try_update(
-                    &mut properties.correlation_data,
-                    value.as_deref().map(|x| Bytes::from_iter(x.to_vec())),
-                )(property)?,
                 Property::UserProperty(value) => {
                     properties.user_property.push(value.into_owned());
                 }
-                p => return Err(MQTTError::UnexpectedProperty(p.to_string(), "".to_string())),
+                // anything else is refused by `PropertyCheck::read` already
+                p => return Err(check.unexpected(&p)),
             }
 
             if data.is_empty() {
@@ -76,18 +82,19 @@ impl ReadData for WillProperties {
 
 impl ReadData for Will {}
 
-#[cfg(feature = "asyncx")]
-pub(crate) use asyncx::*;
 #[cfg(feature = "syncx")]
 pub(crate) use syncx::*;
 
 mod syncx {
-    use std::borrow::Cow;
+    use alloc::{borrow::Cow, string::String};
 
     use bytes::Bytes;
 
     use crate::v5::{
-        commons::{error::MQTTError, property::Property},
+        commons::{
+            error::MQTTError,
+            property::{Property, PropertyCheck, PropertyOwner},
+        },
         traits::{
             bufferio::BufferIO,
             read_data::ReadData,
@@ -104,18 +111,32 @@ mod syncx {
 
         fn write(&self, buf: &mut bytes::BytesMut) -> Result<(), MQTTError> {
             self.encode(buf)?; // 3.1.3.2.1
-
-            Property::WillDelayInterval(self.delay_interval).write(buf)?; // 3.1.3.2.2
-            Property::PayloadFormatIndicator(self.payload_format_indicator).write(buf)?; // 3.1.3.2.3
-            Property::MessageExpiryInterval(self.message_expiry_interval).write(buf)?; // 3.1.3.2.4
-            Property::ContentType(self.content_type.as_deref().map(Cow::Borrowed)).write(buf)?; // 3.1.3.2.5
-            Property::ResponseTopic(self.response_topic.as_deref().map(Cow::Borrowed))
-                .write(buf)?; // 3.1.3.2.6
-            Property::CorrelationData(self.correlation_data.as_deref().map(Cow::Borrowed))
-                .write(buf)?; // 3.1.3.2.7
+            let mut check = PropertyCheck::new(PropertyOwner::Will);
+
+            check.write(Property::WillDelayInterval(self.delay_interval), buf)?; // 3.1.3.2.2
+            check.write(
+                Property::PayloadFormatIndicator(self.payload_format_indicator),
+                buf,
+            )?; // 3.1.3.2.3
+            check.write(
+                Property::MessageExpiryInterval(self.message_expiry_interval),
+                buf,
+            )?; // 3.1.3.2.4
+            check.write(
+                Property::ContentType(self.content_type.as_deref().map(Cow::Borrowed)),
+                buf,
+            )?; // 3.1.3.2.5
+            check.write(
+                Property::ResponseTopic(self.response_topic.as_deref().map(Cow::Borrowed)),
+                buf,
+            )?; // 3.1.3.2.6
+            check.write(
+                Property::CorrelationData(self.correlation_data.as_deref().map(Cow::Borrowed)),
+                buf,
+            )?; // 3.1.3.2.7
             self.user_property
                 .iter()
-                .try_for_each(|kv| Property::UserProperty(Cow::Borrowed(kv)).write(buf))?; // 3.1.3.2.8
+                .try_for_each(|kv| check.write(Property::UserProperty(Cow::Borrowed(kv)), buf))?; // 3.1.3.2.8
 
             Ok(())
         }
@@ -143,8 +164,8 @@ mod syncx {
     impl BufferIO for Will {
         fn write(&self, buf: &mut bytes::BytesMut) -> Result<(), MQTTError> {
             self.properties.write(buf)?;
-            self.topic.write(buf); // 3.1.3.3
-            self.payload.write(buf); // 3.1.3.4
+            self.topic.write(buf)?; // 3.1.3.3
+            self.payload.write(buf)?; // 3.1.3.4
             Ok(())
         }
 
@@ -162,105 +183,3 @@ mod syncx {
         }
     }
 }
-
-mod asyncx {
-    use std::borrow::Cow;
-
-    use bytes::Bytes;
-
-    use crate::v5::commons::property::Property;
-    use crate::v5::{
-        commons::error::MQTTError,
-        traits::{
-            asyncx::{read::Read, write::Write},
-            {read_data::ReadData, streamio::StreamIO},
-        },
-    };
-
-    use super::{Will, WillProperties};
-
-    impl StreamIO for WillProperties {
-        fn length(&self) -> usize {
-            self.len()
-        }
-
-        async fn read<R>(stream: &mut R) -> Result<Self, MQTTError>
-        where
-            R: futures::AsyncReadExt + Unpin,
-        {
-            let Some(len) = Self::parse_len(stream).await? else {
-                return Ok(Self::default());
-            };
-
-            let mut data = Vec::with_capacity(len);
-            stream.read_exact(&mut data).await?;
-
-            let mut data = Bytes::copy_from_slice(&data);
-
-            Self::read_data(&mut data)
-        }
-
-        async fn write<W>(&self, stream: &mut W) -> Result<(), MQTTError>
-        where
-            W: futures::AsyncWriteExt + Unpin,
-        {
-            self.encode(stream).await?;
-
-            Property::WillDelayInterval(self.delay_interval)
-                .write(stream)
-                .await?; // 3.1.3.2.2
-            Property::PayloadFormatIndicator(self.payload_format_indicator)
-                .write(stream)
-                .await?; // 3.1.3.2.3
-            Property::MessageExpiryInterval(self.message_expiry_interval)
-                .write(stream)
-                .await?; // 3.1.3.2.4
-            Property::ContentType(self.content_type.as_deref().map(Cow::Borrowed))
-                .write(stream)
-                .await?; // 3.1.3.2.5
-            Property::ResponseTopic(self.response_topic.as_deref().map(Cow::Borrowed))
-                .write(stream)
-                .await?; // 3.1.3.2.6
-            Property::CorrelationData(self.correlation_data.as_deref().map(Cow::Borrowed))
-                .write(stream)
-                .await?; // 3.1.3.2.7
-            for kv in &self.user_property {
-                Property::UserProperty(Cow::Borrowed(kv))
-                    .write(stream)
-                    .await?; // 3.1.3.2.8
-            }
-
-            Ok(())
-        }
-    }
-
-    impl StreamIO for Will {
-        fn length(&self) -> usize {
-            self.len() + self.properties.variable_length() + self.properties.length()
-        }
-
-        async fn write<W>(&self, stream: &mut W) -> Result<(), MQTTError>
-        where
-            W: futures::AsyncWriteExt + Unpin,
-        {
-            self.properties.write(stream).await?;
-            self.topic.write(stream).await?;
-            self.payload.to_vec().write(stream).await?;
-
-            Ok(())
-        }
-
-        async fn read<R>(stream: &mut R) -> Result<Self, MQTTError>
-        where
-            R: futures::AsyncReadExt + Unpin,
-        {
-            let mut will = Self::default();
-
-            will.properties = WillProperties::read(stream).await?;
-            will.topic = String::read(stream).await?;
-            will.payload = Bytes::from_iter(Vec::read(stream).await?);
-
-            Ok(will)
-        }
-    }
-}
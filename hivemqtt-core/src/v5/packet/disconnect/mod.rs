@@ -15,8 +15,6 @@ pub struct Disconnect {
     pub properties: DisconnectProperties,
 }
 
-#[cfg(feature = "asyncx")]
-pub(crate) use asyncx::*;
 #[cfg(feature = "syncx")]
 pub(crate) use syncx::*;
 
@@ -51,20 +49,24 @@ mod syncx {
         fn write(&self, buf: &mut BytesMut) -> Result<(), MQTTError> {
             FixedHeader::new(PacketType::Disconnect, 0, self.length()).write(buf)?;
 
-            u8::from(self.reason_code).write(buf);
-
             if self.reason_code == DisconnectReasonCode::NormalDisconnection
                 && self.properties.length() == 0
             {
                 return Ok(());
             }
 
+            u8::from(self.reason_code).write(buf)?;
             self.properties.write(buf)?;
             Ok(())
         }
 
         fn read(buf: &mut Bytes) -> Result<Self, MQTTError> {
             let mut packet = Self::default();
+            // a Remaining Length of 0 means a Normal disconnection without properties (3.14.2.1)
+            if !buf.has_remaining() {
+                return Ok(packet);
+            }
+
             packet.reason_code =
                 DisconnectReasonCode::try_from(u8::read(buf)?).map_err(MQTTError::UnknownData)?;
 
@@ -77,64 +79,36 @@ mod syncx {
     }
 }
 
-mod asyncx {
+#[cfg(test)]
+mod tests {
+    use bytes::BytesMut;
 
-    use crate::v5::{
-        commons::error::MQTTError,
-        traits::{
-            asyncx::{read::Read, write::Write},
-            streamio::StreamIO,
-        },
-    };
+    use super::*;
+    use crate::v5::{codec::Decoded, commons::packet::Packet};
 
-    use super::{Disconnect, DisconnectProperties, DisconnectReasonCode, FixedHeader, PacketType};
+    fn round_trip(packet: Disconnect, expected: &[u8]) {
+        let mut buf = BytesMut::new();
+        Packet::Disconnect(packet).encode(&mut buf).unwrap();
+        assert_eq!(&buf[..], expected);
 
-    impl StreamIO for Disconnect {
-        // the length of the dsiconnect variable header
-        fn length(&self) -> usize {
-            // The Reason Code and Property Length can be omitted if the Reason Code is 0x00 (Normal disconnecton) and there are no Properties. In this case the DISCONNECT has a Remaining Length of 0.
-            if self.reason_code == DisconnectReasonCode::NormalDisconnection
-                && self.properties.length() == 0
-            {
-                return 0;
-            }
-            return self.properties.length() + self.properties.variable_length() + 1;
-            // 1 is for the reason code abovegit stat
-        }
-
-        async fn write<W>(&self, stream: &mut W) -> Result<(), crate::v5::commons::error::MQTTError>
-        where
-            W: futures::AsyncWriteExt + Unpin,
-        {
-            FixedHeader::new(PacketType::Disconnect, 0, self.length())
-                .write(stream)
-                .await?;
-
-            u8::from(self.reason_code).write(stream).await?;
-
-            if self.reason_code == DisconnectReasonCode::NormalDisconnection
-                && self.properties.length() == 0
-            {
-                return Ok(());
-            }
-
-            self.properties.write(stream).await?;
-            Ok(())
-        }
-
-        async fn read<R>(stream: &mut R) -> Result<Self, crate::v5::commons::error::MQTTError>
-        where
-            R: futures::AsyncReadExt + Unpin,
-        {
-            let mut packet = Self::default();
-
-            packet.reason_code = DisconnectReasonCode::try_from(u8::read(stream).await?)
-                .map_err(MQTTError::UnknownData)?;
-
-            packet.properties = DisconnectProperties::read(stream).await?;
+        let Ok(Decoded::Packet(Packet::Disconnect(read))) = Packet::decode(&mut buf.freeze()) else {
+            panic!("expected a DISCONNECT");
+        };
+        let mut buf = BytesMut::new();
+        Packet::Disconnect(read).encode(&mut buf).unwrap();
+        assert_eq!(&buf[..], expected);
+    }
 
-            Ok(packet)
-        }
+    #[test]
+    fn omits_the_reason_code_of_a_normal_disconnection_without_properties() {
+        round_trip(Disconnect::default(), b"\xe0\x00");
+        round_trip(
+            Disconnect {
+                reason_code: DisconnectReasonCode::ReceiveMaximumExceeded,
+                ..Default::default()
+            },
+            b"\xe0\x02\x93\x00",
+        );
     }
 }
 
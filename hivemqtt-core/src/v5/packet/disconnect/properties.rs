@@ -1,7 +1,12 @@
+use alloc::{
+    string::String,
+    vec::Vec,
+};
+
 use bytes::Bytes;
 use hivemqtt_macros::Length;
 
-use crate::v5::{commons::error::MQTTError, traits::utils::Utils};
+use crate::v5::commons::{error::MQTTError, packet_type::PacketType, property::PropertyCheck};
 
 use super::{Property, ReadData};
 
@@ -13,31 +18,27 @@ pub struct DisconnectProperties {
     pub server_reference: Option<String>,
 }
 
-#[cfg(feature = "asyncx")]
-pub(crate) use asyncx::*;
 #[cfg(feature = "syncx")]
 pub(crate) use syncx::*;
 
 impl ReadData for DisconnectProperties {
     fn read_data(data: &mut Bytes) -> Result<Self, MQTTError> {
         let mut props = Self::default();
+        let mut check = PropertyCheck::new(PacketType::Disconnect);
 
         loop {
-            let property = Property::read(data)?;
+            let property = check.read(data)?;
             match property {
-                Property::ReasonString(ref v) => Self::try_update(
-                    &mut props.reason_string,
-                    v.as_deref().map(String::from),
-                )(property)?,
+                Property::ReasonString(ref v) => {
+                    props.reason_string = v.as_deref().map(String::from)
+                }
                 Property::UserProperty(v) => props.user_property.push(v.into_owned()),
-                Property::SessionExpiryInterval(v) => {
-                    Self::try_update(&mut props.session_expiry_interval, v)(property)?
+                Property::SessionExpiryInterval(v) => props.session_expiry_interval = v,
+                Property::ServerReference(ref v) => {
+                    props.server_reference = v.as_deref().map(String::from)
                 }
-                Property::ServerReference(ref v) => Self::try_update(
-                    &mut props.server_reference,
-                    v.as_deref().map(String::from),
-                )(property)?,
-                p => return Err(MQTTError::UnexpectedProperty(p.to_string(), "".to_string())),
+                // anything else is refused by `PropertyCheck::read` already
+                p => return Err(check.unexpected(&p)),
             }
 
             if data.is_empty() {
@@ -50,10 +51,14 @@ impl ReadData for DisconnectProperties {
 }
 
 mod syncx {
-    use std::borrow::Cow;
+    use alloc::borrow::Cow;
 
     use crate::v5::{
-        commons::{error::MQTTError, property::Property},
+        commons::{
+            error::MQTTError,
+            packet_type::PacketType,
+            property::{Property, PropertyCheck},
+        },
         traits::bufferio::BufferIO,
     };
 
@@ -66,55 +71,23 @@ mod syncx {
 
         fn write(&self, buf: &mut bytes::BytesMut) -> Result<(), MQTTError> {
             self.encode(buf)?;
-
-            Property::SessionExpiryInterval(self.session_expiry_interval).write(buf)?;
-            Property::ReasonString(self.reason_string.as_deref().map(Cow::Borrowed)).write(buf)?;
+            let mut check = PropertyCheck::new(PacketType::Disconnect);
+
+            check.write(
+                Property::SessionExpiryInterval(self.session_expiry_interval),
+                buf,
+            )?;
+            check.write(
+                Property::ReasonString(self.reason_string.as_deref().map(Cow::Borrowed)),
+                buf,
+            )?;
             self.user_property
                 .iter()
-                .try_for_each(|up| Property::UserProperty(Cow::Borrowed(&up)).write(buf))?;
-            Property::ServerReference(self.server_reference.as_deref().map(Cow::Borrowed))
-                .write(buf)?;
-
-            Ok(())
-        }
-    }
-}
-
-mod asyncx {
-    use std::borrow::Cow;
-
-    use crate::v5::{commons::property::Property, traits::streamio::StreamIO};
-
-    use super::DisconnectProperties;
-
-    impl StreamIO for DisconnectProperties {
-        fn length(&self) -> usize {
-            self.len()
-        }
-
-        async fn write<W>(&self, stream: &mut W) -> Result<(), crate::v5::commons::error::MQTTError>
-        where
-            W: futures::AsyncWriteExt + Unpin,
-        {
-            self.encode(stream).await?;
-
-            Property::SessionExpiryInterval(self.session_expiry_interval)
-                .write(stream)
-                .await?;
-
-            Property::ReasonString(self.reason_string.as_deref().map(Cow::Borrowed))
-                .write(stream)
-                .await?;
-
-            for kv in &self.user_property {
-                Property::UserProperty(Cow::Borrowed(&kv))
-                    .write(stream)
-                    .await?
-            }
-
-            Property::ServerReference(self.server_reference.as_deref().map(Cow::Borrowed))
-                .write(stream)
-                .await?;
+                .try_for_each(|up| check.write(Property::UserProperty(Cow::Borrowed(&up)), buf))?;
+            check.write(
+                Property::ServerReference(self.server_reference.as_deref().map(Cow::Borrowed)),
+                buf,
+            )?;
 
             Ok(())
         }
@@ -1,5 +1,7 @@
 use hivemqtt_macros::FromU8;
 
+use crate::v5::commons::{conformance, error::MQTTError};
+
 #[repr(u8)]
 #[derive(Debug, Clone, Copy, Default, PartialEq, Eq, FromU8)]
 pub enum DisconnectReasonCode {
@@ -23,7 +25,7 @@ pub enum DisconnectReasonCode {
     MessageRateTooHigh = 150,
     QuotaExceeded = 151,
     AdministrativeAction = 152,
-    PayloadFormatIndicator = 153,
+    PayloadFormatInvalid = 153,
     RetainNotSupported = 154,
     QoSNotSupported = 155,
     UseAnotherServer = 156,
@@ -31,6 +33,55 @@ pub enum DisconnectReasonCode {
     SharedSubscriptionsNotSupported = 158,
     ConnectionRateExceeded = 159,
     MaximumConnectTime = 160,
-    SubscriptionIdentifiers = 161,
+    SubscriptionIdentifiersNotSupported = 161,
     WildcardSubscriptionsNotSupported = 162,
-}
\ No newline at end of file
+}
+
+impl From<&MQTTError> for DisconnectReasonCode {
+    /// The reason code to close the connection with, when `error` is found on an incoming packet
+    fn from(error: &MQTTError) -> Self {
+        match error {
+            MQTTError::MalformedPacket
+            | MQTTError::IncompletePacket
+            | MQTTError::IncompleteData(..)
+            | MQTTError::InsufficientBytes
+            | MQTTError::UnknownProperty(_)
+            | MQTTError::PacketIdRequired
+            | MQTTError::PublishPacketId
+            | MQTTError::PayloadTooLong
+            | MQTTError::InvalidProperty(_)
+            | MQTTError::UnknownData(_)
+            | MQTTError::NonMinimalVarInt
+            | MQTTError::NullCharacter => Self::MalformedPacket,
+            // 3.3.1.2 A PUBLISH Packet MUST NOT have both QoS bits set to 1
+            MQTTError::UnsupportedQoS(_) => Self::MalformedPacket,
+            // 2.2.2.2 a property the packet cannot carry, or included more than once
+            MQTTError::UnexpectedProperty(..) | MQTTError::DuplicateProperty(..) => {
+                Self::ProtocolError
+            }
+            MQTTError::ProtocolError(_)
+            | MQTTError::VersionNotSupported(_)
+            | MQTTError::PacketIdConflict(_)
+            | MQTTError::UnknownPacketId(_) => Self::ProtocolError,
+            MQTTError::InvalidTopic(_) => Self::TopicNameInvalid,
+            MQTTError::InvalidTopicAlias(..) | MQTTError::UnknownTopicAlias(_) => {
+                Self::TopicAliasInvalid
+            }
+            MQTTError::MaxPacketSizeExceed(_) => Self::PacketTooLarge,
+            MQTTError::ReceiveMaximumExceeded(_) => Self::ReceiveMaximumExceeded,
+            MQTTError::Intercepted(_) => Self::ImplementationSpecificError,
+            MQTTError::Conformance(rule) => match *rule {
+                conformance::VARINT_NOT_MINIMAL
+                | conformance::UTF8_NULL_CHARACTER
+                | conformance::PUBLISH_QOS3 => Self::MalformedPacket,
+                conformance::SERVER_PACKET_TOO_LARGE => Self::PacketTooLarge,
+                conformance::SERVER_RECEIVE_MAXIMUM_EXCEEDED => Self::ReceiveMaximumExceeded,
+                conformance::TOPIC_ALIAS_ZERO | conformance::SERVER_TOPIC_ALIAS_TOO_LARGE => {
+                    Self::TopicAliasInvalid
+                }
+                _ => Self::ProtocolError,
+            },
+            _ => Self::UnspecifiedError,
+        }
+    }
+}
@@ -1,14 +1,14 @@
-pub(crate) mod connect;
-pub(crate) mod connack;
-pub(crate) mod publish;
-pub(crate) mod puback;
-pub(crate) mod pubrec;
-pub(crate) mod pubrel;
-pub(crate) mod pubcomp;
-pub(crate) mod subscribe;
-pub(crate) mod suback;
-pub(crate) mod unsubscribe;
-pub(crate) mod unsuback;
-pub(crate) mod ping;
-pub(crate) mod disconnect;
-pub(crate) mod auth;
\ No newline at end of file
+pub mod connect;
+pub mod connack;
+pub mod publish;
+pub mod puback;
+pub mod pubrec;
+pub mod pubrel;
+pub mod pubcomp;
+pub mod subscribe;
+pub mod suback;
+pub mod unsubscribe;
+pub mod unsuback;
+pub mod ping;
+pub mod disconnect;
+pub mod auth;
\ No newline at end of file
@@ -9,8 +9,6 @@ pub struct PingReq {}
 #[derive(Debug, Default, PartialEq, Eq)]
 pub struct PingResp;
 
-#[cfg(feature = "asyncx")]
-pub(crate) use asyncx::*;
 #[cfg(feature = "syncx")]
 pub(crate) use syncx::*;
 
@@ -44,47 +42,3 @@ mod syncx {
         }
     }
 }
-
-mod asyncx {
-    use crate::v5::traits::streamio::StreamIO;
-
-    use super::{FixedHeader, PacketType, PingReq, PingResp};
-
-    impl StreamIO for PingReq {
-        async fn write<W>(&self, stream: &mut W) -> Result<(), crate::v5::commons::error::MQTTError>
-        where
-            W: futures::AsyncWriteExt + Unpin,
-        {
-            FixedHeader::new(PacketType::PingReq, 0, 0)
-                .write(stream)
-                .await
-        }
-
-        async fn read<R>(stream: &mut R) -> Result<Self, crate::v5::commons::error::MQTTError>
-        where
-            R: futures::AsyncReadExt + Unpin,
-            Self: Default,
-        {
-            Ok(Self::default())
-        }
-    }
-
-    impl StreamIO for PingResp {
-        async fn write<W>(&self, stream: &mut W) -> Result<(), crate::v5::commons::error::MQTTError>
-        where
-            W: futures::AsyncWriteExt + Unpin,
-        {
-            FixedHeader::new(PacketType::PingResp, 0, 0)
-                .write(stream)
-                .await
-        }
-
-        async fn read<R>(stream: &mut R) -> Result<Self, crate::v5::commons::error::MQTTError>
-        where
-            R: futures::AsyncReadExt + Unpin,
-            Self: Default,
-        {
-            Ok(Self::default())
-        }
-    }
-}
@@ -6,21 +6,21 @@ use crate::v5::{
     traits::read_data::ReadData,
 };
 
-#[derive(Debug, Default, PartialEq, Eq)]
+#[derive(Debug, Default, PartialEq, Eq, Clone)]
 pub struct PubAck {
-    pub(crate) pkid: u16,
-    pub(crate) reason_code: PubAckReasonCode,
-    pub(crate) properties: PubAckProperties,
+    pub pkid: u16,
+    pub reason_code: PubAckReasonCode,
+    pub properties: PubAckProperties,
 }
 
-#[cfg(feature = "asyncx")]
-pub(crate) use asyncx::*;
 #[cfg(feature = "syncx")]
 pub(crate) use syncx::*;
 
 impl ReadData for PubAck {}
 
 mod syncx {
+    use alloc::format;
+
     use crate::v5::{
         commons::error::MQTTError,
         traits::{
@@ -34,7 +34,7 @@ mod syncx {
     impl BufferIO for PubAck {
         /// Length of the Variable Header, encoded as Variable Byte Integer
         fn length(&self) -> usize {
-            let mut len = std::mem::size_of::<u16>(); // packet identifier
+            let mut len = core::mem::size_of::<u16>(); // packet identifier
 
             // only add reason code if there's no properties
             if self.reason_code == PubAckReasonCode::Success && self.properties.length() == 0 {
@@ -48,12 +48,12 @@ mod syncx {
 
         fn write(&self, buf: &mut bytes::BytesMut) -> Result<(), MQTTError> {
             FixedHeader::new(PacketType::PubAck, 0, self.length()).write(buf)?;
-            self.pkid.write(buf);
+            self.pkid.write(buf)?;
             if self.reason_code == PubAckReasonCode::Success && self.properties.length() == 0 {
                 return Ok(());
             }
 
-            u8::from(self.reason_code).write(buf);
+            u8::from(self.reason_code).write(buf)?;
             self.properties.write(buf)?;
             Ok(())
         }
@@ -79,75 +79,6 @@ mod syncx {
     }
 }
 
-mod asyncx {
-    use crate::v5::{
-        commons::error::MQTTError,
-        traits::{
-            asyncx::{read::Read, write::Write},
-            streamio::StreamIO,
-        },
-    };
-
-    use super::{FixedHeader, PacketType, PubAck, PubAckProperties, PubAckReasonCode};
-
-    impl StreamIO for PubAck {
-        /// Length of the Variable Header, encoded as Variable Byte Integer
-        fn length(&self) -> usize {
-            let mut len = std::mem::size_of::<u16>(); // packet identifier
-
-            // only add reason code if there's no properties
-            if self.reason_code == PubAckReasonCode::Success && self.properties.length() == 0 {
-                return len;
-            }
-
-            len += 1; // reason code
-            len += self.properties.length() + self.properties.variable_length();
-            len
-        }
-
-        async fn write<W>(&self, stream: &mut W) -> Result<(), crate::v5::commons::error::MQTTError>
-        where
-            W: futures::AsyncWriteExt + Unpin,
-        {
-            FixedHeader::new(PacketType::PubAck, 0, self.length())
-                .write(stream)
-                .await?;
-            self.pkid.write(stream).await?;
-            if self.reason_code == PubAckReasonCode::Success && self.properties.length() == 0 {
-                return Ok(());
-            }
-
-            u8::from(self.reason_code).write(stream).await?;
-            self.properties.write(stream).await?;
-
-            Ok(())
-        }
-
-        async fn read_with_fixedheader<R>(
-            stream: &mut R,
-            header: &FixedHeader,
-        ) -> Result<Self, crate::v5::commons::error::MQTTError>
-        where
-            R: futures::AsyncReadExt + Unpin,
-            Self: Default,
-        {
-            let mut packet = Self::default();
-            packet.pkid = u16::read(stream).await?;
-
-            if header.remaining_length == 2 {
-                packet.reason_code = PubAckReasonCode::Success;
-                return Ok(packet);
-            }
-
-            packet.reason_code = PubAckReasonCode::try_from(u8::read(stream).await?)
-                .map_err(|e| MQTTError::UnknownData(format!("Uknown reason code: {e}")))?;
-            packet.properties = PubAckProperties::read(stream).await?;
-
-            Ok(packet)
-        }
-    }
-}
-
 // #[cfg(test)]
 // mod tests {
 //     use bytes::{Bytes, BytesMut};
@@ -1,12 +1,15 @@
+use alloc::{
+    string::String,
+    vec::Vec,
+};
+
 use bytes::Bytes;
 use hivemqtt_macros::{FromU8, Length};
 
-use crate::v5::{
-    commons::error::MQTTError,
-    traits::{read_data::ReadData, utils::Utils},
-};
+use crate::v5::{commons::error::MQTTError, traits::read_data::ReadData};
 
 use super::Property;
+use crate::v5::commons::{packet_type::PacketType, property::PropertyCheck};
 
 #[repr(u8)]
 #[derive(Debug, PartialEq, Eq, Clone, Copy, FromU8, Default)]
@@ -23,12 +26,10 @@ pub enum PubAckReasonCode {
     PayloadFormatInvalid = 153,
 }
 
-#[cfg(feature = "asyncx")]
-pub(crate) use asyncx::*;
 #[cfg(feature = "syncx")]
 pub(crate) use syncx::*;
 
-#[derive(Debug, Length, Default, PartialEq, Eq)]
+#[derive(Debug, Length, Default, PartialEq, Eq, Clone)]
 pub struct PubAckProperties {
     pub reason_string: Option<String>,
     pub user_property: Vec<(String, String)>,
@@ -37,17 +38,18 @@ pub struct PubAckProperties {
 impl ReadData for PubAckProperties {
     fn read_data(data: &mut Bytes) -> Result<Self, MQTTError> {
         let mut props = Self::default();
+        let mut check = PropertyCheck::new(PacketType::PubAck);
 
         loop {
-            let property = Property::read(data)?;
+            let property = check.read(data)?;
 
             match property {
-                Property::ReasonString(ref v) => Self::try_update(
-                    &mut props.reason_string,
-                    v.as_deref().map(String::from),
-                )(property)?,
+                Property::ReasonString(ref v) => {
+                    props.reason_string = v.as_deref().map(String::from)
+                }
                 Property::UserProperty(value) => props.user_property.push(value.into_owned()),
-                p => return Err(MQTTError::UnexpectedProperty(p.to_string(), "".to_string())),
+                // anything else is refused by `PropertyCheck::read` already
+                p => return Err(check.unexpected(&p)),
             }
 
             if data.is_empty() {
@@ -60,10 +62,14 @@ impl ReadData for PubAckProperties {
 }
 
 mod syncx {
-    use std::borrow::Cow;
+    use alloc::borrow::Cow;
 
     use crate::v5::{
-        commons::{error::MQTTError, property::Property},
+        commons::{
+            error::MQTTError,
+            packet_type::PacketType,
+            property::{Property, PropertyCheck},
+        },
         traits::bufferio::BufferIO,
     };
 
@@ -76,46 +82,15 @@ mod syncx {
 
         fn write(&self, buf: &mut bytes::BytesMut) -> Result<(), MQTTError> {
             self.encode(buf)?;
+            let mut check = PropertyCheck::new(PacketType::PubAck);
 
-            Property::ReasonString(self.reason_string.as_deref().map(Cow::Borrowed)).write(buf)?;
+            check.write(
+                Property::ReasonString(self.reason_string.as_deref().map(Cow::Borrowed)),
+                buf,
+            )?;
             self.user_property
                 .iter()
-                .try_for_each(|kv| Property::UserProperty(Cow::Borrowed(kv)).write(buf))?;
-            Ok(())
-        }
-    }
-}
-
-mod asyncx {
-    use std::borrow::Cow;
-
-    use crate::v5::{
-        commons::{error::MQTTError, property::Property},
-        traits::streamio::StreamIO,
-    };
-
-    use super::PubAckProperties;
-
-    impl StreamIO for PubAckProperties {
-        fn length(&self) -> usize {
-            self.len()
-        }
-
-        async fn write<W>(&self, stream: &mut W) -> Result<(), MQTTError>
-        where
-            W: futures::AsyncWriteExt + Unpin,
-        {
-            self.encode(stream).await?;
-
-            Property::ReasonString(self.reason_string.as_deref().map(Cow::Borrowed))
-                .write(stream)
-                .await?;
-            for kv in &self.user_property {
-                Property::UserProperty(Cow::Borrowed(kv))
-                    .write(stream)
-                    .await?;
-            }
-
+                .try_for_each(|kv| check.write(Property::UserProperty(Cow::Borrowed(kv)), buf))?;
             Ok(())
         }
     }
@@ -1,21 +1,19 @@
 mod properties;
 
-pub(crate) use properties::{PubCompProperties, PubCompReasonCode};
+pub use properties::{PubCompProperties, PubCompReasonCode};
 
 use crate::v5::{
     commons::{fixed_header::FixedHeader, packet_type::PacketType, property::Property},
     traits::read_data::ReadData,
 };
 
-#[derive(Debug, Default, PartialEq, Eq)]
+#[derive(Debug, Default, PartialEq, Eq, Clone)]
 pub struct PubComp {
-    pub(crate) pkid: u16,
-    pub(crate) reason_code: PubCompReasonCode,
-    pub(crate) properties: PubCompProperties,
+    pub pkid: u16,
+    pub reason_code: PubCompReasonCode,
+    pub properties: PubCompProperties,
 }
 
-#[cfg(feature = "asyncx")]
-pub(crate) use asyncx::*;
 #[cfg(feature = "syncx")]
 pub(crate) use syncx::*;
 
@@ -37,7 +35,7 @@ mod syncx {
 
     impl BufferIO for PubComp {
         fn length(&self) -> usize {
-            let mut len = std::mem::size_of::<u16>(); // packet identifier
+            let mut len = core::mem::size_of::<u16>(); // packet identifier
             if self.reason_code == PubCompReasonCode::Success && self.properties.length() == 0 {
                 return len;
             }
@@ -48,12 +46,12 @@ mod syncx {
         fn write(&self, buf: &mut bytes::BytesMut) -> Result<(), MQTTError> {
             FixedHeader::new(PacketType::PubComp, 0, self.length()).write(buf)?;
 
-            self.pkid.write(buf);
+            self.pkid.write(buf)?;
             if self.properties.length() == 0 && self.reason_code == PubCompReasonCode::Success {
                 return Ok(());
             }
 
-            u8::from(self.reason_code).write(buf);
+            u8::from(self.reason_code).write(buf)?;
             self.properties.write(buf)?;
             Ok(())
         }
@@ -79,74 +77,6 @@ mod syncx {
     }
 }
 
-mod asyncx {
-    use crate::v5::{
-        commons::error::MQTTError,
-        traits::{
-            asyncx::{read::Read, write::Write},
-            streamio::StreamIO,
-        },
-    };
-
-    use super::{
-        properties::{PubCompProperties, PubCompReasonCode},
-        FixedHeader, PacketType, PubComp,
-    };
-
-    impl StreamIO for PubComp {
-        fn length(&self) -> usize {
-            let mut len = std::mem::size_of::<u16>(); // packet identifier
-            if self.reason_code == PubCompReasonCode::Success && self.properties.length() == 0 {
-                return len;
-            }
-            len += 1 + self.properties.length() + self.properties.variable_length(); // reason code
-            len
-        }
-
-        async fn write<W>(&self, stream: &mut W) -> Result<(), crate::v5::commons::error::MQTTError>
-        where
-            W: futures::AsyncWriteExt + Unpin,
-        {
-            FixedHeader::new(PacketType::PubComp, 0, self.length())
-                .write(stream)
-                .await?;
-
-            self.pkid.write(stream).await?;
-            if self.properties.length() == 0 && self.reason_code == PubCompReasonCode::Success {
-                return Ok(());
-            }
-
-            u8::from(self.reason_code).write(stream).await?;
-            self.properties.write(stream).await?;
-
-            Ok(())
-        }
-
-        async fn read_with_fixedheader<R>(
-            stream: &mut R,
-            header: &FixedHeader,
-        ) -> Result<Self, crate::v5::commons::error::MQTTError>
-        where
-            R: futures::AsyncReadExt + Unpin,
-            Self: Default,
-        {
-            let mut packet = Self::default();
-            packet.pkid = u16::read(stream).await?;
-
-            if header.remaining_length == 2 {
-                packet.reason_code = PubCompReasonCode::Success;
-                return Ok(packet);
-            }
-
-            packet.reason_code = PubCompReasonCode::try_from(u8::read(stream).await?)
-                .map_err(|e| MQTTError::UnknownData(e))?;
-            packet.properties = PubCompProperties::read(stream).await?;
-
-            Ok(packet)
-        }
-    }
-}
-
 // #[cfg(test)]
 // mod tests {
 //     use bytes::{Bytes, BytesMut};
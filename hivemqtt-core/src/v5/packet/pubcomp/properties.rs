@@ -1,12 +1,17 @@
+use alloc::{
+    string::String,
+    vec::Vec,
+};
+
 use bytes::Bytes;
 use hivemqtt_macros::{FromU8, Length};
 
-use crate::v5::{commons::error::MQTTError, traits::utils::Utils};
+use crate::v5::commons::{error::MQTTError, packet_type::PacketType, property::PropertyCheck};
 
 use super::{Property, ReadData};
 
 #[derive(Debug, PartialEq, Eq, Default, FromU8, Clone, Copy)]
-pub(crate) enum PubCompReasonCode {
+pub enum PubCompReasonCode {
     #[default]
     Success = 0,
     PacketIdentifierNotFound = 146,
@@ -21,17 +26,18 @@ pub struct PubCompProperties {
 impl ReadData for PubCompProperties {
     fn read_data(data: &mut Bytes) -> Result<Self, MQTTError> {
         let mut props = Self::default();
+        let mut check = PropertyCheck::new(PacketType::PubComp);
 
         loop {
-            let property = Property::read(data)?;
+            let property = check.read(data)?;
 
             match property {
-                Property::ReasonString(ref v) => Self::try_update(
-                    &mut props.reason_string,
-                    v.as_deref().map(String::from),
-                )(property)?,
+                Property::ReasonString(ref v) => {
+                    props.reason_string = v.as_deref().map(String::from)
+                }
                 Property::UserProperty(v) => props.user_property.push(v.into_owned()),
-                p => return Err(MQTTError::UnexpectedProperty(p.to_string(), "".to_string())),
+                // anything else is refused by `PropertyCheck::read` already
+                p => return Err(check.unexpected(&p)),
             };
 
             if data.is_empty() {
@@ -44,10 +50,14 @@ impl ReadData for PubCompProperties {
 }
 
 mod syncx {
-    use std::borrow::Cow;
+    use alloc::borrow::Cow;
 
     use crate::v5::{
-        commons::{error::MQTTError, property::Property},
+        commons::{
+            error::MQTTError,
+            packet_type::PacketType,
+            property::{Property, PropertyCheck},
+        },
         traits::bufferio::BufferIO,
     };
 
@@ -60,47 +70,15 @@ mod syncx {
 
         fn write(&self, buf: &mut bytes::BytesMut) -> Result<(), MQTTError> {
             self.encode(buf)?;
+            let mut check = PropertyCheck::new(PacketType::PubComp);
 
-            Property::ReasonString(self.reason_string.as_deref().map(Cow::Borrowed)).write(buf)?;
+            check.write(
+                Property::ReasonString(self.reason_string.as_deref().map(Cow::Borrowed)),
+                buf,
+            )?;
             self.user_property
                 .iter()
-                .try_for_each(|kv| Property::UserProperty(Cow::Borrowed(kv)).write(buf))?;
-
-            Ok(())
-        }
-    }
-}
-
-mod asyncx {
-    use std::borrow::Cow;
-
-    use crate::v5::{
-        commons::{error::MQTTError, property::Property},
-        traits::streamio::StreamIO,
-    };
-
-    use super::PubCompProperties;
-
-    impl StreamIO for PubCompProperties {
-        fn length(&self) -> usize {
-            self.len()
-        }
-
-        async fn write<W>(&self, stream: &mut W) -> Result<(), MQTTError>
-        where
-            W: futures::AsyncWriteExt + Unpin,
-        {
-            self.encode(stream).await?;
-
-            Property::ReasonString(self.reason_string.as_deref().map(Cow::Borrowed))
-                .write(stream)
-                .await?;
-
-            for kv in &self.user_property {
-                Property::UserProperty(Cow::Borrowed(kv))
-                    .write(stream)
-                    .await?;
-            }
+                .try_for_each(|kv| check.write(Property::UserProperty(Cow::Borrowed(kv)), buf))?;
 
             Ok(())
         }
@@ -1,3 +1,6 @@
+use alloc::string::String;
+use core::str::Utf8Error;
+
 mod properties;
 pub use properties::PublishProperties;
 
@@ -19,14 +22,25 @@ pub struct Publish {
     pub payload: Bytes,
 }
 
-#[cfg(feature = "asyncx")]
-pub(crate) use asyncx::*;
+impl Publish {
+    /// Whether the payload is what its Payload Format Indicator says: UTF-8 Encoded Character Data when it is 1,
+    /// unspecified bytes otherwise (3.3.2.3.2)
+    pub fn check_payload_format(&self) -> Result<(), Utf8Error> {
+        match self.properties.payload_format_indicator {
+            Some(1) => core::str::from_utf8(&self.payload).map(|_| ()),
+            _ => Ok(()),
+        }
+    }
+}
+
 #[cfg(feature = "syncx")]
 pub(crate) use syncx::*;
 
 impl ReadData for Publish {}
 
 mod syncx {
+    use alloc::string::String;
+
     use bytes::Bytes;
 
     use crate::v5::{
@@ -42,11 +56,10 @@ mod syncx {
     impl BufferIO for Publish {
         /// variable header, length of the payload, encoded as Variable Byte Integer
         fn length(&self) -> usize {
-            let mut len = if self.qos != QoS::Zero {
-                self.topic.len() + 2
-            } else {
-                0
-            };
+            let mut len = 2 + self.topic.len(); // topic name
+            if self.qos != QoS::Zero {
+                len += 2; // packet identifier
+            }
             len +=
                 self.properties.length() + self.properties.variable_length() + self.payload.len();
             len
@@ -60,21 +73,31 @@ mod syncx {
             )
             .write(buf)?;
 
-            self.topic.write(buf);
+            self.topic.write(buf)?;
             if self.qos != QoS::Zero {
                 // assignment of a packet id should be done from the client level after user provides us with the publish data
                 self.pkid
                     .ok_or_else(|| MQTTError::PacketIdRequired)?
-                    .write(buf);
+                    .write(buf)?;
             }
 
             self.properties.write(buf)?;
-            self.payload.write(buf);
+            // the payload is not length-prefixed, it takes up the rest of the packet (3.3.3)
+            buf.extend_from_slice(&self.payload);
             Ok(())
         }
 
         /// Publish does not implement `read` only read_with_flag
         fn read_with_fixedheader(buf: &mut Bytes, header: FixedHeader) -> Result<Self, MQTTError> {
+            if buf.len() < header.remaining_length {
+                return Err(MQTTError::IncompleteData(
+                    "Publish",
+                    header.remaining_length,
+                    buf.len(),
+                ));
+            }
+            let buf = &mut buf.split_to(header.remaining_length);
+
             let mut packet = Self::default();
             let flag = header.flags.unwrap_or(0);
 
@@ -89,91 +112,7 @@ mod syncx {
             }
 
             packet.properties = PublishProperties::read(buf)?;
-            packet.payload = Bytes::read(buf)?;
-            Ok(packet)
-        }
-    }
-}
-
-mod asyncx {
-    use bytes::Bytes;
-
-    use crate::v5::{
-        commons::{error::MQTTError, qos::QoS},
-        traits::{
-            asyncx::{read::Read, write::Write},
-            streamio::StreamIO,
-        },
-    };
-
-    use super::{FixedHeader, PacketType, Publish, PublishProperties};
-    impl StreamIO for Publish {
-        /// variable header, length of the payload, encoded as Variable Byte Integer
-        fn length(&self) -> usize {
-            let mut len = if self.qos != QoS::Zero {
-                self.topic.len() + 2
-            } else {
-                0
-            };
-            len +=
-                self.properties.length() + self.properties.variable_length() + self.payload.len();
-            len
-        }
-
-        async fn write<W>(&self, stream: &mut W) -> Result<(), crate::v5::commons::error::MQTTError>
-        where
-            W: futures::AsyncWriteExt + Unpin,
-        {
-            FixedHeader::new(
-                PacketType::Publish,
-                (self.dup as u8) << 3 | (self.qos as u8) << 1 | (self.retain as u8),
-                self.length(),
-            )
-            .write(stream)
-            .await?;
-
-            self.topic.write(stream).await?;
-            if self.qos != QoS::Zero {
-                // assignment of a packet id should be done from the client level after user provides us with the publish data
-                self.pkid
-                    .ok_or_else(|| MQTTError::PacketIdRequired)?
-                    .write(stream)
-                    .await?;
-            }
-
-            self.properties.write(stream).await?;
-            self.payload.to_vec().write(stream).await?;
-            Ok(())
-        }
-
-        async fn read_with_fixedheader<R>(
-            stream: &mut R,
-            header: &FixedHeader,
-        ) -> Result<Self, MQTTError>
-        where
-            R: futures::AsyncReadExt + Unpin,
-            Self: Default,
-        {
-            let mut packet = Self::default();
-            let flag = header.flags.unwrap_or(0);
-
-            packet.topic = String::read(stream).await?;
-            packet.dup = (flag & 0b1000) != 0;
-            let qos = (flag & 0b0110) >> 1;
-            packet.qos = QoS::try_from(qos).map_err(|_| MQTTError::UnsupportedQoS(qos))?;
-            packet.retain = (flag & 0b1) != 0;
-
-            if packet.qos != QoS::Zero {
-                packet.pkid = Some(
-                    u16::read(stream)
-                        .await
-                        .map_err(|_| MQTTError::PacketIdRequired)?,
-                );
-            }
-
-            packet.properties = PublishProperties::read(stream).await?;
-            packet.payload = Bytes::from_iter(Vec::read(stream).await?);
-
+            packet.payload = buf.split_to(buf.len());
             Ok(packet)
         }
     }
@@ -182,12 +121,15 @@ mod asyncx {
 #[cfg(test)]
 mod syncx_tests {
     use super::*;
+    #[cfg(feature = "std")]
     use crate::retest_utils::initialize_pid;
+    use crate::v5::commons::error::MQTTError;
     use crate::v5::traits::bufferio::BufferIO;
     use bytes::BytesMut;
 
     #[test]
     fn read_write_publish() {
+        #[cfg(feature = "std")]
         initialize_pid();
         let packet = Publish {
             dup: true,
@@ -207,18 +149,95 @@ mod syncx_tests {
         packet.write(&mut buf).unwrap();
 
         let expected =
-            b";)\0\x10packagin_plant/#\"\xe2\x05\x01\r#\0\x02\0\x11veryLarge payload".to_vec();
+            b";+\0\x10packagin_plant/#\"\xe2\x05\x01\r#\0\x02veryLarge payload".to_vec();
         assert_eq!(buf.to_vec(), expected);
 
-        let mut expected = Bytes::from_iter(
-            b";)\0\x10packagin_plant/#\"\xe2\x05\x01\r#\0\x02\0\x11veryLarge payload".to_vec()[2..]
-                .to_vec(),
-        );
-        let created_packed = Publish::read_with_fixedheader(
-            &mut expected,
-            FixedHeader::new(PacketType::Publish, 0b1011, 9),
-        )
-        .unwrap();
+        let mut expected = Bytes::from_iter(expected);
+        let header = FixedHeader::read(&mut expected).unwrap();
+        assert_eq!(header.remaining_length, packet.length());
+        let created_packed = Publish::read_with_fixedheader(&mut expected, header).unwrap();
         assert_eq!(created_packed, packet);
     }
+
+    #[test]
+    fn round_trips_user_properties_and_subscription_identifiers() {
+        let packet = Publish {
+            topic: String::from("sensors/temperature"),
+            properties: PublishProperties {
+                user_property: vec![(String::from("unit"), String::from("celsius"))],
+                subscription_identifier: vec![1, 300],
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+
+        let mut buf = BytesMut::new();
+        packet.write(&mut buf).unwrap();
+
+        let mut buf = buf.freeze();
+        let header = FixedHeader::read(&mut buf).unwrap();
+        assert_eq!(header.remaining_length, packet.length());
+        assert_eq!(Publish::read_with_fixedheader(&mut buf, header), Ok(packet));
+    }
+
+    #[test]
+    fn checks_the_payload_against_its_format_indicator() {
+        let mut packet = Publish {
+            payload: Bytes::from_static(b"\xc3\x28"),
+            ..Default::default()
+        };
+        assert!(packet.check_payload_format().is_ok());
+
+        packet.properties.payload_format_indicator = Some(1);
+        assert!(packet.check_payload_format().is_err());
+
+        packet.payload = Bytes::from("température");
+        assert!(packet.check_payload_format().is_ok());
+    }
+
+    #[test]
+    fn refuses_malformed_topics() {
+        let packet = Publish {
+            topic: String::from("sensors\0temperature"),
+            ..Default::default()
+        };
+        assert_eq!(packet.write(&mut BytesMut::new()), Err(MQTTError::NullCharacter));
+
+        // the topic is not valid UTF-8
+        let mut buf = Bytes::from_static(b"\x30\x05\x00\x02\xc3\x28\x00");
+        let header = FixedHeader::read(&mut buf).unwrap();
+        assert_eq!(
+            Publish::read_with_fixedheader(&mut buf, header),
+            Err(MQTTError::MalformedPacket)
+        );
+    }
+
+    #[test]
+    fn takes_the_payload_from_the_rest_of_the_packet() {
+        let packet = Publish {
+            topic: String::from("a/b"),
+            payload: Bytes::from_static(b"hello"),
+            ..Default::default()
+        };
+        let mut buf = BytesMut::new();
+        packet.write(&mut buf).unwrap();
+        // the topic name is there at QoS 0 too, and the payload has no length prefix
+        assert_eq!(buf.to_vec(), b"\x30\x0b\x00\x03a/b\x00hello");
+
+        let mut buf = buf.freeze();
+        let header = FixedHeader::read(&mut buf).unwrap();
+        assert_eq!(Publish::read_with_fixedheader(&mut buf, header), Ok(packet));
+
+        let empty = Publish {
+            topic: String::from("a/b"),
+            qos: QoS::One,
+            pkid: Some(1),
+            ..Default::default()
+        };
+        let mut buf = BytesMut::new();
+        empty.write(&mut buf).unwrap();
+        let mut buf = buf.freeze();
+        let header = FixedHeader::read(&mut buf).unwrap();
+        assert_eq!(Publish::read_with_fixedheader(&mut buf, header), Ok(empty));
+    }
 }
@@ -1,9 +1,15 @@
+use alloc::{
+    string::String,
+    vec::Vec,
+};
+
 use bytes::Bytes;
 use hivemqtt_macros::{FromU8, Length};
 
-use crate::v5::{
-    commons::{error::MQTTError, property::Property},
-    traits::utils::Utils,
+use crate::v5::commons::{
+    error::MQTTError,
+    packet_type::PacketType,
+    property::{Property, PropertyCheck},
 };
 
 use super::ReadData;
@@ -20,34 +26,33 @@ pub enum PubRecReasonCode {
     TopicNameInvalid = 144,
     PacketIdentifierInUse = 145,
     QuotaExceeded = 151,
-    PayloadFormatIndicator = 153,
+    PayloadFormatInvalid = 153,
 }
 
-#[derive(Debug, Length, Default, PartialEq, Eq)]
+#[derive(Debug, Length, Default, PartialEq, Eq, Clone)]
 pub struct PubRecProperties {
     pub reason_string: Option<String>,
     pub user_property: Vec<(String, String)>,
 }
 
-#[cfg(feature = "asyncx")]
-pub(crate) use asyncx::*;
 #[cfg(feature = "syncx")]
 pub(crate) use syncx::*;
 
 impl ReadData for PubRecProperties {
     fn read_data(data: &mut Bytes) -> Result<Self, MQTTError> {
         let mut props = Self::default();
+        let mut check = PropertyCheck::new(PacketType::PubRec);
 
         loop {
-            let property = Property::read(data)?;
+            let property = check.read(data)?;
 
             match property {
-                Property::ReasonString(ref v) => Self::try_update(
-                    &mut props.reason_string,
-                    v.as_deref().map(String::from),
-                )(property)?,
+                Property::ReasonString(ref v) => {
+                    props.reason_string = v.as_deref().map(String::from)
+                }
                 Property::UserProperty(value) => props.user_property.push(value.into_owned()),
-                p => return Err(MQTTError::UnexpectedProperty(p.to_string(), "".to_string())),
+                // anything else is refused by `PropertyCheck::read` already
+                p => return Err(check.unexpected(&p)),
             };
 
             if data.is_empty() {
@@ -60,10 +65,14 @@ impl ReadData for PubRecProperties {
 }
 
 mod syncx {
-    use std::borrow::Cow;
+    use alloc::borrow::Cow;
 
     use crate::v5::{
-        commons::{error::MQTTError, property::Property},
+        commons::{
+            error::MQTTError,
+            packet_type::PacketType,
+            property::{Property, PropertyCheck},
+        },
         traits::bufferio::BufferIO,
     };
 
@@ -76,43 +85,15 @@ mod syncx {
 
         fn write(&self, buf: &mut bytes::BytesMut) -> Result<(), MQTTError> {
             self.encode(buf)?;
+            let mut check = PropertyCheck::new(PacketType::PubRec);
 
-            Property::ReasonString(self.reason_string.as_deref().map(Cow::Borrowed)).write(buf)?;
+            check.write(
+                Property::ReasonString(self.reason_string.as_deref().map(Cow::Borrowed)),
+                buf,
+            )?;
             self.user_property
                 .iter()
-                .try_for_each(|kv| Property::UserProperty(Cow::Borrowed(kv)).write(buf))?;
-
-            Ok(())
-        }
-    }
-}
-
-mod asyncx {
-    use std::borrow::Cow;
-
-    use crate::v5::{commons::property::Property, traits::streamio::StreamIO};
-
-    use super::PubRecProperties;
-
-    impl StreamIO for PubRecProperties {
-        fn length(&self) -> usize {
-            self.len()
-        }
-
-        async fn write<W>(&self, stream: &mut W) -> Result<(), crate::v5::commons::error::MQTTError>
-        where
-            W: futures::AsyncWriteExt + Unpin,
-        {
-            self.encode(stream).await?;
-
-            Property::ReasonString(self.reason_string.as_deref().map(Cow::Borrowed))
-                .write(stream)
-                .await?;
-            for kv in &self.user_property {
-                Property::UserProperty(Cow::Borrowed(kv))
-                    .write(stream)
-                    .await?;
-            }
+                .try_for_each(|kv| check.write(Property::UserProperty(Cow::Borrowed(kv)), buf))?;
 
             Ok(())
         }
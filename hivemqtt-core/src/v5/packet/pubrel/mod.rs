@@ -6,15 +6,13 @@ use crate::v5::{
     traits::read_data::ReadData,
 };
 
-#[derive(Debug, Default, PartialEq, Eq)]
+#[derive(Debug, Default, PartialEq, Eq, Clone)]
 pub struct PubRel {
-    pub(crate) pkid: u16,
-    pub(crate) reason_code: PubRelReasonCode,
-    pub(crate) properties: PubRelProperties,
+    pub pkid: u16,
+    pub reason_code: PubRelReasonCode,
+    pub properties: PubRelProperties,
 }
 
-#[cfg(feature = "asyncx")]
-pub(crate) use asyncx::*;
 #[cfg(feature = "syncx")]
 pub(crate) use syncx::*;
 
@@ -32,7 +30,7 @@ mod syncx {
 
     impl BufferIO for PubRel {
         fn length(&self) -> usize {
-            let mut len = std::mem::size_of::<u16>(); // packet identifier
+            let mut len = core::mem::size_of::<u16>(); // packet identifier
             if self.reason_code == PubRelReasonCode::Success && self.properties.length() == 0 {
                 return len;
             }
@@ -43,11 +41,11 @@ mod syncx {
         fn write(&self, buf: &mut bytes::BytesMut) -> Result<(), MQTTError> {
             FixedHeader::new(PacketType::PubRel, 0b10, self.length()).write(buf)?;
 
-            self.pkid.write(buf);
+            self.pkid.write(buf)?;
             if self.properties.length() == 0 && self.reason_code == PubRelReasonCode::Success {
                 return Ok(());
             }
-            u8::from(self.reason_code).write(buf);
+            u8::from(self.reason_code).write(buf)?;
             self.properties.write(buf)?;
             Ok(())
         }
@@ -73,70 +71,6 @@ mod syncx {
     }
 }
 
-mod asyncx {
-    use crate::v5::{
-        commons::error::MQTTError,
-        traits::{
-            asyncx::{read::Read, write::Write},
-            streamio::StreamIO,
-        },
-    };
-
-    use super::{FixedHeader, PacketType, PubRel, PubRelProperties, PubRelReasonCode};
-
-    impl StreamIO for PubRel {
-        fn length(&self) -> usize {
-            let mut len = std::mem::size_of::<u16>(); // packet identifier
-            if self.reason_code == PubRelReasonCode::Success && self.properties.length() == 0 {
-                return len;
-            }
-            len += 1 + self.properties.length() + self.properties.variable_length(); // reason code
-            len
-        }
-
-        async fn write<W>(&self, stream: &mut W) -> Result<(), crate::v5::commons::error::MQTTError>
-        where
-            W: futures::AsyncWriteExt + Unpin,
-        {
-            FixedHeader::new(PacketType::PubRel, 0b10, self.length())
-                .write(stream)
-                .await?;
-
-            self.pkid.write(stream).await?;
-            if self.properties.length() == 0 && self.reason_code == PubRelReasonCode::Success {
-                return Ok(());
-            }
-            u8::from(self.reason_code).write(stream).await?;
-            self.properties.write(stream).await?;
-
-            Ok(())
-        }
-
-        async fn read_with_fixedheader<R>(
-            stream: &mut R,
-            header: &FixedHeader,
-        ) -> Result<Self, crate::v5::commons::error::MQTTError>
-        where
-            R: futures::AsyncReadExt + Unpin,
-            Self: Default,
-        {
-            let mut packet = Self::default();
-            packet.pkid = u16::read(stream).await?;
-
-            if header.remaining_length == 2 {
-                packet.reason_code = PubRelReasonCode::Success;
-                return Ok(packet);
-            }
-
-            packet.reason_code = PubRelReasonCode::try_from(u8::read(stream).await?)
-                .map_err(|e| MQTTError::UnknownData(e))?;
-            packet.properties = PubRelProperties::read(stream).await?;
-
-            Ok(packet)
-        }
-    }
-}
-
 // #[cfg(test)]
 // mod tests {
 //     use bytes::{Bytes, BytesMut};
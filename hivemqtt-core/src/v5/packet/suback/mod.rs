@@ -1,6 +1,8 @@
+use alloc::vec::Vec;
+
 mod properties;
 mod reason_code;
-use properties::SubAckProperties;
+pub use properties::SubAckProperties;
 pub use reason_code::SubAckReasonCode;
 
 use crate::v5::{
@@ -9,15 +11,13 @@ use crate::v5::{
 };
 
 /// 3.9: Sent by the Server to the Client to confirm receipt and processing of a SUBSCRIBE packet.
-#[derive(Debug, Default, PartialEq, Eq)]
+#[derive(Debug, Default, PartialEq, Eq, Clone)]
 pub struct SubAck {
     pub pkid: u16,
     pub payload: Vec<SubAckReasonCode>,
     pub properties: SubAckProperties,
 }
 
-#[cfg(feature = "asyncx")]
-pub(crate) use asyncx::*;
 #[cfg(feature = "syncx")]
 pub(crate) use syncx::*;
 
@@ -43,9 +43,9 @@ mod syncx {
         fn write(&self, buf: &mut bytes::BytesMut) -> Result<(), MQTTError> {
             FixedHeader::new(PacketType::SubAck, 0, self.length()).write(buf)?;
 
-            self.pkid.write(buf);
+            self.pkid.write(buf)?;
             self.properties.write(buf)?;
-            self.payload.iter().for_each(|x| u8::from(*x).write(buf));
+            self.payload.iter().try_for_each(|x| u8::from(*x).write(buf))?;
             Ok(())
         }
 
@@ -72,59 +72,6 @@ mod syncx {
     }
 }
 
-mod asyncx {
-    use crate::v5::{
-        commons::error::MQTTError,
-        traits::{
-            asyncx::{read::Read, write::Write},
-            streamio::StreamIO,
-        },
-    };
-
-    use super::{properties::SubAckProperties, FixedHeader, PacketType, SubAck, SubAckReasonCode};
-
-    impl StreamIO for SubAck {
-        // Length of the Variable Header plus the length of the Payload
-        fn length(&self) -> usize {
-            2 + self.payload.len() + self.properties.length() + self.properties.variable_length()
-        }
-
-        async fn write<W>(&self, stream: &mut W) -> Result<(), crate::v5::commons::error::MQTTError>
-        where
-            W: futures::AsyncWriteExt + Unpin,
-        {
-            FixedHeader::new(PacketType::SubAck, 0, self.length())
-                .write(stream)
-                .await?;
-
-            self.pkid.write(stream).await?;
-            self.properties.write(stream).await?;
-
-            for payload in &self.payload {
-                u8::from(*payload).write(stream).await?;
-            }
-
-            Ok(())
-        }
-
-        async fn read<R>(stream: &mut R) -> Result<Self, crate::v5::commons::error::MQTTError>
-        where
-            R: futures::AsyncReadExt + Unpin,
-            Self: Default,
-        {
-            let mut packet = Self::default();
-            packet.pkid = u16::read(stream).await?;
-            packet.properties = SubAckProperties::read(stream).await?;
-
-            while let Ok(value) = u8::read(stream).await {
-                SubAckReasonCode::try_from(value).map_err(MQTTError::UnknownData)?;
-            }
-
-            Ok(packet)
-        }
-    }
-}
-
 // #[cfg(test)]
 // mod tests {
 //     use bytes::{Bytes, BytesMut};
@@ -154,7 +101,7 @@ mod asyncx {
 //             SubAckReasonCode::GrantedQoS2,
 //             SubAckReasonCode::QuotaExceeded,
 //             SubAckReasonCode::UnspecifiedError,
-//             SubAckReasonCode::NotAuhtorized,
+//             SubAckReasonCode::NotAuthorized,
 //         ];
 //         packet.properties = SubAckProperties {
 //             reason_string: Some("googoogReason".into()),
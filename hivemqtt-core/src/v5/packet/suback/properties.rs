@@ -1,37 +1,42 @@
+use alloc::{
+    string::String,
+    vec::Vec,
+};
+
 use bytes::Bytes;
 use hivemqtt_macros::Length;
 
-use crate::v5::{
-    commons::{error::MQTTError, property::Property},
-    traits::utils::Utils,
+use crate::v5::commons::{
+    error::MQTTError,
+    packet_type::PacketType,
+    property::{Property, PropertyCheck},
 };
 
 use super::ReadData;
 
-#[derive(Debug, Length, Default, PartialEq, Eq)]
+#[derive(Debug, Length, Default, PartialEq, Eq, Clone)]
 pub struct SubAckProperties {
     pub reason_string: Option<String>,
     pub user_property: Vec<(String, String)>,
 }
 
-#[cfg(feature = "asyncx")]
-pub(crate) use asyncx::*;
 #[cfg(feature = "syncx")]
 pub(crate) use syncx::*;
 
 impl ReadData for SubAckProperties {
     fn read_data(data: &mut Bytes) -> Result<Self, MQTTError> {
         let mut props = Self::default();
+        let mut check = PropertyCheck::new(PacketType::SubAck);
 
         loop {
-            let property = Property::read(data)?;
+            let property = check.read(data)?;
             match property {
-                Property::ReasonString(ref v) => Self::try_update(
-                    &mut props.reason_string,
-                    v.as_deref().map(String::from),
-                )(property)?,
+                Property::ReasonString(ref v) => {
+                    props.reason_string = v.as_deref().map(String::from)
+                }
                 Property::UserProperty(v) => props.user_property.push(v.into_owned()),
-                p => return Err(MQTTError::UnexpectedProperty(p.to_string(), "".to_string())),
+                // anything else is refused by `PropertyCheck::read` already
+                p => return Err(check.unexpected(&p)),
             }
             if data.is_empty() {
                 break;
@@ -43,10 +48,14 @@ impl ReadData for SubAckProperties {
 }
 
 mod syncx {
-    use std::borrow::Cow;
+    use alloc::borrow::Cow;
 
     use crate::v5::{
-        commons::{error::MQTTError, property::Property},
+        commons::{
+            error::MQTTError,
+            packet_type::PacketType,
+            property::{Property, PropertyCheck},
+        },
         traits::bufferio::BufferIO,
     };
 
@@ -59,42 +68,15 @@ mod syncx {
 
         fn write(&self, buf: &mut bytes::BytesMut) -> Result<(), MQTTError> {
             self.encode(buf)?;
-            Property::ReasonString(self.reason_string.as_deref().map(Cow::Borrowed)).write(buf)?;
+            let mut check = PropertyCheck::new(PacketType::SubAck);
+
+            check.write(
+                Property::ReasonString(self.reason_string.as_deref().map(Cow::Borrowed)),
+                buf,
+            )?;
             self.user_property
                 .iter()
-                .try_for_each(|up| Property::UserProperty(Cow::Borrowed(&up)).write(buf))?;
-
-            Ok(())
-        }
-    }
-}
-
-mod asyncx {
-    use std::borrow::Cow;
-
-    use crate::v5::{commons::property::Property, traits::streamio::StreamIO};
-
-    use super::SubAckProperties;
-
-    impl StreamIO for SubAckProperties {
-        fn length(&self) -> usize {
-            self.len()
-        }
-
-        async fn write<W>(&self, stream: &mut W) -> Result<(), crate::v5::commons::error::MQTTError>
-        where
-            W: futures::AsyncWriteExt + Unpin,
-        {
-            self.encode(stream).await?;
-            Property::ReasonString(self.reason_string.as_deref().map(Cow::Borrowed))
-                .write(stream)
-                .await?;
-
-            for kv in &self.user_property {
-                Property::UserProperty(Cow::Borrowed(&kv))
-                    .write(stream)
-                    .await?;
-            }
+                .try_for_each(|up| check.write(Property::UserProperty(Cow::Borrowed(&up)), buf))?;
 
             Ok(())
         }
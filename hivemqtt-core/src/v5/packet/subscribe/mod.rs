@@ -1,7 +1,9 @@
+use alloc::{string::String, vec::Vec};
+
 mod options;
 mod properties;
 
-pub use options::SubscriptionOptions;
+pub use options::{RetainHandling, SubscriptionOptions};
 pub use properties::SubscribeProperties;
 
 use crate::v5::{
@@ -9,8 +11,6 @@ use crate::v5::{
     traits::read_data::ReadData,
 };
 
-#[cfg(feature = "asyncx")]
-pub(crate) use asyncx::*;
 #[cfg(not(feature = "asyncx"))]
 pub(crate) use syncx::*;
 
@@ -25,6 +25,8 @@ pub struct Subscribe {
 impl ReadData for Subscribe {}
 
 mod syncx {
+    use alloc::string::String;
+
     use bytes::Bytes;
 
     use crate::v5::{
@@ -57,10 +59,10 @@ mod syncx {
             }
 
             FixedHeader::new(PacketType::Subscribe, 0b10, self.length()).write(buf)?;
-            self.pkid.write(buf);
+            self.pkid.write(buf)?;
             self.properties.write(buf)?;
             for (topic, options) in &self.payload {
-                topic.write(buf);
+                topic.write(buf)?;
                 options.write(buf)?;
             }
 
@@ -92,78 +94,6 @@ mod syncx {
     }
 }
 
-mod asyncx {
-    use crate::v5::{
-        commons::error::MQTTError,
-        traits::{
-            asyncx::{read::Read, write::Write},
-            streamio::StreamIO,
-        },
-    };
-
-    use super::{FixedHeader, PacketType, Subscribe, SubscribeProperties, SubscriptionOptions};
-
-    impl StreamIO for Subscribe {
-        /// (Length of Variable Header + Length of the Payload)
-        fn length(&self) -> usize {
-            let mut len = 2 + self.properties.length() + self.properties.variable_length(); // packet identifier + properties
-            len += self
-                .payload
-                .iter()
-                .fold(0, |acc, x| acc + (1 + (2 + x.0.len()))); // u8(len) + (string(2) + topic.len())
-
-            len
-        }
-
-        async fn write<W>(&self, stream: &mut W) -> Result<(), crate::v5::commons::error::MQTTError>
-        where
-            W: futures::AsyncWriteExt + Unpin,
-        {
-            if self.payload.len() == 0 {
-                return Err(MQTTError::ProtocolError(
-                    "Must contain at least one topic/subscription option pair",
-                ));
-            }
-
-            FixedHeader::new(PacketType::Subscribe, 0b10, self.length())
-                .write(stream)
-                .await?;
-            self.pkid.write(stream).await?;
-            self.properties.write(stream).await?;
-            for (topic, options) in &self.payload {
-                topic.write(stream).await?;
-                options.write(stream).await?;
-            }
-
-            Ok(())
-        }
-
-        async fn read<R>(stream: &mut R) -> Result<Self, MQTTError>
-        where
-            R: futures::AsyncReadExt + Unpin,
-            Self: Default,
-        {
-            let mut packet = Self::default();
-            packet.pkid = u16::read(stream).await?;
-
-            packet.properties = SubscribeProperties::read(stream).await?;
-
-            while let Ok(topic) = String::read(stream).await {
-                let options = SubscriptionOptions::read(stream).await?;
-                packet.payload.push((topic, options));
-            }
-
-            if packet.payload.len() == 0 {
-                return Err(MQTTError::ProtocolError(
-                    "Must contain at least one topic/subscription option pair",
-                ));
-            }
-
-            Ok(packet)
-        }
-    }
-}
-
 // #[cfg(test)]
 // mod tests {
 //     use bytes::BytesMut;
@@ -11,8 +11,6 @@ pub struct SubscriptionOptions {
     pub retain_handling: RetainHandling,
 }
 
-#[cfg(feature = "asyncx")]
-pub(crate) use asyncx::*;
 #[cfg(feature = "syncx")]
 pub(crate) use syncx::*;
 
@@ -35,7 +33,7 @@ impl TryFrom<u8> for SubscriptionOptions {
         let qos = QoS::try_from(qos).map_err(|_| MQTTError::UnsupportedQoS(qos))?;
         let no_local = (byte & 0b0000_0100) != 0;
         let retain_as_published = (byte & 0b0000_1000) != 0;
-        let retain_handling = RetainHandling::try_from(byte & 0b0011_0000)?;
+        let retain_handling = RetainHandling::try_from((byte & 0b0011_0000) >> 4)?;
 
         Ok(Self {
             qos,
@@ -63,7 +61,7 @@ mod syncx {
         }
 
         fn write(&self, buf: &mut bytes::BytesMut) -> Result<(), MQTTError> {
-            u8::from(*self).write(buf);
+            u8::from(*self).write(buf)?;
             Ok(())
         }
 
@@ -75,37 +73,6 @@ mod syncx {
     }
 }
 
-mod asyncx {
-    use crate::v5::traits::{
-        asyncx::{read::Read, write::Write},
-        streamio::StreamIO,
-    };
-
-    use super::SubscriptionOptions;
-
-    impl StreamIO for SubscriptionOptions {
-        fn length(&self) -> usize {
-            1
-        }
-
-        async fn write<W>(&self, stream: &mut W) -> Result<(), crate::v5::commons::error::MQTTError>
-        where
-            W: futures::AsyncWriteExt + Unpin,
-        {
-            u8::from(*self).write(stream).await
-        }
-
-        async fn read<R>(stream: &mut R) -> Result<Self, crate::v5::commons::error::MQTTError>
-        where
-            R: futures::AsyncReadExt + Unpin,
-            Self: Default,
-        {
-            let byte = u8::read(stream).await?;
-            Self::try_from(byte)
-        }
-    }
-}
-
 #[repr(u8)]
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
 pub enum RetainHandling {
@@ -130,3 +97,27 @@ impl TryFrom<u8> for RetainHandling {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trips_the_retain_handling() {
+        for retain_handling in [RetainHandling::Zero, RetainHandling::One, RetainHandling::Two] {
+            let options = SubscriptionOptions {
+                qos: QoS::One,
+                retain_handling,
+                ..Default::default()
+            };
+            assert_eq!(SubscriptionOptions::try_from(u8::from(options)), Ok(options));
+        }
+
+        let options = SubscriptionOptions {
+            retain_handling: RetainHandling::Two,
+            ..Default::default()
+        };
+        assert_eq!(u8::from(options), 0b0010_0000);
+        assert_eq!(SubscriptionOptions::try_from(0b0011_0000), Err(MQTTError::MalformedPacket));
+    }
+}
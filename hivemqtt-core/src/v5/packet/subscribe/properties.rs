@@ -1,36 +1,38 @@
-use std::ops::Deref;
+use alloc::{
+    string::String,
+    vec::Vec,
+};
+use core::ops::Deref;
 
 use bytes::Bytes;
 use hivemqtt_macros::Length;
 
-use crate::v5::{commons::error::MQTTError, traits::utils::Utils};
+use crate::v5::commons::{error::MQTTError, packet_type::PacketType, property::PropertyCheck};
 
 use super::{Property, ReadData};
 
-#[derive(Debug, Length, Default, PartialEq, Eq)]
+#[derive(Debug, Length, Default, Clone, PartialEq, Eq)]
 pub struct SubscribeProperties {
     pub subscription_id: Option<usize>,
     pub user_property: Vec<(String, String)>,
 }
 
-#[cfg(feature = "asyncx")]
-pub(crate) use asyncx::*;
 #[cfg(feature = "syncx")]
 pub(crate) use syncx::*;
 
 impl ReadData for SubscribeProperties {
     fn read_data(data: &mut Bytes) -> Result<Self, MQTTError> {
         let mut props = Self::default();
+        let mut check = PropertyCheck::new(PacketType::Subscribe);
 
         loop {
-            let property = Property::read(data)?;
+            let property = check.read(data)?;
 
             match property {
-                Property::SubscriptionIdentifier(ref v) => {
-                    Self::try_update(&mut props.subscription_id, Some(*v.deref()))(property)?
-                }
+                Property::SubscriptionIdentifier(ref v) => props.subscription_id = Some(*v.deref()),
                 Property::UserProperty(v) => props.user_property.push(v.into_owned()),
-                p => return Err(MQTTError::UnexpectedProperty(p.to_string(), "".to_string())),
+                // anything else is refused by `PropertyCheck::read` already
+                p => return Err(check.unexpected(&p)),
             }
 
             if data.is_empty() {
@@ -42,10 +44,14 @@ impl ReadData for SubscribeProperties {
 }
 
 mod syncx {
-    use std::borrow::Cow;
+    use alloc::borrow::Cow;
 
     use crate::v5::{
-        commons::{error::MQTTError, property::Property},
+        commons::{
+            error::MQTTError,
+            packet_type::PacketType,
+            property::{Property, PropertyCheck},
+        },
         traits::bufferio::BufferIO,
     };
 
@@ -58,48 +64,14 @@ mod syncx {
 
         fn write(&self, buf: &mut bytes::BytesMut) -> Result<(), MQTTError> {
             self.encode(buf)?;
+            let mut check = PropertyCheck::new(PacketType::Subscribe);
 
             if let Some(id) = &self.subscription_id {
-                Property::SubscriptionIdentifier(Cow::Borrowed(id)).write(buf)?;
+                check.write(Property::SubscriptionIdentifier(Cow::Borrowed(id)), buf)?;
             }
             self.user_property
                 .iter()
-                .try_for_each(|kv| Property::UserProperty(Cow::Borrowed(kv)).write(buf))?;
-
-            Ok(())
-        }
-    }
-}
-
-mod asyncx {
-    use std::borrow::Cow;
-
-    use crate::v5::{commons::property::Property, traits::streamio::StreamIO};
-
-    use super::SubscribeProperties;
-
-    impl StreamIO for SubscribeProperties {
-        fn length(&self) -> usize {
-            self.len()
-        }
-
-        async fn write<W>(&self, stream: &mut W) -> Result<(), crate::v5::commons::error::MQTTError>
-        where
-            W: futures::AsyncWriteExt + Unpin,
-        {
-            self.encode(stream).await?;
-
-            if let Some(id) = &self.subscription_id {
-                Property::SubscriptionIdentifier(Cow::Borrowed(id))
-                    .write(stream)
-                    .await?;
-            }
-
-            for kv in &self.user_property {
-                Property::UserProperty(Cow::Borrowed(kv))
-                    .write(stream)
-                    .await?;
-            }
+                .try_for_each(|kv| check.write(Property::UserProperty(Cow::Borrowed(kv)), buf))?;
 
             Ok(())
         }
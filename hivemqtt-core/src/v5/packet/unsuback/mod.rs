@@ -1,7 +1,9 @@
+use alloc::vec::Vec;
+
 mod properties;
 mod reason_code;
 
-use properties::UnSubAckProperties;
+pub use properties::UnSubAckProperties;
 pub use reason_code::UnSubAckReasonCode;
 
 use crate::v5::{
@@ -11,15 +13,13 @@ use crate::v5::{
 
 // #[cfg(any(not(feature = "asyncx"), feature = "asyncx"))]
 /// Sent by the Server to the Client to confirm receipt of an UNSUBSCRIBE packet
-#[derive(Debug, Default, PartialEq, Eq)]
+#[derive(Debug, Default, PartialEq, Eq, Clone)]
 pub struct UnSubAck {
     pub pkid: u16,
     pub properties: UnSubAckProperties,
     pub payload: Vec<UnSubAckReasonCode>,
 }
 
-#[cfg(feature = "asyncx")]
-pub(crate) use asyncx::*;
 #[cfg(feature = "syncx")]
 pub(crate) use syncx::*;
 
@@ -48,10 +48,10 @@ mod syncx {
             FixedHeader::new(PacketType::UnSubAck, 0, self.length()).write(buf)?;
 
             // packet identifier, properties
-            self.pkid.write(buf);
+            self.pkid.write(buf)?;
             self.properties.write(buf)?;
 
-            self.payload.iter().for_each(|p| u8::from(*p).write(buf));
+            self.payload.iter().try_for_each(|p| u8::from(*p).write(buf))?;
 
             Ok(())
         }
@@ -76,65 +76,6 @@ mod syncx {
     }
 }
 
-mod asyncx {
-    use crate::v5::{
-        commons::error::MQTTError,
-        traits::{
-            asyncx::{read::Read, write::Write},
-            streamio::StreamIO,
-        },
-    };
-
-    use super::{
-        properties::UnSubAckProperties, FixedHeader, PacketType, UnSubAck, UnSubAckReasonCode,
-    };
-
-    impl StreamIO for UnSubAck {
-        // Length of the Variable Header plus the length of the Payload
-        fn length(&self) -> usize {
-            2 + self.properties.length() + self.properties.variable_length() + self.payload.len()
-        }
-
-        async fn write<W>(&self, stream: &mut W) -> Result<(), crate::v5::commons::error::MQTTError>
-        where
-            W: futures::AsyncWriteExt + Unpin,
-        {
-            FixedHeader::new(PacketType::UnSubAck, 0, self.length())
-                .write(stream)
-                .await?;
-
-            // packet identifier, properties
-            self.pkid.write(stream).await?;
-            self.properties.write(stream).await?;
-
-            for p in &self.payload {
-                u8::from(*p).write(stream).await?;
-            }
-
-            Ok(())
-        }
-
-        async fn read<R>(stream: &mut R) -> Result<Self, crate::v5::commons::error::MQTTError>
-        where
-            R: futures::AsyncReadExt + Unpin,
-            Self: Default,
-        {
-            // the assumption here is that the provided buffer has already been advanced by the Fixed Header length
-            let mut packet = Self::default();
-            packet.pkid = u16::read(stream).await?;
-            packet.properties = UnSubAckProperties::read(stream).await?;
-
-            while let Ok(value) = u8::read(stream).await {
-                packet
-                    .payload
-                    .push(UnSubAckReasonCode::try_from(value).map_err(MQTTError::UnknownData)?);
-            }
-
-            Ok(packet)
-        }
-    }
-}
-
 // #[cfg(test)]
 // mod tests {
 //     use bytes::{Bytes, BytesMut};
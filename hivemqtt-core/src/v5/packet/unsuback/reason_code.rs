@@ -4,7 +4,7 @@ use hivemqtt_macros::FromU8;
 #[repr(u8)]
 pub enum UnSubAckReasonCode {
     Success = 0,
-    NoSubscriptionExpired = 17,
+    NoSubscriptionExisted = 17,
     UnspecifiedError = 128,
     ImplementationSpecificError =131,
     NotAuthorized = 135,
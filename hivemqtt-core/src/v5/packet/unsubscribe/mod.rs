@@ -1,3 +1,5 @@
+use alloc::{string::String, vec::Vec};
+
 mod properties;
 pub use properties::UnSubscribeProperties;
 
@@ -10,14 +12,14 @@ pub struct UnSubscribe {
     pub payload: Vec<String>,
 }
 
-#[cfg(feature = "asyncx")]
-pub(crate) use asyncx::*;
 #[cfg(feature = "syncx")]
 pub(crate) use syncx::*;
 
 impl ReadData for UnSubscribe {}
 
 mod syncx {
+    use alloc::string::String;
+
     use crate::v5::{
         commons::{error::MQTTError, fixed_header::FixedHeader, packet_type::PacketType},
         traits::{
@@ -45,9 +47,9 @@ mod syncx {
             };
             FixedHeader::new(PacketType::UnSubscribe, 0b10, self.length()).write(buf)?;
 
-            self.pkid.write(buf);
+            self.pkid.write(buf)?;
             self.properties.write(buf)?;
-            self.payload.iter().for_each(|p| p.write(buf));
+            self.payload.iter().try_for_each(|p| p.write(buf))?;
             Ok(())
         }
 
@@ -75,74 +77,6 @@ mod syncx {
     }
 }
 
-mod asyncx {
-    use crate::v5::{
-        commons::{error::MQTTError, fixed_header::FixedHeader, packet_type::PacketType},
-        traits::{
-            asyncx::{read::Read, write::Write},
-            streamio::StreamIO,
-        },
-    };
-
-    use super::{UnSubscribe, UnSubscribeProperties};
-
-    impl StreamIO for UnSubscribe {
-        /// Length of the Variable Header (2 bytes) plus the length of the Payload
-        fn length(&self) -> usize {
-            // packet identidier + string len
-            2 + self.payload.iter().fold(0, |acc, x| acc + x.len() + 2)
-                + self.properties.length()
-                + self.properties.variable_length()
-        }
-
-        async fn write<W>(&self, stream: &mut W) -> Result<(), crate::v5::commons::error::MQTTError>
-        where
-            W: futures::AsyncWriteExt + Unpin,
-        {
-            if self.payload.is_empty() {
-                return Err(MQTTError::ProtocolError(
-                    "The Payload of an UNSUBSCRIBE packet MUST contain at least one Topic Filter",
-                ));
-            };
-            FixedHeader::new(PacketType::UnSubscribe, 0b10, self.length())
-                .write(stream)
-                .await?;
-
-            self.pkid.write(stream).await?;
-            self.properties.write(stream).await?;
-            for p in &self.payload {
-                p.write(stream).await?;
-            }
-
-            Ok(())
-        }
-
-        async fn read<R>(stream: &mut R) -> Result<Self, MQTTError>
-        where
-            R: futures::AsyncReadExt + Unpin,
-            Self: Default,
-        {
-            // the assumption here is that the provided buffer has already been advanced by the Fixed Header length
-            let mut packet = Self::default();
-
-            packet.pkid = u16::read(stream).await?;
-            packet.properties = UnSubscribeProperties::read(stream).await?;
-
-            while let Ok(value) = String::read(stream).await {
-                packet.payload.push(value);
-            }
-
-            if packet.payload.is_empty() {
-                return Err(MQTTError::ProtocolError(
-                    "The Payload of an UNSUBSCRIBE packet MUST contain at least one Topic Filter",
-                ));
-            };
-
-            Ok(packet)
-        }
-    }
-}
-
 // #[cfg(test)]
 // mod tests {
 //     use bytes::{Bytes, BytesMut};
@@ -1,2 +0,0 @@
-pub(crate) mod read;
-pub(crate) mod write;
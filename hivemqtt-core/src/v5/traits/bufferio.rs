@@ -1,5 +1,5 @@
 use crate::v5::{
-    commons::fixed_header::FixedHeader,
+    commons::{fixed_header::FixedHeader, primitives::VarInt},
     traits::syncx::{read::Read, write::Write},
 };
 use bytes::{Bytes, BytesMut};
@@ -9,49 +9,15 @@ use crate::v5::commons::error::MQTTError;
 use super::read_data::ReadData;
 
 pub(crate) trait BufferIO: Sized + ReadData {
-    /// Encodes a non-negative Integer into the Variable Byte Integer encoding
+    /// Encodes the length of the struct as a Variable Byte Integer
     fn encode(&self, buf: &mut BytesMut) -> Result<(), MQTTError> {
-        let mut len = self.length();
-
-        // 268_435_455
-        if len > 0xFFFFFFF {
-            return Err(MQTTError::PayloadTooLong);
-        }
-
-        for _ in 0..4 {
-            let mut byte = len % 128;
-            len /= 128;
-
-            if len > 0 {
-                byte |= 128;
-            }
-
-            (byte as u8).write(buf); // writes the encoded byte into the buffer
-            if len == 0 {
-                break;
-            }
-        }
-        Ok(())
+        VarInt::try_from(self.length())?.write(buf)
     }
 
-    /// Decodes a Variable byte Inetger
+    /// Decodes a Variable Byte Integer, returns its value and the number of bytes it was encoded with
     fn decode(buf: &mut Bytes) -> Result<(usize, usize), MQTTError> {
-        let mut result = 0;
-
-        for i in 0..4 {
-            if buf.is_empty() {
-                return Err(MQTTError::MalformedPacket);
-            }
-            let byte = u8::read(buf)?;
-
-            result += ((byte as usize) & 0x7F) << (7 * i);
-
-            if (byte & 0x80) == 0 {
-                return Ok((result, i + 1));
-            }
-        }
-
-        return Err(MQTTError::MalformedPacket);
+        let value = VarInt::read(buf)?;
+        Ok((value.into(), value.encoded_len()))
     }
 
     /// Allows a struct specify what it's length is to it's external users
@@ -101,15 +67,17 @@ pub(crate) trait BufferIO: Sized + ReadData {
     }
 
     fn variable_length(&self) -> usize {
-        let len = self.length();
-        if len >= 2_097_152 {
-            4
-        } else if len >= 16_384 {
-            3
-        } else if len >= 128 {
-            2
-        } else {
-            1
+        // too long to be encoded at all, `encode` refuses it
+        VarInt::try_from(self.length()).map_or(4, VarInt::encoded_len)
+    }
+
+    /// Checks that the whole packet (fixed header included) is not larger than `max_size` bytes
+    fn is_valid(&self, max_size: usize) -> Result<(), MQTTError> {
+        let size = 1 + self.variable_length() + self.length();
+        if size > max_size {
+            return Err(MQTTError::MaxPacketSizeExceed(size));
         }
+
+        Ok(())
     }
 }
@@ -1,10 +1,8 @@
-pub(crate) mod asyncx;
-pub(crate) mod syncx; // sync traits // async traits
+pub(crate) mod syncx;
 
 pub(crate) mod bufferio;
-pub(crate) mod streamio;
 
 pub(crate) mod read_data;
 pub(crate) mod utils;
 
-pub(crate) mod pkid_mgr;
+pub mod pkid_mgr;
@@ -1,6 +1,11 @@
+use alloc::string::String;
+
 use bytes::{Buf, Bytes};
 
-use crate::v5::commons::error::MQTTError;
+use crate::v5::commons::{
+    error::MQTTError,
+    primitives::{BinaryData, Utf8String},
+};
 
 pub(crate) trait Read: Sized {
     fn read(buf: &mut Bytes) -> Result<Self, MQTTError>;
@@ -9,7 +14,7 @@ pub(crate) trait Read: Sized {
 
 impl Read for u8 {
     fn read(buf: &mut Bytes) -> Result<Self, MQTTError> {
-        let len = std::mem::size_of::<u8>();
+        let len = core::mem::size_of::<u8>();
         if buf.is_empty() { return Err(MQTTError::IncompleteData("u8", len, buf.len()))}
         
         Ok(buf.get_u8())
@@ -18,7 +23,7 @@ impl Read for u8 {
 
 impl Read for u16 {
     fn read(buf: &mut Bytes) -> Result<Self, MQTTError> {
-        let len = std::mem::size_of::<u16>();
+        let len = core::mem::size_of::<u16>();
 
         if buf.len() < len { return Err(MQTTError::IncompleteData("u16", len, buf.len()))}
         
@@ -28,7 +33,7 @@ impl Read for u16 {
 
 impl Read for u32 {
     fn read(buf: &mut Bytes) -> Result<Self, MQTTError> {
-        let len = std::mem::size_of::<u32>();
+        let len = core::mem::size_of::<u32>();
         if buf.len() < len { return Err(MQTTError::IncompleteData("u32", len, buf.len()))}
         
         Ok(buf.get_u32())
@@ -38,23 +43,13 @@ impl Read for u32 {
 
 impl Read for String {
     fn read(buf: &mut Bytes) -> Result<Self, MQTTError> {
-        let data = Bytes::read(buf)?;
-
-        match String::from_utf8(data.to_vec()) {
-            Ok(d) => Ok(d),
-            Err(e) => Err(MQTTError::Utf8Error(e)), // should be Malformed packet see 1.5.4 (UTF-8 Encoded String)
-        }
+        Utf8String::read(buf).map(Utf8String::into_inner)
     }
 }
 
 
 impl Read for Bytes {
     fn read(buf: &mut Bytes) -> Result<Self, MQTTError> {
-        let len = buf.get_u16() as usize;
-
-        if len > buf.len() {
-            return Err(MQTTError::IncompleteData("Bytes", len, buf.len()))
-        }
-        Ok(buf.split_to(len))
+        BinaryData::read(buf).map(BinaryData::into_inner)
     }
-}
\ No newline at end of file
+}
@@ -1,37 +1,45 @@
+use alloc::string::String;
+
 use bytes::{BufMut, Bytes, BytesMut};
 
+use crate::v5::commons::{
+    error::MQTTError,
+    primitives::{BinaryData, Utf8String},
+};
+
 pub(crate) trait Write: Sized {
-    fn write(&self, buf: &mut BytesMut);
+    fn write(&self, buf: &mut BytesMut) -> Result<(), MQTTError>;
 }
 
 impl Write for u8 {
-    fn write(&self, buf: &mut BytesMut) {
+    fn write(&self, buf: &mut BytesMut) -> Result<(), MQTTError> {
         buf.put_u8(*self);
+        Ok(())
     }
 }
 
 impl Write for u16 {
-    fn write(&self, buf: &mut BytesMut) {
+    fn write(&self, buf: &mut BytesMut) -> Result<(), MQTTError> {
         buf.put_u16(*self);
+        Ok(())
     }
 }
 
 impl Write for u32 {
-    fn write(&self, buf: &mut BytesMut) {
+    fn write(&self, buf: &mut BytesMut) -> Result<(), MQTTError> {
         buf.put_u32(*self);
+        Ok(())
     }
 }
 
 impl Write for Bytes {
-    fn write(&self, buf: &mut BytesMut) {
-        buf.put_u16(self.len() as u16);
-        buf.extend_from_slice(self);
+    fn write(&self, buf: &mut BytesMut) -> Result<(), MQTTError> {
+        BinaryData::write_slice(self, buf)
     }
 }
 
 impl Write for String {
-    fn write(&self, buf: &mut BytesMut) {
-        buf.put_u16(self.len() as u16);
-        buf.extend_from_slice(self.as_bytes());
+    fn write(&self, buf: &mut BytesMut) -> Result<(), MQTTError> {
+        Utf8String::write_str(self, buf)
     }
 }
@@ -1 +1 @@
-pub(crate) mod topic;
\ No newline at end of file
+pub mod topic;
\ No newline at end of file
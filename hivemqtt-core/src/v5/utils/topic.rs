@@ -2,9 +2,95 @@ use crate::v5::commons::error::MQTTError;
 
 pub(crate) fn parse_alias(alias: u16, alias_max: u16) -> Result<u16, MQTTError> {
     if alias == 0 || alias > alias_max {
-        return Err(MQTTError::InvalidProperty(
-            format!("Topic Alias Must be non-zero and less than or equal to topic alias maximum {alias_max} but got {alias:?}")
-        ));
+        return Err(MQTTError::InvalidTopicAlias(alias, alias_max));
     }
     Ok(alias)
 }
+
+/// 4.7.1 Validates a Topic Filter: `#` may only appear on its own as the last level, and `+` may only occupy an entire level
+pub fn validate_filter(filter: &str) -> Result<(), MQTTError> {
+    // All Topic Names and Topic Filters MUST be at least one character long [MQTT-4.7.3-1]
+    if filter.is_empty() {
+        return Err(MQTTError::InvalidTopic("empty topic filter"));
+    }
+
+    let mut levels = filter.split('/').peekable();
+    while let Some(level) = levels.next() {
+        match level {
+            "#" if levels.peek().is_some() => {
+                return Err(MQTTError::InvalidTopic("# must be the last level"))
+            }
+            "#" | "+" => {}
+            level if level.contains(['#', '+']) => {
+                return Err(MQTTError::InvalidTopic("wildcards must occupy an entire level"))
+            }
+            _ => {}
+        }
+    }
+
+    Ok(())
+}
+
+/// 4.7 Whether the Topic Name `topic` matches the (valid) Topic Filter `filter`.
+/// Topics starting with `$` are not matched by filters starting with a wildcard [MQTT-4.7.2-1]
+pub fn matches(filter: &str, topic: &str) -> bool {
+    if topic.starts_with('$') && filter.starts_with(['#', '+']) {
+        return false;
+    }
+
+    let mut filter = filter.split('/');
+    let mut topic = topic.split('/');
+
+    loop {
+        match (filter.next(), topic.next()) {
+            // "sport/#" also matches "sport", since # includes the parent level
+            (Some("#"), _) => return true,
+            (Some("+"), Some(_)) => continue,
+            (Some(f), Some(t)) if f == t => continue,
+            (None, None) => return true,
+            _ => return false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn validates_topic_filters() {
+        assert!(validate_filter("sport/tennis/#").is_ok());
+        assert!(validate_filter("#").is_ok());
+        assert!(validate_filter("+/tennis/+").is_ok());
+        assert!(validate_filter("/").is_ok());
+
+        assert!(validate_filter("").is_err());
+        assert!(validate_filter("sport/tennis#").is_err());
+        assert!(validate_filter("sport/#/ranking").is_err());
+        assert!(validate_filter("sport+").is_err());
+    }
+
+    #[test]
+    fn matches_wildcards() {
+        assert!(matches("sport/tennis/player1/#", "sport/tennis/player1"));
+        assert!(matches("sport/tennis/player1/#", "sport/tennis/player1/score/wimbledon"));
+        assert!(matches("sport/#", "sport"));
+        assert!(matches("sport/+", "sport/"));
+        assert!(matches("+/+", "/finance"));
+        assert!(matches("/+", "/finance"));
+        assert!(matches("#", "sport/tennis"));
+
+        assert!(!matches("sport/tennis/+", "sport/tennis/player1/ranking"));
+        assert!(!matches("sport/+", "sport"));
+        assert!(!matches("+", "/finance"));
+        assert!(!matches("sport/tennis", "sport/Tennis"));
+    }
+
+    #[test]
+    fn does_not_match_system_topics_with_leading_wildcards() {
+        assert!(!matches("#", "$SYS/uptime"));
+        assert!(!matches("+/monitor/Clients", "$SYS/monitor/Clients"));
+        assert!(matches("$SYS/#", "$SYS/uptime"));
+        assert!(matches("$SYS/monitor/+", "$SYS/monitor/Clients"));
+    }
+}
@@ -56,6 +56,7 @@ pub fn derive_length(input: TokenStream) -> TokenStream {
 
 
 
+/// The generated `TryFrom<u8>` fails with an `alloc::string::String`: the deriving crate needs `extern crate alloc`
 #[proc_macro_derive(FromU8)]
 pub fn derive_u8(input: TokenStream) -> TokenStream {
     let _input = parse_macro_input!(input as DeriveInput);
@@ -80,12 +81,12 @@ pub fn derive_u8(input: TokenStream) -> TokenStream {
     
     let try_from_u8 = quote! {
         impl TryFrom<u8> for #struct_name {
-            type Error = String;
+            type Error = ::alloc::string::String;
             
             fn try_from(value: u8) -> Result<Self, Self::Error> {
                 match value {
                     #( #discriminant => Ok(#struct_name::#variant), )*
-                    v => Err((format!("{}", v)))
+                    v => Err(::alloc::format!("{}", v))
                 }
             }
         }
@@ -1,3 +1,5 @@
+extern crate alloc;
+
 use hivemqtt_macros::Length;
 use hivemqtt_macros::FromU8;
 
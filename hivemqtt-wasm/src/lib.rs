@@ -0,0 +1,2 @@
+//! The WebAssembly build of `hivemqtt_core`, for `wasm-pack` (see the README).
+pub use hivemqtt_core::*;